@@ -0,0 +1,122 @@
+//! The active expiration cycle of redis (`activeExpireCycle`): expired keys are otherwise
+//! only skipped when read, so keys nobody reads again would stay in memory forever.
+//!
+//! `hz` times a second the cycle samples keys with a TTL and deletes the expired ones, and
+//! keeps sampling while more than an acceptable share of each sample turned out expired,
+//! within a time budget of a fraction of the cycle period. `effort` (1 to 10) trades CPU
+//! for memory: bigger samples, a lower acceptable share of expired keys and a bigger budget.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{exec_lock::ExecLock, log, persistence::Store, prelude::*, replication::Replicas};
+
+const KEYS_PER_LOOP: usize = 20;
+const ACCEPTABLE_STALE_PERCENT: usize = 10;
+const TIME_PERCENT: u64 = 25;
+
+/// Starts the cycle on its own thread. Only the main node runs it: deletions are propagated
+/// to the replicas as DEL, so they do not expire keys on their own clocks.
+pub fn start<T: Store + Send + 'static>(
+    mut store: T,
+    replicas: Replicas,
+    exec_lock: ExecLock,
+    hz: u32,
+    effort: u32,
+    databases: usize,
+) {
+    let effort = effort.clamp(1, 10) as usize - 1;
+    let period = Duration::from_micros(1_000_000 / hz.clamp(1, 500) as u64);
+    let mut cycle = Cycle {
+        keys_per_loop: KEYS_PER_LOOP + KEYS_PER_LOOP / 4 * effort,
+        acceptable_stale: ACCEPTABLE_STALE_PERCENT - effort,
+        budget: period * (TIME_PERCENT + 2 * effort as u64) as u32 / 100,
+        databases,
+        next_db: 0,
+    };
+
+    thread::spawn(move || loop {
+        thread::sleep(period);
+        cycle.run(&mut store, &replicas, &exec_lock);
+    });
+}
+
+struct Cycle {
+    keys_per_loop: usize,
+    acceptable_stale: usize,
+    budget: Duration,
+    databases: usize,
+    /// Where the next cycle starts, so that databases are not starved when the budget runs
+    /// out before all of them were visited.
+    next_db: usize,
+}
+
+impl Cycle {
+    fn run<T: Store>(&mut self, store: &mut T, replicas: &Replicas, exec_lock: &ExecLock) {
+        let started = Instant::now();
+        let mut expired = 0;
+
+        for _ in 0..self.databases {
+            if started.elapsed() > self.budget {
+                break;
+            }
+            let db = self.next_db;
+            self.next_db = (self.next_db + 1) % self.databases;
+
+            match self.expire_db(db, store, replicas, exec_lock, started) {
+                Ok(count) => expired += count,
+                Err(e) => {
+                    log::error(f!("Active expiration failed: {}", e));
+                    return;
+                }
+            }
+        }
+
+        if expired > 0 {
+            log::debug(f!(
+                "Active expiration removed {} keys in {:?}",
+                expired,
+                started.elapsed()
+            ));
+        }
+    }
+
+    /// Samples the database until few enough of the keys sampled turn out expired or the
+    /// budget of the cycle is spent. Returns how many keys were deleted.
+    fn expire_db<T: Store>(
+        &self,
+        db: usize,
+        store: &mut T,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+        started: Instant,
+    ) -> Result<usize> {
+        store.select(db)?;
+        replicas.select(db);
+
+        let mut expired = 0;
+        loop {
+            // NOTE: each round takes the exec lock on its own, letting cmds run in between
+            let sample = exec_lock.run(|| {
+                let sample = store.expire_sample(self.keys_per_loop)?;
+                for key in &sample.expired {
+                    replicas.propagate(&["DEL", key]);
+                }
+                return anyhow::Ok(sample);
+            })?;
+
+            expired += sample.expired.len();
+            let stale = sample.expired.len() + sample.pruned;
+            if sample.sampled == 0
+                || stale * 100 <= sample.sampled * self.acceptable_stale
+                || started.elapsed() > self.budget
+            {
+                return Ok(expired);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use thiserror::Error;
+
+/// Errors that are reported back to the client as RESP simple errors.
+/// Anything else bubbling up from a cmd is treated as a server side failure.
+#[derive(Debug, Error)]
+pub enum RedisError {
+    #[error("ERR unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+    #[error("ERR argument is not valid UTF-8")]
+    NotUtf8,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR increment would produce NaN or Infinity")]
+    NanOrInfinity,
+    #[error("ERR value is out of range")]
+    OutOfRange,
+    #[error("ERR value is out of range, must be positive")]
+    NotPositive,
+    #[error("ERR timeout is not a float or out of range")]
+    InvalidTimeout,
+    #[error("ERR timeout is negative")]
+    NegativeTimeout,
+    #[error("NOGROUP {0}")]
+    NoGroup(String),
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    BusyGroup,
+    #[error("WRONGTYPE Key is not a valid HyperLogLog string value.")]
+    InvalidHll,
+    #[error("INVALIDOBJ Corrupted HLL object detected")]
+    CorruptedHll,
+    #[error("ERR DB index is out of range")]
+    DbIndexOutOfRange,
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+    #[error("EXECABORT Transaction discarded because of previous errors.")]
+    ExecAbort,
+    #[error("EXECABORT Transaction discarded because of: {0}")]
+    ExecAbortBecause(String),
+    #[error("ERR {0}")]
+    Generic(String),
+}
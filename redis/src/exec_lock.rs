@@ -0,0 +1,211 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+/// Serializes cmd execution across the client threads, the way redis runs every cmd on a
+/// single thread. A cmd and its propagation to the replicas happen under the same lock, so
+/// the replicas see writes in the order they were applied.
+///
+/// Blocking cmds release the lock while parked and are woken up whenever a write cmd runs.
+/// Each client blocks through its own clone, which knows the database the client selected.
+#[derive(Clone)]
+pub struct ExecLock {
+    blocked: Arc<Mutex<BlockedClients>>,
+    keys_ready: Arc<Condvar>,
+    db: Cell<usize>,
+    /// The client of this clone holds the lock, running a transaction.
+    held: Cell<bool>,
+}
+
+/// FIFO queues of the clients blocked on each key of each database. Clients are identified
+/// by a ticket handed out when they start waiting.
+struct BlockedClients {
+    queues: HashMap<(usize, String), VecDeque<u64>>,
+    next_ticket: u64,
+}
+
+impl ExecLock {
+    pub fn new() -> Self {
+        return ExecLock {
+            blocked: Arc::new(Mutex::new(BlockedClients::new())),
+            keys_ready: Arc::new(Condvar::new()),
+            db: Cell::new(0),
+            held: Cell::new(false),
+        };
+    }
+
+    /// Database the keys blocked on through this clone belong to.
+    pub fn select(&self, db: usize) {
+        self.db.set(db);
+    }
+
+    pub fn run<R>(&self, cmd: impl FnOnce() -> R) -> R {
+        let _guard = self.lock();
+        self.held.set(true);
+        let result = cmd();
+        self.held.set(false);
+        return result;
+    }
+
+    /// Lets the blocked clients re-check the keys they are waiting on.
+    pub fn signal_keys_ready(&self) {
+        self.keys_ready.notify_all();
+    }
+
+    /// Runs `attempt` against `keys` in order until one of them yields a result. If none
+    /// does, the caller is parked until a write happens or the timeout elapses (`None` waits
+    /// forever). Clients waiting on the same key are served FIFO: only the oldest waiter of a
+    /// key is allowed to attempt on it.
+    pub fn block_on<R>(
+        &self,
+        keys: &[String],
+        timeout: Option<Duration>,
+        attempt: impl FnMut(&str) -> Result<Option<R>>,
+    ) -> Result<Option<R>> {
+        return self.park(keys, timeout, true, attempt);
+    }
+
+    /// Like `block_on`, for cmds that do not consume what they wait for (XREAD). No turns
+    /// are taken: every waiter attempts on every wake up, so one that is still not satisfied
+    /// cannot hold back the others.
+    pub fn block_on_shared<R>(
+        &self,
+        keys: &[String],
+        timeout: Option<Duration>,
+        attempt: impl FnMut(&str) -> Result<Option<R>>,
+    ) -> Result<Option<R>> {
+        return self.park(keys, timeout, false, attempt);
+    }
+
+    fn park<R>(
+        &self,
+        keys: &[String],
+        timeout: Option<Duration>,
+        take_turns: bool,
+        mut attempt: impl FnMut(&str) -> Result<Option<R>>,
+    ) -> Result<Option<R>> {
+        // NOTE: blocking cmds run from EXEC cannot wait, like in redis they get a single
+        // attempt, as if their timeout elapsed right away
+        if self.held.get() {
+            for key in keys {
+                if let Some(result) = attempt(key)? {
+                    return Ok(Some(result));
+                }
+            }
+            return Ok(None);
+        }
+
+        // NOTE: a timeout too far away to be represented is as good as waiting forever
+        let deadline = timeout.and_then(|timeout| return Instant::now().checked_add(timeout));
+        let db = self.db.get();
+        let mut blocked = self.lock();
+
+        for key in keys {
+            if take_turns && blocked.has_waiters(db, key) {
+                continue;
+            }
+            if let Some(result) = attempt(key)? {
+                return Ok(Some(result));
+            }
+        }
+
+        let ticket = take_turns.then(|| return blocked.enqueue(db, keys));
+
+        loop {
+            blocked = match deadline {
+                None => self
+                    .keys_ready
+                    .wait(blocked)
+                    .unwrap_or_else(|poisoned| return poisoned.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    self.keys_ready
+                        .wait_timeout(blocked, deadline - now)
+                        .unwrap_or_else(|poisoned| return poisoned.into_inner())
+                        .0
+                }
+            };
+
+            for key in keys {
+                if ticket.is_some_and(|ticket| return !blocked.is_next(db, key, ticket)) {
+                    continue;
+                }
+
+                let result = attempt(key);
+                if let Ok(None) = result {
+                    continue;
+                }
+
+                if let Some(ticket) = ticket {
+                    blocked.dequeue(ticket);
+                    // NOTE: the next waiter in line may be able to take what is left on the key
+                    self.signal_keys_ready();
+                }
+                return result;
+            }
+        }
+
+        if let Some(ticket) = ticket {
+            blocked.dequeue(ticket);
+            self.signal_keys_ready();
+        }
+        return Ok(None);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BlockedClients> {
+        // NOTE: a cmd panicking must not take the whole server down with it
+        return self
+            .blocked
+            .lock()
+            .unwrap_or_else(|poisoned| return poisoned.into_inner());
+    }
+}
+
+impl BlockedClients {
+    fn new() -> Self {
+        return BlockedClients {
+            queues: HashMap::new(),
+            next_ticket: 0,
+        };
+    }
+
+    fn enqueue(&mut self, db: usize, keys: &[String]) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        for key in keys {
+            let queue = self.queues.entry((db, key.clone())).or_default();
+            if !queue.contains(&ticket) {
+                queue.push_back(ticket);
+            }
+        }
+        return ticket;
+    }
+
+    fn dequeue(&mut self, ticket: u64) {
+        self.queues.retain(|_, queue| {
+            queue.retain(|waiting| return *waiting != ticket);
+            return !queue.is_empty();
+        });
+    }
+
+    fn has_waiters(&self, db: usize, key: &str) -> bool {
+        return self.queues.contains_key(&(db, key.to_string()));
+    }
+
+    fn is_next(&self, db: usize, key: &str, ticket: u64) -> bool {
+        return self
+            .queues
+            .get(&(db, key.to_string()))
+            .and_then(|queue| return queue.front())
+            .is_some_and(|next| return *next == ticket);
+    }
+}
@@ -0,0 +1,484 @@
+//! Geohashes as redis computes them for its geo cmds: 26 steps per axis interleaved into
+//! a 52 bit integer, which is exactly representable as a sorted set score. Latitudes are
+//! limited to the range of web mercator.
+
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+pub const LATITUDE_MIN: f64 = -85.05112878;
+pub const LATITUDE_MAX: f64 = 85.05112878;
+
+const STEP_MAX: u32 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const MERCATOR_MAX: f64 = 20037726.37;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+// Positions of the neighbors in the scan order of `search_ranges`, after the center.
+const NORTH: usize = 1;
+const SOUTH: usize = 2;
+const EAST: usize = 3;
+const WEST: usize = 4;
+const NORTH_EAST: usize = 5;
+const NORTH_WEST: usize = 6;
+const SOUTH_EAST: usize = 7;
+const SOUTH_WEST: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hash {
+    pub bits: u64,
+    pub step: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    longitude: (f64, f64),
+    latitude: (f64, f64),
+}
+
+/// BYRADIUS / BYBOX of GEOSEARCH, in meters.
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+/// Whether the coordinates can be indexed.
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    return (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude)
+        && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude);
+}
+
+/// The 52 bit score of a point. Callers check that the coordinates are valid.
+pub fn encode(longitude: f64, latitude: f64) -> u64 {
+    return encode_in(
+        (LONGITUDE_MIN, LONGITUDE_MAX),
+        (LATITUDE_MIN, LATITUDE_MAX),
+        longitude,
+        latitude,
+        STEP_MAX,
+    )
+    .bits;
+}
+
+/// Center of the cell of a 52 bit score, as (longitude, latitude).
+pub fn decode(score: u64) -> (f64, f64) {
+    let area = decode_area(Hash {
+        bits: score,
+        step: STEP_MAX,
+    });
+    let longitude =
+        ((area.longitude.0 + area.longitude.1) / 2.0).clamp(LONGITUDE_MIN, LONGITUDE_MAX);
+    let latitude = ((area.latitude.0 + area.latitude.1) / 2.0).clamp(LATITUDE_MIN, LATITUDE_MAX);
+    return (longitude, latitude);
+}
+
+/// The standard 11 character geohash of a score, which is based on latitudes in
+/// [-90, 90] rather than the mercator range the scores use.
+pub fn to_base32(score: u64) -> String {
+    let (longitude, latitude) = decode(score);
+    let hash = encode_in(
+        (-180.0, 180.0),
+        (-90.0, 90.0),
+        longitude,
+        latitude,
+        STEP_MAX,
+    );
+    return (0..11)
+        .map(|index| {
+            // NOTE: 52 bits give 10 full characters, the last one is padded with zeros
+            let value = if index == 10 {
+                0
+            } else {
+                (hash.bits >> (52 - (index + 1) * 5)) & 0x1f
+            };
+            return BASE32[value as usize] as char;
+        })
+        .collect();
+}
+
+/// Great circle distance in meters.
+pub fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let v = ((deg_rad(to.0) - deg_rad(from.0)) / 2.0).sin();
+    // NOTE: the same longitude leaves only the latitude difference to account for
+    if v == 0.0 {
+        return latitude_distance(from.1, to.1);
+    }
+    let (from_latitude, to_latitude) = (deg_rad(from.1), deg_rad(to.1));
+    let u = ((to_latitude - from_latitude) / 2.0).sin();
+    let a = u * u + from_latitude.cos() * to_latitude.cos() * v * v;
+    return 2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin();
+}
+
+/// Distance in meters from the center of the shape to the point, `None` when the point
+/// falls outside of it.
+pub fn distance_if_within(center: (f64, f64), shape: Shape, point: (f64, f64)) -> Option<f64> {
+    return match shape {
+        Shape::Radius(radius) => Some(distance(center, point)).filter(|distance| {
+            return *distance <= radius;
+        }),
+        Shape::Box { width, height } => {
+            if latitude_distance(point.1, center.1) > height / 2.0 {
+                return None;
+            }
+            if distance((point.0, point.1), (center.0, point.1)) > width / 2.0 {
+                return None;
+            }
+            Some(distance(center, point))
+        }
+    };
+}
+
+/// Half open score ranges of the cells around `center` that cover the shape, in the
+/// order redis scans them: the center cell, then its neighbors.
+pub fn search_ranges(center: (f64, f64), shape: Shape) -> Vec<(u64, u64)> {
+    let (longitude, latitude) = center;
+    let radius = match shape {
+        Shape::Radius(radius) => radius,
+        Shape::Box { width, height } => ((width / 2.0).powi(2) + (height / 2.0).powi(2)).sqrt(),
+    };
+    let (min_longitude, min_latitude, max_longitude, max_latitude) = bounding_box(center, shape);
+
+    let mut step = estimate_step(radius, latitude);
+    let mut hash = encode_wgs84(longitude, latitude, step);
+    let mut neighbors = Neighbors::of(hash);
+
+    // The estimate can fall short when the center is close to the edge of its cell
+    let north = decode_area(neighbors.north);
+    let south = decode_area(neighbors.south);
+    let east = decode_area(neighbors.east);
+    let west = decode_area(neighbors.west);
+    if step > 1
+        && (north.latitude.1 < max_latitude
+            || south.latitude.0 > min_latitude
+            || east.longitude.1 < max_longitude
+            || west.longitude.0 > min_longitude)
+    {
+        step -= 1;
+        hash = encode_wgs84(longitude, latitude, step);
+        neighbors = Neighbors::of(hash);
+    }
+
+    let mut cells = [
+        Some(hash),
+        Some(neighbors.north),
+        Some(neighbors.south),
+        Some(neighbors.east),
+        Some(neighbors.west),
+        Some(neighbors.north_east),
+        Some(neighbors.north_west),
+        Some(neighbors.south_east),
+        Some(neighbors.south_west),
+    ];
+    // Neighbors on a side the center cell already covers the shape to are useless
+    let area = decode_area(hash);
+    if step >= 2 {
+        let mut skip = |indexes: [usize; 3]| indexes.iter().for_each(|index| cells[*index] = None);
+        if area.latitude.0 < min_latitude {
+            skip([SOUTH, SOUTH_EAST, SOUTH_WEST]);
+        }
+        if area.latitude.1 > max_latitude {
+            skip([NORTH, NORTH_EAST, NORTH_WEST]);
+        }
+        if area.longitude.0 < min_longitude {
+            skip([WEST, NORTH_WEST, SOUTH_WEST]);
+        }
+        if area.longitude.1 > max_longitude {
+            skip([EAST, NORTH_EAST, SOUTH_EAST]);
+        }
+    }
+
+    let mut ranges = Vec::new();
+    let mut previous: Option<Hash> = None;
+    for cell in cells.into_iter().flatten() {
+        // NOTE: huge radiuses make adjacent neighbors the same cell
+        if previous == Some(cell) {
+            continue;
+        }
+        let shift = 52 - cell.step * 2;
+        ranges.push((cell.bits << shift, (cell.bits + 1) << shift));
+        previous = Some(cell);
+    }
+    return ranges;
+}
+
+fn encode_in(
+    longitude_range: (f64, f64),
+    latitude_range: (f64, f64),
+    longitude: f64,
+    latitude: f64,
+    step: u32,
+) -> Hash {
+    let latitude_offset = (latitude - latitude_range.0) / (latitude_range.1 - latitude_range.0);
+    let longitude_offset =
+        (longitude - longitude_range.0) / (longitude_range.1 - longitude_range.0);
+    let scale = (1u64 << step) as f64;
+    return Hash {
+        bits: interleave(
+            (latitude_offset * scale) as u32,
+            (longitude_offset * scale) as u32,
+        ),
+        step,
+    };
+}
+
+fn encode_wgs84(longitude: f64, latitude: f64, step: u32) -> Hash {
+    return encode_in(
+        (LONGITUDE_MIN, LONGITUDE_MAX),
+        (LATITUDE_MIN, LATITUDE_MAX),
+        longitude,
+        latitude,
+        step,
+    );
+}
+
+fn decode_area(hash: Hash) -> Area {
+    let (latitude, longitude) = deinterleave(hash.bits);
+    let cells = (1u64 << hash.step) as f64;
+    let longitude_scale = LONGITUDE_MAX - LONGITUDE_MIN;
+    let latitude_scale = LATITUDE_MAX - LATITUDE_MIN;
+    return Area {
+        longitude: (
+            LONGITUDE_MIN + (longitude as f64 / cells) * longitude_scale,
+            LONGITUDE_MIN + ((longitude as f64 + 1.0) / cells) * longitude_scale,
+        ),
+        latitude: (
+            LATITUDE_MIN + (latitude as f64 / cells) * latitude_scale,
+            LATITUDE_MIN + ((latitude as f64 + 1.0) / cells) * latitude_scale,
+        ),
+    };
+}
+
+/// Latitude bits go to the even positions, longitude bits to the odd ones.
+fn interleave(latitude: u32, longitude: u32) -> u64 {
+    return spread(latitude) | spread(longitude) << 1;
+}
+
+fn deinterleave(bits: u64) -> (u32, u32) {
+    return (squash(bits), squash(bits >> 1));
+}
+
+fn spread(value: u32) -> u64 {
+    let mut value = value as u64;
+    value = (value | value << 16) & 0x0000_FFFF_0000_FFFF;
+    value = (value | value << 8) & 0x00FF_00FF_00FF_00FF;
+    value = (value | value << 4) & 0x0F0F_0F0F_0F0F_0F0F;
+    value = (value | value << 2) & 0x3333_3333_3333_3333;
+    value = (value | value << 1) & 0x5555_5555_5555_5555;
+    return value;
+}
+
+fn squash(value: u64) -> u32 {
+    let mut value = value & 0x5555_5555_5555_5555;
+    value = (value | value >> 1) & 0x3333_3333_3333_3333;
+    value = (value | value >> 2) & 0x0F0F_0F0F_0F0F_0F0F;
+    value = (value | value >> 4) & 0x00FF_00FF_00FF_00FF;
+    value = (value | value >> 8) & 0x0000_FFFF_0000_FFFF;
+    value = (value | value >> 16) & 0x0000_0000_FFFF_FFFF;
+    return value as u32;
+}
+
+// NOTE: radians go back to degrees dividing by the same factor like in redis, which can
+// differ from `to_degrees` in the last bit
+fn deg_rad(degrees: f64) -> f64 {
+    return degrees * (std::f64::consts::PI / 180.0);
+}
+
+fn rad_deg(radians: f64) -> f64 {
+    return radians / (std::f64::consts::PI / 180.0);
+}
+
+fn latitude_distance(from: f64, to: f64) -> f64 {
+    return EARTH_RADIUS_IN_METERS * (deg_rad(to) - deg_rad(from)).abs();
+}
+
+/// Steps of the coarsest cells whose neighborhood still covers the radius, made coarser
+/// towards the poles where cells get narrower.
+fn estimate_step(mut radius: f64, latitude: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+    return step.clamp(1, STEP_MAX as i32) as u32;
+}
+
+/// (min longitude, min latitude, max longitude, max latitude) around the shape.
+fn bounding_box(center: (f64, f64), shape: Shape) -> (f64, f64, f64, f64) {
+    let (longitude, latitude) = center;
+    let (half_width, half_height) = match shape {
+        Shape::Radius(radius) => (radius, radius),
+        Shape::Box { width, height } => (width / 2.0, height / 2.0),
+    };
+
+    let latitude_delta = rad_deg(half_height / EARTH_RADIUS_IN_METERS);
+    let longitude_delta = |latitude: f64| {
+        return rad_deg(half_width / EARTH_RADIUS_IN_METERS / deg_rad(latitude).cos());
+    };
+    // NOTE: the wider edge of the box is the one closer to the equator
+    let longitude_delta = if latitude < 0.0 {
+        longitude_delta(latitude - latitude_delta)
+    } else {
+        longitude_delta(latitude + latitude_delta)
+    };
+    return (
+        longitude - longitude_delta,
+        latitude - latitude_delta,
+        longitude + longitude_delta,
+        latitude + latitude_delta,
+    );
+}
+
+struct Neighbors {
+    north: Hash,
+    south: Hash,
+    east: Hash,
+    west: Hash,
+    north_east: Hash,
+    north_west: Hash,
+    south_east: Hash,
+    south_west: Hash,
+}
+
+impl Neighbors {
+    fn of(hash: Hash) -> Self {
+        let moved = |x: i8, y: i8| return move_y(move_x(hash, x), y);
+        return Neighbors {
+            north: moved(0, 1),
+            south: moved(0, -1),
+            east: moved(1, 0),
+            west: moved(-1, 0),
+            north_east: moved(1, 1),
+            north_west: moved(-1, 1),
+            south_east: moved(1, -1),
+            south_west: moved(-1, -1),
+        };
+    }
+}
+
+/// Moves a cell east or west, wrapping around.
+fn move_x(hash: Hash, direction: i8) -> Hash {
+    if direction == 0 {
+        return hash;
+    }
+    let mut x = hash.bits & 0xAAAA_AAAA_AAAA_AAAA;
+    let y = hash.bits & 0x5555_5555_5555_5555;
+    let zz = 0x5555_5555_5555_5555u64 >> (64 - hash.step * 2);
+    if direction > 0 {
+        x = x.wrapping_add(zz + 1);
+    } else {
+        x = (x | zz).wrapping_sub(zz + 1);
+    }
+    x &= 0xAAAA_AAAA_AAAA_AAAAu64 >> (64 - hash.step * 2);
+    return Hash {
+        bits: x | y,
+        step: hash.step,
+    };
+}
+
+/// Moves a cell north or south, wrapping around.
+fn move_y(hash: Hash, direction: i8) -> Hash {
+    if direction == 0 {
+        return hash;
+    }
+    let x = hash.bits & 0xAAAA_AAAA_AAAA_AAAA;
+    let mut y = hash.bits & 0x5555_5555_5555_5555;
+    let zz = 0xAAAA_AAAA_AAAA_AAAAu64 >> (64 - hash.step * 2);
+    if direction > 0 {
+        y = y.wrapping_add(zz + 1);
+    } else {
+        y = (y | zz).wrapping_sub(zz + 1);
+    }
+    y &= 0x5555_5555_5555_5555u64 >> (64 - hash.step * 2);
+    return Hash {
+        bits: x | y,
+        step: hash.step,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode, distance, distance_if_within, encode, is_valid, search_ranges, to_base32, Shape,
+        LATITUDE_MAX, LATITUDE_MIN, LONGITUDE_MAX, LONGITUDE_MIN,
+    };
+
+    const PALERMO: (f64, f64) = (13.361389, 38.115556);
+    const CATANIA: (f64, f64) = (15.087269, 37.502669);
+
+    #[test]
+    fn scores_and_hashes_like_redis() {
+        assert_eq!(encode(PALERMO.0, PALERMO.1), 3479099956230698);
+        assert_eq!(encode(CATANIA.0, CATANIA.1), 3479447370796909);
+        assert_eq!(to_base32(3479099956230698), "sqc8b49rny0");
+        assert_eq!(to_base32(3479447370796909), "sqdtr74hyu0");
+
+        let (longitude, latitude) = decode(3479099956230698);
+        assert!((longitude - PALERMO.0).abs() < 1e-5 && (latitude - PALERMO.1).abs() < 1e-5);
+        // NOTE: GEODIST measures between the points as stored
+        let stored = distance(decode(3479099956230698), decode(3479447370796909));
+        assert!((stored - 166274.1516).abs() < 1e-4);
+    }
+
+    #[test]
+    fn tells_points_within_shapes() {
+        assert!(distance_if_within(PALERMO, Shape::Radius(200_000.0), CATANIA).is_some());
+        assert!(distance_if_within(PALERMO, Shape::Radius(100_000.0), CATANIA).is_none());
+        let narrow = Shape::Box {
+            width: 400_000.0,
+            height: 10_000.0,
+        };
+        assert!(distance_if_within(PALERMO, narrow, CATANIA).is_none());
+    }
+
+    #[test]
+    fn search_ranges_cover_the_center() {
+        let centers = [
+            PALERMO,
+            (0.0, 0.0),
+            (LONGITUDE_MIN, LATITUDE_MIN),
+            (LONGITUDE_MAX, LATITUDE_MAX),
+            (179.9999, -85.0),
+        ];
+        let shapes = [
+            Shape::Radius(0.0),
+            Shape::Radius(1.0),
+            Shape::Radius(100_000.0),
+            Shape::Radius(f64::INFINITY),
+            Shape::Box {
+                width: 1e9,
+                height: 0.0,
+            },
+        ];
+        for center in centers {
+            assert!(is_valid(center.0, center.1));
+            let score = encode(center.0, center.1);
+            let (longitude, latitude) = decode(score);
+            assert!(is_valid(longitude, latitude));
+
+            for shape in shapes {
+                let ranges = search_ranges(center, shape);
+                assert!(
+                    ranges
+                        .iter()
+                        .any(|(min, max)| return (*min..*max).contains(&score)),
+                    "{center:?} {shape:?}"
+                );
+            }
+        }
+
+        let ranges = search_ranges(PALERMO, Shape::Radius(200_000.0));
+        let catania = encode(CATANIA.0, CATANIA.1);
+        assert!(ranges
+            .iter()
+            .any(|(min, max)| return (*min..*max).contains(&catania)));
+    }
+}
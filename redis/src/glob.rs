@@ -0,0 +1,138 @@
+/// Glob style matching as done by redis' `stringmatchlen`: `*`, `?`, `[a-z]`, `[^x]` and
+/// backslash escapes. Works on bytes, so multi byte chars count as several `?`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    return matches_bytes(pattern.as_bytes(), text.as_bytes());
+}
+
+fn matches_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+
+    while p < pattern.len() && t < text.len() {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (t..text.len()).any(|start| {
+                    return matches_bytes(&pattern[p + 1..], &text[start..]);
+                });
+            }
+            b'?' => t += 1,
+            b'[' => {
+                p += 1;
+                let negate = pattern.get(p) == Some(&b'^');
+                if negate {
+                    p += 1;
+                }
+
+                let mut matched = false;
+                loop {
+                    match pattern.get(p) {
+                        None => {
+                            // NOTE: unterminated class, the last char is treated as the end
+                            p -= 1;
+                            break;
+                        }
+                        Some(b']') => break,
+                        Some(b'\\') if p + 1 < pattern.len() => {
+                            p += 1;
+                            matched |= pattern[p] == text[t];
+                        }
+                        Some(start) if p + 2 < pattern.len() && pattern[p + 1] == b'-' => {
+                            let (low, high) = if *start <= pattern[p + 2] {
+                                (*start, pattern[p + 2])
+                            } else {
+                                (pattern[p + 2], *start)
+                            };
+                            matched |= low <= text[t] && text[t] <= high;
+                            p += 2;
+                        }
+                        Some(c) => matched |= *c == text[t],
+                    }
+                    p += 1;
+                }
+
+                if matched == negate {
+                    return false;
+                }
+                t += 1;
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if pattern[p] != text[t] {
+                    return false;
+                }
+                t += 1;
+            }
+            c => {
+                if c != text[t] {
+                    return false;
+                }
+                t += 1;
+            }
+        }
+        p += 1;
+    }
+
+    if t == text.len() {
+        while p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+        }
+    }
+
+    return p == pattern.len() && t == text.len();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn matches_like_redis() {
+        for (pattern, text) in [
+            ("*", ""),
+            ("*", "anything"),
+            ("h?llo", "hello"),
+            ("h*llo", "hllo"),
+            ("h**llo", "heeello"),
+            ("h[ae]llo", "hallo"),
+            ("h[^e]llo", "hallo"),
+            ("h[a-b]llo", "hbllo"),
+            ("h[b-a]llo", "hallo"),
+            ("h\\*llo", "h*llo"),
+            ("h[\\]]llo", "h]llo"),
+            ("news.*", "news.tech"),
+        ] {
+            assert!(matches(pattern, text), "{pattern} should match {text}");
+        }
+
+        for (pattern, text) in [
+            ("", "a"),
+            ("?", ""),
+            ("h?llo", "hllo"),
+            ("h[ae]llo", "hillo"),
+            ("h[^e]llo", "hello"),
+            ("h\\*llo", "hello"),
+            ("news.*", "news"),
+        ] {
+            assert!(!matches(pattern, text), "{pattern} should not match {text}");
+        }
+    }
+
+    #[test]
+    fn survives_malformed_patterns() {
+        assert!(!matches("[", "a"));
+        assert!(matches("[^", "a"));
+        assert!(matches("[a-", "-"));
+        assert!(!matches("[]", "]"));
+        assert!(!matches("a[", "a["));
+        assert!(matches("\\", "\\"));
+        assert!(!matches("[\\", "a"));
+        assert!(!matches("*[", "abc"));
+        assert!(!matches("[a", ""));
+    }
+}
@@ -0,0 +1,60 @@
+//! Hash slots as redis cluster assigns them to keys: CRC16 (XMODEM) of the key modulo
+//! 16384. When the key has a hash tag, a non empty `{...}` section, only the tag is hashed
+//! so related keys can be kept on the same slot.
+
+const SLOTS: usize = 16384;
+
+pub fn key_slot(key: &str) -> usize {
+    let key = key.as_bytes();
+    let hashed = match key.iter().position(|byte| return *byte == b'{') {
+        Some(open) => {
+            let tag = &key[open + 1..];
+            match tag.iter().position(|byte| return *byte == b'}') {
+                Some(close) if close > 0 => &tag[..close],
+                _ => key,
+            }
+        }
+        None => key,
+    };
+    return crc16(hashed) as usize % SLOTS;
+}
+
+/// CRC16 with the 0x1021 polynomial and a zero initial value, bit by bit.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    return crc;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc16, key_slot, SLOTS};
+
+    #[test]
+    fn hashes_like_redis_cluster() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot("somekey"), 11058);
+        assert_eq!(key_slot("foo{hash_tag}"), 2515);
+        assert_eq!(key_slot(""), 0);
+    }
+
+    #[test]
+    fn hashes_only_non_empty_tags() {
+        assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+        assert_eq!(key_slot("x{a}{b}"), key_slot("a"));
+        assert_eq!(key_slot("{{a}}"), key_slot("{a"));
+        for key in ["{}a", "a{", "a}", "{a", "a{}b{c}"] {
+            let whole_key = crc16(key.as_bytes()) as usize % SLOTS;
+            assert_eq!(key_slot(key), whole_key, "{key}");
+        }
+    }
+}
@@ -1,9 +1,19 @@
 #![deny(clippy::implicit_return)]
 #![allow(clippy::needless_return)]
-
+#![allow(clippy::upper_case_acronyms)]
+
+mod active_expire;
+mod errors;
+mod exec_lock;
+mod geohash;
+mod glob;
+mod keyslot;
 mod log;
 mod persistence;
 mod prelude;
+mod pubsub;
+mod random;
+mod replication;
 mod resp_protocol;
 
 use core::panic;
@@ -16,13 +26,20 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Result;
-use persistence::{in_mem::InMemStore, Store};
+use anyhow::{anyhow, Result};
+use exec_lock::ExecLock;
+use persistence::{
+    in_mem::InMemStore, EncodingLimits, EvictionPolicy, LfuSettings, MaxMemory, Store,
+};
+use pubsub::PubSub;
+use replication::Replicas;
 use resp_protocol::data_types::ArrayStack;
 
+use crate::errors::RedisError;
 use crate::prelude::*;
+use crate::resp_protocol::cmds::RESPCmd;
 use crate::resp_protocol::data_types::RESPType;
-use crate::resp_protocol::{cmds, data_types};
+use crate::resp_protocol::{cmds, data_types, reply, util, Subscriptions, Transaction};
 
 fn main() -> Result<()> {
     let config = Arc::new(parse_args());
@@ -30,11 +47,28 @@ fn main() -> Result<()> {
     let listener = TcpListener::bind(address)?;
 
     match &config.role {
-        ServerRole::Main { id } => start_as_main(&id)?,
-        ServerRole::Replica { main_addr } => start_as_replica(&main_addr, config.port)?,
+        ServerRole::Main { id } => start_as_main(id)?,
+        ServerRole::Replica { main_addr } => start_as_replica(main_addr, config.port)?,
     }
 
-    let store = InMemStore::new();
+    let store = InMemStore::new(
+        config.databases,
+        config.max_memory.clone(),
+        config.encodings,
+    );
+    let replicas = Replicas::new();
+    let exec_lock = ExecLock::new();
+    let pubsub = PubSub::new();
+    if let ServerRole::Main { .. } = config.role {
+        active_expire::start(
+            store.clone(),
+            replicas.clone(),
+            exec_lock.clone(),
+            config.hz,
+            config.active_expire_effort,
+            config.databases,
+        );
+    }
     println!("[INFO] Listening on port {}", config.port);
 
     for stream in listener.incoming() {
@@ -42,9 +76,19 @@ fn main() -> Result<()> {
             Ok(stream) => {
                 let mut store_clone = store.clone();
                 let config = Arc::clone(&config);
+                let replicas = replicas.clone();
+                let exec_lock = exec_lock.clone();
+                let pubsub = pubsub.clone();
 
                 thread::spawn(move || {
-                    handle_client(stream, &config, &mut store_clone);
+                    handle_client(
+                        stream,
+                        &config,
+                        &mut store_clone,
+                        &replicas,
+                        &exec_lock,
+                        &pubsub,
+                    );
                 });
             }
             Err(e) => {
@@ -106,17 +150,32 @@ fn start_as_main(_id: &str) -> Result<()> {
     return Ok(());
 }
 
-fn handle_client<T: Store>(stream: TcpStream, config: &Arc<Config>, store: &mut T) {
+fn handle_client<T: Store>(
+    stream: TcpStream,
+    config: &Arc<Config>,
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+    pubsub: &PubSub,
+) {
     let mut reader = BufReader::new(&stream);
     let mut writer = BufWriter::new(&stream);
     let mut array_stack = ArrayStack::new();
+    let mut transaction = Transaction::new();
+    let mut subscriptions = match Subscriptions::new(&stream) {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => {
+            log::error(f!("Could not clone the client connection: {}", e));
+            return;
+        }
+    };
 
     loop {
         log::info("Searching for new command");
         let next_data = data_types::read_next_data_optional(&mut reader);
         if next_data.is_none() {
             log::info("Reached end of stream.");
-            return;
+            break;
         }
 
         let data = next_data.unwrap();
@@ -126,19 +185,124 @@ fn handle_client<T: Store>(stream: TcpStream, config: &Arc<Config>, store: &mut
                 array_stack.start_new_array(size);
             }
             RESPType::BulkString { size } => {
-                match cmds::parse(size, &mut reader, &mut array_stack) {
+                let cmd = cmds::parse(size, &mut reader, &mut array_stack);
+                let raw_args = match util::read_args(&mut reader, &mut array_stack) {
+                    Ok(raw_args) => raw_args,
+                    Err(e) => {
+                        log::error(f!("Could not read cmd arguments: {}", e));
+                        break;
+                    }
+                };
+
+                match cmd {
                     Ok(cmd) => {
-                        match cmd.execute(&mut reader, &mut writer, &mut array_stack, store, config)
-                        {
-                            Ok(_) => log::debug(f!("Cmd {:?} ran successfully", cmd)),
+                        let name = f!("{:?}", cmd);
+                        // NOTE: binary safe cmds only go through the raw args, which they read
+                        // themselves
+                        let args = match util::text_args(&raw_args) {
+                            Ok(args) => args,
+                            Err(_) if cmd.is_binary_safe() => Vec::new(),
                             Err(e) => {
-                                log::error(f!("Unexpected error executing cmd {:?}: {}", cmd, e))
+                                log::error(f!("Invalid args for cmd {}: {}", name, e));
+                                transaction.abort();
+                                _ = subscriptions.reply_error(
+                                    &mut writer,
+                                    anyhow!(RedisError::NotUtf8),
+                                    pubsub,
+                                );
+                                continue;
+                            }
+                        };
+                        let result = match cmd {
+                            RESPCmd::QUIT => {
+                                subscriptions.close(pubsub);
+                                _ = reply::ok(&mut writer);
+                                _ = writer.flush();
+                                break;
+                            }
+                            RESPCmd::RESET => reset_client(
+                                &mut writer,
+                                &args,
+                                &mut transaction,
+                                &mut subscriptions,
+                                store,
+                                replicas,
+                                exec_lock,
+                                pubsub,
+                            ),
+                            RESPCmd::SUBSCRIBE
+                            | RESPCmd::UNSUBSCRIBE
+                            | RESPCmd::PSUBSCRIBE
+                            | RESPCmd::PUNSUBSCRIBE
+                            | RESPCmd::SSUBSCRIBE
+                            | RESPCmd::SUNSUBSCRIBE
+                                if transaction.is_active() =>
+                            {
+                                transaction.refuse(&mut writer)
+                            }
+                            RESPCmd::SUBSCRIBE => {
+                                subscriptions.subscribe(&mut writer, &args, pubsub)
+                            }
+                            RESPCmd::UNSUBSCRIBE => {
+                                subscriptions.unsubscribe(&mut writer, &args, pubsub)
+                            }
+                            RESPCmd::PSUBSCRIBE => {
+                                subscriptions.psubscribe(&mut writer, &args, pubsub)
+                            }
+                            RESPCmd::PUNSUBSCRIBE => {
+                                subscriptions.punsubscribe(&mut writer, &args, pubsub)
+                            }
+                            RESPCmd::SSUBSCRIBE => {
+                                subscriptions.ssubscribe(&mut writer, &args, pubsub)
+                            }
+                            RESPCmd::SUNSUBSCRIBE => {
+                                subscriptions.sunsubscribe(&mut writer, &args, pubsub)
+                            }
+                            RESPCmd::PING if subscriptions.is_active() => {
+                                subscriptions.ping(&mut writer, &args, pubsub)
+                            }
+                            _ if subscriptions.is_active() => {
+                                subscriptions.reject(&mut writer, &name.to_lowercase(), pubsub)
+                            }
+                            RESPCmd::MULTI => transaction.multi(&mut writer, &args),
+                            RESPCmd::DISCARD => transaction.discard(&mut writer, &args, store),
+                            RESPCmd::WATCH => transaction.watch(&mut writer, &args, store),
+                            RESPCmd::UNWATCH if !transaction.is_active() => {
+                                transaction.unwatch(&mut writer, &args, store)
+                            }
+                            RESPCmd::EXEC => transaction.exec(
+                                &mut writer,
+                                &args,
+                                store,
+                                config,
+                                replicas,
+                                exec_lock,
+                                pubsub,
+                            ),
+                            cmd if transaction.is_active() => {
+                                transaction.queue(&mut writer, cmd, raw_args)
+                            }
+                            cmd => cmd.execute(
+                                &mut writer,
+                                &raw_args,
+                                store,
+                                config,
+                                replicas,
+                                exec_lock,
+                                pubsub,
+                            ),
+                        };
+                        match result {
+                            Ok(_) => log::debug(f!("Cmd {} ran successfully", name)),
+                            Err(e) => {
+                                log::error(f!("Unexpected error executing cmd {}: {}", name, e))
                             }
                         }
                     }
                     Err(e) => {
                         log::error(f!("Unsupported cmd: {}", e));
-                        // TODO: inform unsupported cmd on response writer!
+                        transaction.abort();
+                        _ = subscriptions.reply_error(&mut writer, e, pubsub);
                     }
                 }
             }
@@ -148,10 +312,44 @@ fn handle_client<T: Store>(stream: TcpStream, config: &Arc<Config>, store: &mut
                     data
                 ));
                 // TODO: inform invalid msg on response writer!
-                return;
+                break;
             }
         }
     }
+
+    subscriptions.close(pubsub);
+    if let Err(e) = transaction.close(store) {
+        log::error(f!("Could not unwatch the keys of the client: {}", e));
+    }
+}
+
+/// RESET
+///
+/// Brings the connection back to the state of a new one: out of any transaction and
+/// subscription, on the first database.
+#[allow(clippy::too_many_arguments)]
+fn reset_client<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    transaction: &mut Transaction,
+    subscriptions: &mut Subscriptions,
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+    pubsub: &PubSub,
+) -> Result<()> {
+    if !args.is_empty() {
+        reply::error(writer, &RedisError::WrongArity("reset".into()))?;
+    } else {
+        transaction.close(store)?;
+        subscriptions.close(pubsub);
+        store.select(0)?;
+        replicas.select(0);
+        exec_lock.select(0);
+        reply::simple_string(writer, "RESET")?;
+    }
+    writer.flush()?;
+    return Ok(());
 }
 
 // TODO: use clap
@@ -168,6 +366,57 @@ fn parse_args() -> Config {
 
         if capture == "--port" {
             cfg.port = arg.parse::<u16>().expect("Valid port");
+        } else if capture == "--hz" {
+            cfg.hz = arg.parse::<u32>().expect("Valid hz").clamp(1, 500);
+        } else if capture == "--active-expire-effort" {
+            cfg.active_expire_effort = arg
+                .parse::<u32>()
+                .ok()
+                .filter(|effort| return (1..=10).contains(effort))
+                .expect("Valid active expire effort (1 to 10)");
+        } else if capture == "--databases" {
+            cfg.databases = arg
+                .parse::<usize>()
+                .ok()
+                .filter(|databases| return *databases >= 1)
+                .expect("Valid number of databases");
+        } else if capture == "--maxmemory" {
+            cfg.max_memory.limit = parse_memory(arg).expect("Valid maxmemory");
+        } else if capture == "--maxmemory-policy" {
+            cfg.max_memory.policy = EvictionPolicy::parse(arg).expect("Valid maxmemory policy");
+        } else if capture == "--maxmemory-samples" {
+            cfg.max_memory.samples = arg
+                .parse::<usize>()
+                .ok()
+                .filter(|samples| return (1..=64).contains(samples))
+                .expect("Valid maxmemory samples (1 to 64)");
+        } else if capture == "--lfu-log-factor" {
+            cfg.max_memory.lfu.log_factor = arg.parse::<u32>().expect("Valid lfu log factor");
+        } else if capture == "--lfu-decay-time" {
+            cfg.max_memory.lfu.decay_time = arg.parse::<u32>().expect("Valid lfu decay time");
+        } else if capture == "--hash-max-listpack-entries" {
+            cfg.encodings.hash_max_listpack_entries = arg
+                .parse::<usize>()
+                .expect("Valid hash max listpack entries");
+        } else if capture == "--hash-max-listpack-value" {
+            cfg.encodings.hash_max_listpack_value =
+                arg.parse::<usize>().expect("Valid hash max listpack value");
+        } else if capture == "--list-max-listpack-size" {
+            cfg.encodings.list_max_listpack_size = arg
+                .parse::<i64>()
+                .ok()
+                .filter(|size| return *size != 0 && *size >= -5)
+                .expect("Valid list max listpack size (positive, or -1 to -5)");
+        } else if capture == "--set-max-intset-entries" {
+            cfg.encodings.set_max_intset_entries =
+                arg.parse::<usize>().expect("Valid set max intset entries");
+        } else if capture == "--zset-max-listpack-entries" {
+            cfg.encodings.zset_max_listpack_entries = arg
+                .parse::<usize>()
+                .expect("Valid zset max listpack entries");
+        } else if capture == "--zset-max-listpack-value" {
+            cfg.encodings.zset_max_listpack_value =
+                arg.parse::<usize>().expect("Valid zset max listpack value");
         } else if capture == "--replicaof" {
             cfg.role = ServerRole::Replica {
                 main_addr: arg.clone(),
@@ -180,15 +429,61 @@ fn parse_args() -> Config {
     return cfg;
 }
 
+/// Parses a number of bytes with an optional unit like redis config files do: k, m and g are
+/// powers of 1000, kb, mb and gb powers of 1024.
+fn parse_memory(arg: &str) -> Option<usize> {
+    let arg = arg.to_lowercase();
+    let digits = arg
+        .find(|c: char| return !c.is_ascii_digit())
+        .unwrap_or(arg.len());
+    let multiplier = match &arg[digits..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    return arg[..digits].parse::<usize>().ok()?.checked_mul(multiplier);
+}
+
 struct Config {
     port: u16,
     role: ServerRole,
+    /// Frequency of the background tasks, like the active expiration cycle.
+    hz: u32,
+    active_expire_effort: u32,
+    databases: usize,
+    max_memory: MaxMemory,
+    encodings: EncodingLimits,
 }
 
 impl Config {
     fn default() -> Config {
         return Config {
             port: 6379,
+            hz: 10,
+            active_expire_effort: 1,
+            databases: 16,
+            max_memory: MaxMemory {
+                limit: 0,
+                policy: EvictionPolicy::NoEviction,
+                samples: 5,
+                lfu: LfuSettings {
+                    log_factor: 10,
+                    decay_time: 1,
+                },
+            },
+            encodings: EncodingLimits {
+                hash_max_listpack_entries: 128,
+                hash_max_listpack_value: 64,
+                list_max_listpack_size: -2,
+                set_max_intset_entries: 512,
+                zset_max_listpack_entries: 128,
+                zset_max_listpack_value: 64,
+            },
             // TODO: generate random id
             role: ServerRole::Main {
                 id: String::from("8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb"),
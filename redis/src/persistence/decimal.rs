@@ -0,0 +1,133 @@
+use crate::prelude::*;
+
+/// Decimals redis writes the result of float increments with (`%.17Lf`).
+const DECIMALS: u32 = 17;
+
+/// A float as an exact decimal: `mantissa / 10^scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// The decimal the shortest representation of `value` spells out, unless it takes more
+    /// digits than fit.
+    fn parse(value: f64) -> Option<Self> {
+        let written = f!("{}", value);
+        let (integer, fraction) = written.split_once('.').unwrap_or((&written, ""));
+        let scale = u32::try_from(fraction.len()).ok()?;
+        10_i128.checked_pow(scale)?;
+        let mantissa = f!("{}{}", integer, fraction).parse::<i128>().ok()?;
+        return Some(Decimal { mantissa, scale });
+    }
+
+    fn rescale(self, scale: u32) -> Option<Self> {
+        let mantissa = self
+            .mantissa
+            .checked_mul(10_i128.checked_pow(scale - self.scale)?)?;
+        return Some(Decimal { mantissa, scale });
+    }
+
+    fn checked_add(self, other: Decimal) -> Option<Self> {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.rescale(scale)?, other.rescale(scale)?);
+        return Some(Decimal {
+            mantissa: a.mantissa.checked_add(b.mantissa)?,
+            scale,
+        });
+    }
+
+    /// Rounds half away from zero to at most `decimals` decimals.
+    fn round(self, decimals: u32) -> Self {
+        if self.scale <= decimals {
+            return self;
+        }
+        let divisor = 10_i128.pow(self.scale - decimals);
+        let mut mantissa = self.mantissa / divisor;
+        if (self.mantissa % divisor).abs() * 2 >= divisor {
+            mantissa += self.mantissa.signum();
+        }
+        return Decimal {
+            mantissa,
+            scale: decimals,
+        };
+    }
+
+    /// Plain decimal notation without trailing zeros.
+    fn format(self) -> String {
+        let divisor = 10_i128.pow(self.scale);
+        let magnitude = self.mantissa.unsigned_abs();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let integer = magnitude / divisor as u128;
+        let fraction = f!(
+            "{:0width$}",
+            magnitude % divisor as u128,
+            width = self.scale as usize
+        );
+        let fraction = fraction.trim_end_matches('0');
+        return if fraction.is_empty() {
+            f!("{}{}", sign, integer)
+        } else {
+            f!("{}{}.{}", sign, integer, fraction)
+        };
+    }
+}
+
+/// Adds up the operands of a float increment and formats the result the way redis does.
+///
+/// Redis sums them as long doubles and writes 17 decimals, trailing zeros trimmed, so 0.1 +
+/// 0.2 is 0.3 there where f64 gives 0.30000000000000004. Adding them as exact decimals
+/// matches that. Operands taking too many digits for it are added as f64.
+pub fn add(current: f64, increment: f64) -> (f64, String) {
+    let sum = Decimal::parse(current)
+        .zip(Decimal::parse(increment))
+        .and_then(|(current, increment)| return current.checked_add(increment));
+    let written = match sum {
+        Some(sum) => sum.round(DECIMALS).format(),
+        None => f!("{}", current + increment),
+    };
+    let value = written.parse::<f64>().unwrap_or(current + increment);
+    return (value, written);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::add;
+    use crate::prelude::*;
+
+    fn sum(current: f64, increment: f64) -> String {
+        return add(current, increment).1;
+    }
+
+    #[test]
+    fn adds_like_long_doubles() {
+        assert_eq!(sum(0.1, 0.2), "0.3");
+        assert_eq!(sum(10.5, 0.1), "10.6");
+        assert_eq!(sum(3.0e3, 1.2), "3001.2");
+        assert_eq!(sum(-0.1, 0.1), "0");
+        assert_eq!(sum(-1.5, 0.25), "-1.25");
+        assert_eq!(sum(0.0, -0.5), "-0.5");
+    }
+
+    #[test]
+    fn writes_integers_without_decimals() {
+        assert_eq!(sum(1.0, 2.0), "3");
+        assert_eq!(sum(5.0e3, 2.0e2), "5200");
+        assert_eq!(sum(-7.0, 0.0), "-7");
+    }
+
+    #[test]
+    fn rounds_to_17_decimals() {
+        assert_eq!(sum(0.000_000_000_000_000_004, 0.0), "0");
+        assert_eq!(sum(0.000_000_000_000_000_005, 0.0), "0.00000000000000001");
+        assert_eq!(sum(1.234_567_890_123_456_7, 0.0), "1.2345678901234567");
+    }
+
+    #[test]
+    fn falls_back_to_f64_past_the_digits_that_fit() {
+        assert_eq!(sum(1.0e300, 1.0), f!("{}", 1.0e300));
+        assert_eq!(add(1.0e300, 1.0).0, 1.0e300);
+        assert_eq!(add(0.1, 0.2).0, 0.3);
+    }
+}
@@ -1,80 +1,385 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    ops::Deref,
+    sync::{atomic::AtomicUsize, Arc, Mutex, MutexGuard},
 };
 
-use super::Store;
+use anyhow::Result;
+
+use super::{
+    current_timestamp, EncodingLimits, LfuSettings, MaxMemory, SetCondition, SetExpiry, SetOptions,
+    SetOutcome, Store,
+};
+use crate::{errors::RedisError, random};
+use eviction::Access;
+use expiration::VolatileKeys;
+use hashes::Hash;
+use lists::List;
+use memory::MemoryUsage;
+use scan::ScanIndex;
+use sets::Set;
+use sorted_sets::SortedSet;
+use streams::Stream;
+use watches::WatchedKeys;
 
+mod bitmaps;
+mod databases;
+mod eviction;
+mod expiration;
+mod hashes;
+mod hyperloglogs;
+mod intset;
+mod keys;
+mod listpack;
+mod lists;
+mod memory;
+mod objects;
+mod scan;
+mod sets;
+mod skiplist;
+mod sorted_sets;
+mod streams;
+mod strings;
+mod watches;
+
+/// Each client works on its own clone, which tracks the database the client selected.
 #[derive(Clone)]
 pub struct InMemStore {
-    store: Arc<Mutex<HashMap<String, Value>>>,
+    /// Keyspace of the selected database.
+    store: Arc<Mutex<Keyspace>>,
+    databases: Arc<Vec<Arc<Mutex<Keyspace>>>>,
+    db: usize,
+    max_memory: Arc<MaxMemory>,
+    /// Highest memory used measured so far.
+    peak_memory: Arc<AtomicUsize>,
+    encodings: EncodingLimits,
+}
+
+/// The keys and their values, plus the indexes SCAN and the active expiration cycle walk.
+/// Derefs to the values for reading, writes go through `insert` / `remove` / `get_mut`
+/// which keep the indexes and the memory accounting up to date, and touch the watched keys.
+/// Writes that may change nothing check with a read first, so they do not dirty watchers.
+struct Keyspace {
+    values: HashMap<String, Value>,
+    /// Keys that may have a TTL.
+    volatile: VolatileKeys,
+    /// Keys found expired by the cmds, deleted or replaced, until their deletion is
+    /// propagated.
+    lazily_expired: Vec<String>,
+    scan_index: ScanIndex,
+    memory: MemoryUsage,
+    lfu: LfuSettings,
+    encodings: EncodingLimits,
+    watched: WatchedKeys,
 }
 
 #[derive(Clone)]
 pub struct Value {
-    data: String,
+    data: Data,
+    expires_at: Option<u128>,
+    access: Access,
+    /// Whether a string was modified in place, which redis keeps raw whatever its length.
+    raw: bool,
+}
+
+#[derive(Clone)]
+pub enum Data {
+    String(Vec<u8>),
+    List(List),
+    Hash(Hash),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+#[derive(Clone)]
+pub struct HashField {
+    value: String,
     expires_at: Option<u128>,
 }
 
+impl Value {
+    fn new(data: Data) -> Self {
+        return Value {
+            data,
+            expires_at: None,
+            access: Access::new(),
+            raw: false,
+        };
+    }
+
+    /// Whether the key has expired, which a hash also does once all of its fields have.
+    fn is_expired(&self, now: u128) -> bool {
+        return match (self.expires_at, &self.data) {
+            (Some(expires_at), _) if now >= expires_at => true,
+            (_, Data::Hash(hash)) => hash.is_expired(now),
+            _ => false,
+        };
+    }
+
+    /// Whether the key has a TTL, or is a hash with fields that have one.
+    fn is_volatile(&self) -> bool {
+        return self.expires_at.is_some()
+            || matches!(&self.data, Data::Hash(hash) if hash.has_field_ttl());
+    }
+}
+
 impl InMemStore {
-    pub fn new() -> Self {
+    /// A store of `databases` empty databases, the first one selected.
+    pub fn new(databases: usize, max_memory: MaxMemory, encodings: EncodingLimits) -> Self {
+        let databases = (0..databases)
+            .map(|_| return Arc::new(Mutex::new(Keyspace::new(max_memory.lfu, encodings))))
+            .collect::<Vec<_>>();
         return InMemStore {
-            store: Arc::new(Mutex::new(HashMap::new())),
+            store: Arc::clone(&databases[0]),
+            databases: Arc::new(databases),
+            db: 0,
+            max_memory: Arc::new(max_memory),
+            peak_memory: Arc::new(AtomicUsize::new(0)),
+            encodings,
         };
     }
 }
 
+impl Deref for Keyspace {
+    type Target = HashMap<String, Value>;
+
+    fn deref(&self) -> &Self::Target {
+        return &self.values;
+    }
+}
+
+impl Keyspace {
+    fn new(lfu: LfuSettings, encodings: EncodingLimits) -> Self {
+        return Keyspace {
+            values: HashMap::new(),
+            volatile: VolatileKeys::new(),
+            lazily_expired: Vec::new(),
+            scan_index: ScanIndex::new(),
+            memory: MemoryUsage::new(),
+            lfu,
+            encodings,
+            watched: WatchedKeys::new(),
+        };
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        match self.values.get(&key) {
+            Some(previous) => {
+                if previous.is_expired(current_timestamp()) {
+                    self.lazily_expired.push(key.clone());
+                }
+                self.memory.remove(&key, previous);
+            }
+            None => self.scan_index.insert(&key),
+        }
+        self.memory.add(&key, &value);
+        self.watched.touch(&key, true);
+        return self.values.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Value> {
+        let value = self.values.remove(key);
+        if let Some(value) = &value {
+            self.scan_index.remove(key);
+            self.memory.remove(key, value);
+            self.watched.touch(key, false);
+        }
+        return value;
+    }
+
+    /// Deletes the key if it has expired, keeping track of it for the deletion to be
+    /// propagated, see `KeyStore::take_expired`.
+    fn remove_expired(&mut self, key: &str, now: u128) {
+        if self
+            .values
+            .get(key)
+            .is_some_and(|value| return value.is_expired(now))
+        {
+            self.remove(key);
+            self.lazily_expired.push(key.to_string());
+        }
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let value = self.values.get_mut(key);
+        if let Some(value) = &value {
+            self.memory.modify(key, value);
+            self.watched.touch(key, true);
+        }
+        return value;
+    }
+}
+
 impl Store for InMemStore {
-    fn set(&mut self, key: String, value: String) {
-        let mut store = self.store.lock().unwrap();
-        store.insert(
-            key,
-            Value {
-                data: value,
-                expires_at: None,
-            },
-        );
-    }
-
-    fn set_expiring(&mut self, key: String, value: String, expiry_in_millis: u32) {
-        let timestamp = current_timestamp();
-        let expires_at = timestamp + expiry_in_millis as u128;
-
-        let mut store = self.store.lock().unwrap();
-        store.insert(
-            key,
-            Value {
-                data: value,
-                expires_at: Some(expires_at),
-            },
-        );
-    }
-
-    fn get(&self, key: &str) -> Option<String> {
-        let store = self.store.lock().unwrap();
-        let value = store.get(key);
-        if value.is_none() {
-            return None;
+    fn set(&mut self, key: String, value: Vec<u8>, options: SetOptions) -> Result<SetOutcome> {
+        let mut store = lock(&self.store);
+        // NOTE: looked up for reading, a SET that is not applied leaves the watchers alone
+        let (previous, previous_expiry) = match live_value(&store, &key) {
+            Some(Value {
+                data: Data::String(previous),
+                expires_at,
+                ..
+            }) => (Some(Some(previous.clone())), *expires_at),
+            Some(_) if options.get => return Err(RedisError::WrongType.into()),
+            Some(existing) => (Some(None), existing.expires_at),
+            None => (None, None),
+        };
+
+        let applied = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::IfMissing => previous.is_none(),
+            SetCondition::IfExists => previous.is_some(),
+        };
+        if applied {
+            let expires_at = match options.expiry {
+                SetExpiry::Clear => None,
+                SetExpiry::Keep => previous_expiry,
+                SetExpiry::At(expires_at) => Some(expires_at),
+            };
+            if expires_at.is_some() {
+                store.volatile.insert(&key);
+            }
+            store.insert(
+                key,
+                Value {
+                    data: Data::String(value),
+                    expires_at,
+                    access: Access::new(),
+                    raw: false,
+                },
+            );
         }
 
-        let value = value.unwrap().clone();
-        if value.expires_at.is_none() {
-            return Some(value.data);
+        return Ok(SetOutcome {
+            applied,
+            previous: previous.flatten(),
+        });
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let store = lock(&self.store);
+        let value = live_value(&store, key);
+        if value.is_none() {
+            return Ok(None);
         }
 
-        let now = current_timestamp();
-        return if now < value.expires_at.unwrap() {
-            Some(value.data)
-        } else {
-            None
+        return match &value.unwrap().data {
+            Data::String(data) => Ok(Some(data.clone())),
+            _ => Err(RedisError::WrongType.into()),
         };
     }
 }
 
-fn current_timestamp() -> u128 {
-    let now = SystemTime::now();
-    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap();
-    return since_epoch.as_millis();
+/// Looks up a key for reading, ignoring it if it has already expired. Counts as an access
+/// of the key for the eviction policies.
+fn live_value<'a>(store: &'a Keyspace, key: &str) -> Option<&'a Value> {
+    let now = current_timestamp();
+    let value = store.get(key).filter(|value| return !value.is_expired(now));
+    if let Some(value) = value {
+        value.access.touch(now, store.lfu);
+    }
+    return value;
+}
+
+/// Locks a database. One left poisoned by a cmd that panicked holding it is still used, rather
+/// than failing every later cmd on it.
+fn lock(database: &Mutex<Keyspace>) -> MutexGuard<'_, Keyspace> {
+    return database
+        .lock()
+        .unwrap_or_else(|poisoned| return poisoned.into_inner());
+}
+
+/// Looks up a key for writing. Expired keys are removed, so the caller can treat them as
+/// missing and create a fresh value in their place. Like the expired values `insert`
+/// replaces, they are kept track of for their deletion to be propagated, see
+/// `KeyStore::take_expired`.
+fn live_value_mut<'a>(store: &'a mut Keyspace, key: &str) -> Option<&'a mut Value> {
+    let now = current_timestamp();
+    store.remove_expired(key, now);
+    let lfu = store.lfu;
+    let value = store.get_mut(key);
+    if let Some(value) = &value {
+        value.access.touch(now, lfu);
+    }
+    return value;
+}
+
+/// `count` positions of `0..len` picked at random, repeats allowed, as HRANDFIELD and
+/// SRANDMEMBER reply to negative counts. A count too large for the reply to be allocated is
+/// out of range.
+fn random_picks<T>(len: usize, count: u64, pick: impl Fn(usize) -> T) -> Result<Vec<T>> {
+    let mut picked = Vec::new();
+    picked
+        .try_reserve_exact(count as usize)
+        .map_err(|_| return RedisError::OutOfRange)?;
+    picked.extend((0..count).map(|_| return pick(random::below(len))));
+    return Ok(picked);
+}
+
+/// A store with the default configuration, for the tests of the data types.
+#[cfg(test)]
+fn test_store() -> InMemStore {
+    use super::EvictionPolicy;
+
+    let lfu = LfuSettings {
+        log_factor: 10,
+        decay_time: 1,
+    };
+    let max_memory = MaxMemory {
+        limit: 0,
+        policy: EvictionPolicy::NoEviction,
+        samples: 5,
+        lfu,
+    };
+    let encodings = EncodingLimits {
+        hash_max_listpack_entries: 128,
+        hash_max_listpack_value: 64,
+        list_max_listpack_size: -2,
+        set_max_intset_entries: 512,
+        zset_max_listpack_entries: 128,
+        zset_max_listpack_value: 64,
+    };
+    return InMemStore::new(16, max_memory, encodings);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::{lock, random_picks, test_store};
+    use crate::persistence::{SetCondition, SetExpiry, SetOptions, Store};
+
+    #[test]
+    fn random_picks_repeat_positions() {
+        let picked = random_picks(3, 50, |index| return index).unwrap();
+        assert_eq!(picked.len(), 50);
+        assert!(picked.iter().all(|index| return *index < 3));
+        assert!(random_picks(3, 0, |index| return index).unwrap().is_empty());
+    }
+
+    #[test]
+    fn random_picks_reject_counts_too_large_to_reply() {
+        assert!(random_picks(1, i64::MIN.unsigned_abs(), |_| return String::new()).is_err());
+        assert!(random_picks(1, 1 << 50, |index| return index).is_err());
+    }
+
+    #[test]
+    fn databases_outlive_a_panic_holding_their_lock() {
+        let mut store = test_store();
+        let options = SetOptions {
+            condition: SetCondition::Always,
+            expiry: SetExpiry::Clear,
+            get: false,
+        };
+        store.set("k".into(), b"v".to_vec(), options).unwrap();
+
+        let result = panic::catch_unwind(|| {
+            let _keyspace = lock(&store.store);
+            panic!("A cmd failed");
+        });
+        assert!(result.is_err());
+        assert!(store.store.is_poisoned());
+        assert_eq!(store.get("k").unwrap(), Some(b"v".to_vec()));
+    }
 }
@@ -0,0 +1,301 @@
+use anyhow::Result;
+
+use super::{
+    lock,
+    strings::{check_len, string, string_or_create},
+    Data, InMemStore, Value,
+};
+use crate::persistence::{
+    BitOperation, BitRange, BitUnit, BitfieldOp, BitfieldOverflow, BitfieldType, BitmapStore,
+};
+
+impl BitmapStore for InMemStore {
+    fn bit_set(&mut self, key: &str, offset: usize, value: bool) -> Result<bool> {
+        let mut store = lock(&self.store);
+        check_len(offset / 8 + 1)?;
+
+        let bytes = string_or_create(&mut store, key)?;
+        if bytes.len() <= offset / 8 {
+            bytes.resize(offset / 8 + 1, 0);
+        }
+        let previous = bit(bytes, offset);
+        let mask = 0x80 >> (offset % 8);
+        if value {
+            bytes[offset / 8] |= mask;
+        } else {
+            bytes[offset / 8] &= !mask;
+        }
+        return Ok(previous);
+    }
+
+    fn bit_get(&self, key: &str, offset: usize) -> Result<bool> {
+        let store = lock(&self.store);
+        return Ok(string(&store, key)?.is_some_and(|bytes| return bit(bytes, offset)));
+    }
+
+    fn bit_count(&self, key: &str, range: Option<BitRange>) -> Result<usize> {
+        let store = lock(&self.store);
+        let bytes = match string(&store, key)? {
+            Some(bytes) => bytes,
+            None => return Ok(0),
+        };
+
+        return Ok(match bit_span(bytes.len(), range) {
+            Some((first, last)) => count_ones(bytes, first, last),
+            None => 0,
+        });
+    }
+
+    fn bit_position(&self, key: &str, value: bool, range: Option<BitRange>) -> Result<i64> {
+        let store = lock(&self.store);
+        let bytes = match string(&store, key)? {
+            Some(bytes) => bytes,
+            None => return Ok(if value { -1 } else { 0 }),
+        };
+        let (first, last) = match bit_span(bytes.len(), range) {
+            Some(span) => span,
+            None => return Ok(-1),
+        };
+
+        // NOTE: whole bytes without the bit looked for are skipped at once
+        let skipped = if value { 0x00 } else { 0xFF };
+        let mut position = first;
+        while position <= last {
+            if position % 8 == 0 && position + 7 <= last && bytes[position / 8] == skipped {
+                position += 8;
+                continue;
+            }
+            if bit(bytes, position) == value {
+                return Ok(position as i64);
+            }
+            position += 1;
+        }
+
+        // Without an explicit end the string counts as padded with clear bits on the right
+        let end_given = range.is_some_and(|range| return range.end.is_some());
+        if !value && !end_given {
+            return Ok(((last / 8 + 1) * 8) as i64);
+        }
+        return Ok(-1);
+    }
+
+    fn bit_op(
+        &mut self,
+        operation: BitOperation,
+        destination: &str,
+        keys: &[String],
+    ) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let sources = keys
+            .iter()
+            .map(|key| return string(&store, key))
+            .collect::<Result<Vec<_>>>()?;
+        let len = sources
+            .iter()
+            .map(|source| return source.map_or(0, |bytes| return bytes.len()))
+            .max()
+            .unwrap_or(0);
+
+        let byte_at = |source: Option<&Vec<u8>>, index: usize| {
+            return source
+                .and_then(|bytes| return bytes.get(index).copied())
+                .unwrap_or(0);
+        };
+        let result = (0..len)
+            .map(|index| {
+                let mut bytes = sources.iter().map(|source| return byte_at(*source, index));
+                let first = bytes.next().unwrap_or(0);
+                return match operation {
+                    BitOperation::And => bytes.fold(first, |acc, byte| return acc & byte),
+                    BitOperation::Or => bytes.fold(first, |acc, byte| return acc | byte),
+                    BitOperation::Xor => bytes.fold(first, |acc, byte| return acc ^ byte),
+                    BitOperation::Not => !first,
+                };
+            })
+            .collect::<Vec<u8>>();
+
+        if result.is_empty() {
+            store.remove(destination);
+        } else {
+            store.insert(destination.to_string(), Value::new(Data::String(result)));
+        }
+        return Ok(len);
+    }
+
+    fn bitfield(&mut self, key: &str, ops: &[BitfieldOp]) -> Result<Vec<Option<i64>>> {
+        let mut store = lock(&self.store);
+
+        // Like redis, the string is created and grown up front when anything gets written
+        let written_len = ops
+            .iter()
+            .filter_map(|op| {
+                return match *op {
+                    BitfieldOp::Get { .. } => None,
+                    BitfieldOp::Set { field, offset, .. }
+                    | BitfieldOp::IncrBy { field, offset, .. } => {
+                        Some((offset + field.bits as usize).div_ceil(8))
+                    }
+                };
+            })
+            .max();
+        let mut bytes = match written_len {
+            Some(len) => {
+                check_len(len)?;
+                let bytes = string_or_create(&mut store, key)?;
+                if bytes.len() < len {
+                    bytes.resize(len, 0);
+                }
+                std::mem::take(bytes)
+            }
+            None => string(&store, key)?.cloned().unwrap_or_default(),
+        };
+
+        let replies = ops
+            .iter()
+            .map(|op| {
+                return match *op {
+                    BitfieldOp::Get { field, offset } => Some(read_field(&bytes, field, offset)),
+                    BitfieldOp::Set {
+                        field,
+                        offset,
+                        value,
+                        overflow,
+                    } => {
+                        let previous = read_field(&bytes, field, offset);
+                        let updated = add_to_field(field, value, 0, overflow)?;
+                        write_field(&mut bytes, field, offset, updated);
+                        Some(previous)
+                    }
+                    BitfieldOp::IncrBy {
+                        field,
+                        offset,
+                        increment,
+                        overflow,
+                    } => {
+                        let previous = read_field(&bytes, field, offset);
+                        let updated = add_to_field(field, previous, increment, overflow)?;
+                        write_field(&mut bytes, field, offset, updated);
+                        Some(updated)
+                    }
+                };
+            })
+            .collect();
+
+        if written_len.is_some() {
+            *string_or_create(&mut store, key)? = bytes;
+        }
+        return Ok(replies);
+    }
+}
+
+fn bit(bytes: &[u8], offset: usize) -> bool {
+    return bytes
+        .get(offset / 8)
+        .is_some_and(|byte| return byte & (0x80 >> (offset % 8)) != 0);
+}
+
+/// Turns a BITCOUNT / BITPOS range into the first and last bit it covers in a string of
+/// `len` bytes, with the same clamping as redis. `None` when the range is empty.
+fn bit_span(len: usize, range: Option<BitRange>) -> Option<(usize, usize)> {
+    let range = match range {
+        Some(range) => range,
+        None if len == 0 => return None,
+        None => return Some((0, len * 8 - 1)),
+    };
+
+    let total = match range.unit {
+        BitUnit::Byte => len as i64,
+        BitUnit::Bit => len as i64 * 8,
+    };
+    let resolve = |index: i64| {
+        return if index < 0 { total + index } else { index }.max(0);
+    };
+    let start = resolve(range.start);
+    let end = resolve(range.end.unwrap_or(-1)).min(total - 1);
+    if start > end {
+        return None;
+    }
+
+    return Some(match range.unit {
+        BitUnit::Byte => (start as usize * 8, end as usize * 8 + 7),
+        BitUnit::Bit => (start as usize, end as usize),
+    });
+}
+
+fn count_ones(bytes: &[u8], first: usize, last: usize) -> usize {
+    let (first_byte, last_byte) = (first / 8, last / 8);
+    let all = bytes[first_byte..=last_byte]
+        .iter()
+        .map(|byte| return byte.count_ones() as usize)
+        .sum::<usize>();
+
+    let before = bytes[first_byte] & !(0xFF >> (first % 8));
+    let after = bytes[last_byte] & 0xFFu8.checked_shr(last as u32 % 8 + 1).unwrap_or(0);
+    return all - before.count_ones() as usize - after.count_ones() as usize;
+}
+
+/// Reads the field most significant bit first. Bits past the end of the string are clear.
+fn read_field(bytes: &[u8], field: BitfieldType, offset: usize) -> i64 {
+    let mut value = (0..field.bits as usize).fold(0u64, |value, index| {
+        return value << 1 | bit(bytes, offset + index) as u64;
+    });
+    if field.signed && field.bits < 64 && value & (1 << (field.bits - 1)) != 0 {
+        value |= u64::MAX << field.bits;
+    }
+    return value as i64;
+}
+
+/// Callers make sure the string is long enough to hold the field.
+fn write_field(bytes: &mut [u8], field: BitfieldType, offset: usize, value: i64) {
+    for index in 0..field.bits as usize {
+        let position = offset + index;
+        let mask = 0x80 >> (position % 8);
+        if (value as u64 >> (field.bits as usize - 1 - index)) & 1 == 1 {
+            bytes[position / 8] |= mask;
+        } else {
+            bytes[position / 8] &= !mask;
+        }
+    }
+}
+
+/// Adds `increment` to `value`, handling results that do not fit in the field as asked by
+/// OVERFLOW. `None` when the write has to fail instead.
+fn add_to_field(
+    field: BitfieldType,
+    value: i64,
+    increment: i64,
+    overflow: BitfieldOverflow,
+) -> Option<i64> {
+    let (min, max) = if field.signed {
+        (
+            -(1i128 << (field.bits - 1)),
+            (1i128 << (field.bits - 1)) - 1,
+        )
+    } else {
+        (0, (1i128 << field.bits) - 1)
+    };
+    // NOTE: like redis, the value to SET on an unsigned field is read as unsigned
+    let value = if field.signed {
+        value as i128
+    } else {
+        value as u64 as i128
+    };
+
+    let sum = value + increment as i128;
+    if (min..=max).contains(&sum) {
+        return Some(sum as i64);
+    }
+    return match overflow {
+        BitfieldOverflow::Fail => None,
+        BitfieldOverflow::Sat if sum > max => Some(max as i64),
+        BitfieldOverflow::Sat => Some(min as i64),
+        BitfieldOverflow::Wrap => {
+            let wrapped = sum.rem_euclid(1i128 << field.bits);
+            Some(if wrapped > max {
+                wrapped - (1i128 << field.bits)
+            } else {
+                wrapped
+            } as i64)
+        }
+    };
+}
@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+
+use super::{keys::free_lazily, live_value, lock, InMemStore, Keyspace};
+use crate::{errors::RedisError, persistence::DatabaseStore};
+
+impl DatabaseStore for InMemStore {
+    fn select(&mut self, db: usize) -> Result<()> {
+        self.store = Arc::clone(self.database(db)?);
+        self.db = db;
+        return Ok(());
+    }
+
+    fn move_key(&mut self, key: &str, db: usize) -> Result<bool> {
+        let target = self.database(db)?;
+        if db == self.db {
+            return Err(anyhow!(RedisError::Generic(
+                "source and destination objects are the same".into()
+            )));
+        }
+
+        let mut store = lock(&self.store);
+        let mut target = lock(target);
+        if live_value(&store, key).is_none() || live_value(&target, key).is_some() {
+            return Ok(false);
+        }
+
+        let value = store.remove(key).unwrap();
+        if value.is_volatile() {
+            target.volatile.insert(key);
+        }
+        target.insert(key.to_string(), value);
+        return Ok(true);
+    }
+
+    fn swap_dbs(&mut self, first: usize, second: usize) -> Result<()> {
+        let (first, second) = (self.database(first)?, self.database(second)?);
+        if Arc::ptr_eq(first, second) {
+            return Ok(());
+        }
+
+        let (mut first, mut second) = (lock(first), lock(second));
+        let (first, second) = (&mut *first, &mut *second);
+        first
+            .watched
+            .touch_replaced(&first.values, Some(&second.values));
+        second
+            .watched
+            .touch_replaced(&second.values, Some(&first.values));
+        // NOTE: the watched keys stay with the database index, like the clients watching them
+        std::mem::swap(first, second);
+        std::mem::swap(&mut first.watched, &mut second.watched);
+        return Ok(());
+    }
+
+    fn flush(&mut self, all: bool, lazy: bool) -> Result<()> {
+        let databases = if all {
+            self.databases.iter().collect::<Vec<_>>()
+        } else {
+            vec![&self.store]
+        };
+
+        for database in databases {
+            let mut database = lock(database);
+            let database = &mut *database;
+            database.watched.touch_replaced(&database.values, None);
+            let mut flushed =
+                std::mem::replace(database, Keyspace::new(self.max_memory.lfu, self.encodings));
+            std::mem::swap(&mut database.watched, &mut flushed.watched);
+            if lazy {
+                free_lazily(flushed);
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl InMemStore {
+    /// Keyspace of database `db`.
+    pub(super) fn database(&self, db: usize) -> Result<&Arc<Mutex<Keyspace>>> {
+        return self
+            .databases
+            .get(db)
+            .ok_or(anyhow!(RedisError::DbIndexOutOfRange));
+    }
+}
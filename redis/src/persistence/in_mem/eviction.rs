@@ -0,0 +1,212 @@
+//! Eviction of keys once the memory used goes over maxmemory, with the approximated LRU and
+//! LFU of redis: instead of keeping every key ordered by access, a few keys are sampled in
+//! each database and the best candidates found so far are kept in a small pool.
+
+use std::{
+    cell::Cell,
+    sync::{atomic::Ordering, Arc},
+};
+
+use anyhow::Result;
+
+use super::{current_timestamp, lock, InMemStore, Keyspace, Value};
+use crate::{
+    persistence::{Eviction, EvictionPolicy, LfuSettings, MaxMemory, MemoryStore},
+    random,
+};
+
+/// Counter new keys start with, so that they get a chance to be accessed before being
+/// evicted (`LFU_INIT_VAL` in redis).
+const LFU_INIT_VAL: u8 = 5;
+/// Best candidates to evict kept across samplings (`EVPOOL_SIZE` in redis).
+const EVICTION_POOL_SIZE: usize = 16;
+const MS_PER_MINUTE: u128 = 60_000;
+
+/// When a value was last accessed and how often, for the LRU and LFU policies. Reads record
+/// accesses too, hence the cells.
+#[derive(Clone)]
+pub struct Access {
+    /// Unix time in ms.
+    at: Cell<u128>,
+    /// Logarithmic counter of the accesses, as of `at`.
+    frequency: Cell<u8>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        return Access {
+            at: Cell::new(current_timestamp()),
+            frequency: Cell::new(LFU_INIT_VAL),
+        };
+    }
+
+    pub fn touch(&self, now: u128, lfu: LfuSettings) {
+        let frequency = self.frequency(now, lfu);
+        self.frequency.set(log_increment(frequency, lfu.log_factor));
+        self.at.set(now);
+    }
+
+    /// Ms since the last access.
+    pub fn idle_time(&self, now: u128) -> u128 {
+        return now.saturating_sub(self.at.get());
+    }
+
+    /// The counter, decremented once per `decay_time` minutes passed since the last access.
+    pub fn frequency(&self, now: u128, lfu: LfuSettings) -> u8 {
+        if lfu.decay_time == 0 {
+            return self.frequency.get();
+        }
+        let periods = self.idle_time(now) / MS_PER_MINUTE / lfu.decay_time as u128;
+        return self
+            .frequency
+            .get()
+            .saturating_sub(periods.min(u8::MAX as u128) as u8);
+    }
+}
+
+/// Increments the counter with a probability that shrinks as it grows, so that 255 stands
+/// for about a million accesses with the default log factor of 10.
+fn log_increment(frequency: u8, log_factor: u32) -> u8 {
+    if frequency == u8::MAX {
+        return frequency;
+    }
+    let base = frequency.saturating_sub(LFU_INIT_VAL) as f64;
+    let probability = 1.0 / (base * log_factor as f64 + 1.0);
+    return if random::unit() < probability {
+        frequency + 1
+    } else {
+        frequency
+    };
+}
+
+/// A key worth evicting, the higher the score the better.
+struct Candidate {
+    score: u128,
+    db: usize,
+    key: String,
+}
+
+impl MemoryStore for InMemStore {
+    fn used_memory(&mut self) -> Result<usize> {
+        let used = self
+            .databases
+            .iter()
+            .map(|database| return lock(database).used_memory())
+            .sum();
+        self.peak_memory.fetch_max(used, Ordering::Relaxed);
+        return Ok(used);
+    }
+
+    fn evict(&mut self) -> Result<Eviction> {
+        let max_memory = Arc::clone(&self.max_memory);
+        let mut eviction = Eviction {
+            evicted: Vec::new(),
+            fits: true,
+        };
+        if max_memory.limit == 0 {
+            return Ok(eviction);
+        }
+
+        let mut pool = Vec::new();
+        while self.used_memory()? > max_memory.limit {
+            let candidate = match max_memory.policy {
+                EvictionPolicy::NoEviction => None,
+                EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom => {
+                    self.random_candidate(max_memory.policy.is_volatile())
+                }
+                _ => {
+                    self.sample_candidates(&mut pool, &max_memory);
+                    self.best_candidate(&mut pool)
+                }
+            };
+
+            match candidate {
+                Some((db, key)) => {
+                    lock(&self.databases[db]).remove(&key);
+                    eviction.evicted.push((db, key));
+                }
+                None => {
+                    eviction.fits = false;
+                    return Ok(eviction);
+                }
+            }
+        }
+        return Ok(eviction);
+    }
+}
+
+impl InMemStore {
+    /// A random key of the first database, starting from a random one, that has any.
+    fn random_candidate(&self, volatile: bool) -> Option<(usize, String)> {
+        let start = random::below(self.databases.len());
+        for offset in 0..self.databases.len() {
+            let db = (start + offset) % self.databases.len();
+            let mut store = lock(&self.databases[db]);
+            if let Some(key) = store.sample(1, volatile).pop() {
+                return Some((db, key));
+            }
+        }
+        return None;
+    }
+
+    /// Adds the keys sampled in every database to the pool, keeping the best ones.
+    fn sample_candidates(&self, pool: &mut Vec<Candidate>, max_memory: &MaxMemory) {
+        let now = current_timestamp();
+        for (db, database) in self.databases.iter().enumerate() {
+            let mut store = lock(database);
+            for key in store.sample(max_memory.samples, max_memory.policy.is_volatile()) {
+                if pool
+                    .iter()
+                    .any(|candidate| return candidate.db == db && candidate.key == key)
+                {
+                    continue;
+                }
+                let score = eviction_score(&store[&key], max_memory, now);
+                // NOTE: the pool is sorted by ascending score
+                let position = pool.partition_point(|candidate| return candidate.score < score);
+                if pool.len() == EVICTION_POOL_SIZE {
+                    if position == 0 {
+                        continue;
+                    }
+                    pool.remove(0);
+                    pool.insert(position - 1, Candidate { score, db, key });
+                } else {
+                    pool.insert(position, Candidate { score, db, key });
+                }
+            }
+        }
+    }
+
+    /// Takes the best candidate of the pool that still exists.
+    fn best_candidate(&self, pool: &mut Vec<Candidate>) -> Option<(usize, String)> {
+        while let Some(candidate) = pool.pop() {
+            if lock(&self.databases[candidate.db]).contains_key(&candidate.key) {
+                return Some((candidate.db, candidate.key));
+            }
+        }
+        return None;
+    }
+}
+
+impl Keyspace {
+    /// Up to `count` keys picked at random among all the keys or the ones with a TTL.
+    fn sample(&mut self, count: usize, volatile: bool) -> Vec<String> {
+        if volatile {
+            return self.sample_volatile(count);
+        }
+        return (0..count)
+            .filter_map(|_| return self.scan_index.random().map(str::to_string))
+            .collect();
+    }
+}
+
+/// How good a candidate for eviction the value is under the policy.
+fn eviction_score(value: &Value, max_memory: &MaxMemory, now: u128) -> u128 {
+    return match max_memory.policy {
+        EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+            (u8::MAX - value.access.frequency(now, max_memory.lfu)) as u128
+        }
+        EvictionPolicy::VolatileTtl => u128::MAX - value.expires_at.unwrap_or(u128::MAX),
+        _ => value.access.idle_time(now),
+    };
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+use super::{current_timestamp, Keyspace};
+use crate::{persistence::ExpireSample, random};
+
+/// Keys that were given a TTL, or hashes whose fields were, in a vec for random picks and a map of their positions to
+/// keep them unique. Entries are not removed when their key is deleted or persisted, the
+/// sampling prunes them once it comes across them.
+pub struct VolatileKeys {
+    keys: Vec<String>,
+    positions: HashMap<String, usize>,
+}
+
+impl VolatileKeys {
+    pub fn new() -> Self {
+        return VolatileKeys {
+            keys: Vec::new(),
+            positions: HashMap::new(),
+        };
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        if !self.positions.contains_key(key) {
+            self.positions.insert(key.to_string(), self.keys.len());
+            self.keys.push(key.to_string());
+        }
+    }
+
+    fn swap_remove(&mut self, index: usize) -> String {
+        let key = self.keys.swap_remove(index);
+        self.positions.remove(&key);
+        if let Some(moved) = self.keys.get(index) {
+            self.positions.insert(moved.clone(), index);
+        }
+        return key;
+    }
+}
+
+impl Keyspace {
+    /// Up to `count` keys with a TTL picked at random, expired or not. The entries of keys
+    /// found without a TTL any more, on themselves or on their fields, are pruned along the
+    /// way.
+    pub(super) fn sample_volatile(&mut self, count: usize) -> Vec<String> {
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            if self.volatile.keys.is_empty() {
+                break;
+            }
+            let index = random::below(self.volatile.keys.len());
+            let key = &self.volatile.keys[index];
+            match self.values.get(key) {
+                Some(value) if value.expires_at.is_some() => keys.push(key.clone()),
+                Some(value) if value.is_volatile() => {}
+                _ => {
+                    self.volatile.swap_remove(index);
+                }
+            }
+        }
+        return keys;
+    }
+
+    /// Picks `count` entries of the volatile index at random, deleting the keys that have
+    /// expired, hashes left without a live field included, and pruning the entries of keys
+    /// that no longer have a TTL.
+    pub(super) fn expire_sample(&mut self, count: usize) -> ExpireSample {
+        let now = current_timestamp();
+        let mut sample = ExpireSample {
+            sampled: 0,
+            pruned: 0,
+            expired: Vec::new(),
+        };
+
+        while sample.sampled < count && !self.volatile.keys.is_empty() {
+            sample.sampled += 1;
+            let index = random::below(self.volatile.keys.len());
+            match self.values.get(&self.volatile.keys[index]) {
+                Some(value) if value.is_expired(now) => {
+                    let key = self.volatile.swap_remove(index);
+                    self.remove(&key);
+                    sample.expired.push(key);
+                }
+                Some(value) if value.is_volatile() => {}
+                _ => {
+                    self.volatile.swap_remove(index);
+                    sample.pruned += 1;
+                }
+            }
+        }
+        return sample;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_store;
+    use crate::persistence::{
+        DatabaseStore, KeyStore, SetCondition, SetExpiry, SetOptions, Store, StringStore,
+    };
+
+    #[test]
+    fn keys_deleted_for_having_expired_are_taken_once() {
+        let mut store = test_store();
+        store.select(3).unwrap();
+        let expired = SetOptions {
+            condition: SetCondition::Always,
+            expiry: SetExpiry::At(1),
+            get: false,
+        };
+        store.set("k".into(), b"1".to_vec(), expired).unwrap();
+        store.set("read".into(), b"1".to_vec(), expired).unwrap();
+        store.set("old".into(), b"1".to_vec(), expired).unwrap();
+
+        assert_eq!(store.get("read").unwrap(), None);
+        assert_eq!(store.string_incr_by("k", 1).unwrap(), 1);
+        let always = SetOptions {
+            expiry: SetExpiry::Clear,
+            ..expired
+        };
+        store.set("old".into(), b"2".to_vec(), always).unwrap();
+        let taken = [(3, "k".to_string()), (3, "old".to_string())];
+        assert_eq!(store.take_expired().unwrap(), taken);
+        assert!(store.take_expired().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,632 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    current_timestamp,
+    listpack::Listpack,
+    live_value, live_value_mut, lock,
+    memory::{sampled_size, COLLECTION_OVERHEAD, ELEMENT_OVERHEAD, EXPIRY_OVERHEAD},
+    random_picks, Data, HashField, InMemStore, Keyspace, Value,
+};
+use crate::{
+    errors::RedisError,
+    persistence::{
+        add_floats, parse_integer, EncodingLimits, ExpireCondition, HashStore, NO_FIELD_TTL,
+        NO_SUCH_FIELD,
+    },
+    random,
+};
+
+/// Small hashes are a listpack of their fields and values one after the other. Past the
+/// `hash-max-listpack-*` limits, or once a field gets a TTL the listpack has no room for,
+/// they become a table.
+#[derive(Clone)]
+pub enum Hash {
+    Listpack(Listpack),
+    Table(HashMap<String, HashField>),
+}
+
+impl HashStore for InMemStore {
+    fn hash_set(&mut self, key: &str, pairs: &[(String, String)]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let limits = store.encodings;
+        let hash = hash_or_create(&mut store, key)?;
+
+        return Ok(pairs
+            .iter()
+            .filter(|(field, value)| return hash.set(field, value, false, &limits))
+            .count());
+    }
+
+    fn hash_set_if_missing(&mut self, key: &str, field: &str, value: &str) -> Result<bool> {
+        let mut store = lock(&self.store);
+        let limits = store.encodings;
+        if hash(&mut store, key)?.is_some_and(|hash| return hash.get(field).is_some()) {
+            return Ok(false);
+        }
+
+        let hash = hash_or_create(&mut store, key)?;
+        return Ok(hash.set(field, value, false, &limits));
+    }
+
+    fn hash_get(&self, key: &str, fields: &[String]) -> Result<Vec<Option<String>>> {
+        let mut store = lock(&self.store);
+        let hash = hash(&mut store, key)?;
+
+        return Ok(fields
+            .iter()
+            .map(|field| {
+                return hash
+                    .as_ref()
+                    .and_then(|hash| return hash.get(field))
+                    .map(str::to_string);
+            })
+            .collect());
+    }
+
+    fn hash_get_all(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let mut store = lock(&self.store);
+        return Ok(match hash(&mut store, key)? {
+            Some(hash) => hash
+                .iter()
+                .map(|(field, value)| return (field.to_string(), value.to_string()))
+                .collect(),
+            None => Vec::new(),
+        });
+    }
+
+    fn hash_len(&self, key: &str) -> Result<usize> {
+        let mut store = lock(&self.store);
+        return Ok(hash(&mut store, key)?.map_or(0, |hash| return hash.live_len()));
+    }
+
+    fn hash_delete(&mut self, key: &str, fields: &[String]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        if !hash(&mut store, key)?
+            .is_some_and(|hash| return fields.iter().any(|field| return hash.get(field).is_some()))
+        {
+            return Ok(0);
+        }
+        let hash = match hash_mut(&mut store, key)? {
+            Some(hash) => hash,
+            None => return Ok(0),
+        };
+
+        let deleted = fields
+            .iter()
+            .filter(|field| return hash.remove(field))
+            .count();
+
+        if hash.len() == 0 {
+            store.remove(key);
+        }
+        return Ok(deleted);
+    }
+
+    fn hash_incr_by(&mut self, key: &str, field: &str, increment: i64) -> Result<i64> {
+        let mut store = lock(&self.store);
+        let limits = store.encodings;
+        let current = match hash(&mut store, key)?.and_then(|hash| return hash.get(field)) {
+            Some(value) => parse_integer(value)
+                .ok_or(RedisError::Generic("hash value is not an integer".into()))?,
+            None => 0,
+        };
+
+        let updated = current.checked_add(increment).ok_or(RedisError::Overflow)?;
+        let hash = hash_or_create(&mut store, key)?;
+        hash.set(field, &updated.to_string(), true, &limits);
+        return Ok(updated);
+    }
+
+    fn hash_incr_by_float(&mut self, key: &str, field: &str, increment: f64) -> Result<String> {
+        let mut store = lock(&self.store);
+        let limits = store.encodings;
+        let current = match hash(&mut store, key)?.and_then(|hash| return hash.get(field)) {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| return value.is_finite())
+                .ok_or(RedisError::Generic("hash value is not a float".into()))?,
+            None => 0.0,
+        };
+
+        let (updated, written) = add_floats(current, increment);
+        if !updated.is_finite() {
+            return Err(anyhow!(RedisError::NanOrInfinity));
+        }
+        let hash = hash_or_create(&mut store, key)?;
+        hash.set(field, &written, true, &limits);
+        return Ok(written);
+    }
+
+    fn hash_random_fields(&self, key: &str, count: i64) -> Result<Vec<(String, String)>> {
+        let mut store = lock(&self.store);
+        let hash = match hash(&mut store, key)? {
+            Some(hash) => hash,
+            None => return Ok(Vec::new()),
+        };
+
+        let entries = hash.iter().collect::<Vec<(&str, &str)>>();
+        let to_pair = |index: usize| {
+            let (field, value) = entries[index];
+            return (field.to_string(), value.to_string());
+        };
+
+        return Ok(if count >= 0 {
+            random::distinct_indexes(entries.len(), count as usize)
+                .into_iter()
+                .map(to_pair)
+                .collect()
+        } else {
+            random_picks(entries.len(), count.unsigned_abs(), to_pair)?
+        });
+    }
+
+    fn hash_expire(
+        &mut self,
+        key: &str,
+        fields: &[String],
+        expires_at: u128,
+        condition: ExpireCondition,
+    ) -> Result<Vec<i64>> {
+        let mut store = lock(&self.store);
+        // NOTE: checked for reading first, so that setting no TTL leaves the watchers alone
+        let unchanged = match hash(&mut store, key)? {
+            Some(hash) => fields
+                .iter()
+                .map(|field| {
+                    return match hash.expires_at(field) {
+                        Some(current) if condition.allows(current, expires_at) => None,
+                        Some(_) => Some(0),
+                        None => Some(NO_SUCH_FIELD),
+                    };
+                })
+                .collect::<Option<Vec<i64>>>(),
+            None => Some(vec![NO_SUCH_FIELD; fields.len()]),
+        };
+        if let Some(codes) = unchanged {
+            return Ok(codes);
+        }
+        let hash = match hash_mut(&mut store, key)? {
+            Some(hash) => hash,
+            None => return Ok(vec![NO_SUCH_FIELD; fields.len()]),
+        };
+
+        let now = current_timestamp();
+        let codes = fields
+            .iter()
+            .map(|field| {
+                let current = match hash.expires_at(field) {
+                    Some(current) => current,
+                    None => return NO_SUCH_FIELD,
+                };
+
+                if !condition.allows(current, expires_at) {
+                    return 0;
+                }
+
+                if expires_at <= now {
+                    hash.remove(field);
+                    return 2;
+                }
+
+                hash.set_expiry(field, Some(expires_at));
+                return 1;
+            })
+            .collect();
+
+        if hash.len() == 0 {
+            store.remove(key);
+        } else if hash.has_field_ttl() {
+            store.volatile.insert(key);
+        }
+        return Ok(codes);
+    }
+
+    fn hash_ttl(&self, key: &str, fields: &[String]) -> Result<Vec<i64>> {
+        let mut store = lock(&self.store);
+        let hash = match hash(&mut store, key)? {
+            Some(hash) => hash,
+            None => return Ok(vec![NO_SUCH_FIELD; fields.len()]),
+        };
+
+        let now = current_timestamp();
+        return Ok(fields
+            .iter()
+            .map(|field| {
+                return match hash.expires_at(field) {
+                    Some(Some(expires_at)) => (expires_at - now) as i64,
+                    Some(_) => NO_FIELD_TTL,
+                    None => NO_SUCH_FIELD,
+                };
+            })
+            .collect());
+    }
+
+    fn hash_persist(&mut self, key: &str, fields: &[String]) -> Result<Vec<i64>> {
+        let mut store = lock(&self.store);
+        let unchanged = match hash(&mut store, key)? {
+            Some(hash) => fields
+                .iter()
+                .map(|field| {
+                    return match hash.expires_at(field) {
+                        Some(Some(_)) => None,
+                        Some(_) => Some(NO_FIELD_TTL),
+                        None => Some(NO_SUCH_FIELD),
+                    };
+                })
+                .collect::<Option<Vec<i64>>>(),
+            None => Some(vec![NO_SUCH_FIELD; fields.len()]),
+        };
+        if let Some(codes) = unchanged {
+            return Ok(codes);
+        }
+        let hash = match hash_mut(&mut store, key)? {
+            Some(hash) => hash,
+            None => return Ok(vec![NO_SUCH_FIELD; fields.len()]),
+        };
+
+        return Ok(fields
+            .iter()
+            .map(|field| {
+                return match hash.expires_at(field) {
+                    Some(Some(_)) => {
+                        hash.set_expiry(field, None);
+                        1
+                    }
+                    Some(_) => NO_FIELD_TTL,
+                    None => NO_SUCH_FIELD,
+                };
+            })
+            .collect());
+    }
+}
+
+impl Hash {
+    fn new() -> Self {
+        return Hash::Listpack(Listpack::new());
+    }
+
+    pub(super) fn len(&self) -> usize {
+        return match self {
+            Hash::Listpack(listpack) => listpack.len() / 2,
+            Hash::Table(hash) => hash.len(),
+        };
+    }
+
+    /// Fields left once the expired ones are skipped.
+    fn live_len(&self) -> usize {
+        return match self {
+            Hash::Listpack(listpack) => listpack.len() / 2,
+            Hash::Table(_) => self.iter().count(),
+        };
+    }
+
+    fn get(&self, field: &str) -> Option<&str> {
+        return match self {
+            Hash::Listpack(listpack) => listpack
+                .pairs()
+                .find(|(current, _)| return *current == field)
+                .map(|(_, value)| return value),
+            Hash::Table(hash) => hash
+                .get(field)
+                .filter(|entry| return entry.is_live(current_timestamp()))
+                .map(|entry| return entry.value.as_str()),
+        };
+    }
+
+    /// TTL of the field, `None` when the field is missing.
+    fn expires_at(&self, field: &str) -> Option<Option<u128>> {
+        return match self {
+            Hash::Listpack(_) => self.get(field).map(|_| return None),
+            Hash::Table(hash) => hash
+                .get(field)
+                .filter(|entry| return entry.is_live(current_timestamp()))
+                .map(|entry| return entry.expires_at),
+        };
+    }
+
+    /// The fields and their values, skipping the expired ones.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        let now = current_timestamp();
+        return match self {
+            Hash::Listpack(listpack) => Box::new(listpack.pairs()),
+            Hash::Table(hash) => Box::new(
+                hash.iter()
+                    .filter(move |(_, entry)| return entry.is_live(now))
+                    .map(|(field, entry)| return (field.as_str(), entry.value.as_str())),
+            ),
+        };
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        return match self {
+            Hash::Listpack(_) => "listpack",
+            Hash::Table(_) => "hashtable",
+        };
+    }
+
+    pub(super) fn memory_usage(&self, samples: usize) -> usize {
+        return COLLECTION_OVERHEAD
+            + match self {
+                Hash::Listpack(listpack) => listpack.size(),
+                Hash::Table(hash) => {
+                    sampled_size(hash.iter(), hash.len(), samples, |(field, entry)| {
+                        let expiry = match entry.expires_at {
+                            Some(_) => EXPIRY_OVERHEAD,
+                            None => 0,
+                        };
+                        return 2 * ELEMENT_OVERHEAD + field.len() + entry.value.len() + expiry;
+                    })
+                }
+            };
+    }
+
+    /// Sets the value of the field, clearing its TTL unless `keep_ttl`. Returns whether the
+    /// field was created.
+    fn set(&mut self, field: &str, value: &str, keep_ttl: bool, limits: &EncodingLimits) -> bool {
+        match self {
+            Hash::Listpack(listpack) => {
+                let position = listpack.find_pair(field);
+                match position {
+                    Some(position) => listpack.replace(2 * position + 1, value),
+                    None => {
+                        listpack.push(field);
+                        listpack.push(value);
+                    }
+                }
+                if listpack.len() / 2 > limits.hash_max_listpack_entries
+                    || field.len() > limits.hash_max_listpack_value
+                    || value.len() > limits.hash_max_listpack_value
+                {
+                    self.convert();
+                }
+                return position.is_none();
+            }
+            Hash::Table(hash) => match hash.get_mut(field) {
+                Some(entry) => {
+                    entry.value = value.to_string();
+                    if !keep_ttl {
+                        entry.expires_at = None;
+                    }
+                    return false;
+                }
+                None => {
+                    hash.insert(field.to_string(), HashField::new(value.to_string()));
+                    return true;
+                }
+            },
+        }
+    }
+
+    fn remove(&mut self, field: &str) -> bool {
+        return match self {
+            Hash::Listpack(listpack) => match listpack.find_pair(field) {
+                Some(position) => {
+                    listpack.remove_pair(position);
+                    true
+                }
+                None => false,
+            },
+            Hash::Table(hash) => hash.remove(field).is_some(),
+        };
+    }
+
+    /// Sets or clears the TTL of an existing field.
+    fn set_expiry(&mut self, field: &str, expires_at: Option<u128>) {
+        if expires_at.is_some() {
+            self.convert();
+        }
+        if let Hash::Table(hash) = self {
+            if let Some(entry) = hash.get_mut(field) {
+                entry.expires_at = expires_at;
+            }
+        }
+    }
+
+    /// Whether every field has expired, which makes the key expire with them.
+    pub(super) fn is_expired(&self, now: u128) -> bool {
+        return match self {
+            Hash::Listpack(_) => false,
+            Hash::Table(hash) => {
+                !hash.is_empty() && hash.values().all(|entry| return !entry.is_live(now))
+            }
+        };
+    }
+
+    pub(super) fn has_field_ttl(&self) -> bool {
+        return match self {
+            Hash::Listpack(_) => false,
+            Hash::Table(hash) => hash.values().any(|entry| return entry.expires_at.is_some()),
+        };
+    }
+
+    fn remove_expired(&mut self, now: u128) {
+        if let Hash::Table(hash) = self {
+            hash.retain(|_, entry| return entry.is_live(now));
+        }
+    }
+
+    /// Moves a listpack over to a table.
+    fn convert(&mut self) {
+        if let Hash::Listpack(listpack) = self {
+            *self = Hash::Table(
+                listpack
+                    .pairs()
+                    .map(|(field, value)| {
+                        return (field.to_string(), HashField::new(value.to_string()));
+                    })
+                    .collect(),
+            );
+        }
+    }
+}
+
+impl HashField {
+    fn new(value: String) -> Self {
+        return HashField {
+            value,
+            expires_at: None,
+        };
+    }
+
+    fn is_live(&self, now: u128) -> bool {
+        return !self
+            .expires_at
+            .is_some_and(|expires_at| return expires_at <= now);
+    }
+}
+
+/// Looks up a hash for reading. Its expired fields are skipped rather than dropped, but a
+/// hash without any other field is deleted like any expired key.
+fn hash<'a>(store: &'a mut Keyspace, key: &str) -> Result<Option<&'a Hash>> {
+    store.remove_expired(key, current_timestamp());
+    return match live_value(store, key) {
+        Some(Value {
+            data: Data::Hash(hash),
+            ..
+        }) => Ok(Some(hash)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+/// Looks up a hash for writing, dropping its expired fields first. The key itself is removed when no
+/// field survives, so callers never see an empty hash.
+fn hash_mut<'a>(store: &'a mut Keyspace, key: &str) -> Result<Option<&'a mut Hash>> {
+    let hash = match live_value_mut(store, key) {
+        Some(Value {
+            data: Data::Hash(hash),
+            ..
+        }) => hash,
+        Some(_) => return Err(RedisError::WrongType.into()),
+        None => return Ok(None),
+    };
+
+    hash.remove_expired(current_timestamp());
+
+    if hash.len() == 0 {
+        store.remove(key);
+        store.lazily_expired.push(key.to_string());
+        return Ok(None);
+    }
+    return Ok(match store.get_mut(key) {
+        Some(Value {
+            data: Data::Hash(hash),
+            ..
+        }) => Some(hash),
+        _ => None,
+    });
+}
+
+fn hash_or_create<'a>(store: &'a mut Keyspace, key: &str) -> Result<&'a mut Hash> {
+    if hash_mut(store, key)?.is_none() {
+        store.insert(key.to_string(), Value::new(Data::Hash(Hash::new())));
+    }
+    return match store.get_mut(key) {
+        Some(Value {
+            data: Data::Hash(hash),
+            ..
+        }) => Ok(hash),
+        _ => unreachable!("The hash was just created"),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{lock, test_store, InMemStore};
+    use super::{Data, Hash};
+    use crate::persistence::{current_timestamp, ExpireCondition, HashStore, KeyStore};
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs
+            .iter()
+            .map(|(field, value)| return (field.to_string(), value.to_string()))
+            .collect();
+    }
+
+    /// Gives the field a TTL through HPEXPIREAT, then moves it into the past.
+    fn expire_field(store: &mut InMemStore, key: &str, field: &str) {
+        let expires_at = current_timestamp() + 60_000;
+        let fields = [field.to_string()];
+        store
+            .hash_expire(key, &fields, expires_at, ExpireCondition::Always)
+            .unwrap();
+        let mut keyspace = lock(&store.store);
+        if let Some(Data::Hash(Hash::Table(hash))) = keyspace
+            .values
+            .get_mut(key)
+            .map(|value| return &mut value.data)
+        {
+            hash.get_mut(field).unwrap().expires_at = Some(1);
+        }
+    }
+
+    #[test]
+    fn hashes_are_deleted_once_their_last_field_expires() {
+        let mut store = test_store();
+        store
+            .hash_set("h", &pairs(&[("a", "1"), ("b", "2")]))
+            .unwrap();
+        expire_field(&mut store, "h", "a");
+        assert_eq!(store.hash_len("h").unwrap(), 1);
+        assert_eq!(store.exists(&["h".to_string()]).unwrap(), 1);
+
+        expire_field(&mut store, "h", "b");
+        assert_eq!(store.key_type("h").unwrap(), None);
+        assert_eq!(store.key_count().unwrap(), 0);
+        assert_eq!(store.take_expired().unwrap(), [(0, "h".to_string())]);
+    }
+
+    #[test]
+    fn hashes_left_without_live_fields_are_expired_actively() {
+        let mut store = test_store();
+        store.hash_set("h", &pairs(&[("a", "1")])).unwrap();
+        store
+            .hash_set("kept", &pairs(&[("a", "1"), ("b", "2")]))
+            .unwrap();
+        expire_field(&mut store, "h", "a");
+        expire_field(&mut store, "kept", "a");
+
+        let mut expired = Vec::new();
+        for _ in 0..100 {
+            expired.extend(store.expire_sample(2).unwrap().expired);
+        }
+        assert_eq!(expired, ["h"]);
+        assert_eq!(store.key_count().unwrap(), 1);
+        assert_eq!(store.hash_len("kept").unwrap(), 1);
+    }
+
+    #[test]
+    fn random_fields_come_with_their_values() {
+        let mut store = test_store();
+        let all = pairs(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        store.hash_set("h", &all).unwrap();
+
+        let mut fields = store.hash_random_fields("h", 10).unwrap();
+        fields.sort();
+        assert_eq!(fields, all);
+        let repeated = store.hash_random_fields("h", -20).unwrap();
+        assert_eq!(repeated.len(), 20);
+        assert!(repeated.iter().all(|pair| return all.contains(pair)));
+    }
+
+    #[test]
+    fn random_fields_skip_expired_ones() {
+        let mut store = test_store();
+        store
+            .hash_set("h", &pairs(&[("a", "1"), ("b", "2")]))
+            .unwrap();
+        expire_field(&mut store, "h", "a");
+
+        assert_eq!(
+            store.hash_random_fields("h", 2).unwrap(),
+            pairs(&[("b", "2")])
+        );
+        assert_eq!(
+            store.hash_random_fields("h", -2).unwrap(),
+            pairs(&[("b", "2"), ("b", "2")])
+        );
+    }
+}
@@ -0,0 +1,82 @@
+mod sketch;
+
+use anyhow::Result;
+
+use super::{
+    lock,
+    strings::{set_in_place, string, string_mut},
+    Data, InMemStore, Value,
+};
+use crate::persistence::HyperLogLogStore;
+
+impl HyperLogLogStore for InMemStore {
+    fn hll_add(&mut self, key: &str, elements: &[String]) -> Result<bool> {
+        let mut store = lock(&self.store);
+        // NOTE: checked for reading first, raising no register must not touch the watchers of
+        // the key
+        let created = match string(&store, key)? {
+            Some(sketch) if !sketch::raises_registers(sketch, elements)? => return Ok(false),
+            Some(_) => false,
+            None => true,
+        };
+        if created {
+            store.insert(key.to_string(), Value::new(Data::String(sketch::empty())));
+        }
+
+        let changed = sketch::add(string_mut(&mut store, key)?.unwrap(), elements)?;
+        return Ok(created || changed);
+    }
+
+    fn hll_count(&mut self, keys: &[String]) -> Result<u64> {
+        let mut store = lock(&self.store);
+        if let [key] = keys {
+            let sketch = match string_mut(&mut store, key)? {
+                Some(sketch) => sketch,
+                None => return Ok(0),
+            };
+            sketch::validate(sketch)?;
+            if let Some(cardinality) = sketch::cached_cardinality(sketch) {
+                return Ok(cardinality);
+            }
+
+            let cardinality = sketch::cardinality(&sketch::registers(sketch)?);
+            sketch::cache_cardinality(sketch, cardinality);
+            return Ok(cardinality);
+        }
+
+        let mut union = vec![0; sketch::REGISTERS];
+        for key in keys {
+            if let Some(sketch) = string(&store, key)? {
+                sketch::validate(sketch)?;
+                merge_registers(&mut union, &sketch::registers(sketch)?);
+            }
+        }
+        return Ok(sketch::cardinality(&union));
+    }
+
+    fn hll_merge(&mut self, destination: &str, keys: &[String]) -> Result<()> {
+        let mut store = lock(&self.store);
+        let mut union = vec![0; sketch::REGISTERS];
+        let mut any_dense = false;
+        for key in keys
+            .iter()
+            .map(|key| return key.as_str())
+            .chain([destination])
+        {
+            if let Some(sketch) = string(&store, key)? {
+                any_dense |= sketch::validate(sketch)?;
+                merge_registers(&mut union, &sketch::registers(sketch)?);
+            }
+        }
+
+        // NOTE: like redis, the result only goes dense if one of the inputs was
+        set_in_place(&mut store, destination, sketch::encode(&union, any_dense));
+        return Ok(());
+    }
+}
+
+fn merge_registers(union: &mut [u8], registers: &[u8]) {
+    for (max, value) in union.iter_mut().zip(registers) {
+        *max = (*max).max(*value);
+    }
+}
@@ -0,0 +1,141 @@
+/// Sorted integers packed with the smallest width that fits all of them (2, 4 or 8 bytes),
+/// like the intset redis keeps small sets of integers in. Adding an integer that does not
+/// fit upgrades every element to the wider encoding.
+#[derive(Clone)]
+pub struct IntSet {
+    width: usize,
+    bytes: Vec<u8>,
+}
+
+impl IntSet {
+    pub fn new() -> Self {
+        return IntSet {
+            width: 2,
+            bytes: Vec::new(),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.bytes.len() / self.width;
+    }
+
+    /// Bytes taken by the elements.
+    pub fn size(&self) -> usize {
+        return self.bytes.len();
+    }
+
+    pub fn get(&self, index: usize) -> i64 {
+        let element = &self.bytes[index * self.width..(index + 1) * self.width];
+        return match self.width {
+            2 => i16::from_le_bytes(element.try_into().unwrap()) as i64,
+            4 => i32::from_le_bytes(element.try_into().unwrap()) as i64,
+            _ => i64::from_le_bytes(element.try_into().unwrap()),
+        };
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        return (0..self.len()).map(|index| return self.get(index));
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        return self.search(value).is_ok();
+    }
+
+    /// Returns whether the value was added.
+    pub fn insert(&mut self, value: i64) -> bool {
+        if width_of(value) > self.width {
+            self.upgrade(width_of(value));
+        }
+        let index = match self.search(value) {
+            Ok(_) => return false,
+            Err(index) => index,
+        };
+        let offset = index * self.width;
+        self.bytes
+            .splice(offset..offset, value.to_le_bytes()[..self.width].to_vec());
+        return true;
+    }
+
+    /// Returns whether the value was there.
+    pub fn remove(&mut self, value: i64) -> bool {
+        let index = match self.search(value) {
+            Ok(index) => index,
+            Err(_) => return false,
+        };
+        self.bytes
+            .drain(index * self.width..(index + 1) * self.width);
+        return true;
+    }
+
+    /// Binary search, the index of the value or where it would be inserted.
+    fn search(&self, value: i64) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.get(middle).cmp(&value) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => return Ok(middle),
+            }
+        }
+        return Err(low);
+    }
+
+    fn upgrade(&mut self, width: usize) {
+        let values = self.iter().collect::<Vec<i64>>();
+        self.width = width;
+        self.bytes = values
+            .into_iter()
+            .flat_map(|value| return value.to_le_bytes()[..width].to_vec())
+            .collect();
+    }
+}
+
+fn width_of(value: i64) -> usize {
+    return if i16::try_from(value).is_ok() {
+        2
+    } else if i32::try_from(value).is_ok() {
+        4
+    } else {
+        8
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IntSet;
+
+    #[test]
+    fn keeps_integers_sorted_across_upgrades() {
+        let mut set = IntSet::new();
+        for value in [5, -3, 5, i16::MAX as i64, i16::MIN as i64] {
+            set.insert(value);
+        }
+        assert_eq!(set.size(), 4 * 2);
+
+        assert!(set.insert(i16::MAX as i64 + 1));
+        assert_eq!(set.size(), 5 * 4);
+        assert!(set.insert(i64::MIN));
+        assert!(set.insert(i64::MAX));
+        assert!(!set.insert(i64::MAX));
+        assert_eq!(set.size(), 7 * 8);
+
+        let expected = [i64::MIN, -32768, -3, 5, 32767, 32768, i64::MAX];
+        assert_eq!(set.iter().collect::<Vec<_>>(), expected);
+        assert!(expected.iter().all(|value| return set.contains(*value)));
+        assert!(!set.contains(0));
+    }
+
+    #[test]
+    fn removes_only_what_is_there() {
+        let mut set = IntSet::new();
+        assert!(!set.remove(1));
+        set.insert(1);
+        set.insert(i64::MIN);
+        assert!(!set.remove(2));
+        assert!(set.remove(i64::MIN));
+        assert!(set.remove(1));
+        assert_eq!(set.len(), 0);
+        assert!(!set.contains(1));
+    }
+}
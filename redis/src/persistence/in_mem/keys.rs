@@ -0,0 +1,259 @@
+use std::{
+    sync::{
+        mpsc::{self, Sender},
+        Mutex, OnceLock,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{current_timestamp, live_value, lock, Data, Hash, InMemStore, List, Set, SortedSet};
+use crate::{
+    errors::RedisError,
+    glob,
+    persistence::{ExpireCondition, ExpireSample, KeyStore, ScanFilter},
+    random,
+};
+
+/// Values holding more elements than this are freed on the background thread by UNLINK,
+/// smaller ones are cheaper to drop right away (`LAZYFREE_THRESHOLD` in redis).
+const LAZY_FREE_THRESHOLD: usize = 64;
+
+impl KeyStore for InMemStore {
+    fn delete(&mut self, keys: &[String], lazy: bool) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let now = current_timestamp();
+
+        let mut deleted = 0;
+        for key in keys {
+            let value = match store.remove(key) {
+                Some(value) => value,
+                None => continue,
+            };
+            if !value.is_expired(now) {
+                deleted += 1;
+            }
+            if lazy && value.data.free_effort() > LAZY_FREE_THRESHOLD {
+                free_lazily(value);
+            }
+        }
+        return Ok(deleted);
+    }
+
+    fn exists(&self, keys: &[String]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let now = current_timestamp();
+        for key in keys {
+            store.remove_expired(key, now);
+        }
+        return Ok(keys
+            .iter()
+            .filter(|key| return live_value(&store, key).is_some())
+            .count());
+    }
+
+    fn key_type(&self, key: &str) -> Result<Option<&'static str>> {
+        let mut store = lock(&self.store);
+        store.remove_expired(key, current_timestamp());
+        return Ok(live_value(&store, key).map(|value| return value.data.type_name()));
+    }
+
+    fn rename(&mut self, key: &str, new_key: &str, only_missing: bool) -> Result<bool> {
+        let mut store = lock(&self.store);
+        if live_value(&store, key).is_none() {
+            return Err(anyhow!(RedisError::Generic("no such key".into())));
+        }
+        if key == new_key {
+            return Ok(!only_missing);
+        }
+        if only_missing && live_value(&store, new_key).is_some() {
+            return Ok(false);
+        }
+
+        let value = store.remove(key).unwrap();
+        if value.is_volatile() {
+            store.volatile.insert(new_key);
+        }
+        store.insert(new_key.to_string(), value);
+        return Ok(true);
+    }
+
+    fn copy(
+        &mut self,
+        source: &str,
+        destination: &str,
+        db: Option<usize>,
+        replace: bool,
+    ) -> Result<bool> {
+        // NOTE: the selected database is locked once, whichever way it is named
+        let target = match db {
+            Some(db) if db != self.db => Some(self.database(db)?),
+            _ => None,
+        };
+        let mut store = lock(&self.store);
+        if target.is_none() && source == destination {
+            return Err(anyhow!(RedisError::Generic(
+                "source and destination objects are the same".into()
+            )));
+        }
+        let value = match live_value(&store, source) {
+            Some(value) => value.clone(),
+            None => return Ok(false),
+        };
+
+        let mut target = target.map(|target| return lock(target));
+        let target = match target.as_deref_mut() {
+            Some(target) => target,
+            None => &mut *store,
+        };
+        if !replace && live_value(target, destination).is_some() {
+            return Ok(false);
+        }
+
+        if value.is_volatile() {
+            target.volatile.insert(destination);
+        }
+        target.insert(destination.to_string(), value);
+        return Ok(true);
+    }
+
+    fn expire(
+        &mut self,
+        key: &str,
+        expires_at: u128,
+        conditions: &[ExpireCondition],
+    ) -> Result<bool> {
+        let mut store = lock(&self.store);
+        let current = match live_value(&store, key) {
+            Some(value) => value.expires_at,
+            None => return Ok(false),
+        };
+        if !conditions
+            .iter()
+            .all(|condition| return condition.allows(current, expires_at))
+        {
+            return Ok(false);
+        }
+
+        if expires_at <= current_timestamp() {
+            store.remove(key);
+        } else if let Some(value) = store.get_mut(key) {
+            value.expires_at = Some(expires_at);
+            store.volatile.insert(key);
+        }
+        return Ok(true);
+    }
+
+    fn expires_at(&self, key: &str) -> Result<Option<Option<u128>>> {
+        let store = lock(&self.store);
+        return Ok(live_value(&store, key).map(|value| return value.expires_at));
+    }
+
+    fn persist(&mut self, key: &str) -> Result<bool> {
+        let mut store = lock(&self.store);
+        if !live_value(&store, key).is_some_and(|value| return value.expires_at.is_some()) {
+            return Ok(false);
+        }
+        if let Some(value) = store.get_mut(key) {
+            value.expires_at = None;
+        }
+        return Ok(true);
+    }
+
+    fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let store = lock(&self.store);
+        let now = current_timestamp();
+        return Ok(store
+            .iter()
+            .filter(|(key, value)| return !value.is_expired(now) && glob::matches(pattern, key))
+            .map(|(key, _)| return key.clone())
+            .collect());
+    }
+
+    fn scan(&self, cursor: u64, count: usize, filter: ScanFilter) -> Result<(u64, Vec<String>)> {
+        return Ok(lock(&self.store).scan(cursor, count, filter));
+    }
+
+    fn random_key(&self) -> Result<Option<String>> {
+        let store = lock(&self.store);
+        let now = current_timestamp();
+        let keys = store
+            .iter()
+            .filter(|(_, value)| return !value.is_expired(now))
+            .map(|(key, _)| return key)
+            .collect::<Vec<&String>>();
+
+        if keys.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(keys[random::below(keys.len())].clone()));
+    }
+
+    fn expire_sample(&mut self, count: usize) -> Result<ExpireSample> {
+        return Ok(lock(&self.store).expire_sample(count));
+    }
+
+    fn take_expired(&mut self) -> Result<Vec<(usize, String)>> {
+        let mut expired = Vec::new();
+        for (db, store) in self.databases.iter().enumerate() {
+            let mut store = lock(store);
+            expired.extend(store.lazily_expired.drain(..).map(|key| return (db, key)));
+        }
+        return Ok(expired);
+    }
+
+    fn key_count(&self) -> Result<usize> {
+        return Ok(lock(&self.store).len());
+    }
+}
+
+impl Data {
+    /// Name TYPE replies with.
+    pub(super) fn type_name(&self) -> &'static str {
+        return match self {
+            Data::String(_) => "string",
+            Data::List(_) => "list",
+            Data::Hash(_) => "hash",
+            Data::Set(_) => "set",
+            Data::SortedSet(_) => "zset",
+            Data::Stream(_) => "stream",
+        };
+    }
+
+    /// Rough cost of dropping the value: one allocation for strings and compact encodings,
+    /// one per element for the other collections.
+    fn free_effort(&self) -> usize {
+        return match self {
+            Data::String(_)
+            | Data::List(List::Listpack(_))
+            | Data::Hash(Hash::Listpack(_))
+            | Data::Set(Set::IntSet(_))
+            | Data::SortedSet(SortedSet::Listpack(_)) => 1,
+            Data::List(list) => list.len(),
+            Data::Hash(hash) => hash.len(),
+            Data::Set(set) => set.len(),
+            Data::SortedSet(sorted_set) => sorted_set.len(),
+            Data::Stream(stream) => stream.len(),
+        };
+    }
+}
+
+/// Hands a value (or a whole flushed database) over to the lazy free thread, started on
+/// first use, so that dropping it does not hold up the client that deleted it nor the store
+/// lock.
+pub(super) fn free_lazily<T: Send + 'static>(garbage: T) {
+    static LAZY_FREE: OnceLock<Mutex<Sender<Box<dyn Send>>>> = OnceLock::new();
+
+    let sender = LAZY_FREE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Box<dyn Send>>();
+        thread::spawn(move || {
+            for garbage in receiver {
+                drop(garbage);
+            }
+        });
+        return Mutex::new(sender);
+    });
+    // NOTE: the thread lives as long as the process, so sending never fails
+    sender.lock().unwrap().send(Box::new(garbage)).unwrap();
+}
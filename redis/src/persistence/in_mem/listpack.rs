@@ -0,0 +1,189 @@
+use std::iter;
+
+/// Strings packed one after the other in a single allocation, each prefixed by its length,
+/// like the listpack redis keeps small collections in. Lookups are linear, which is cheap
+/// for the few entries a listpack is allowed to grow to.
+#[derive(Clone)]
+pub struct Listpack {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+pub struct Iter<'a> {
+    bytes: &'a [u8],
+}
+
+impl Listpack {
+    pub fn new() -> Self {
+        return Listpack {
+            bytes: Vec::new(),
+            len: 0,
+        };
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    /// Bytes taken by the entries and their headers.
+    pub fn size(&self) -> usize {
+        return self.bytes.len();
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        return Iter { bytes: &self.bytes };
+    }
+
+    /// Entries taken two by two, for the listpacks holding pairs.
+    pub fn pairs(&self) -> impl Iterator<Item = (&str, &str)> {
+        let mut entries = self.iter();
+        return iter::from_fn(move || return Some((entries.next()?, entries.next()?)));
+    }
+
+    /// Index of the pair whose first entry is `key`.
+    pub fn find_pair(&self, key: &str) -> Option<usize> {
+        return self.pairs().position(|(current, _)| return current == key);
+    }
+
+    /// Inserts before the entry at `index`, at the end when `index` is the length.
+    pub fn insert(&mut self, index: usize, entry: &str) {
+        let offset = self.offset(index);
+        let mut encoded = encode_len(entry.len());
+        encoded.extend_from_slice(entry.as_bytes());
+        self.bytes.splice(offset..offset, encoded);
+        self.len += 1;
+    }
+
+    pub fn push(&mut self, entry: &str) {
+        self.insert(self.len, entry);
+    }
+
+    pub fn remove(&mut self, index: usize) -> String {
+        let offset = self.offset(index);
+        let (len, header) = decode_len(&self.bytes[offset..]);
+        let removed = self
+            .bytes
+            .drain(offset..offset + header + len)
+            .skip(header)
+            .collect::<Vec<u8>>();
+        self.len -= 1;
+        return String::from_utf8(removed).expect("Listpack entries are strings");
+    }
+
+    /// Removes the pair at `index`, counted in pairs.
+    pub fn remove_pair(&mut self, index: usize) {
+        self.remove(2 * index);
+        self.remove(2 * index);
+    }
+
+    pub fn replace(&mut self, index: usize, entry: &str) {
+        self.remove(index);
+        self.insert(index, entry);
+    }
+
+    /// Byte offset of the entry at `index`, the end of the entries for the length.
+    fn offset(&self, index: usize) -> usize {
+        assert!(index <= self.len, "Listpack index out of bounds");
+        let mut offset = 0;
+        for _ in 0..index {
+            let (len, header) = decode_len(&self.bytes[offset..]);
+            offset += header + len;
+        }
+        return offset;
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let (len, header) = decode_len(self.bytes);
+        let (entry, rest) = self.bytes[header..].split_at(len);
+        self.bytes = rest;
+        return Some(std::str::from_utf8(entry).expect("Listpack entries are strings"));
+    }
+}
+
+/// LEB128: 7 bits per byte, the high bit set on every byte but the last.
+fn encode_len(mut len: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            encoded.push(byte);
+            return encoded;
+        }
+        encoded.push(byte | 0x80);
+    }
+}
+
+/// Returns the length and the bytes its encoding took.
+fn decode_len(bytes: &[u8]) -> (usize, usize) {
+    let mut len = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        len |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return (len, i + 1);
+        }
+    }
+    unreachable!("Truncated listpack entry header");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Listpack;
+
+    #[test]
+    fn keeps_entries_of_any_length_in_order() {
+        let long = "x".repeat(200);
+        let longer = "y".repeat(20_000);
+        let mut listpack = Listpack::new();
+        for entry in ["", "\u{e9}t\u{e9}", &long, &longer] {
+            listpack.push(entry);
+        }
+        listpack.insert(0, "first");
+        listpack.insert(2, "middle");
+
+        let expected = ["first", "", "middle", "\u{e9}t\u{e9}", &long, &longer];
+        assert_eq!(listpack.len(), expected.len());
+        assert_eq!(listpack.iter().collect::<Vec<_>>(), expected);
+        // NOTE: headers of 1, 2 and 3 bytes
+        let entries = expected
+            .iter()
+            .map(|entry| return entry.len())
+            .sum::<usize>();
+        assert_eq!(listpack.size(), entries + 4 + 2 + 3);
+
+        assert_eq!(listpack.remove(5), longer);
+        listpack.replace(4, "short");
+        assert_eq!(listpack.remove(0), "first");
+        assert_eq!(
+            listpack.iter().collect::<Vec<_>>(),
+            ["", "middle", "\u{e9}t\u{e9}", "short"]
+        );
+    }
+
+    #[test]
+    fn looks_up_and_removes_pairs() {
+        let mut listpack = Listpack::new();
+        for entry in ["a", "1", "b", "2", "c"] {
+            listpack.push(entry);
+        }
+        // NOTE: a trailing entry without its pair is left out
+        assert_eq!(
+            listpack.pairs().collect::<Vec<_>>(),
+            [("a", "1"), ("b", "2")]
+        );
+        assert_eq!(listpack.find_pair("b"), Some(1));
+        assert_eq!(listpack.find_pair("1"), None);
+        assert_eq!(listpack.find_pair("c"), None);
+
+        listpack.remove_pair(0);
+        assert_eq!(listpack.iter().collect::<Vec<_>>(), ["b", "2", "c"]);
+    }
+}
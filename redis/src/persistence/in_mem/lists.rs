@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use super::{
+    listpack::Listpack,
+    live_value, live_value_mut, lock,
+    memory::{sampled_size, COLLECTION_OVERHEAD, ELEMENT_OVERHEAD},
+    Data, InMemStore, Keyspace, Value,
+};
+use crate::{
+    errors::RedisError,
+    persistence::{EncodingLimits, ListEnd, ListStore},
+};
+
+/// Size of the listpack a negative `list-max-listpack-size` of -1 stands for, doubling with
+/// each step down to -5.
+const LISTPACK_SIZE_UNIT: usize = 4096;
+
+/// Small lists are a single listpack. Past `list-max-listpack-size` they become a deque,
+/// which stands for the quicklist of listpacks redis moves to.
+#[derive(Clone)]
+pub enum List {
+    Listpack(Listpack),
+    Quicklist(VecDeque<String>),
+}
+
+impl ListStore for InMemStore {
+    fn push(
+        &mut self,
+        key: &str,
+        values: &[String],
+        end: ListEnd,
+        only_existing: bool,
+    ) -> Result<usize> {
+        let mut store = lock(&self.store);
+
+        if only_existing && list(&store, key)?.is_none() {
+            return Ok(0);
+        }
+
+        let limits = store.encodings;
+        let list = list_or_create(&mut store, key)?;
+        for value in values {
+            list.push(value, end, &limits);
+        }
+
+        return Ok(list.len());
+    }
+
+    fn pop(&mut self, key: &str, end: ListEnd, count: usize) -> Result<Vec<String>> {
+        let mut store = lock(&self.store);
+        return pop_from(&mut store, key, end, count);
+    }
+
+    fn pop_first(
+        &mut self,
+        keys: &[String],
+        end: ListEnd,
+        count: usize,
+    ) -> Result<Option<(String, Vec<String>)>> {
+        let mut store = lock(&self.store);
+        for key in keys {
+            let popped = pop_from(&mut store, key, end, count)?;
+            if !popped.is_empty() {
+                return Ok(Some((key.clone(), popped)));
+            }
+        }
+        return Ok(None);
+    }
+
+    fn list_len(&self, key: &str) -> Result<usize> {
+        let store = lock(&self.store);
+        return Ok(list(&store, key)?.map_or(0, |list| return list.len()));
+    }
+
+    fn range(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>> {
+        let store = lock(&self.store);
+        let list = match list(&store, key)? {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+
+        let len = list.len() as i64;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            len + stop
+        } else {
+            stop.min(len - 1)
+        };
+
+        if start > stop || start >= len {
+            return Ok(Vec::new());
+        }
+
+        return Ok(list
+            .iter()
+            .skip(start as usize)
+            .take((stop - start + 1) as usize)
+            .map(str::to_string)
+            .collect());
+    }
+
+    fn move_element(
+        &mut self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<String>> {
+        let mut store = lock(&self.store);
+        return move_between(&mut store, source, destination, from, to);
+    }
+}
+
+impl List {
+    fn new() -> Self {
+        return List::Listpack(Listpack::new());
+    }
+
+    pub(super) fn len(&self) -> usize {
+        return match self {
+            List::Listpack(listpack) => listpack.len(),
+            List::Quicklist(list) => list.len(),
+        };
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        return match self {
+            List::Listpack(listpack) => Box::new(listpack.iter()),
+            List::Quicklist(list) => Box::new(list.iter().map(String::as_str)),
+        };
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        return match self {
+            List::Listpack(_) => "listpack",
+            List::Quicklist(_) => "quicklist",
+        };
+    }
+
+    pub(super) fn memory_usage(&self, samples: usize) -> usize {
+        return COLLECTION_OVERHEAD
+            + match self {
+                List::Listpack(listpack) => listpack.size(),
+                List::Quicklist(list) => {
+                    sampled_size(list.iter(), list.len(), samples, |element| {
+                        return ELEMENT_OVERHEAD + element.len();
+                    })
+                }
+            };
+    }
+
+    fn push(&mut self, value: &str, end: ListEnd, limits: &EncodingLimits) {
+        match self {
+            List::Listpack(listpack) => {
+                let index = match end {
+                    ListEnd::Left => 0,
+                    ListEnd::Right => listpack.len(),
+                };
+                listpack.insert(index, value);
+                if exceeds_listpack(listpack, limits) {
+                    *self = List::Quicklist(listpack.iter().map(str::to_string).collect());
+                }
+            }
+            List::Quicklist(list) => match end {
+                ListEnd::Left => list.push_front(value.to_string()),
+                ListEnd::Right => list.push_back(value.to_string()),
+            },
+        }
+    }
+
+    fn pop(&mut self, end: ListEnd) -> Option<String> {
+        return match self {
+            List::Listpack(listpack) if listpack.len() == 0 => None,
+            List::Listpack(listpack) => Some(match end {
+                ListEnd::Left => listpack.remove(0),
+                ListEnd::Right => listpack.remove(listpack.len() - 1),
+            }),
+            List::Quicklist(list) => match end {
+                ListEnd::Left => list.pop_front(),
+                ListEnd::Right => list.pop_back(),
+            },
+        };
+    }
+}
+
+fn exceeds_listpack(listpack: &Listpack, limits: &EncodingLimits) -> bool {
+    return match limits.list_max_listpack_size {
+        size if size > 0 => listpack.len() > size as usize,
+        size => listpack.size() > LISTPACK_SIZE_UNIT << (size.clamp(-5, -1).unsigned_abs() - 1),
+    };
+}
+
+fn list<'a>(store: &'a Keyspace, key: &str) -> Result<Option<&'a List>> {
+    return match live_value(store, key) {
+        Some(Value {
+            data: Data::List(list),
+            ..
+        }) => Ok(Some(list)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn list_mut<'a>(store: &'a mut Keyspace, key: &str) -> Result<Option<&'a mut List>> {
+    return match live_value_mut(store, key) {
+        Some(Value {
+            data: Data::List(list),
+            ..
+        }) => Ok(Some(list)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn list_or_create<'a>(store: &'a mut Keyspace, key: &str) -> Result<&'a mut List> {
+    if list(store, key)?.is_none() {
+        store.insert(key.to_string(), Value::new(Data::List(List::new())));
+    }
+    return Ok(list_mut(store, key)?.unwrap());
+}
+
+/// Pops up to `count` elements, deleting the key once its list is emptied.
+fn pop_from(store: &mut Keyspace, key: &str, end: ListEnd, count: usize) -> Result<Vec<String>> {
+    // NOTE: popping nothing leaves the watchers of the key alone
+    if count == 0 {
+        return list(store, key).map(|_| return Vec::new());
+    }
+    let list = match list_mut(store, key)? {
+        Some(list) => list,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut popped = Vec::with_capacity(count.min(list.len()));
+    while popped.len() < count {
+        match list.pop(end) {
+            Some(value) => popped.push(value),
+            None => break,
+        }
+    }
+
+    if list.len() == 0 {
+        store.remove(key);
+    }
+    return Ok(popped);
+}
+
+fn move_between(
+    store: &mut Keyspace,
+    source: &str,
+    destination: &str,
+    from: ListEnd,
+    to: ListEnd,
+) -> Result<Option<String>> {
+    if list(store, source)?.is_none() {
+        return Ok(None);
+    }
+    // NOTE: validating the destination type before popping, so a WRONGTYPE leaves the source intact
+    list(store, destination)?;
+
+    let value = pop_from(store, source, from, 1)?.pop().unwrap();
+
+    let limits = store.encodings;
+    list_or_create(store, destination)?.push(&value, to, &limits);
+
+    return Ok(Some(value));
+}
@@ -0,0 +1,125 @@
+//! Approximate accounting of the memory taken by the keyspace, the way MEMORY USAGE
+//! estimates it in redis: fixed overheads for the structures plus the bytes of the strings,
+//! with collections extrapolated from a sample of their elements.
+
+use std::collections::HashSet;
+
+use super::{Data, Keyspace, Value};
+use crate::persistence::DEFAULT_MEMORY_SAMPLES;
+
+/// Dict entry, object header and string header a key costs on top of its bytes.
+const KEY_OVERHEAD: usize = 48;
+/// Entry of the dict of expires.
+pub(super) const EXPIRY_OVERHEAD: usize = 24;
+/// Header of a collection value.
+pub(super) const COLLECTION_OVERHEAD: usize = 64;
+/// Allocation header and pointers each element of a collection costs.
+pub(super) const ELEMENT_OVERHEAD: usize = 16;
+
+/// Bytes taken by the keys and values of a keyspace. Values handed out for writing may
+/// change size, so their share is taken off when they are handed out and added back the
+/// next time the total is read.
+pub struct MemoryUsage {
+    /// Bytes of the keys that are not dirty.
+    used: usize,
+    dirty: HashSet<String>,
+}
+
+impl MemoryUsage {
+    pub fn new() -> Self {
+        return MemoryUsage {
+            used: 0,
+            dirty: HashSet::new(),
+        };
+    }
+
+    pub fn add(&mut self, key: &str, value: &Value) {
+        self.used += entry_size(key, value, DEFAULT_MEMORY_SAMPLES);
+    }
+
+    pub fn remove(&mut self, key: &str, value: &Value) {
+        if !self.dirty.remove(key) {
+            self.used = self
+                .used
+                .saturating_sub(entry_size(key, value, DEFAULT_MEMORY_SAMPLES));
+        }
+    }
+
+    /// The value of `key` is about to be changed in place.
+    pub fn modify(&mut self, key: &str, value: &Value) {
+        if !self.dirty.contains(key) {
+            self.used = self
+                .used
+                .saturating_sub(entry_size(key, value, DEFAULT_MEMORY_SAMPLES));
+            self.dirty.insert(key.to_string());
+        }
+    }
+}
+
+impl Keyspace {
+    pub(super) fn used_memory(&mut self) -> usize {
+        let memory = &mut self.memory;
+        for key in memory.dirty.drain() {
+            if let Some(value) = self.values.get(&key) {
+                memory.used += entry_size(&key, value, DEFAULT_MEMORY_SAMPLES);
+            }
+        }
+        return memory.used;
+    }
+
+    /// Bytes taken by the table of the keys and by the one of the TTLs, out of the memory
+    /// used.
+    pub(super) fn overhead(&self) -> (usize, usize) {
+        let volatile = self
+            .values
+            .values()
+            .filter(|value| return value.expires_at.is_some())
+            .count();
+        return (self.len() * KEY_OVERHEAD, volatile * EXPIRY_OVERHEAD);
+    }
+}
+
+impl Value {
+    /// Bytes taken by the value, collections being extrapolated from `samples` of their
+    /// elements (all of them for 0).
+    pub(super) fn memory_usage(&self, samples: usize) -> usize {
+        let expiry = match self.expires_at {
+            Some(_) => EXPIRY_OVERHEAD,
+            None => 0,
+        };
+        return expiry + self.data.memory_usage(samples);
+    }
+}
+
+impl Data {
+    fn memory_usage(&self, samples: usize) -> usize {
+        return match self {
+            Data::String(value) => ELEMENT_OVERHEAD + value.len(),
+            Data::List(list) => list.memory_usage(samples),
+            Data::Hash(hash) => hash.memory_usage(samples),
+            Data::Set(set) => set.memory_usage(samples),
+            Data::SortedSet(sorted_set) => sorted_set.memory_usage(samples),
+            Data::Stream(stream) => stream.memory_usage(samples),
+        };
+    }
+}
+
+/// Size of `len` elements, extrapolated from the first `samples` of them (all of them for 0).
+pub(super) fn sampled_size<T>(
+    elements: impl Iterator<Item = T>,
+    len: usize,
+    samples: usize,
+    size: impl Fn(T) -> usize,
+) -> usize {
+    let samples = if samples == 0 { len } else { samples.min(len) };
+    if samples == 0 {
+        return 0;
+    }
+    let sampled = elements.take(samples).map(size).sum::<usize>();
+    return sampled * len / samples;
+}
+
+/// Bytes a key and its value take in the keyspace.
+pub(super) fn entry_size(key: &str, value: &Value, samples: usize) -> usize {
+    return KEY_OVERHEAD + key.len() + value.memory_usage(samples);
+}
@@ -0,0 +1,156 @@
+use std::sync::atomic::Ordering;
+
+use anyhow::Result;
+
+use super::{current_timestamp, lock, memory::entry_size, Data, InMemStore, Keyspace, Value};
+use crate::persistence::{
+    parse_integer, DatabaseOverhead, EvictionPolicy, MemoryStats, MemoryStore, ObjectInfo,
+    ObjectStore,
+};
+
+/// Strings up to this length are allocated along with their object header.
+const EMBSTR_SIZE_LIMIT: usize = 44;
+/// Integers below this are shared by every key holding them (`OBJ_SHARED_INTEGERS`).
+const SHARED_INTEGERS: i64 = 10_000;
+/// Refcount of the shared objects, which are never freed.
+const SHARED_REFCOUNT: i64 = i32::MAX as i64;
+
+impl ObjectStore for InMemStore {
+    fn object(&self, key: &str) -> Result<Option<ObjectInfo>> {
+        let store = lock(&self.store);
+        let now = current_timestamp();
+        let value = match unaccessed_value(&store, key, now) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let shared = match &value.data {
+            Data::String(string) => {
+                self.shares_integers()
+                    && as_integer(string).is_some_and(|n| return (0..SHARED_INTEGERS).contains(&n))
+            }
+            _ => false,
+        };
+        return Ok(Some(ObjectInfo {
+            encoding: value.encoding(),
+            refcount: if shared { SHARED_REFCOUNT } else { 1 },
+            idle_time: value.access.idle_time(now),
+            frequency: value.access.frequency(now, store.lfu),
+        }));
+    }
+
+    fn memory_usage(&self, key: &str, samples: usize) -> Result<Option<usize>> {
+        let store = lock(&self.store);
+        return Ok(unaccessed_value(&store, key, current_timestamp())
+            .map(|value| return entry_size(key, value, samples)));
+    }
+
+    fn memory_stats(&mut self) -> Result<MemoryStats> {
+        let used = self.used_memory()?;
+        let mut keys = 0;
+        let mut overheads = Vec::new();
+        for (db, database) in self.databases.iter().enumerate() {
+            let store = lock(database);
+            if store.is_empty() {
+                continue;
+            }
+            keys += store.len();
+            let (main, expires) = store.overhead();
+            overheads.push(DatabaseOverhead { db, main, expires });
+        }
+
+        return Ok(MemoryStats {
+            used,
+            peak: self.peak_memory.load(Ordering::Relaxed),
+            keys,
+            overheads,
+        });
+    }
+}
+
+impl InMemStore {
+    /// Redis keeps small integers as shared objects, unless the maxmemory policy needs the
+    /// access time or frequency of each key.
+    fn shares_integers(&self) -> bool {
+        return self.max_memory.limit == 0
+            || !matches!(
+                self.max_memory.policy,
+                EvictionPolicy::AllKeysLru
+                    | EvictionPolicy::VolatileLru
+                    | EvictionPolicy::AllKeysLfu
+                    | EvictionPolicy::VolatileLfu
+            );
+    }
+}
+
+impl Value {
+    /// Internal representation OBJECT ENCODING reports.
+    fn encoding(&self) -> &'static str {
+        return match &self.data {
+            Data::String(_) if self.raw => "raw",
+            Data::String(string) if as_integer(string).is_some() => "int",
+            Data::String(string) if string.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            Data::String(_) => "raw",
+            Data::List(list) => list.encoding(),
+            Data::Hash(hash) => hash.encoding(),
+            Data::Set(set) => set.encoding(),
+            Data::SortedSet(sorted_set) => sorted_set.encoding(),
+            Data::Stream(_) => "stream",
+        };
+    }
+}
+
+/// Looks up a live key without recording an access.
+fn unaccessed_value<'a>(store: &'a Keyspace, key: &str, now: u128) -> Option<&'a Value> {
+    return store.get(key).filter(|value| return !value.is_expired(now));
+}
+
+/// The integer a string holds, if it is in the canonical form redis stores as an int.
+fn as_integer(string: &[u8]) -> Option<i64> {
+    return parse_integer(std::str::from_utf8(string).ok()?);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{test_store, InMemStore};
+    use crate::persistence::{
+        BitmapStore, ObjectStore, SetCondition, SetExpiry, SetOptions, Store, StringStore,
+    };
+
+    fn encoding(store: &InMemStore, key: &str) -> &'static str {
+        return store.object(key).unwrap().unwrap().encoding;
+    }
+
+    fn set(store: &mut InMemStore, key: &str, value: &str) {
+        let options = SetOptions {
+            condition: SetCondition::Always,
+            expiry: SetExpiry::Clear,
+            get: false,
+        };
+        store.set(key.into(), value.into(), options).unwrap();
+    }
+
+    #[test]
+    fn strings_modified_in_place_are_raw() {
+        let mut store = test_store();
+        set(&mut store, "s", "ab");
+        set(&mut store, "n", "1");
+        assert_eq!(encoding(&store, "s"), "embstr");
+        assert_eq!(encoding(&store, "n"), "int");
+
+        store.string_append("s", b"c").unwrap();
+        store.string_append("n", b"2").unwrap();
+        store.string_set_range("r", 0, b"x").unwrap();
+        store.bit_set("b", 1, true).unwrap();
+        for key in ["s", "n", "r", "b"] {
+            assert_eq!(encoding(&store, key), "raw");
+        }
+
+        store.string_append("new", b"ab").unwrap();
+        assert_eq!(encoding(&store, "new"), "embstr");
+        store.string_incr_by("n", 1).unwrap();
+        assert_eq!(encoding(&store, "n"), "int");
+        set(&mut store, "s", "ab");
+        assert_eq!(encoding(&store, "s"), "embstr");
+    }
+}
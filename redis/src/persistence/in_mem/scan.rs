@@ -0,0 +1,112 @@
+//! SCAN over the keyspace with the guarantee of redis: every key present during the whole
+//! scan is returned, however the keyspace grows or shrinks in between.
+//!
+//! Redis gets there by walking the buckets of its hash table in reverse binary order, so
+//! that growing or shrinking the table never moves a bucket behind the cursor. Buckets are
+//! the low bits of the hash, and reversing them turns that walk into an ascending one: bucket
+//! `b` of a table with `2^n` buckets holds the keys whose bit reversed hash starts with the n
+//! bit reversal of `b`. Keys are indexed here by their bit reversed hash, so a cursor is
+//! a position in that order which stays valid for any table size, and a scan returns each
+//! key exactly once.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet},
+    hash::{Hash, Hasher},
+    ops::Bound,
+};
+
+use super::{current_timestamp, Keyspace};
+use crate::{glob, persistence::ScanFilter, random};
+
+/// Smallest table size redis uses, in bits.
+const MIN_TABLE_BITS: u32 = 2;
+
+/// Keys ordered by their bit reversed hash.
+pub struct ScanIndex {
+    keys: BTreeSet<(u64, String)>,
+}
+
+impl ScanIndex {
+    pub fn new() -> Self {
+        return ScanIndex {
+            keys: BTreeSet::new(),
+        };
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        self.keys.insert((position(key), key.to_string()));
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.keys.remove(&(position(key), key.to_string()));
+    }
+
+    /// A key picked at random: the first one at or after a random position. Keys following
+    /// wide gaps are picked more often, like keys alone in their bucket are by redis.
+    pub fn random(&self) -> Option<&str> {
+        let start = (random::next_u64(), String::new());
+        return self
+            .keys
+            .range(start..)
+            .chain(self.keys.iter())
+            .next()
+            .map(|(_, key)| return key.as_str());
+    }
+}
+
+impl Keyspace {
+    /// Returns the next cursor, 0 once the scan is complete, and the live keys of the buckets
+    /// visited that pass the filters. Whole buckets are visited until at least `count` keys
+    /// were looked at, like the buckets of a redis table sized for the current keyspace.
+    pub(super) fn scan(&self, cursor: u64, count: usize, filter: ScanFilter) -> (u64, Vec<String>) {
+        let table_bits =
+            (self.values.len().next_power_of_two().trailing_zeros()).max(MIN_TABLE_BITS);
+        let bucket = |position: u64| return position >> (u64::BITS - table_bits);
+
+        let now = current_timestamp();
+        let mut keys = Vec::new();
+        let mut visited = 0;
+        let mut entries = self
+            .scan_index
+            .keys
+            .range((
+                Bound::Included((cursor.reverse_bits(), String::new())),
+                Bound::Unbounded,
+            ))
+            .peekable();
+
+        while let Some((position, key)) = entries.next() {
+            visited += 1;
+            let value = &self.values[key];
+            let matches = !value.is_expired(now)
+                && filter
+                    .pattern
+                    .map_or(true, |pattern| return glob::matches(pattern, key))
+                && filter.key_type.map_or(true, |key_type| {
+                    return value.data.type_name().eq_ignore_ascii_case(key_type);
+                });
+            if matches {
+                keys.push(key.clone());
+            }
+
+            let next = match entries.peek() {
+                Some((next, _)) => *next,
+                None => return (0, keys),
+            };
+            if visited >= count && bucket(next) != bucket(*position) {
+                // NOTE: the start of the next bucket, reversed back into a bucket index
+                let start = bucket(next) << (u64::BITS - table_bits);
+                return (start.reverse_bits(), keys);
+            }
+        }
+        return (0, keys);
+    }
+}
+
+/// Hash of the key with its bits reversed. The hasher is unseeded, so that positions stay
+/// the same for the life of the process.
+fn position(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    return hasher.finish().reverse_bits();
+}
@@ -0,0 +1,414 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use anyhow::Result;
+
+use super::{
+    intset::IntSet,
+    live_value, live_value_mut, lock,
+    memory::{sampled_size, COLLECTION_OVERHEAD, ELEMENT_OVERHEAD},
+    random_picks, Data, InMemStore, Keyspace, Value,
+};
+use crate::{
+    errors::RedisError,
+    persistence::{parse_integer, EncodingLimits, SetOperation, SetStore},
+    random,
+};
+
+/// Sets holding only integers are an intset. A member that is not an integer, or more
+/// than `set-max-intset-entries` of them, turns the set into a table.
+#[derive(Clone)]
+pub enum Set {
+    IntSet(IntSet),
+    Table(HashSet<String>),
+}
+
+impl SetStore for InMemStore {
+    fn set_add(&mut self, key: &str, members: &[String]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let limits = store.encodings;
+        // NOTE: checked for reading first, adding nothing must not touch the watchers of the key
+        if set(&store, key)?
+            .is_some_and(|set| return members.iter().all(|member| return set.contains(member)))
+        {
+            return Ok(0);
+        }
+        let set = set_or_create(&mut store, key)?;
+
+        return Ok(members
+            .iter()
+            .filter(|member| return set.insert(member, &limits))
+            .count());
+    }
+
+    fn set_remove(&mut self, key: &str, members: &[String]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        if !set(&store, key)?
+            .is_some_and(|set| return members.iter().any(|member| return set.contains(member)))
+        {
+            return Ok(0);
+        }
+        let set = match set_mut(&mut store, key)? {
+            Some(set) => set,
+            None => return Ok(0),
+        };
+
+        let removed = members
+            .iter()
+            .filter(|member| return set.remove(member))
+            .count();
+
+        if set.len() == 0 {
+            store.remove(key);
+        }
+        return Ok(removed);
+    }
+
+    fn set_members(&self, key: &str) -> Result<Vec<String>> {
+        let store = lock(&self.store);
+        return Ok(match set(&store, key)? {
+            Some(set) => set.iter().map(Cow::into_owned).collect(),
+            None => Vec::new(),
+        });
+    }
+
+    fn set_contains(&self, key: &str, members: &[String]) -> Result<Vec<bool>> {
+        let store = lock(&self.store);
+        let set = set(&store, key)?;
+
+        return Ok(members
+            .iter()
+            .map(|member| return set.is_some_and(|set| return set.contains(member)))
+            .collect());
+    }
+
+    fn set_card(&self, key: &str) -> Result<usize> {
+        let store = lock(&self.store);
+        return Ok(set(&store, key)?.map_or(0, |set| return set.len()));
+    }
+
+    fn set_pop(&mut self, key: &str, count: usize) -> Result<Vec<String>> {
+        let mut store = lock(&self.store);
+        let set = match set_mut(&mut store, key)? {
+            Some(set) => set,
+            None => return Ok(Vec::new()),
+        };
+
+        let members = set.iter().collect::<Vec<Cow<str>>>();
+        let popped = random::distinct_indexes(members.len(), count)
+            .into_iter()
+            .map(|index| return members[index].to_string())
+            .collect::<Vec<String>>();
+
+        for member in &popped {
+            set.remove(member);
+        }
+        if set.len() == 0 {
+            store.remove(key);
+        }
+        return Ok(popped);
+    }
+
+    fn set_random_members(&self, key: &str, count: i64) -> Result<Vec<String>> {
+        let store = lock(&self.store);
+        let members = match set(&store, key)? {
+            Some(set) => set.iter().collect::<Vec<Cow<str>>>(),
+            None => return Ok(Vec::new()),
+        };
+
+        return Ok(if count >= 0 {
+            random::distinct_indexes(members.len(), count as usize)
+                .into_iter()
+                .map(|index| return members[index].to_string())
+                .collect()
+        } else {
+            random_picks(members.len(), count.unsigned_abs(), |index| {
+                return members[index].to_string();
+            })?
+        });
+    }
+
+    fn set_combine(&self, keys: &[String], operation: SetOperation) -> Result<Vec<String>> {
+        let store = lock(&self.store);
+        return Ok(combine(&store, keys, operation)?.into_iter().collect());
+    }
+
+    fn set_combine_store(
+        &mut self,
+        destination: &str,
+        keys: &[String],
+        operation: SetOperation,
+    ) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let result = combine(&store, keys, operation)?;
+        let len = result.len();
+
+        if result.is_empty() {
+            store.remove(destination);
+        } else {
+            let set = Set::from_members(result, &store.encodings);
+            store.insert(destination.to_string(), Value::new(Data::Set(set)));
+        }
+        return Ok(len);
+    }
+
+    fn set_intersection_card(&self, keys: &[String], limit: usize) -> Result<usize> {
+        let store = lock(&self.store);
+        let sets = lookup_sets(&store, keys)?
+            .into_iter()
+            .collect::<Option<Vec<&Set>>>();
+        let mut sets = match sets {
+            Some(sets) => sets,
+            None => return Ok(0),
+        };
+        sets.sort_by_key(|set| return set.len());
+
+        let (smallest, others) = sets.split_first().unwrap();
+        let mut card = 0;
+        for member in smallest.iter() {
+            if limit != 0 && card == limit {
+                break;
+            }
+            if others.iter().all(|set| return set.contains(&member)) {
+                card += 1;
+            }
+        }
+        return Ok(card);
+    }
+
+    fn set_move(&mut self, source: &str, destination: &str, member: &str) -> Result<bool> {
+        let mut store = lock(&self.store);
+
+        // NOTE: both keys are type checked before anything is moved
+        let in_source = match set(&store, source)? {
+            Some(set) => set.contains(member),
+            None => false,
+        };
+        set(&store, destination)?;
+
+        if !in_source || source == destination {
+            return Ok(in_source);
+        }
+
+        if let Some(set) = set_mut(&mut store, source)? {
+            set.remove(member);
+            if set.len() == 0 {
+                store.remove(source);
+            }
+        }
+        let limits = store.encodings;
+        set_or_create(&mut store, destination)?.insert(member, &limits);
+        return Ok(true);
+    }
+}
+
+impl Set {
+    fn new() -> Self {
+        return Set::IntSet(IntSet::new());
+    }
+
+    /// A set of the members in the most compact encoding that fits them.
+    fn from_members(members: impl IntoIterator<Item = String>, limits: &EncodingLimits) -> Self {
+        let mut set = Set::new();
+        for member in members {
+            set.insert(&member, limits);
+        }
+        return set;
+    }
+
+    pub(super) fn len(&self) -> usize {
+        return match self {
+            Set::IntSet(intset) => intset.len(),
+            Set::Table(set) => set.len(),
+        };
+    }
+
+    pub(super) fn contains(&self, member: &str) -> bool {
+        return match self {
+            Set::IntSet(intset) => parse_integer(member).is_some_and(|n| return intset.contains(n)),
+            Set::Table(set) => set.contains(member),
+        };
+    }
+
+    /// Members of an intset are written out as they are read.
+    pub(super) fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, str>> + '_> {
+        return match self {
+            Set::IntSet(intset) => {
+                Box::new(intset.iter().map(|n| return Cow::Owned(n.to_string())))
+            }
+            Set::Table(set) => Box::new(
+                set.iter()
+                    .map(|member| return Cow::Borrowed(member.as_str())),
+            ),
+        };
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        return match self {
+            Set::IntSet(_) => "intset",
+            Set::Table(_) => "hashtable",
+        };
+    }
+
+    pub(super) fn memory_usage(&self, samples: usize) -> usize {
+        return COLLECTION_OVERHEAD
+            + match self {
+                Set::IntSet(intset) => intset.size(),
+                Set::Table(set) => sampled_size(set.iter(), set.len(), samples, |member| {
+                    return ELEMENT_OVERHEAD + member.len();
+                }),
+            };
+    }
+
+    /// Returns whether the member was added.
+    fn insert(&mut self, member: &str, limits: &EncodingLimits) -> bool {
+        if let Set::IntSet(intset) = self {
+            if let Some(n) = parse_integer(member) {
+                if intset.contains(n) {
+                    return false;
+                }
+                if intset.len() < limits.set_max_intset_entries {
+                    return intset.insert(n);
+                }
+            }
+            *self = Set::Table(intset.iter().map(|n| return n.to_string()).collect());
+        }
+        return match self {
+            Set::Table(set) => set.insert(member.to_string()),
+            Set::IntSet(_) => unreachable!("The set was just converted"),
+        };
+    }
+
+    fn remove(&mut self, member: &str) -> bool {
+        return match self {
+            Set::IntSet(intset) => parse_integer(member).is_some_and(|n| return intset.remove(n)),
+            Set::Table(set) => set.remove(member),
+        };
+    }
+}
+
+fn set<'a>(store: &'a Keyspace, key: &str) -> Result<Option<&'a Set>> {
+    return match live_value(store, key) {
+        Some(Value {
+            data: Data::Set(set),
+            ..
+        }) => Ok(Some(set)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn set_mut<'a>(store: &'a mut Keyspace, key: &str) -> Result<Option<&'a mut Set>> {
+    return match live_value_mut(store, key) {
+        Some(Value {
+            data: Data::Set(set),
+            ..
+        }) => Ok(Some(set)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn set_or_create<'a>(store: &'a mut Keyspace, key: &str) -> Result<&'a mut Set> {
+    if set(store, key)?.is_none() {
+        store.insert(key.to_string(), Value::new(Data::Set(Set::new())));
+    }
+    return Ok(set_mut(store, key)?.unwrap());
+}
+
+/// Type checks every key, like redis does even when the result is already known to be
+/// empty. Missing keys are kept as `None`.
+fn lookup_sets<'a>(store: &'a Keyspace, keys: &[String]) -> Result<Vec<Option<&'a Set>>> {
+    return keys.iter().map(|key| return set(store, key)).collect();
+}
+
+fn combine(store: &Keyspace, keys: &[String], operation: SetOperation) -> Result<HashSet<String>> {
+    let sets = lookup_sets(store, keys)?;
+
+    return Ok(match operation {
+        SetOperation::Intersection => {
+            let mut sets = match sets.into_iter().collect::<Option<Vec<&Set>>>() {
+                Some(sets) => sets,
+                None => return Ok(HashSet::new()),
+            };
+            sets.sort_by_key(|set| return set.len());
+
+            let (smallest, others) = sets.split_first().unwrap();
+            smallest
+                .iter()
+                .filter(|member| return others.iter().all(|set| return set.contains(member)))
+                .map(Cow::into_owned)
+                .collect()
+        }
+        SetOperation::Union => sets
+            .into_iter()
+            .flatten()
+            .flat_map(Set::iter)
+            .map(Cow::into_owned)
+            .collect(),
+        SetOperation::Difference => {
+            let (first, others) = sets.split_first().unwrap();
+            match first {
+                Some(first) => first
+                    .iter()
+                    .filter(|member| {
+                        return !others
+                            .iter()
+                            .flatten()
+                            .any(|set| return set.contains(member));
+                    })
+                    .map(Cow::into_owned)
+                    .collect(),
+                None => HashSet::new(),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_store;
+    use crate::persistence::{ObjectStore, SetStore};
+
+    fn members(members: &[&str]) -> Vec<String> {
+        return members
+            .iter()
+            .map(|member| return member.to_string())
+            .collect();
+    }
+
+    #[test]
+    fn random_members_are_picked_from_both_encodings() {
+        let mut store = test_store();
+        store.set_add("ints", &members(&["3", "1", "2"])).unwrap();
+        store.set_add("words", &members(&["a", "1", "b"])).unwrap();
+        assert_eq!(store.object("ints").unwrap().unwrap().encoding, "intset");
+        assert_eq!(
+            store.object("words").unwrap().unwrap().encoding,
+            "hashtable"
+        );
+
+        for (key, all) in [("ints", ["1", "2", "3"]), ("words", ["1", "a", "b"])] {
+            let mut picked = store.set_random_members(key, 10).unwrap();
+            picked.sort();
+            assert_eq!(picked, members(&all));
+            let repeated = store.set_random_members(key, -20).unwrap();
+            assert_eq!(repeated.len(), 20);
+            assert!(repeated
+                .iter()
+                .all(|member| return all.contains(&member.as_str())));
+        }
+    }
+
+    #[test]
+    fn random_members_of_a_single_member_set_repeat_it() {
+        let mut store = test_store();
+        store.set_add("s", &members(&["-7"])).unwrap();
+
+        assert_eq!(store.set_random_members("s", 2).unwrap(), members(&["-7"]));
+        assert_eq!(
+            store.set_random_members("s", -3).unwrap(),
+            members(&["-7", "-7", "-7"])
+        );
+        assert!(store.set_random_members("missing", -3).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::random;
+
+const MAX_LEVEL: usize = 32;
+const HEAD: usize = 0;
+
+/// Skiplist ordered by (score, member), the index redis keeps next to the member -> score
+/// dict of a sorted set. Every link also stores its span (how many elements it skips), so
+/// ranks can be computed while descending, making rank lookups O(log n) as well.
+///
+/// Nodes live in an arena and link to each other by index. The head is a sentinel at
+/// index 0, freed slots are reused by later inserts.
+#[derive(Clone)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    level: usize,
+    len: usize,
+    tail: Option<usize>,
+}
+
+#[derive(Clone)]
+struct Node {
+    member: String,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Link>,
+}
+
+#[derive(Clone, Copy)]
+struct Link {
+    forward: Option<usize>,
+    span: usize,
+}
+
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    next: Option<usize>,
+    reverse: bool,
+}
+
+impl SkipList {
+    pub fn new() -> Self {
+        let head = Node {
+            member: String::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![
+                Link {
+                    forward: None,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        return SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            level: 1,
+            len: 0,
+            tail: None,
+        };
+    }
+
+    /// Inserts an element that must not be in the list yet.
+    pub fn insert(&mut self, member: String, score: f64) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.nodes[next].is_before(&member, score) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = self.alloc(Node {
+            member,
+            score,
+            backward: None,
+            levels: vec![
+                Link {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        });
+
+        for i in 0..level {
+            let previous = self.nodes[update[i]].levels[i];
+            self.nodes[node].levels[i] = Link {
+                forward: previous.forward,
+                span: previous.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Link {
+                forward: Some(node),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[previous].levels[i].span += 1;
+        }
+
+        self.nodes[node].backward = if update[0] == HEAD {
+            None
+        } else {
+            Some(update[0])
+        };
+        match self.nodes[node].levels[0].forward {
+            Some(next) => self.nodes[next].backward = Some(node),
+            None => self.tail = Some(node),
+        }
+        self.len += 1;
+    }
+
+    /// Removes the element, returning whether it was found.
+    pub fn remove(&mut self, member: &str, score: f64) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.nodes[next].is_before(member, score) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let node = match self.nodes[x].levels[0].forward {
+            Some(next) if self.nodes[next].score == score && self.nodes[next].member == member => {
+                next
+            }
+            _ => return false,
+        };
+
+        for (i, &previous) in update.iter().enumerate().take(self.level) {
+            if self.nodes[previous].levels[i].forward == Some(node) {
+                let removed = self.nodes[node].levels[i];
+                self.nodes[previous].levels[i] = Link {
+                    forward: removed.forward,
+                    span: self.nodes[previous].levels[i].span + removed.span - 1,
+                };
+            } else {
+                self.nodes[previous].levels[i].span -= 1;
+            }
+        }
+
+        let backward = self.nodes[node].backward;
+        match self.nodes[node].levels[0].forward {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+
+        self.len -= 1;
+        self.release(node);
+        return true;
+    }
+
+    /// 0 based rank of the element, if it is in the list.
+    pub fn rank(&self, member: &str, score: f64) -> Option<usize> {
+        let rank = self.count_while(|m, s| return s < score || (s == score && m < member));
+        return self
+            .node_at(rank)
+            .filter(|&node| return self.nodes[node].member == member)
+            .map(|_| return rank);
+    }
+
+    /// Counts the leading elements for which `is_before` holds. The predicate must hold for
+    /// a prefix of the list only, so it can be used to binary search range boundaries.
+    pub fn count_while(&self, is_before: impl Fn(&str, f64) -> bool) -> usize {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !is_before(&self.nodes[next].member, self.nodes[next].score) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        return rank;
+    }
+
+    /// Iterates from the element at `rank`, towards the tail or towards the head.
+    pub fn iter_from(&self, rank: usize, reverse: bool) -> Iter<'_> {
+        return Iter {
+            list: self,
+            next: self.node_at(rank),
+            reverse,
+        };
+    }
+
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        if rank == self.len - 1 {
+            return self.tail;
+        }
+
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        return None;
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        return match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+    }
+
+    fn release(&mut self, index: usize) {
+        let node = &mut self.nodes[index];
+        node.member = String::new();
+        node.levels = Vec::new();
+        self.free.push(index);
+    }
+}
+
+impl Node {
+    fn is_before(&self, member: &str, score: f64) -> bool {
+        return self.score < score || (self.score == score && self.member.as_str() < member);
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = &self.list.nodes[self.next?];
+        self.next = if self.reverse {
+            node.backward
+        } else {
+            node.levels[0].forward
+        };
+        return Some((&node.member, node.score));
+    }
+}
+
+/// Level of a new node: each extra level is kept with a 1/4 probability, like in redis.
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && random::next_u64() % 4 == 0 {
+        level += 1;
+    }
+    return level;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SkipList;
+    use crate::{prelude::*, random};
+
+    /// Elements in the order the list keeps them, by score then member.
+    fn sorted(elements: &[(String, f64)]) -> Vec<(String, f64)> {
+        let mut sorted = elements.to_vec();
+        sorted.sort_by(|a, b| return a.1.total_cmp(&b.1).then_with(|| return a.0.cmp(&b.0)));
+        return sorted;
+    }
+
+    fn assert_matches(list: &SkipList, expected: &[(String, f64)]) {
+        let forward = list
+            .iter_from(0, false)
+            .map(|(member, score)| return (member.to_string(), score))
+            .collect::<Vec<_>>();
+        assert_eq!(forward, expected);
+
+        let mut backward = match expected.len() {
+            0 => Vec::new(),
+            len => list
+                .iter_from(len - 1, true)
+                .map(|(member, score)| return (member.to_string(), score))
+                .collect::<Vec<_>>(),
+        };
+        backward.reverse();
+        assert_eq!(backward, expected);
+
+        for (rank, (member, score)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(member, *score), Some(rank));
+            assert_eq!(
+                list.iter_from(rank, false).next(),
+                Some((member.as_str(), *score))
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_elements_ordered_by_score_then_member() {
+        let mut list = SkipList::new();
+        let mut elements = Vec::new();
+        for i in 0..500 {
+            // NOTE: few distinct scores, so many ties are broken by member
+            let element = (f!("m{}", i), (random::below(20) as f64) - 10.0);
+            list.insert(element.0.clone(), element.1);
+            elements.push(element);
+        }
+        assert_matches(&list, &sorted(&elements));
+
+        while !elements.is_empty() {
+            let (member, score) = elements.swap_remove(random::below(elements.len()));
+            assert!(list.remove(&member, score));
+            assert!(!list.remove(&member, score));
+            if elements.len() % 50 == 0 {
+                assert_matches(&list, &sorted(&elements));
+            }
+        }
+
+        // NOTE: the freed nodes get reused
+        list.insert("again".into(), 1.0);
+        assert_matches(&list, &[("again".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn handles_missing_elements_and_out_of_range_ranks() {
+        let mut list = SkipList::new();
+        assert_eq!(list.rank("a", 1.0), None);
+        assert_eq!(list.iter_from(0, false).next(), None);
+        assert!(!list.remove("a", 1.0));
+
+        list.insert("a".into(), 1.0);
+        list.insert("b".into(), f64::INFINITY);
+        list.insert("c".into(), f64::NEG_INFINITY);
+        assert_eq!(list.rank("a", 2.0), None);
+        assert_eq!(list.rank("z", 1.0), None);
+        assert!(!list.remove("a", 2.0));
+        assert_eq!(list.iter_from(3, true).next(), None);
+        assert_eq!(list.count_while(|_, score| return score < 1.0), 1);
+        assert_eq!(list.count_while(|_, _| return true), 3);
+    }
+}
@@ -0,0 +1,646 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    listpack::Listpack,
+    live_value, live_value_mut, lock,
+    memory::{sampled_size, COLLECTION_OVERHEAD, ELEMENT_OVERHEAD},
+    sets::Set,
+    skiplist::SkipList,
+    Data, InMemStore, Keyspace, Value,
+};
+use crate::{
+    errors::RedisError,
+    persistence::{
+        format_float, Aggregate, EncodingLimits, LexBound, ScoreEnd, SetOperation, SortedSetStore,
+        ZAddFlags, ZAddOutcome, ZRange,
+    },
+};
+
+/// Forward links and backward pointer of a skiplist node, at the average level.
+const SKIPLIST_LINKS_SIZE: usize = 32;
+
+/// Small sorted sets are a listpack of their members and scores one after the other,
+/// ordered by score then member. Past the `zset-max-listpack-*` limits they become a
+/// member -> score dict for O(1) score lookups plus a skiplist keeping the members ordered
+/// by score for ranks and ranges, the same pairing redis uses.
+#[derive(Clone)]
+pub enum SortedSet {
+    Listpack(Listpack),
+    Skiplist {
+        scores: HashMap<String, f64>,
+        index: SkipList,
+    },
+}
+
+impl SortedSetStore for InMemStore {
+    fn zset_add(
+        &mut self,
+        key: &str,
+        pairs: &[(f64, String)],
+        flags: ZAddFlags,
+    ) -> Result<ZAddOutcome> {
+        let mut store = lock(&self.store);
+
+        // NOTE: checked for reading first, so that changing nothing leaves the watchers alone
+        match sorted_set(&store, key)? {
+            Some(sorted_set) => {
+                let mut outcome = ZAddOutcome::default();
+                let mut changes = false;
+                for (score, member) in pairs {
+                    let current = sorted_set.score(member);
+                    if let Some(score) = zadd_score(current, *score, flags)? {
+                        changes |= current != Some(score);
+                        outcome.score = Some(score);
+                    }
+                }
+                if !changes {
+                    return Ok(outcome);
+                }
+            }
+            None if flags.only_existing => return Ok(ZAddOutcome::default()),
+            None => {}
+        }
+
+        let limits = store.encodings;
+        let sorted_set = sorted_set_or_create(&mut store, key)?;
+        let mut outcome = ZAddOutcome::default();
+        for (score, member) in pairs {
+            let current = sorted_set.score(member);
+            let score = match zadd_score(current, *score, flags)? {
+                Some(score) => score,
+                None => continue,
+            };
+
+            match current {
+                Some(current) if score == current => {}
+                Some(_) => {
+                    sorted_set.insert(member, score, &limits);
+                    outcome.updated += 1;
+                }
+                None => {
+                    sorted_set.insert(member, score, &limits);
+                    outcome.added += 1;
+                }
+            }
+            outcome.score = Some(score);
+        }
+
+        if sorted_set.len() == 0 {
+            store.remove(key);
+        }
+        return Ok(outcome);
+    }
+
+    fn zset_remove(&mut self, key: &str, members: &[String]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        if !sorted_set(&store, key)?.is_some_and(|sorted_set| {
+            return members
+                .iter()
+                .any(|member| return sorted_set.score(member).is_some());
+        }) {
+            return Ok(0);
+        }
+        let sorted_set = match sorted_set_mut(&mut store, key)? {
+            Some(sorted_set) => sorted_set,
+            None => return Ok(0),
+        };
+
+        let removed = members
+            .iter()
+            .filter(|member| return sorted_set.remove(member))
+            .count();
+
+        if sorted_set.len() == 0 {
+            store.remove(key);
+        }
+        return Ok(removed);
+    }
+
+    fn zset_score(&self, key: &str, member: &str) -> Result<Option<f64>> {
+        let store = lock(&self.store);
+        return Ok(sorted_set(&store, key)?.and_then(|sorted_set| return sorted_set.score(member)));
+    }
+
+    fn zset_card(&self, key: &str) -> Result<usize> {
+        let store = lock(&self.store);
+        return Ok(sorted_set(&store, key)?.map_or(0, |sorted_set| return sorted_set.len()));
+    }
+
+    fn zset_rank(&self, key: &str, member: &str, reverse: bool) -> Result<Option<(usize, f64)>> {
+        let store = lock(&self.store);
+        let sorted_set = match sorted_set(&store, key)? {
+            Some(sorted_set) => sorted_set,
+            None => return Ok(None),
+        };
+
+        let score = match sorted_set.score(member) {
+            Some(score) => score,
+            None => return Ok(None),
+        };
+        let rank = sorted_set.rank(member).unwrap();
+        let rank = if reverse {
+            sorted_set.len() - 1 - rank
+        } else {
+            rank
+        };
+        return Ok(Some((rank, score)));
+    }
+
+    fn zset_range(
+        &self,
+        key: &str,
+        range: &ZRange,
+        reverse: bool,
+        limit: Option<(i64, i64)>,
+    ) -> Result<Vec<(String, f64)>> {
+        let store = lock(&self.store);
+        return Ok(match sorted_set(&store, key)? {
+            Some(sorted_set) => sorted_set.range(range, reverse, limit),
+            None => Vec::new(),
+        });
+    }
+
+    fn zset_count(&self, key: &str, range: &ZRange) -> Result<usize> {
+        let store = lock(&self.store);
+        return Ok(match sorted_set(&store, key)? {
+            Some(sorted_set) => {
+                let (first, end) = sorted_set.rank_bounds(range, false);
+                end.saturating_sub(first)
+            }
+            None => 0,
+        });
+    }
+
+    fn zset_pop(&mut self, key: &str, end: ScoreEnd, count: usize) -> Result<Vec<(String, f64)>> {
+        let mut store = lock(&self.store);
+        let sorted_set = match sorted_set_mut(&mut store, key)? {
+            Some(sorted_set) => sorted_set,
+            None => return Ok(Vec::new()),
+        };
+
+        let popped = match end {
+            ScoreEnd::Min => sorted_set.iter_from(0, false),
+            ScoreEnd::Max => sorted_set.iter_from(sorted_set.len() - 1, true),
+        }
+        .take(count)
+        .map(|(member, score)| return (member.to_string(), score))
+        .collect::<Vec<(String, f64)>>();
+
+        for (member, _) in &popped {
+            sorted_set.remove(member);
+        }
+        if sorted_set.len() == 0 {
+            store.remove(key);
+        }
+        return Ok(popped);
+    }
+
+    fn zset_combine_store(
+        &mut self,
+        destination: &str,
+        keys: &[String],
+        weights: &[f64],
+        aggregate: Aggregate,
+        operation: SetOperation,
+    ) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let inputs = keys
+            .iter()
+            .map(|key| return CombineInput::lookup(&store, key))
+            .collect::<Result<Vec<Option<CombineInput>>>>()?;
+
+        let weighted = |score: f64, index: usize| {
+            let score = score * weights.get(index).copied().unwrap_or(1.0);
+            // NOTE: 0 * inf, redis settles it as 0
+            return if score.is_nan() { 0.0 } else { score };
+        };
+
+        let mut result = HashMap::new();
+        match operation {
+            SetOperation::Union => {
+                for (index, input) in inputs.iter().enumerate() {
+                    let input = match input {
+                        Some(input) => input,
+                        None => continue,
+                    };
+                    for (member, score) in input.entries() {
+                        let score = weighted(score, index);
+                        result
+                            .entry(member.to_string())
+                            .and_modify(|current| *current = aggregate.apply(*current, score))
+                            .or_insert(score);
+                    }
+                }
+            }
+            SetOperation::Intersection => {
+                if let Some(inputs) = inputs
+                    .iter()
+                    .map(Option::as_ref)
+                    .collect::<Option<Vec<_>>>()
+                {
+                    let (first, others) = inputs.split_first().unwrap();
+                    'members: for (member, score) in first.entries() {
+                        let mut combined = weighted(score, 0);
+                        for (index, input) in others.iter().enumerate() {
+                            match input.score(&member) {
+                                Some(score) => {
+                                    combined = aggregate.apply(combined, weighted(score, index + 1))
+                                }
+                                None => continue 'members,
+                            }
+                        }
+                        result.insert(member.to_string(), combined);
+                    }
+                }
+            }
+            SetOperation::Difference => {
+                let (first, others) = inputs.split_first().unwrap();
+                if let Some(first) = first {
+                    for (member, score) in first.entries() {
+                        if !others.iter().flatten().any(|input| {
+                            return input.score(&member).is_some();
+                        }) {
+                            result.insert(member.to_string(), weighted(score, 0));
+                        }
+                    }
+                }
+            }
+        }
+
+        return Ok(replace(&mut store, destination, result));
+    }
+
+    fn zset_store(&mut self, destination: &str, pairs: Vec<(String, f64)>) -> Result<usize> {
+        let mut store = lock(&self.store);
+        return Ok(replace(&mut store, destination, pairs));
+    }
+}
+
+impl SortedSet {
+    fn new() -> Self {
+        return SortedSet::Listpack(Listpack::new());
+    }
+
+    pub(super) fn len(&self) -> usize {
+        return match self {
+            SortedSet::Listpack(listpack) => listpack.len() / 2,
+            SortedSet::Skiplist { scores, .. } => scores.len(),
+        };
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        return match self {
+            SortedSet::Listpack(_) => "listpack",
+            SortedSet::Skiplist { .. } => "skiplist",
+        };
+    }
+
+    /// Members of a skiplist are held by both the dict of scores and the skiplist.
+    pub(super) fn memory_usage(&self, samples: usize) -> usize {
+        return COLLECTION_OVERHEAD
+            + match self {
+                SortedSet::Listpack(listpack) => listpack.size(),
+                SortedSet::Skiplist { scores, .. } => {
+                    sampled_size(scores.keys(), scores.len(), samples, |member| {
+                        return 2 * (ELEMENT_OVERHEAD + member.len() + 8) + SKIPLIST_LINKS_SIZE;
+                    })
+                }
+            };
+    }
+
+    fn score(&self, member: &str) -> Option<f64> {
+        return match self {
+            SortedSet::Listpack(listpack) => scored_pairs(listpack)
+                .find(|(current, _)| return *current == member)
+                .map(|(_, score)| return score),
+            SortedSet::Skiplist { scores, .. } => scores.get(member).copied(),
+        };
+    }
+
+    /// Rank of the member, in ascending order.
+    fn rank(&self, member: &str) -> Option<usize> {
+        return match self {
+            SortedSet::Listpack(listpack) => listpack.find_pair(member),
+            SortedSet::Skiplist { scores, index } => scores
+                .get(member)
+                .and_then(|score| return index.rank(member, *score)),
+        };
+    }
+
+    /// Elements from the one at `rank`, towards the highest scores or the lowest ones if
+    /// `reverse`.
+    fn iter_from(&self, rank: usize, reverse: bool) -> Box<dyn Iterator<Item = (&str, f64)> + '_> {
+        return match self {
+            SortedSet::Listpack(listpack) => {
+                let mut elements = scored_pairs(listpack).collect::<Vec<(&str, f64)>>();
+                if reverse {
+                    elements.truncate(rank + 1);
+                    Box::new(elements.into_iter().rev())
+                } else {
+                    Box::new(elements.into_iter().skip(rank))
+                }
+            }
+            SortedSet::Skiplist { index, .. } => Box::new(index.iter_from(rank, reverse)),
+        };
+    }
+
+    /// Counts the leading elements for which `is_before` holds, see `SkipList::count_while`.
+    fn count_while(&self, is_before: impl Fn(&str, f64) -> bool) -> usize {
+        return match self {
+            SortedSet::Listpack(listpack) => scored_pairs(listpack)
+                .take_while(|(member, score)| return is_before(member, *score))
+                .count(),
+            SortedSet::Skiplist { index, .. } => index.count_while(is_before),
+        };
+    }
+
+    /// Adds the member or moves it to its new score.
+    fn insert(&mut self, member: &str, score: f64, limits: &EncodingLimits) {
+        match self {
+            SortedSet::Listpack(listpack) => {
+                if let Some(rank) = listpack.find_pair(member) {
+                    listpack.remove_pair(rank);
+                }
+                let rank = scored_pairs(listpack)
+                    .take_while(|(current, current_score)| {
+                        return *current_score < score
+                            || (*current_score == score && *current < member);
+                    })
+                    .count();
+                listpack.insert(2 * rank, member);
+                listpack.insert(2 * rank + 1, &format_float(score));
+
+                if listpack.len() / 2 > limits.zset_max_listpack_entries
+                    || member.len() > limits.zset_max_listpack_value
+                {
+                    self.convert();
+                }
+            }
+            SortedSet::Skiplist { scores, index } => {
+                if let Some(current) = scores.insert(member.to_string(), score) {
+                    index.remove(member, current);
+                }
+                index.insert(member.to_string(), score);
+            }
+        }
+    }
+
+    fn remove(&mut self, member: &str) -> bool {
+        return match self {
+            SortedSet::Listpack(listpack) => match listpack.find_pair(member) {
+                Some(rank) => {
+                    listpack.remove_pair(rank);
+                    true
+                }
+                None => false,
+            },
+            SortedSet::Skiplist { scores, index } => match scores.remove(member) {
+                Some(score) => index.remove(member, score),
+                None => false,
+            },
+        };
+    }
+
+    /// Moves a listpack over to a dict and a skiplist.
+    fn convert(&mut self) {
+        if let SortedSet::Listpack(listpack) = self {
+            let mut scores = HashMap::new();
+            let mut index = SkipList::new();
+            for (member, score) in scored_pairs(listpack) {
+                scores.insert(member.to_string(), score);
+                index.insert(member.to_string(), score);
+            }
+            *self = SortedSet::Skiplist { scores, index };
+        }
+    }
+
+    /// Ranks (in ascending order) of the elements within the range, as a half open
+    /// `first..end` interval.
+    fn rank_bounds(&self, range: &ZRange, reverse: bool) -> (usize, usize) {
+        let len = self.len() as i64;
+        return match range {
+            ZRange::Rank { start, stop } => {
+                let start = if *start < 0 { len + start } else { *start }.max(0);
+                let stop = if *stop < 0 { len + stop } else { *stop }.min(len - 1);
+                if start > stop {
+                    return (0, 0);
+                }
+                if reverse {
+                    ((len - 1 - stop) as usize, (len - start) as usize)
+                } else {
+                    (start as usize, stop as usize + 1)
+                }
+            }
+            ZRange::Score { min, max } => {
+                let first = self.count_while(|_, score| {
+                    return score < min.value || (min.exclusive && score == min.value);
+                });
+                let end = self.count_while(|_, score| {
+                    return score < max.value || (!max.exclusive && score == max.value);
+                });
+                (first, end)
+            }
+            ZRange::Lex { min, max } => {
+                let first = self.count_while(|member, _| {
+                    return match min {
+                        LexBound::NegativeInfinity => false,
+                        LexBound::PositiveInfinity => true,
+                        LexBound::Inclusive(min) => member < min.as_str(),
+                        LexBound::Exclusive(min) => member <= min.as_str(),
+                    };
+                });
+                let end = self.count_while(|member, _| {
+                    return match max {
+                        LexBound::NegativeInfinity => false,
+                        LexBound::PositiveInfinity => true,
+                        LexBound::Inclusive(max) => member <= max.as_str(),
+                        LexBound::Exclusive(max) => member < max.as_str(),
+                    };
+                });
+                (first, end)
+            }
+        };
+    }
+
+    fn range(
+        &self,
+        range: &ZRange,
+        reverse: bool,
+        limit: Option<(i64, i64)>,
+    ) -> Vec<(String, f64)> {
+        let (first, end) = self.rank_bounds(range, reverse);
+        if first >= end {
+            return Vec::new();
+        }
+
+        let (offset, count) = limit.unwrap_or((0, -1));
+        if offset < 0 {
+            return Vec::new();
+        }
+        let count = if count < 0 {
+            usize::MAX
+        } else {
+            count as usize
+        };
+
+        let elements = if reverse {
+            self.iter_from(end - 1, true)
+        } else {
+            self.iter_from(first, false)
+        };
+        return elements
+            .take(end - first)
+            .skip(offset as usize)
+            .take(count)
+            .map(|(member, score)| return (member.to_string(), score))
+            .collect();
+    }
+}
+
+impl Aggregate {
+    fn apply(&self, current: f64, score: f64) -> f64 {
+        return match self {
+            Aggregate::Sum => {
+                let sum = current + score;
+                // NOTE: inf + -inf, redis settles it as 0
+                if sum.is_nan() {
+                    0.0
+                } else {
+                    sum
+                }
+            }
+            Aggregate::Min => current.min(score),
+            Aggregate::Max => current.max(score),
+        };
+    }
+}
+
+/// Score ZADD leaves a member with given its `current` one, `None` when the flags skip it.
+fn zadd_score(current: Option<f64>, score: f64, flags: ZAddFlags) -> Result<Option<f64>> {
+    if (flags.only_missing && current.is_some()) || (flags.only_existing && current.is_none()) {
+        return Ok(None);
+    }
+
+    let score = match current {
+        Some(current) if flags.increment => current + score,
+        _ => score,
+    };
+    if score.is_nan() {
+        return Err(anyhow!(RedisError::Generic(
+            "resulting score is not a number (NaN)".into()
+        )));
+    }
+
+    if current.is_some_and(|current| {
+        return (flags.only_greater && score <= current) || (flags.only_less && score >= current);
+    }) {
+        return Ok(None);
+    }
+    return Ok(Some(score));
+}
+
+/// ZUNIONSTORE and ZINTERSTORE also accept plain sets, whose members all score 1.
+enum CombineInput<'a> {
+    Set(&'a Set),
+    SortedSet(&'a SortedSet),
+}
+
+impl<'a> CombineInput<'a> {
+    fn lookup(store: &'a Keyspace, key: &str) -> Result<Option<Self>> {
+        return match live_value(store, key) {
+            Some(Value {
+                data: Data::Set(set),
+                ..
+            }) => Ok(Some(CombineInput::Set(set))),
+            Some(Value {
+                data: Data::SortedSet(sorted_set),
+                ..
+            }) => Ok(Some(CombineInput::SortedSet(sorted_set))),
+            Some(_) => Err(RedisError::WrongType.into()),
+            None => Ok(None),
+        };
+    }
+
+    fn entries(&self) -> Vec<(Cow<'a, str>, f64)> {
+        return match self {
+            CombineInput::Set(set) => set.iter().map(|m| return (m, 1.0)).collect(),
+            CombineInput::SortedSet(sorted_set) => sorted_set
+                .iter_from(0, false)
+                .map(|(member, score)| return (Cow::Borrowed(member), score))
+                .collect(),
+        };
+    }
+
+    fn score(&self, member: &str) -> Option<f64> {
+        return match self {
+            CombineInput::Set(set) => set.contains(member).then_some(1.0),
+            CombineInput::SortedSet(sorted_set) => sorted_set.score(member),
+        };
+    }
+}
+
+fn sorted_set<'a>(store: &'a Keyspace, key: &str) -> Result<Option<&'a SortedSet>> {
+    return match live_value(store, key) {
+        Some(Value {
+            data: Data::SortedSet(sorted_set),
+            ..
+        }) => Ok(Some(sorted_set)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn sorted_set_mut<'a>(store: &'a mut Keyspace, key: &str) -> Result<Option<&'a mut SortedSet>> {
+    return match live_value_mut(store, key) {
+        Some(Value {
+            data: Data::SortedSet(sorted_set),
+            ..
+        }) => Ok(Some(sorted_set)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn sorted_set_or_create<'a>(store: &'a mut Keyspace, key: &str) -> Result<&'a mut SortedSet> {
+    if sorted_set(store, key)?.is_none() {
+        store.insert(
+            key.to_string(),
+            Value::new(Data::SortedSet(SortedSet::new())),
+        );
+    }
+    return Ok(sorted_set_mut(store, key)?.unwrap());
+}
+
+/// Overwrites `key` with a sorted set of the pairs, or deletes it when there are none.
+/// Returns the size of the new sorted set.
+fn replace(
+    store: &mut Keyspace,
+    key: &str,
+    pairs: impl IntoIterator<Item = (String, f64)>,
+) -> usize {
+    let limits = store.encodings;
+    let mut sorted_set = SortedSet::new();
+    for (member, score) in pairs {
+        sorted_set.insert(&member, score, &limits);
+    }
+
+    let len = sorted_set.len();
+    if len == 0 {
+        store.remove(key);
+    } else {
+        store.insert(key.to_string(), Value::new(Data::SortedSet(sorted_set)));
+    }
+    return len;
+}
+
+/// Members of a listpack with their scores read back.
+fn scored_pairs(listpack: &Listpack) -> impl Iterator<Item = (&str, f64)> {
+    return listpack.pairs().map(|(member, score)| {
+        return (
+            member,
+            score.parse::<f64>().expect("Listpack scores are floats"),
+        );
+    });
+}
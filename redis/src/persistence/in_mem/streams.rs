@@ -0,0 +1,312 @@
+mod groups;
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+
+use groups::ConsumerGroup;
+
+use super::{
+    current_timestamp, live_value, live_value_mut, lock,
+    memory::{sampled_size, COLLECTION_OVERHEAD, ELEMENT_OVERHEAD},
+    Data, InMemStore, Keyspace, Value,
+};
+use crate::{
+    errors::RedisError,
+    persistence::{StreamEntry, StreamId, StreamStore, StreamTrim, TrimStrategy, XAddId},
+};
+
+/// Entries per radix tree node in redis (`stream-node-max-entries`), the granularity of
+/// approximate trimming.
+const NODE_MAX_ENTRIES: usize = 100;
+
+/// Bytes of an entry ID.
+const STREAM_ID_SIZE: usize = 16;
+
+/// Unlike the other collections, a stream stays around once emptied: its last ID must not
+/// go backwards.
+#[derive(Clone)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Vec<(String, String)>>,
+    last_id: StreamId,
+    /// Greatest ID removed by XDEL, tells whether consumer group lags can still be computed.
+    max_deleted_id: StreamId,
+    /// Entries ever added, the logical position of the last entry.
+    entries_added: u64,
+    groups: BTreeMap<String, ConsumerGroup>,
+}
+
+impl StreamStore for InMemStore {
+    fn stream_add(
+        &mut self,
+        key: &str,
+        id: XAddId,
+        fields: Vec<(String, String)>,
+        make_stream: bool,
+        trim: Option<StreamTrim>,
+    ) -> Result<Option<StreamId>> {
+        let mut store = lock(&self.store);
+
+        let last_id = match stream(&store, key)? {
+            Some(stream) => stream.last_id,
+            None if !make_stream => return Ok(None),
+            None => StreamId::MIN,
+        };
+        let id = next_id(last_id, id)?;
+
+        let stream = stream_or_create(&mut store, key)?;
+        stream.entries.insert(id, fields);
+        stream.last_id = id;
+        stream.entries_added += 1;
+        if let Some(trim) = trim {
+            stream.trim(trim);
+        }
+        return Ok(Some(id));
+    }
+
+    fn stream_len(&self, key: &str) -> Result<usize> {
+        let store = lock(&self.store);
+        return Ok(stream(&store, key)?.map_or(0, |stream| return stream.entries.len()));
+    }
+
+    fn stream_last_id(&self, key: &str) -> Result<Option<StreamId>> {
+        let store = lock(&self.store);
+        return Ok(stream(&store, key)?.map(|stream| return stream.last_id));
+    }
+
+    fn stream_range(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<StreamEntry>> {
+        let store = lock(&self.store);
+        let stream = match stream(&store, key)? {
+            Some(stream) => stream,
+            None => return Ok(Vec::new()),
+        };
+        if start > end {
+            return Ok(Vec::new());
+        }
+
+        let range = stream.entries.range(start..=end);
+        let entries: Box<dyn Iterator<Item = _>> = if reverse {
+            Box::new(range.rev())
+        } else {
+            Box::new(range)
+        };
+        return Ok(entries
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| return (*id, fields.clone()))
+            .collect());
+    }
+
+    fn stream_trim(&mut self, key: &str, trim: StreamTrim) -> Result<usize> {
+        let mut store = lock(&self.store);
+        // NOTE: checked for reading first, trimming nothing must not touch the watchers of the key
+        if !stream(&store, key)?.is_some_and(|stream| return stream.removable(&trim) > 0) {
+            return Ok(0);
+        }
+        return Ok(match stream_mut(&mut store, key)? {
+            Some(stream) => stream.trim(trim),
+            None => 0,
+        });
+    }
+
+    fn stream_delete(&mut self, key: &str, ids: &[StreamId]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        if !stream(&store, key)?.is_some_and(|stream| {
+            return ids.iter().any(|id| return stream.entries.contains_key(id));
+        }) {
+            return Ok(0);
+        }
+        let stream = match stream_mut(&mut store, key)? {
+            Some(stream) => stream,
+            None => return Ok(0),
+        };
+
+        let mut deleted = 0;
+        for id in ids {
+            if stream.entries.remove(id).is_some() {
+                stream.max_deleted_id = stream.max_deleted_id.max(*id);
+                deleted += 1;
+            }
+        }
+        return Ok(deleted);
+    }
+}
+
+impl Stream {
+    fn new() -> Self {
+        return Stream {
+            entries: BTreeMap::new(),
+            last_id: StreamId::MIN,
+            max_deleted_id: StreamId::MIN,
+            entries_added: 0,
+            groups: BTreeMap::new(),
+        };
+    }
+
+    pub(super) fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    /// Entries are extrapolated from a sample, consumer groups are all accounted for.
+    pub(super) fn memory_usage(&self, samples: usize) -> usize {
+        let entries = sampled_size(self.entries.values(), self.len(), samples, |fields| {
+            return STREAM_ID_SIZE
+                + ELEMENT_OVERHEAD
+                + fields
+                    .iter()
+                    .map(|(field, value)| return 2 * ELEMENT_OVERHEAD + field.len() + value.len())
+                    .sum::<usize>();
+        });
+        let groups = self
+            .groups
+            .iter()
+            .map(|(name, group)| return name.len() + group.memory_usage())
+            .sum::<usize>();
+        return COLLECTION_OVERHEAD + entries + groups;
+    }
+
+    fn first_id(&self) -> StreamId {
+        return self
+            .entries
+            .first_key_value()
+            .map_or(StreamId::MIN, |(id, _)| return *id);
+    }
+
+    /// Whether an entry in `start..` was deleted, making logical positions unreliable.
+    fn has_tombstones_from(&self, start: StreamId) -> bool {
+        if self.entries.is_empty() || self.max_deleted_id == StreamId::MIN {
+            return false;
+        }
+        return start <= self.max_deleted_id;
+    }
+
+    /// Logical position of `id` (how many entries were added up to it), when it can be told
+    /// from the counters alone.
+    fn estimate_entries_read(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.entries.is_empty() && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        if id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if id > self.last_id {
+            return None;
+        }
+
+        let first_id = self.first_id();
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            let before_first = self.entries_added - self.entries.len() as u64;
+            if id < first_id {
+                return Some(before_first);
+            }
+            if id == first_id {
+                return Some(before_first + 1);
+            }
+        }
+        return None;
+    }
+
+    fn trim(&mut self, trim: StreamTrim) -> usize {
+        let removable = self.removable(&trim);
+        for _ in 0..removable {
+            self.entries.pop_first();
+        }
+        return removable;
+    }
+
+    /// Entries `trim` would remove, the oldest ones.
+    fn removable(&self, trim: &StreamTrim) -> usize {
+        let mut removable = match trim.strategy {
+            TrimStrategy::MaxLen(max_len) => self.entries.len().saturating_sub(max_len),
+            TrimStrategy::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+
+        if trim.approximate {
+            let limit = match trim.limit {
+                Some(0) => usize::MAX,
+                Some(limit) => limit,
+                None => NODE_MAX_ENTRIES * 100,
+            };
+            removable = removable.min(limit);
+            removable -= removable % NODE_MAX_ENTRIES;
+        }
+        return removable;
+    }
+}
+
+/// Resolves the ID of a new entry, which must be greater than every ID the stream has seen.
+fn next_id(last_id: StreamId, id: XAddId) -> Result<StreamId> {
+    let id = match id {
+        XAddId::Auto => {
+            let now = current_timestamp() as u64;
+            if now > last_id.ms {
+                StreamId { ms: now, seq: 0 }
+            } else {
+                last_id.next().ok_or(RedisError::Generic(
+                    "The stream has exhausted the last possible ID, unable to add more items"
+                        .into(),
+                ))?
+            }
+        }
+        XAddId::AutoSequence(ms) if ms == last_id.ms => match last_id.seq.checked_add(1) {
+            Some(seq) => StreamId { ms, seq },
+            None => return Err(anyhow!(id_too_small())),
+        },
+        XAddId::AutoSequence(ms) => StreamId { ms, seq: 0 },
+        XAddId::Explicit(id) => id,
+    };
+
+    if id == StreamId::MIN {
+        return Err(anyhow!(RedisError::Generic(
+            "The ID specified in XADD must be greater than 0-0".into()
+        )));
+    }
+    if id <= last_id {
+        return Err(anyhow!(id_too_small()));
+    }
+    return Ok(id);
+}
+
+fn id_too_small() -> RedisError {
+    return RedisError::Generic(
+        "The ID specified in XADD is equal or smaller than the target stream top item".into(),
+    );
+}
+
+fn stream<'a>(store: &'a Keyspace, key: &str) -> Result<Option<&'a Stream>> {
+    return match live_value(store, key) {
+        Some(Value {
+            data: Data::Stream(stream),
+            ..
+        }) => Ok(Some(stream)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn stream_mut<'a>(store: &'a mut Keyspace, key: &str) -> Result<Option<&'a mut Stream>> {
+    return match live_value_mut(store, key) {
+        Some(Value {
+            data: Data::Stream(stream),
+            ..
+        }) => Ok(Some(stream)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+fn stream_or_create<'a>(store: &'a mut Keyspace, key: &str) -> Result<&'a mut Stream> {
+    if stream(store, key)?.is_none() {
+        store.insert(key.to_string(), Value::new(Data::Stream(Stream::new())));
+    }
+    return Ok(stream_mut(store, key)?.unwrap());
+}
@@ -0,0 +1,625 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{
+        Bound::{Excluded, Unbounded},
+        RangeInclusive,
+    },
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    stream, stream_mut, stream_or_create, Stream, COLLECTION_OVERHEAD, NODE_MAX_ENTRIES,
+    STREAM_ID_SIZE,
+};
+use crate::{
+    errors::RedisError,
+    persistence::{
+        current_timestamp,
+        in_mem::{lock, InMemStore, Keyspace},
+        Claim, ClaimOptions, ClaimOutcome, ConsumerInfo, GroupInfo, GroupRead, GroupStartId,
+        PendingInfo, PendingSummary, StreamGroupStore, StreamId, StreamInfo,
+    },
+    prelude::*,
+};
+
+/// XAUTOCLAIM looks at up to `COUNT * AUTOCLAIM_ATTEMPTS_FACTOR` pending entries per call.
+const AUTOCLAIM_ATTEMPTS_FACTOR: usize = 10;
+
+/// Bytes of an entry of the PEL: its ID, consumer and delivery metadata.
+const PENDING_ENTRY_SIZE: usize = 64;
+
+/// A group keeps the pending entries list (PEL) of everything delivered but not acked yet,
+/// each consumer keeps the IDs it owns out of it.
+#[derive(Clone)]
+pub struct ConsumerGroup {
+    last_delivered: StreamId,
+    /// Logical position of `last_delivered` in the stream, `None` when it is unknown.
+    entries_read: Option<u64>,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<String, Consumer>,
+}
+
+#[derive(Clone)]
+struct PendingEntry {
+    consumer: String,
+    delivery_time: u128,
+    delivery_count: u64,
+}
+
+#[derive(Clone)]
+struct Consumer {
+    /// Last time the consumer tried to read or claim.
+    seen_time: u128,
+    /// Last time it actually got entries.
+    active_time: Option<u128>,
+    pending: BTreeSet<StreamId>,
+}
+
+impl StreamGroupStore for InMemStore {
+    fn stream_group_create(
+        &mut self,
+        key: &str,
+        group: &str,
+        start: GroupStartId,
+        make_stream: bool,
+        entries_read: Option<u64>,
+    ) -> Result<()> {
+        let mut store = lock(&self.store);
+        let stream = if make_stream {
+            stream_or_create(&mut store, key)?
+        } else {
+            stream_mut(&mut store, key)?.ok_or_else(key_required)?
+        };
+
+        if stream.groups.contains_key(group) {
+            return Err(anyhow!(RedisError::BusyGroup));
+        }
+        let last_delivered = stream.resolve_start(start);
+        stream.groups.insert(
+            group.to_string(),
+            ConsumerGroup::new(last_delivered, entries_read),
+        );
+        return Ok(());
+    }
+
+    fn stream_group_set_id(
+        &mut self,
+        key: &str,
+        group: &str,
+        start: GroupStartId,
+        entries_read: Option<u64>,
+    ) -> Result<()> {
+        let mut store = lock(&self.store);
+        let stream = xgroup_target(&mut store, key, group)?;
+        let last_delivered = stream.resolve_start(start);
+
+        let group = stream.groups.get_mut(group).unwrap();
+        group.last_delivered = last_delivered;
+        group.entries_read = entries_read;
+        return Ok(());
+    }
+
+    fn stream_group_destroy(&mut self, key: &str, group: &str) -> Result<bool> {
+        let mut store = lock(&self.store);
+        let stream = stream_mut(&mut store, key)?.ok_or_else(key_required)?;
+        return Ok(stream.groups.remove(group).is_some());
+    }
+
+    fn stream_group_exists(&self, key: &str, group: &str) -> Result<bool> {
+        let store = lock(&self.store);
+        return Ok(
+            stream(&store, key)?.is_some_and(|stream| return stream.groups.contains_key(group))
+        );
+    }
+
+    fn stream_consumer_create(&mut self, key: &str, group: &str, consumer: &str) -> Result<bool> {
+        let mut store = lock(&self.store);
+        let stream = xgroup_target(&mut store, key, group)?;
+        let group = stream.groups.get_mut(group).unwrap();
+        return Ok(group.ensure_consumer(consumer, current_timestamp()));
+    }
+
+    fn stream_consumer_delete(&mut self, key: &str, group: &str, consumer: &str) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let stream = xgroup_target(&mut store, key, group)?;
+        let group = stream.groups.get_mut(group).unwrap();
+
+        let consumer = match group.consumers.remove(consumer) {
+            Some(consumer) => consumer,
+            None => return Ok(0),
+        };
+        for id in &consumer.pending {
+            group.pending.remove(id);
+        }
+        return Ok(consumer.pending.len());
+    }
+
+    fn stream_group_read(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        after: Option<StreamId>,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Result<GroupRead> {
+        let now = current_timestamp();
+        let mut store = lock(&self.store);
+        let stream = group_target(&mut store, key, group)?;
+        let count = count.unwrap_or(usize::MAX);
+
+        if let Some(after) = after {
+            let group = stream.groups.get_mut(group).unwrap();
+            let created_consumer = group.ensure_consumer(consumer, now);
+
+            let consumer = group.consumers.get(consumer).unwrap();
+            let ids: Vec<StreamId> = consumer
+                .pending
+                .range((Excluded(after), Unbounded))
+                .take(count)
+                .copied()
+                .collect();
+
+            let mut entries = Vec::with_capacity(ids.len());
+            for id in ids {
+                let fields = stream.entries.get(&id).cloned();
+                if fields.is_some() {
+                    let pending = group.pending.get_mut(&id).unwrap();
+                    pending.delivery_time = now;
+                    pending.delivery_count += 1;
+                }
+                entries.push((id, fields));
+            }
+            return Ok(GroupRead {
+                entries,
+                created_consumer,
+                last_delivered: None,
+                delivered_at: now,
+            });
+        }
+
+        let (last_delivered, mut entries_read) = {
+            let group = &stream.groups[group];
+            (group.last_delivered, group.entries_read)
+        };
+        let delivered: Vec<(StreamId, Vec<(String, String)>)> = stream
+            .entries
+            .range((Excluded(last_delivered), Unbounded))
+            .take(count)
+            .map(|(id, fields)| return (*id, fields.clone()))
+            .collect();
+        for (id, _) in &delivered {
+            entries_read = match entries_read {
+                Some(read) if !stream.has_tombstones_from(*id) => Some(read + 1),
+                _ => stream.estimate_entries_read(*id),
+            };
+        }
+
+        let group = stream.groups.get_mut(group).unwrap();
+        let created_consumer = group.ensure_consumer(consumer, now);
+        let last_delivered = match delivered.last() {
+            Some((id, _)) => {
+                group.last_delivered = *id;
+                group.entries_read = entries_read;
+                group.consumers.get_mut(consumer).unwrap().active_time = Some(now);
+                Some((*id, entries_read))
+            }
+            None => None,
+        };
+        if !no_ack {
+            for (id, _) in &delivered {
+                group.assign(*id, consumer, now, 1);
+            }
+        }
+
+        return Ok(GroupRead {
+            entries: delivered
+                .into_iter()
+                .map(|(id, fields)| return (id, Some(fields)))
+                .collect(),
+            created_consumer,
+            last_delivered,
+            delivered_at: now,
+        });
+    }
+
+    fn stream_ack(&mut self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let group = match stream_mut(&mut store, key)? {
+            Some(stream) => match stream.groups.get_mut(group) {
+                Some(group) => group,
+                None => return Ok(0),
+            },
+            None => return Ok(0),
+        };
+        return Ok(ids.iter().filter(|id| return group.ack(**id)).count());
+    }
+
+    fn stream_pending_summary(&self, key: &str, group: &str) -> Result<PendingSummary> {
+        let store = lock(&self.store);
+        let group = stream(&store, key)?
+            .and_then(|stream| return stream.groups.get(group))
+            .ok_or_else(|| return no_such_group(key, group))?;
+
+        let bounds = match (
+            group.pending.keys().next(),
+            group.pending.keys().next_back(),
+        ) {
+            (Some(min), Some(max)) => Some((*min, *max)),
+            _ => None,
+        };
+        return Ok(PendingSummary {
+            count: group.pending.len(),
+            bounds,
+            consumers: group
+                .consumers
+                .iter()
+                .filter(|(_, consumer)| return !consumer.pending.is_empty())
+                .map(|(name, consumer)| return (name.clone(), consumer.pending.len()))
+                .collect(),
+        });
+    }
+
+    fn stream_pending(
+        &self,
+        key: &str,
+        group: &str,
+        min_idle: u128,
+        ids: RangeInclusive<StreamId>,
+        count: usize,
+        consumer: Option<&str>,
+    ) -> Result<Vec<PendingInfo>> {
+        let now = current_timestamp();
+        let store = lock(&self.store);
+        let group = stream(&store, key)?
+            .and_then(|stream| return stream.groups.get(group))
+            .ok_or_else(|| return no_such_group(key, group))?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        return Ok(group
+            .pending
+            .range(ids)
+            .filter(|(_, pending)| {
+                return consumer.map_or(true, |name| return pending.consumer == name);
+            })
+            .map(|(id, pending)| {
+                return PendingInfo {
+                    id: *id,
+                    consumer: pending.consumer.clone(),
+                    idle: now.saturating_sub(pending.delivery_time),
+                    deliveries: pending.delivery_count,
+                };
+            })
+            .filter(|info| return info.idle >= min_idle)
+            .take(count)
+            .collect());
+    }
+
+    fn stream_claim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u128,
+        ids: &[StreamId],
+        options: ClaimOptions,
+    ) -> Result<ClaimOutcome> {
+        let now = current_timestamp();
+        let mut store = lock(&self.store);
+        let stream = group_target(&mut store, key, group)?;
+        let group = stream.groups.get_mut(group).unwrap();
+
+        let delivery_time = match (options.time, options.idle) {
+            (Some(time), _) => time.min(now),
+            (None, Some(idle)) => now.saturating_sub(idle),
+            (None, None) => now,
+        };
+        group.ensure_consumer(consumer, now);
+
+        let mut outcome = ClaimOutcome::default();
+        if let Some(last_id) = options
+            .last_id
+            .filter(|id| return *id > group.last_delivered)
+        {
+            group.last_delivered = last_id;
+            outcome.last_delivered = Some((last_id, group.entries_read));
+        }
+        for &id in ids {
+            let fields = stream.entries.get(&id);
+            let previous_count = match (group.pending.get(&id), fields) {
+                (Some(_), None) => {
+                    group.ack(id);
+                    outcome.deleted.push(id);
+                    continue;
+                }
+                (Some(pending), Some(_)) => {
+                    if now.saturating_sub(pending.delivery_time) < min_idle {
+                        continue;
+                    }
+                    pending.delivery_count
+                }
+                (None, Some(_)) if options.force && min_idle == 0 => 1,
+                (None, _) => continue,
+            };
+
+            let delivery_count = match options.retry_count {
+                Some(retry_count) => retry_count,
+                None if options.just_id => previous_count,
+                None => previous_count + 1,
+            };
+            group.assign(id, consumer, delivery_time, delivery_count);
+            outcome.claimed.push(Claim {
+                entry: (id, fields.unwrap().clone()),
+                delivery_time,
+                delivery_count,
+            });
+        }
+
+        if !outcome.claimed.is_empty() {
+            group.consumers.get_mut(consumer).unwrap().active_time = Some(now);
+        }
+        return Ok(outcome);
+    }
+
+    fn stream_auto_claim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u128,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    ) -> Result<(ClaimOutcome, StreamId)> {
+        let now = current_timestamp();
+        let mut store = lock(&self.store);
+        let stream = group_target(&mut store, key, group)?;
+        let group = stream.groups.get_mut(group).unwrap();
+        group.ensure_consumer(consumer, now);
+
+        let mut outcome = ClaimOutcome::default();
+        let mut attempts = count.saturating_mul(AUTOCLAIM_ATTEMPTS_FACTOR);
+        let mut cursor = Some(start);
+        while let Some(from) = cursor {
+            if attempts == 0 || outcome.claimed.len() == count {
+                break;
+            }
+            let (id, pending) = match group.pending.range(from..).next() {
+                Some((id, pending)) => (*id, pending),
+                None => {
+                    cursor = None;
+                    break;
+                }
+            };
+            attempts -= 1;
+            cursor = id.next();
+
+            let fields = match stream.entries.get(&id) {
+                Some(fields) => fields,
+                None => {
+                    group.ack(id);
+                    outcome.deleted.push(id);
+                    continue;
+                }
+            };
+            if now.saturating_sub(pending.delivery_time) < min_idle {
+                continue;
+            }
+
+            let delivery_count = if just_id {
+                pending.delivery_count
+            } else {
+                pending.delivery_count + 1
+            };
+            group.assign(id, consumer, now, delivery_count);
+            outcome.claimed.push(Claim {
+                entry: (id, fields.clone()),
+                delivery_time: now,
+                delivery_count,
+            });
+        }
+
+        if !outcome.claimed.is_empty() {
+            group.consumers.get_mut(consumer).unwrap().active_time = Some(now);
+        }
+        let next = cursor
+            .and_then(|from| return group.pending.range(from..).next())
+            .map_or(StreamId::MIN, |(id, _)| return *id);
+        return Ok((outcome, next));
+    }
+
+    fn stream_info(&self, key: &str) -> Result<StreamInfo> {
+        let store = lock(&self.store);
+        let stream = stream(&store, key)?.ok_or_else(no_such_key)?;
+
+        let entry =
+            |(id, fields): (&StreamId, &Vec<(String, String)>)| return (*id, fields.clone());
+        let radix_tree_keys = stream.entries.len().div_ceil(NODE_MAX_ENTRIES);
+        return Ok(StreamInfo {
+            length: stream.entries.len(),
+            radix_tree_keys,
+            radix_tree_nodes: radix_tree_keys + 1,
+            last_generated_id: stream.last_id,
+            max_deleted_id: stream.max_deleted_id,
+            entries_added: stream.entries_added,
+            recorded_first_id: stream.first_id(),
+            groups: stream.groups.len(),
+            first_entry: stream.entries.first_key_value().map(entry),
+            last_entry: stream.entries.last_key_value().map(entry),
+        });
+    }
+
+    fn stream_groups_info(&self, key: &str) -> Result<Vec<GroupInfo>> {
+        let store = lock(&self.store);
+        let stream = stream(&store, key)?.ok_or_else(no_such_key)?;
+
+        return Ok(stream
+            .groups
+            .iter()
+            .map(|(name, group)| {
+                return GroupInfo {
+                    name: name.clone(),
+                    consumers: group.consumers.len(),
+                    pending: group.pending.len(),
+                    last_delivered_id: group.last_delivered,
+                    entries_read: group.entries_read,
+                    lag: stream.lag(group),
+                };
+            })
+            .collect());
+    }
+
+    fn stream_consumers_info(&self, key: &str, group: &str) -> Result<Vec<ConsumerInfo>> {
+        let now = current_timestamp();
+        let store = lock(&self.store);
+        let stream = stream(&store, key)?.ok_or_else(no_such_key)?;
+        let consumers = match stream.groups.get(group) {
+            Some(group) => &group.consumers,
+            None => return Err(anyhow!(missing_group(key, group))),
+        };
+
+        return Ok(consumers
+            .iter()
+            .map(|(name, consumer)| {
+                return ConsumerInfo {
+                    name: name.clone(),
+                    pending: consumer.pending.len(),
+                    idle: now.saturating_sub(consumer.seen_time),
+                    inactive: consumer
+                        .active_time
+                        .map(|active| return now.saturating_sub(active)),
+                };
+            })
+            .collect());
+    }
+}
+
+impl Stream {
+    fn resolve_start(&self, start: GroupStartId) -> StreamId {
+        return match start {
+            GroupStartId::LastEntry => self.last_id,
+            GroupStartId::Id(id) => id,
+        };
+    }
+
+    /// How many entries the group has yet to read, when it can be known.
+    fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_from(group.last_delivered) => Some(read),
+            _ => self.estimate_entries_read(group.last_delivered),
+        };
+        return entries_read.map(|read| return self.entries_added.saturating_sub(read));
+    }
+}
+
+impl ConsumerGroup {
+    fn new(last_delivered: StreamId, entries_read: Option<u64>) -> Self {
+        return ConsumerGroup {
+            last_delivered,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        };
+    }
+
+    /// Bytes taken by the group itself, its PEL and its consumers.
+    pub(super) fn memory_usage(&self) -> usize {
+        let consumers = self
+            .consumers
+            .iter()
+            .map(|(name, consumer)| {
+                return COLLECTION_OVERHEAD + name.len() + consumer.pending.len() * STREAM_ID_SIZE;
+            })
+            .sum::<usize>();
+        return COLLECTION_OVERHEAD + self.pending.len() * PENDING_ENTRY_SIZE + consumers;
+    }
+
+    /// Marks the consumer as seen, creating it if needed. Returns whether it was created.
+    fn ensure_consumer(&mut self, name: &str, now: u128) -> bool {
+        if let Some(consumer) = self.consumers.get_mut(name) {
+            consumer.seen_time = now;
+            return false;
+        }
+        self.consumers.insert(
+            name.to_string(),
+            Consumer {
+                seen_time: now,
+                active_time: None,
+                pending: BTreeSet::new(),
+            },
+        );
+        return true;
+    }
+
+    /// Makes `consumer` (which must exist) the owner of a pending entry.
+    fn assign(&mut self, id: StreamId, consumer: &str, delivery_time: u128, delivery_count: u64) {
+        let previous = self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_time,
+                delivery_count,
+            },
+        );
+        if let Some(previous) = previous {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.consumers.get_mut(consumer).unwrap().pending.insert(id);
+    }
+
+    fn ack(&mut self, id: StreamId) -> bool {
+        let pending = match self.pending.remove(&id) {
+            Some(pending) => pending,
+            None => return false,
+        };
+        if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+            owner.pending.remove(&id);
+        }
+        return true;
+    }
+}
+
+/// Stream holding the group, as required by XREADGROUP, XPENDING, XCLAIM and XAUTOCLAIM.
+fn group_target<'a>(store: &'a mut Keyspace, key: &str, group: &str) -> Result<&'a mut Stream> {
+    return match stream_mut(store, key)? {
+        Some(stream) if stream.groups.contains_key(group) => Ok(stream),
+        _ => Err(anyhow!(no_such_group(key, group))),
+    };
+}
+
+/// Same as `group_target` for XGROUP, which tells a missing key from a missing group.
+fn xgroup_target<'a>(store: &'a mut Keyspace, key: &str, group: &str) -> Result<&'a mut Stream> {
+    let stream = stream_mut(store, key)?.ok_or_else(key_required)?;
+    if !stream.groups.contains_key(group) {
+        return Err(anyhow!(missing_group(key, group)));
+    }
+    return Ok(stream);
+}
+
+fn no_such_group(key: &str, group: &str) -> RedisError {
+    return RedisError::NoGroup(f!("No such key '{key}' or consumer group '{group}'"));
+}
+
+fn missing_group(key: &str, group: &str) -> RedisError {
+    return RedisError::NoGroup(f!("No such consumer group '{group}' for key name '{key}'"));
+}
+
+fn key_required() -> RedisError {
+    return RedisError::Generic(
+        "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to \
+         use the MKSTREAM option to create an empty stream automatically."
+            .into(),
+    );
+}
+
+fn no_such_key() -> RedisError {
+    return RedisError::Generic("no such key".into());
+}
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, Result};
+
+use super::{live_value, live_value_mut, lock, Data, InMemStore, Keyspace, Value};
+use crate::{
+    errors::RedisError,
+    persistence::{add_floats, parse_integer, SetExpiry, StringStore},
+};
+
+/// Largest string value redis accepts (`proto-max-bulk-len`).
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+impl StringStore for InMemStore {
+    fn string_incr_by(&mut self, key: &str, increment: i64) -> Result<i64> {
+        let mut store = lock(&self.store);
+        let current = match string(&store, key)? {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(parse_integer)
+                .ok_or(RedisError::NotInteger)?,
+            None => 0,
+        };
+
+        let updated = current.checked_add(increment).ok_or(RedisError::Overflow)?;
+        set_keeping_ttl(&mut store, key, updated.to_string().into_bytes());
+        return Ok(updated);
+    }
+
+    fn string_incr_by_float(&mut self, key: &str, increment: f64) -> Result<String> {
+        let mut store = lock(&self.store);
+        let current = match string(&store, key)? {
+            Some(value) => parse::<f64>(value)
+                .filter(|value| return value.is_finite())
+                .ok_or(RedisError::NotFloat)?,
+            None => 0.0,
+        };
+
+        let (updated, written) = add_floats(current, increment);
+        if !updated.is_finite() {
+            return Err(anyhow!(RedisError::NanOrInfinity));
+        }
+        set_keeping_ttl(&mut store, key, written.clone().into_bytes());
+        return Ok(written);
+    }
+
+    fn string_append(&mut self, key: &str, value: &[u8]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let current = string(&store, key)?;
+        let existed = current.is_some();
+        let mut updated = current.cloned().unwrap_or_default();
+        check_len(updated.len() + value.len())?;
+
+        updated.extend_from_slice(value);
+        let len = updated.len();
+        // NOTE: like in redis, appending to a missing key is a plain SET
+        if existed {
+            set_in_place(&mut store, key, updated);
+        } else {
+            set_keeping_ttl(&mut store, key, updated);
+        }
+        return Ok(len);
+    }
+
+    fn string_set_range(&mut self, key: &str, offset: usize, value: &[u8]) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let current = string(&store, key)?;
+        if value.is_empty() {
+            return Ok(current.map_or(0, |current| return current.len()));
+        }
+        check_len(offset + value.len())?;
+
+        let mut bytes = current.cloned().unwrap_or_default();
+        if bytes.len() < offset + value.len() {
+            bytes.resize(offset + value.len(), 0);
+        }
+        bytes[offset..offset + value.len()].copy_from_slice(value);
+
+        let len = bytes.len();
+        set_in_place(&mut store, key, bytes);
+        return Ok(len);
+    }
+
+    fn string_get_del(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut store = lock(&self.store);
+        let value = string(&store, key)?.cloned();
+        if value.is_some() {
+            store.remove(key);
+        }
+        return Ok(value);
+    }
+
+    fn string_get_ex(&mut self, key: &str, expiry: SetExpiry) -> Result<Option<Vec<u8>>> {
+        let mut store = lock(&self.store);
+        let (data, current) = match live_value(&store, key) {
+            Some(Value {
+                data: Data::String(data),
+                expires_at,
+                ..
+            }) => (data.clone(), *expires_at),
+            Some(_) => return Err(RedisError::WrongType.into()),
+            None => return Ok(None),
+        };
+
+        let expires_at = match expiry {
+            SetExpiry::Keep => return Ok(Some(data)),
+            SetExpiry::Clear => None,
+            SetExpiry::At(expires_at) => Some(expires_at),
+        };
+        // NOTE: a TTL left as it was does not touch the watchers of the key
+        if expires_at != current {
+            if let Some(value) = store.get_mut(key) {
+                value.expires_at = expires_at;
+            }
+            if expires_at.is_some() {
+                store.volatile.insert(key);
+            }
+        }
+        return Ok(Some(data));
+    }
+
+    fn string_get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        let store = lock(&self.store);
+        return Ok(keys
+            .iter()
+            .map(|key| return string(&store, key).ok().flatten().cloned())
+            .collect());
+    }
+
+    fn string_set_many(&mut self, pairs: &[(String, Vec<u8>)], only_missing: bool) -> Result<bool> {
+        let mut store = lock(&self.store);
+        if only_missing
+            && pairs
+                .iter()
+                .any(|(key, _)| return live_value(&store, key).is_some())
+        {
+            return Ok(false);
+        }
+
+        for (key, value) in pairs {
+            store.insert(key.clone(), Value::new(Data::String(value.clone())));
+        }
+        return Ok(true);
+    }
+}
+
+pub(super) fn string<'a>(store: &'a Keyspace, key: &str) -> Result<Option<&'a Vec<u8>>> {
+    return match live_value(store, key) {
+        Some(Value {
+            data: Data::String(value),
+            ..
+        }) => Ok(Some(value)),
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+/// Looks up a string to modify it in place, which leaves it raw.
+pub(super) fn string_mut<'a>(
+    store: &'a mut Keyspace,
+    key: &str,
+) -> Result<Option<&'a mut Vec<u8>>> {
+    return match live_value_mut(store, key) {
+        Some(Value {
+            data: Data::String(value),
+            raw,
+            ..
+        }) => {
+            *raw = true;
+            Ok(Some(value))
+        }
+        Some(_) => Err(RedisError::WrongType.into()),
+        None => Ok(None),
+    };
+}
+
+pub(super) fn string_or_create<'a>(store: &'a mut Keyspace, key: &str) -> Result<&'a mut Vec<u8>> {
+    if string(store, key)?.is_none() {
+        store.insert(key.to_string(), Value::new(Data::String(Vec::new())));
+    }
+    return Ok(string_mut(store, key)?.unwrap());
+}
+
+/// Replaces the string at `key`, leaving its expiry alone.
+pub(super) fn set_keeping_ttl(store: &mut Keyspace, key: &str, value: Vec<u8>) {
+    match live_value_mut(store, key) {
+        Some(existing) => {
+            existing.data = Data::String(value);
+            existing.raw = false;
+        }
+        None => {
+            store.insert(key.to_string(), Value::new(Data::String(value)));
+        }
+    }
+}
+
+/// Like `set_keeping_ttl`, for a string modified in place rather than replaced.
+pub(super) fn set_in_place(store: &mut Keyspace, key: &str, value: Vec<u8>) {
+    set_keeping_ttl(store, key, value);
+    if let Some(existing) = store.values.get_mut(key) {
+        existing.raw = true;
+    }
+}
+
+pub(super) fn check_len(len: usize) -> Result<()> {
+    if len > MAX_STRING_LEN {
+        return Err(anyhow!(RedisError::Generic(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".into()
+        )));
+    }
+    return Ok(());
+}
+
+/// Parses a number stored as a string value, which needs to be valid UTF-8 first.
+fn parse<N: std::str::FromStr>(value: &[u8]) -> Option<N> {
+    return std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| return value.parse().ok());
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::{lock, InMemStore, Value};
+use crate::persistence::{current_timestamp, WatchStore, Watcher};
+
+/// Clients watching the keys of a database. A key that had already expired when watched is
+/// stale: deleting it changes nothing logically, so it does not dirty the watcher.
+pub(super) struct WatchedKeys {
+    keys: HashMap<String, Vec<WatchEntry>>,
+}
+
+struct WatchEntry {
+    watcher: Watcher,
+    stale: bool,
+}
+
+impl WatchStore for InMemStore {
+    fn watch(&mut self, key: &str, watcher: &Watcher) -> Result<usize> {
+        let mut store = lock(&self.store);
+        let stale = store
+            .get(key)
+            .is_some_and(|value| return value.is_expired(current_timestamp()));
+        store.watched.add(key, watcher, stale);
+        return Ok(self.db);
+    }
+
+    fn unwatch(&mut self, keys: &[(usize, String)], watcher: &Watcher) -> Result<()> {
+        for (db, key) in keys {
+            lock(self.database(*db)?).watched.remove(key, watcher);
+        }
+        return Ok(());
+    }
+
+    fn watched_keys_expired(&self, keys: &[(usize, String)], watcher: &Watcher) -> Result<bool> {
+        let now = current_timestamp();
+        for (db, key) in keys {
+            let store = lock(self.database(*db)?);
+            if !store.watched.is_stale(key, watcher)
+                && store
+                    .get(key)
+                    .is_some_and(|value| return value.is_expired(now))
+            {
+                return Ok(true);
+            }
+        }
+        return Ok(false);
+    }
+}
+
+impl WatchedKeys {
+    pub(super) fn new() -> Self {
+        return WatchedKeys {
+            keys: HashMap::new(),
+        };
+    }
+
+    fn add(&mut self, key: &str, watcher: &Watcher, stale: bool) {
+        let entries = self.keys.entry(key.to_string()).or_default();
+        if !entries.iter().any(|entry| return entry.watcher.is(watcher)) {
+            entries.push(WatchEntry {
+                watcher: watcher.clone(),
+                stale,
+            });
+        }
+    }
+
+    fn remove(&mut self, key: &str, watcher: &Watcher) {
+        if let Some(entries) = self.keys.get_mut(key) {
+            entries.retain(|entry| return !entry.watcher.is(watcher));
+            if entries.is_empty() {
+                self.keys.remove(key);
+            }
+        }
+    }
+
+    fn is_stale(&self, key: &str, watcher: &Watcher) -> bool {
+        return self.keys.get(key).is_some_and(|entries| {
+            return entries
+                .iter()
+                .any(|entry| return entry.watcher.is(watcher) && entry.stale);
+        });
+    }
+
+    /// Marks the watchers of a modified key dirty, `exists` telling whether the key is still
+    /// there after the modification.
+    pub(super) fn touch(&mut self, key: &str, exists: bool) {
+        for entry in self.keys.get_mut(key).into_iter().flatten() {
+            if entry.stale && !exists {
+                entry.stale = false;
+            } else {
+                entry.watcher.mark_dirty();
+            }
+        }
+    }
+
+    /// Touches the watched keys of a database whose values are replaced wholesale: by none
+    /// on FLUSH, by the values of another database on SWAPDB. Only keys that exist on either
+    /// side are modified, an expired key replacing a missing one just makes it stale.
+    pub(super) fn touch_replaced(
+        &mut self,
+        emptied: &HashMap<String, Value>,
+        replaced_with: Option<&HashMap<String, Value>>,
+    ) {
+        let now = current_timestamp();
+        for (key, entries) in &mut self.keys {
+            let exists_in_emptied = emptied.contains_key(key);
+            let replacement = replaced_with.and_then(|values| return values.get(key));
+            if !exists_in_emptied && replacement.is_none() {
+                continue;
+            }
+
+            let replacement_expired = replacement.is_some_and(|value| return value.is_expired(now));
+            for entry in entries {
+                if entry.stale && replacement.is_none() {
+                    entry.stale = false;
+                } else if (entry.stale || !exists_in_emptied) && replacement_expired {
+                    entry.stale = true;
+                } else {
+                    entry.watcher.mark_dirty();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_store;
+    use crate::persistence::{
+        ExpireCondition, HashStore, KeyStore, SetCondition, SetExpiry, SetOptions, SetStore,
+        SortedSetStore, Store, WatchStore, Watcher, ZAddFlags,
+    };
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        return values
+            .iter()
+            .map(|value| return value.to_string())
+            .collect();
+    }
+
+    #[test]
+    fn reads_leave_watchers_alone() {
+        let mut store = test_store();
+        store.hash_set("h", &[("f".into(), "v".into())]).unwrap();
+        let watcher = Watcher::new();
+        store.watch("h", &watcher).unwrap();
+
+        store.hash_get("h", &strings(&["f", "missing"])).unwrap();
+        store.hash_get_all("h").unwrap();
+        store.hash_len("h").unwrap();
+        store.hash_ttl("h", &strings(&["f"])).unwrap();
+        store.hash_random_fields("h", -3).unwrap();
+        assert!(!watcher.is_dirty());
+    }
+
+    #[test]
+    fn writes_changing_nothing_leave_watchers_alone() {
+        let mut store = test_store();
+        let always = SetOptions {
+            condition: SetCondition::Always,
+            expiry: SetExpiry::Clear,
+            get: false,
+        };
+        store.set("k".into(), b"v".to_vec(), always).unwrap();
+        store.set_add("s", &strings(&["a"])).unwrap();
+        store
+            .zset_add("z", &[(1.0, "a".into())], ZAddFlags::default())
+            .unwrap();
+        let watcher = Watcher::new();
+        for key in ["k", "s", "z"] {
+            store.watch(key, &watcher).unwrap();
+        }
+
+        let only_missing = SetOptions {
+            condition: SetCondition::IfMissing,
+            ..always
+        };
+        assert!(
+            !store
+                .set("k".into(), b"w".to_vec(), only_missing)
+                .unwrap()
+                .applied
+        );
+        assert!(!store.persist("k").unwrap());
+        assert!(!store
+            .expire("k", u128::MAX, &[ExpireCondition::IfSome])
+            .unwrap());
+        assert_eq!(store.set_remove("s", &strings(&["b"])).unwrap(), 0);
+        assert_eq!(store.set_add("s", &strings(&["a"])).unwrap(), 0);
+        let only_missing = ZAddFlags {
+            only_missing: true,
+            ..ZAddFlags::default()
+        };
+        assert_eq!(
+            store
+                .zset_add("z", &[(2.0, "a".into())], only_missing)
+                .unwrap()
+                .added,
+            0
+        );
+        assert!(!watcher.is_dirty());
+
+        assert_eq!(store.set_remove("s", &strings(&["a"])).unwrap(), 1);
+        assert!(watcher.is_dirty());
+    }
+}
@@ -1,7 +1,1019 @@
+use std::{
+    fmt,
+    ops::RangeInclusive,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+
+use crate::prelude::*;
+
 pub mod in_mem;
 
-pub trait Store {
-    fn set(&mut self, key: String, value: String);
-    fn set_expiring(&mut self, key: String, value: String, expiry_in_millis: u32);
-    fn get(&self, key: &str) -> Option<String>;
+mod decimal;
+
+/// Unix time in ms, the unit every expiry is stored in.
+pub fn current_timestamp() -> u128 {
+    let now = SystemTime::now();
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap();
+    return since_epoch.as_millis();
+}
+
+/// Formats a float the way redis replies scores: as short as possible while still round
+/// tripping, in plain decimal notation unless the exponent is below -4 or above 16, where
+/// `%.17g` switches to scientific notation (`1e+300`, `1.5e-05`).
+pub fn format_float(value: f64) -> String {
+    if !value.is_finite() {
+        return f!("{}", value);
+    }
+    let scientific = f!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if (-4..17).contains(&exponent) {
+        return f!("{}", value);
+    }
+    let sign = if exponent < 0 { '-' } else { '+' };
+    return f!("{}e{}{:02}", mantissa, sign, exponent.unsigned_abs());
+}
+
+/// The integer a string holds, if it is written the way redis would write it back
+/// (`string2ll`): no sign for positives, no leading zeros or spaces. Args are parsed as
+/// strictly, and only those strings are stored as integers, so that they read back unchanged.
+pub fn parse_integer(value: &str) -> Option<i64> {
+    let n = value.parse::<i64>().ok()?;
+    return (n.to_string() == value).then_some(n);
+}
+
+/// Adds a float increment the way INCRBYFLOAT and HINCRBYFLOAT do, returning the result
+/// along with how it is written.
+pub fn add_floats(current: f64, increment: f64) -> (f64, String) {
+    return decimal::add(current, increment);
+}
+
+pub trait Store:
+    KeyStore
+    + DatabaseStore
+    + WatchStore
+    + MemoryStore
+    + ObjectStore
+    + StringStore
+    + BitmapStore
+    + HyperLogLogStore
+    + ListStore
+    + HashStore
+    + SetStore
+    + SortedSetStore
+    + StreamStore
+    + StreamGroupStore
+{
+    fn set(&mut self, key: String, value: Vec<u8>, options: SetOptions) -> Result<SetOutcome>;
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// NX / XX of SET.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetCondition {
+    Always,
+    IfMissing,
+    IfExists,
+}
+
+/// TTL of a key written by SET: none (the default clears it), the one it already had
+/// (KEEPTTL) or a unix time in ms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetExpiry {
+    Clear,
+    Keep,
+    At(u128),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SetOptions {
+    pub condition: SetCondition,
+    pub expiry: SetExpiry,
+    /// Whether the previous value is wanted (GET), which requires it to be a string.
+    pub get: bool,
+}
+
+#[derive(Debug)]
+pub struct SetOutcome {
+    pub applied: bool,
+    pub previous: Option<Vec<u8>>,
+}
+
+/// One round of the active expiration cycle.
+#[derive(Debug)]
+pub struct ExpireSample {
+    pub sampled: usize,
+    /// Sampled keys found without a TTL any more, whose tracking was dropped.
+    pub pruned: usize,
+    pub expired: Vec<String>,
+}
+
+/// MATCH and TYPE options of SCAN.
+#[derive(Debug, Clone, Copy)]
+pub struct ScanFilter<'a> {
+    pub pattern: Option<&'a str>,
+    pub key_type: Option<&'a str>,
+}
+
+/// Cmds on keys, whatever type of value they hold.
+pub trait KeyStore {
+    /// Returns how many of the keys existed. With `lazy` set, large values are freed on a
+    /// background thread instead of the caller's (UNLINK).
+    fn delete(&mut self, keys: &[String], lazy: bool) -> Result<usize>;
+    /// Counts the keys that exist, a key given twice counting twice.
+    fn exists(&self, keys: &[String]) -> Result<usize>;
+    /// Name of the type of the value, `None` for missing keys.
+    fn key_type(&self, key: &str) -> Result<Option<&'static str>>;
+    /// Moves the value along with its TTL, overwriting `new_key` unless `only_missing` is set.
+    /// Returns whether it was moved. Errors if `key` is missing.
+    fn rename(&mut self, key: &str, new_key: &str, only_missing: bool) -> Result<bool>;
+    /// Copies the value along with its TTL to `destination` in database `db` (the selected
+    /// one if `None`), overwriting it only if `replace` is set. Returns whether it was copied.
+    fn copy(
+        &mut self,
+        source: &str,
+        destination: &str,
+        db: Option<usize>,
+        replace: bool,
+    ) -> Result<bool>;
+    /// Sets the expiry (unix time in ms) if all the conditions allow it, deleting the key when
+    /// the time has already passed. Returns whether the key was changed.
+    fn expire(
+        &mut self,
+        key: &str,
+        expires_at: u128,
+        conditions: &[ExpireCondition],
+    ) -> Result<bool>;
+    /// Expiry of the key in unix time ms: `None` if the key is missing, `Some(None)` if it
+    /// has no TTL.
+    fn expires_at(&self, key: &str) -> Result<Option<Option<u128>>>;
+    /// Removes the TTL, returning whether there was one.
+    fn persist(&mut self, key: &str) -> Result<bool>;
+    /// Keys matching the glob style pattern.
+    fn keys(&self, pattern: &str) -> Result<Vec<String>>;
+    /// One step of SCAN: the cursor to continue from, 0 once done, and a batch of keys
+    /// passing the filter. Keys present for the whole scan are returned exactly once.
+    fn scan(&self, cursor: u64, count: usize, filter: ScanFilter) -> Result<(u64, Vec<String>)>;
+    fn random_key(&self) -> Result<Option<String>>;
+    /// Checks `count` random keys among the ones with a TTL, deleting those that expired.
+    fn expire_sample(&mut self, count: usize) -> Result<ExpireSample>;
+    /// Keys of every database that cmds found expired and deleted since the last call, along
+    /// with their database, for their deletion to be propagated.
+    fn take_expired(&mut self) -> Result<Vec<(usize, String)>>;
+    /// Number of keys, including the ones expired but not removed yet.
+    fn key_count(&self) -> Result<usize>;
+}
+
+/// Cmds on the numbered databases. Each client has its own selected database, the one every
+/// other cmd works on.
+pub trait DatabaseStore {
+    fn select(&mut self, db: usize) -> Result<()>;
+    /// Moves the key along with its TTL to database `db`, unless the key exists there.
+    /// Returns whether it was moved.
+    fn move_key(&mut self, key: &str, db: usize) -> Result<bool>;
+    /// Swaps the contents of the databases, for the clients that selected them as well.
+    fn swap_dbs(&mut self, first: usize, second: usize) -> Result<()>;
+    /// Empties the selected database, or every database when `all` is set. With `lazy` the
+    /// values are freed on a background thread.
+    fn flush(&mut self, all: bool, lazy: bool) -> Result<()>;
+}
+
+/// A client watching keys for its next EXEC. Clones share the dirty flag, raised once any
+/// of the keys is modified.
+#[derive(Clone)]
+pub struct Watcher {
+    dirty: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        return Watcher {
+            dirty: Arc::new(AtomicBool::new(false)),
+        };
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        return self.dirty.load(Ordering::SeqCst);
+    }
+
+    /// Clears the flag, once the client no longer watches any key.
+    pub fn reset(&self) {
+        self.dirty.store(false, Ordering::SeqCst);
+    }
+
+    fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    fn is(&self, other: &Watcher) -> bool {
+        return Arc::ptr_eq(&self.dirty, &other.dirty);
+    }
+}
+
+/// Keys watched by the clients (WATCH). Every modification of a watched key marks its
+/// watchers dirty: writes, expiry, eviction and flushes alike. Reads and writes that end up
+/// changing nothing do not.
+pub trait WatchStore {
+    /// Watches the key of the selected database, returning the database.
+    fn watch(&mut self, key: &str, watcher: &Watcher) -> Result<usize>;
+    fn unwatch(&mut self, keys: &[(usize, String)], watcher: &Watcher) -> Result<()>;
+    /// Whether any of the keys, live when watched, has expired since even though nothing
+    /// deleted it yet.
+    fn watched_keys_expired(&self, keys: &[(usize, String)], watcher: &Watcher) -> Result<bool>;
+}
+
+/// maxmemory and how keys are picked for eviction once the store goes over it.
+#[derive(Debug, Clone)]
+pub struct MaxMemory {
+    /// Bytes the keys and values may take, 0 for no limit.
+    pub limit: usize,
+    pub policy: EvictionPolicy,
+    /// Keys sampled in each database to find the best one to evict (`maxmemory-samples`).
+    pub samples: usize,
+    pub lfu: LfuSettings,
+}
+
+/// How the access frequency of a key, a logarithmic counter of 0 to 255, is maintained.
+#[derive(Debug, Clone, Copy)]
+pub struct LfuSettings {
+    /// The higher, the more accesses it takes to increment the counter (`lfu-log-factor`).
+    pub log_factor: u32,
+    /// Minutes without access after which the counter is decremented, 0 to never decrement
+    /// it (`lfu-decay-time`).
+    pub decay_time: u32,
+}
+
+/// Sizes up to which collections are kept in a compact encoding, a listpack or an intset,
+/// before being converted to their full structure for good.
+#[derive(Debug, Clone, Copy)]
+pub struct EncodingLimits {
+    pub hash_max_listpack_entries: usize,
+    /// Longest field or value (`hash-max-listpack-value`).
+    pub hash_max_listpack_value: usize,
+    /// Entries when positive. When negative, bytes of the listpack: -1 for 4 KB, -2 for
+    /// 8 KB, up to -5 for 64 KB (`list-max-listpack-size`).
+    pub list_max_listpack_size: i64,
+    pub set_max_intset_entries: usize,
+    pub zset_max_listpack_entries: usize,
+    /// Longest member (`zset-max-listpack-value`).
+    pub zset_max_listpack_value: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionPolicy {
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    const NAMES: [(&'static str, EvictionPolicy); 8] = [
+        ("noeviction", EvictionPolicy::NoEviction),
+        ("allkeys-lru", EvictionPolicy::AllKeysLru),
+        ("allkeys-lfu", EvictionPolicy::AllKeysLfu),
+        ("allkeys-random", EvictionPolicy::AllKeysRandom),
+        ("volatile-lru", EvictionPolicy::VolatileLru),
+        ("volatile-lfu", EvictionPolicy::VolatileLfu),
+        ("volatile-random", EvictionPolicy::VolatileRandom),
+        ("volatile-ttl", EvictionPolicy::VolatileTtl),
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        return EvictionPolicy::NAMES
+            .iter()
+            .find(|(known, _)| return known.eq_ignore_ascii_case(name))
+            .map(|(_, policy)| return *policy);
+    }
+
+    /// Whether keys are picked by their access frequency, the one tracked then instead of the
+    /// access time.
+    pub fn is_lfu(&self) -> bool {
+        return matches!(
+            self,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu
+        );
+    }
+
+    /// Whether only keys with a TTL may be evicted.
+    pub fn is_volatile(&self) -> bool {
+        return matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        );
+    }
+}
+
+/// Outcome of making room under maxmemory.
+#[derive(Debug)]
+pub struct Eviction {
+    /// Keys evicted, along with their database.
+    pub evicted: Vec<(usize, String)>,
+    /// Whether the memory used is within maxmemory, false when the policy ran out of keys
+    /// to evict.
+    pub fits: bool,
+}
+
+/// Accounting of the memory taken by the keys and values, kept within maxmemory by evicting
+/// keys.
+pub trait MemoryStore {
+    /// Bytes taken by the keys and values of every database, estimated the way MEMORY USAGE
+    /// does.
+    fn used_memory(&mut self) -> Result<usize>;
+    /// Evicts keys picked by the maxmemory policy until the memory used fits the limit.
+    fn evict(&mut self) -> Result<Eviction>;
+}
+
+/// How a value is held and accessed, as reported by OBJECT.
+#[derive(Debug)]
+pub struct ObjectInfo {
+    pub encoding: &'static str,
+    /// References to the value, which are many for the integers shared across keys.
+    pub refcount: i64,
+    /// Ms since the key was last accessed.
+    pub idle_time: u128,
+    /// Logarithmic access counter of the LFU policies.
+    pub frequency: u8,
+}
+
+/// Elements of a collection MEMORY USAGE samples by default.
+pub const DEFAULT_MEMORY_SAMPLES: usize = 5;
+
+/// What MEMORY STATS reports.
+#[derive(Debug)]
+pub struct MemoryStats {
+    /// Bytes taken by the keys and values.
+    pub used: usize,
+    /// Highest `used` measured so far.
+    pub peak: usize,
+    pub keys: usize,
+    /// Bytes taken by the tables of the databases that have keys, included in `used`.
+    pub overheads: Vec<DatabaseOverhead>,
+}
+
+#[derive(Debug)]
+pub struct DatabaseOverhead {
+    pub db: usize,
+    /// Table of the keys.
+    pub main: usize,
+    /// Table of the TTLs.
+    pub expires: usize,
+}
+
+/// Introspection of how the keys and values are held in memory (OBJECT and MEMORY cmds).
+/// Looking keys up here does not count as an access.
+pub trait ObjectStore {
+    fn object(&self, key: &str) -> Result<Option<ObjectInfo>>;
+    /// Bytes taken by the key and its value, collections being extrapolated from `samples`
+    /// of their elements (all of them for 0).
+    fn memory_usage(&self, key: &str, samples: usize) -> Result<Option<usize>>;
+    fn memory_stats(&mut self) -> Result<MemoryStats>;
+}
+
+/// Cmds on string values. Updates keep the TTL of the key.
+pub trait StringStore {
+    /// Adds to the integer stored at `key`, a missing key counting as 0.
+    fn string_incr_by(&mut self, key: &str, increment: i64) -> Result<i64>;
+    /// Returns the value written.
+    fn string_incr_by_float(&mut self, key: &str, increment: f64) -> Result<String>;
+    /// Returns the length of the string afterwards.
+    fn string_append(&mut self, key: &str, value: &[u8]) -> Result<usize>;
+    /// Overwrites the string from `offset` on, padding it with zero bytes if it is shorter.
+    /// Returns the length of the string afterwards.
+    fn string_set_range(&mut self, key: &str, offset: usize, value: &[u8]) -> Result<usize>;
+    fn string_get_del(&mut self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Gets the string while changing its TTL: `Keep` leaves it alone, `Clear` persists it.
+    fn string_get_ex(&mut self, key: &str, expiry: SetExpiry) -> Result<Option<Vec<u8>>>;
+    /// Values of the keys, `None` for missing keys and keys that do not hold a string.
+    fn string_get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>>;
+    /// Sets all the pairs clearing their TTLs, or none of them if `only_missing` is set and
+    /// any of the keys exists. Returns whether they were set.
+    fn string_set_many(&mut self, pairs: &[(String, Vec<u8>)], only_missing: bool) -> Result<bool>;
+}
+
+/// Unit of the start / end of BITCOUNT and BITPOS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitUnit {
+    Byte,
+    Bit,
+}
+
+/// Range given to BITCOUNT / BITPOS, where negative indexes count from the end. BITPOS
+/// treats the bits past the string as clear only when no end is given.
+#[derive(Debug, Clone, Copy)]
+pub struct BitRange {
+    pub start: i64,
+    pub end: Option<i64>,
+    pub unit: BitUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// Integer type of a BITFIELD field, e.g. i5 or u8. Unsigned fields are at most 63 bits
+/// wide so that every value fits in an i64.
+#[derive(Debug, Clone, Copy)]
+pub struct BitfieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldOverflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// A BITFIELD subcommand. `offset` is in bits, `#N` offsets already being resolved.
+#[derive(Debug, Clone, Copy)]
+pub enum BitfieldOp {
+    Get {
+        field: BitfieldType,
+        offset: usize,
+    },
+    Set {
+        field: BitfieldType,
+        offset: usize,
+        value: i64,
+        overflow: BitfieldOverflow,
+    },
+    IncrBy {
+        field: BitfieldType,
+        offset: usize,
+        increment: i64,
+        overflow: BitfieldOverflow,
+    },
+}
+
+/// Cmds addressing string values bit by bit. Bits are numbered from the most significant
+/// bit of the first byte, and strings are padded with zero bytes when written past the end.
+pub trait BitmapStore {
+    /// Returns the previous value of the bit.
+    fn bit_set(&mut self, key: &str, offset: usize, value: bool) -> Result<bool>;
+    fn bit_get(&self, key: &str, offset: usize) -> Result<bool>;
+    fn bit_count(&self, key: &str, range: Option<BitRange>) -> Result<usize>;
+    /// Position of the first bit set to `value`, -1 if there is none.
+    fn bit_position(&self, key: &str, value: bool, range: Option<BitRange>) -> Result<i64>;
+    /// Stores the result in `destination`, deleting it when the result is empty. Missing
+    /// keys count as strings of zero bytes. Returns the length of the result.
+    fn bit_op(
+        &mut self,
+        operation: BitOperation,
+        destination: &str,
+        keys: &[String],
+    ) -> Result<usize>;
+    /// Runs the subcommands in order, with one reply each: the value read (GET), the value
+    /// before (SET) or after (INCRBY) the write, or `None` when FAIL stopped an overflow.
+    fn bitfield(&mut self, key: &str, ops: &[BitfieldOp]) -> Result<Vec<Option<i64>>>;
+}
+
+/// Cmds on HyperLogLog sketches, which are string values in the encoding redis uses.
+pub trait HyperLogLogStore {
+    /// Returns whether the sketch changed, creating it counting as a change.
+    fn hll_add(&mut self, key: &str, elements: &[String]) -> Result<bool>;
+    /// Estimates the cardinality of the union of the sketches. The estimate of a single
+    /// sketch is cached in it until the sketch changes.
+    fn hll_count(&mut self, keys: &[String]) -> Result<u64>;
+    /// Merges the sketches into `destination`, which also counts as a source if it exists.
+    fn hll_merge(&mut self, destination: &str, keys: &[String]) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+pub trait ListStore {
+    /// Pushes the values one by one, returning the list length afterwards. When `only_existing`
+    /// is set nothing is pushed to missing keys (LPUSHX / RPUSHX).
+    fn push(
+        &mut self,
+        key: &str,
+        values: &[String],
+        end: ListEnd,
+        only_existing: bool,
+    ) -> Result<usize>;
+    /// Pops up to `count` elements. An empty result means the key does not exist.
+    fn pop(&mut self, key: &str, end: ListEnd, count: usize) -> Result<Vec<String>>;
+    /// Pops from the first non empty list among `keys`.
+    fn pop_first(
+        &mut self,
+        keys: &[String],
+        end: ListEnd,
+        count: usize,
+    ) -> Result<Option<(String, Vec<String>)>>;
+    fn list_len(&self, key: &str) -> Result<usize>;
+    fn range(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>>;
+    fn move_element(
+        &mut self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<String>>;
+}
+
+/// NX / XX / GT / LT flags of the expire cmds. A missing expiry counts as an infinite TTL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpireCondition {
+    Always,
+    IfNone,
+    IfSome,
+    IfGreater,
+    IfLess,
+}
+
+impl ExpireCondition {
+    pub fn allows(&self, current: Option<u128>, new: u128) -> bool {
+        return match (self, current) {
+            (ExpireCondition::Always, _) => true,
+            (ExpireCondition::IfNone, current) => current.is_none(),
+            (ExpireCondition::IfSome, current) => current.is_some(),
+            (ExpireCondition::IfGreater, Some(current)) => new > current,
+            (ExpireCondition::IfGreater, None) => false,
+            (ExpireCondition::IfLess, Some(current)) => new < current,
+            (ExpireCondition::IfLess, None) => true,
+        };
+    }
+}
+
+/// Reply codes of the hash field TTL cmds (HEXPIRE, HTTL, HPERSIST).
+pub const NO_SUCH_FIELD: i64 = -2;
+pub const NO_FIELD_TTL: i64 = -1;
+
+pub trait HashStore {
+    /// Sets the fields, clearing their TTLs. Returns how many fields were created.
+    fn hash_set(&mut self, key: &str, pairs: &[(String, String)]) -> Result<usize>;
+    fn hash_set_if_missing(&mut self, key: &str, field: &str, value: &str) -> Result<bool>;
+    fn hash_get(&self, key: &str, fields: &[String]) -> Result<Vec<Option<String>>>;
+    fn hash_get_all(&self, key: &str) -> Result<Vec<(String, String)>>;
+    fn hash_len(&self, key: &str) -> Result<usize>;
+    fn hash_delete(&mut self, key: &str, fields: &[String]) -> Result<usize>;
+    fn hash_incr_by(&mut self, key: &str, field: &str, increment: i64) -> Result<i64>;
+    /// Returns the value written.
+    fn hash_incr_by_float(&mut self, key: &str, field: &str, increment: f64) -> Result<String>;
+    /// Picks `count` random fields, distinct when positive and possibly repeated when negative.
+    fn hash_random_fields(&self, key: &str, count: i64) -> Result<Vec<(String, String)>>;
+    /// Sets the expiry (unix time in ms) of each field, replying per field with
+    /// `NO_SUCH_FIELD`, 0 (condition not met), 1 (set) or 2 (deleted, the time has passed).
+    fn hash_expire(
+        &mut self,
+        key: &str,
+        fields: &[String],
+        expires_at: u128,
+        condition: ExpireCondition,
+    ) -> Result<Vec<i64>>;
+    /// Remaining TTL of each field in ms, or `NO_SUCH_FIELD` / `NO_FIELD_TTL`.
+    fn hash_ttl(&self, key: &str, fields: &[String]) -> Result<Vec<i64>>;
+    /// Replies per field with `NO_SUCH_FIELD`, `NO_FIELD_TTL` or 1 (TTL removed).
+    fn hash_persist(&mut self, key: &str, fields: &[String]) -> Result<Vec<i64>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperation {
+    Intersection,
+    Union,
+    Difference,
+}
+
+pub trait SetStore {
+    /// Returns how many of the members were not already in the set.
+    fn set_add(&mut self, key: &str, members: &[String]) -> Result<usize>;
+    fn set_remove(&mut self, key: &str, members: &[String]) -> Result<usize>;
+    fn set_members(&self, key: &str) -> Result<Vec<String>>;
+    fn set_contains(&self, key: &str, members: &[String]) -> Result<Vec<bool>>;
+    fn set_card(&self, key: &str) -> Result<usize>;
+    /// Removes and returns up to `count` random members.
+    fn set_pop(&mut self, key: &str, count: usize) -> Result<Vec<String>>;
+    /// Picks `count` random members, distinct when positive and possibly repeated when negative.
+    fn set_random_members(&self, key: &str, count: i64) -> Result<Vec<String>>;
+    /// Missing keys count as empty sets. For `Difference` the first key is the minuend.
+    fn set_combine(&self, keys: &[String], operation: SetOperation) -> Result<Vec<String>>;
+    /// Overwrites `destination` with the result (deleting it when empty), returning its size.
+    fn set_combine_store(
+        &mut self,
+        destination: &str,
+        keys: &[String],
+        operation: SetOperation,
+    ) -> Result<usize>;
+    /// Size of the intersection, counting no further than `limit` when it is not zero.
+    fn set_intersection_card(&self, keys: &[String], limit: usize) -> Result<usize>;
+    fn set_move(&mut self, source: &str, destination: &str, member: &str) -> Result<bool>;
+}
+
+/// NX / XX / GT / LT / INCR flags of ZADD.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZAddFlags {
+    pub only_missing: bool,
+    pub only_existing: bool,
+    pub only_greater: bool,
+    pub only_less: bool,
+    pub increment: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ZAddOutcome {
+    pub added: usize,
+    /// Existing members whose score changed.
+    pub updated: usize,
+    /// Score of the member after an INCR, `None` when the flags prevented the update.
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    NegativeInfinity,
+    PositiveInfinity,
+    Inclusive(String),
+    Exclusive(String),
+}
+
+/// The range kinds of the unified ZRANGE syntax. Lex ranges assume all members share the
+/// same score, as in redis.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRange {
+    Rank { start: i64, stop: i64 },
+    Score { min: ScoreBound, max: ScoreBound },
+    Lex { min: LexBound, max: LexBound },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreEnd {
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+pub trait SortedSetStore {
+    fn zset_add(
+        &mut self,
+        key: &str,
+        pairs: &[(f64, String)],
+        flags: ZAddFlags,
+    ) -> Result<ZAddOutcome>;
+    fn zset_remove(&mut self, key: &str, members: &[String]) -> Result<usize>;
+    fn zset_score(&self, key: &str, member: &str) -> Result<Option<f64>>;
+    fn zset_card(&self, key: &str) -> Result<usize>;
+    /// 0 based rank of the member (counting from the highest score when `reverse`) and
+    /// its score.
+    fn zset_rank(&self, key: &str, member: &str, reverse: bool) -> Result<Option<(usize, f64)>>;
+    /// Members and scores within `range`. With `reverse` the elements are walked from the
+    /// highest score and rank ranges count from there. `limit` is an (offset, count) pair
+    /// where a negative count means no limit.
+    fn zset_range(
+        &self,
+        key: &str,
+        range: &ZRange,
+        reverse: bool,
+        limit: Option<(i64, i64)>,
+    ) -> Result<Vec<(String, f64)>>;
+    fn zset_count(&self, key: &str, range: &ZRange) -> Result<usize>;
+    fn zset_pop(&mut self, key: &str, end: ScoreEnd, count: usize) -> Result<Vec<(String, f64)>>;
+    /// Overwrites `destination` with the combination of the sorted sets (or plain sets,
+    /// whose members score 1) at `keys`, each score multiplied by its key weight. Returns
+    /// the resulting size.
+    fn zset_combine_store(
+        &mut self,
+        destination: &str,
+        keys: &[String],
+        weights: &[f64],
+        aggregate: Aggregate,
+        operation: SetOperation,
+    ) -> Result<usize>;
+    /// Overwrites `destination` with a sorted set of the pairs, deleting it when there are
+    /// none. Returns the resulting size.
+    fn zset_store(&mut self, destination: &str, pairs: Vec<(String, f64)>) -> Result<usize>;
+}
+
+/// `<ms>-<seq>` ID of a stream entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn next(&self) -> Option<StreamId> {
+        return match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self
+                .ms
+                .checked_add(1)
+                .map(|ms| return StreamId { ms, seq: 0 }),
+        };
+    }
+
+    pub fn previous(&self) -> Option<StreamId> {
+        return match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => self.ms.checked_sub(1).map(|ms| {
+                return StreamId { ms, seq: u64::MAX };
+            }),
+        };
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}-{}", self.ms, self.seq);
+    }
+}
+
+pub type StreamEntry = (StreamId, Vec<(String, String)>);
+
+/// ID argument of XADD: `*`, `<ms>-*` or a full ID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XAddId {
+    Auto,
+    AutoSequence(u64),
+    Explicit(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// `<MAXLEN | MINID> [= | ~] threshold [LIMIT count]` of XADD and XTRIM. Approximate trims
+/// only ever remove whole nodes worth of entries, like the radix tree of redis does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamTrim {
+    pub strategy: TrimStrategy,
+    pub approximate: bool,
+    pub limit: Option<usize>,
+}
+
+pub trait StreamStore {
+    /// Appends an entry, creating the stream unless `make_stream` is unset, and trims it
+    /// afterwards. Returns the ID of the new entry, `None` if the stream did not exist.
+    fn stream_add(
+        &mut self,
+        key: &str,
+        id: XAddId,
+        fields: Vec<(String, String)>,
+        make_stream: bool,
+        trim: Option<StreamTrim>,
+    ) -> Result<Option<StreamId>>;
+    fn stream_len(&self, key: &str) -> Result<usize>;
+    /// Greatest ID the stream has ever held, which survives the entry being deleted.
+    fn stream_last_id(&self, key: &str) -> Result<Option<StreamId>>;
+    /// Entries with IDs within `start..=end`, walked backwards when `reverse`.
+    fn stream_range(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<StreamEntry>>;
+    /// Returns how many entries were removed.
+    fn stream_trim(&mut self, key: &str, trim: StreamTrim) -> Result<usize>;
+    fn stream_delete(&mut self, key: &str, ids: &[StreamId]) -> Result<usize>;
+}
+
+/// Entry as delivered to a consumer group, `None` once it has been deleted from the stream.
+pub type GroupEntry = (StreamId, Option<Vec<(String, String)>>);
+
+/// Where a consumer group starts reading from: an ID, or the last entry (`$`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupStartId {
+    LastEntry,
+    Id(StreamId),
+}
+
+/// What XREADGROUP did to the group, so it can be replicated.
+#[derive(Debug)]
+pub struct GroupRead {
+    pub entries: Vec<GroupEntry>,
+    pub created_consumer: bool,
+    /// The new last delivered ID and entries read counter, when they moved.
+    pub last_delivered: Option<(StreamId, Option<u64>)>,
+    pub delivered_at: u128,
+}
+
+#[derive(Debug)]
+pub struct PendingSummary {
+    pub count: usize,
+    /// Smallest and greatest pending IDs.
+    pub bounds: Option<(StreamId, StreamId)>,
+    pub consumers: Vec<(String, usize)>,
+}
+
+#[derive(Debug)]
+pub struct PendingInfo {
+    pub id: StreamId,
+    pub consumer: String,
+    pub idle: u128,
+    pub deliveries: u64,
+}
+
+/// IDLE / TIME / RETRYCOUNT / FORCE / JUSTID / LASTID options of XCLAIM.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClaimOptions {
+    pub idle: Option<u128>,
+    pub time: Option<u128>,
+    pub retry_count: Option<u64>,
+    pub force: bool,
+    pub just_id: bool,
+    pub last_id: Option<StreamId>,
+}
+
+#[derive(Debug)]
+pub struct Claim {
+    pub entry: StreamEntry,
+    pub delivery_time: u128,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ClaimOutcome {
+    pub claimed: Vec<Claim>,
+    /// Pending IDs dropped because their entries no longer exist.
+    pub deleted: Vec<StreamId>,
+    /// The new last delivered ID and entries read counter, when LASTID moved them.
+    pub last_delivered: Option<(StreamId, Option<u64>)>,
+}
+
+#[derive(Debug)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub radix_tree_keys: usize,
+    pub radix_tree_nodes: usize,
+    pub last_generated_id: StreamId,
+    pub max_deleted_id: StreamId,
+    pub entries_added: u64,
+    pub recorded_first_id: StreamId,
+    pub groups: usize,
+    pub first_entry: Option<StreamEntry>,
+    pub last_entry: Option<StreamEntry>,
+}
+
+#[derive(Debug)]
+pub struct GroupInfo {
+    pub name: String,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered_id: StreamId,
+    pub entries_read: Option<u64>,
+    pub lag: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct ConsumerInfo {
+    pub name: String,
+    pub pending: usize,
+    pub idle: u128,
+    pub inactive: Option<u128>,
+}
+
+/// Consumer groups of a stream. Idle times are in ms.
+pub trait StreamGroupStore {
+    fn stream_group_create(
+        &mut self,
+        key: &str,
+        group: &str,
+        start: GroupStartId,
+        make_stream: bool,
+        entries_read: Option<u64>,
+    ) -> Result<()>;
+    fn stream_group_set_id(
+        &mut self,
+        key: &str,
+        group: &str,
+        start: GroupStartId,
+        entries_read: Option<u64>,
+    ) -> Result<()>;
+    fn stream_group_destroy(&mut self, key: &str, group: &str) -> Result<bool>;
+    fn stream_group_exists(&self, key: &str, group: &str) -> Result<bool>;
+    fn stream_consumer_create(&mut self, key: &str, group: &str, consumer: &str) -> Result<bool>;
+    /// Returns how many entries the consumer had pending.
+    fn stream_consumer_delete(&mut self, key: &str, group: &str, consumer: &str) -> Result<usize>;
+    /// Reads for `consumer`: entries never delivered to the group when `after` is `None`
+    /// (the `>` ID), otherwise the consumer's own pending entries after that ID.
+    fn stream_group_read(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        after: Option<StreamId>,
+        count: Option<usize>,
+        no_ack: bool,
+    ) -> Result<GroupRead>;
+    fn stream_ack(&mut self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize>;
+    fn stream_pending_summary(&self, key: &str, group: &str) -> Result<PendingSummary>;
+    fn stream_pending(
+        &self,
+        key: &str,
+        group: &str,
+        min_idle: u128,
+        ids: RangeInclusive<StreamId>,
+        count: usize,
+        consumer: Option<&str>,
+    ) -> Result<Vec<PendingInfo>>;
+    fn stream_claim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u128,
+        ids: &[StreamId],
+        options: ClaimOptions,
+    ) -> Result<ClaimOutcome>;
+    /// Claims up to `count` idle pending entries starting at `start`, returning the ID the
+    /// next call should start at (`0-0` once the whole list was scanned).
+    #[allow(clippy::too_many_arguments)]
+    fn stream_auto_claim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u128,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+    ) -> Result<(ClaimOutcome, StreamId)>;
+    fn stream_info(&self, key: &str) -> Result<StreamInfo>;
+    fn stream_groups_info(&self, key: &str) -> Result<Vec<GroupInfo>>;
+    fn stream_consumers_info(&self, key: &str, group: &str) -> Result<Vec<ConsumerInfo>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_float, parse_integer};
+
+    #[test]
+    fn formats_floats_like_redis_scores() {
+        assert_eq!(format_float(1.5), "1.5");
+        assert_eq!(format_float(0.1), "0.1");
+        assert_eq!(format_float(-3.0), "-3");
+        assert_eq!(format_float(0.0001), "0.0001");
+        assert_eq!(format_float(1e16), "10000000000000000");
+        assert_eq!(format_float(1e17), "1e+17");
+        assert_eq!(format_float(1e300), "1e+300");
+        assert_eq!(format_float(-1.25e-5), "-1.25e-05");
+        assert_eq!(format_float(5e-324), "5e-324");
+        assert_eq!(format_float(f64::INFINITY), "inf");
+        assert_eq!(format_float(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn parses_only_integers_redis_would_write_back() {
+        assert_eq!(parse_integer("0"), Some(0));
+        assert_eq!(parse_integer("-12"), Some(-12));
+        assert_eq!(parse_integer("9223372036854775807"), Some(i64::MAX));
+        assert_eq!(parse_integer("-9223372036854775808"), Some(i64::MIN));
+        for value in [
+            "",
+            "-",
+            "-0",
+            "00",
+            "+1",
+            "01",
+            " 1",
+            "1 ",
+            "1.0",
+            "9223372036854775808",
+        ] {
+            assert_eq!(parse_integer(value), None, "{value}");
+        }
+    }
 }
@@ -0,0 +1,427 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufWriter, Write},
+    mem,
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{glob, keyslot, log, prelude::*, resp_protocol::reply};
+
+/// Output a subscriber may have waiting before it is disconnected, the defaults of
+/// `client-output-buffer-limit pubsub` in redis: 32mb at once, or 8mb for 60 seconds.
+const OUTPUT_HARD_LIMIT: usize = 32 * 1024 * 1024;
+const OUTPUT_SOFT_LIMIT: usize = 8 * 1024 * 1024;
+const OUTPUT_SOFT_LIMIT_TIME: Duration = Duration::from_secs(60);
+
+/// Channels and patterns the clients subscribed to, shared by the client threads. PUBLISH
+/// and SPUBLISH queue the messages on the subscribers, see `Subscriber`.
+///
+/// Shard channels live apart, grouped by the hash slot of their name like keys are. Only
+/// their subscribers get what SPUBLISH sends, patterns never match them.
+///
+/// A subscribed client queues its own replies too, with the registry locked, so they come
+/// in order with the messages published to it.
+#[derive(Clone)]
+pub struct PubSub {
+    registry: Arc<Mutex<Registry>>,
+}
+
+pub struct Registry {
+    channels: HashMap<String, Vec<Subscriber>>,
+    patterns: HashMap<String, Vec<Subscriber>>,
+    shard_channels: HashMap<usize, HashMap<String, Vec<Subscriber>>>,
+}
+
+/// Connection of a subscribed client, a clone of the one its thread serves.
+///
+/// What is sent to it is queued and written out by a thread of its own, so a client that
+/// does not read what it is sent cannot hold up the publishers. It is disconnected once its
+/// queue outgrows the output limits.
+#[derive(Clone)]
+pub struct Subscriber {
+    stream: Arc<TcpStream>,
+    output: Arc<Output>,
+}
+
+struct Output {
+    queue: Mutex<OutputQueue>,
+    ready: Condvar,
+}
+
+struct OutputQueue {
+    pending: VecDeque<Vec<u8>>,
+    /// Bytes pending, counting those being written.
+    size: usize,
+    /// Since when more than the soft limit is pending.
+    over_soft_limit_since: Option<Instant>,
+    /// The writer thread is to stop once nothing is pending.
+    stopping: bool,
+    /// The connection was dropped, nothing is written to it anymore.
+    disconnected: bool,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        return PubSub {
+            registry: Arc::new(Mutex::new(Registry {
+                channels: HashMap::new(),
+                patterns: HashMap::new(),
+                shard_channels: HashMap::new(),
+            })),
+        };
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Registry> {
+        return self.registry.lock().unwrap();
+    }
+}
+
+impl Registry {
+    /// Returns whether the subscriber was not subscribed to the channel yet.
+    pub fn subscribe(&mut self, channel: &str, subscriber: &Subscriber) -> bool {
+        return add(&mut self.channels, channel, subscriber);
+    }
+
+    /// Returns whether the subscriber was subscribed to the channel.
+    pub fn unsubscribe(&mut self, channel: &str, subscriber: &Subscriber) -> bool {
+        return remove(&mut self.channels, channel, subscriber);
+    }
+
+    pub fn psubscribe(&mut self, pattern: &str, subscriber: &Subscriber) -> bool {
+        return add(&mut self.patterns, pattern, subscriber);
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &str, subscriber: &Subscriber) -> bool {
+        return remove(&mut self.patterns, pattern, subscriber);
+    }
+
+    pub fn ssubscribe(&mut self, channel: &str, subscriber: &Subscriber) -> bool {
+        let slot = keyslot::key_slot(channel);
+        return add(
+            self.shard_channels.entry(slot).or_default(),
+            channel,
+            subscriber,
+        );
+    }
+
+    pub fn sunsubscribe(&mut self, channel: &str, subscriber: &Subscriber) -> bool {
+        let slot = keyslot::key_slot(channel);
+        let channels = match self.shard_channels.get_mut(&slot) {
+            Some(channels) => channels,
+            None => return false,
+        };
+        let removed = remove(channels, channel, subscriber);
+        if channels.is_empty() {
+            self.shard_channels.remove(&slot);
+        }
+        return removed;
+    }
+
+    /// Sends the message to the subscribers of the channel, then to those of the patterns
+    /// matching it. Returns how many messages were sent: a client subscribed both to the
+    /// channel and to a pattern gets it twice, one already disconnected is not counted.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut receivers = 0;
+        for subscriber in self.channels.get(channel).into_iter().flatten() {
+            if subscriber.send(&["message", channel, message]) {
+                receivers += 1;
+            }
+        }
+        for (pattern, subscribers) in &self.patterns {
+            if !glob::matches(pattern, channel) {
+                continue;
+            }
+            for subscriber in subscribers {
+                if subscriber.send(&["pmessage", pattern, channel, message]) {
+                    receivers += 1;
+                }
+            }
+        }
+        return receivers;
+    }
+
+    /// Sends the message to the subscribers of the shard channel, returning how many got it,
+    /// so not counting those already disconnected. It is queued on them like what PUBLISH
+    /// sends, so they share its output limits.
+    pub fn spublish(&self, channel: &str, message: &str) -> usize {
+        let subscribers = self
+            .shard_channels
+            .get(&keyslot::key_slot(channel))
+            .and_then(|channels| return channels.get(channel));
+        return subscribers
+            .into_iter()
+            .flatten()
+            .filter(|subscriber| return subscriber.send(&["smessage", channel, message]))
+            .count();
+    }
+
+    /// Channels with at least one subscriber, those matching `pattern` when given.
+    pub fn channels(&self, pattern: Option<&str>) -> Vec<String> {
+        return matching(self.channels.keys(), pattern);
+    }
+
+    /// Subscribers of the channel, not counting those of matching patterns.
+    pub fn subscribers(&self, channel: &str) -> usize {
+        return self.channels.get(channel).map_or(0, Vec::len);
+    }
+
+    /// Shard channels with at least one subscriber, those matching `pattern` when given.
+    pub fn shard_channels(&self, pattern: Option<&str>) -> Vec<String> {
+        return matching(
+            self.shard_channels.values().flat_map(HashMap::keys),
+            pattern,
+        );
+    }
+
+    pub fn shard_subscribers(&self, channel: &str) -> usize {
+        return self
+            .shard_channels
+            .get(&keyslot::key_slot(channel))
+            .and_then(|channels| return channels.get(channel))
+            .map_or(0, Vec::len);
+    }
+
+    /// Patterns with at least one subscriber.
+    pub fn patterns(&self) -> usize {
+        return self.patterns.len();
+    }
+}
+
+impl Subscriber {
+    pub fn new(stream: &TcpStream) -> Result<Self> {
+        return Ok(Subscriber {
+            stream: Arc::new(stream.try_clone()?),
+            output: Arc::new(Output {
+                queue: Mutex::new(OutputQueue {
+                    pending: VecDeque::new(),
+                    size: 0,
+                    over_soft_limit_since: None,
+                    stopping: false,
+                    disconnected: false,
+                }),
+                ready: Condvar::new(),
+            }),
+        });
+    }
+
+    /// Starts the thread writing out the queued output, once the client subscribes.
+    pub fn start(&self) -> JoinHandle<()> {
+        self.lock().stopping = false;
+        let subscriber = self.clone();
+        return thread::spawn(move || subscriber.write_pending());
+    }
+
+    /// Stops the writer thread once it wrote out everything pending, so that the client
+    /// can write to its connection itself again.
+    pub fn stop(&self, writer: JoinHandle<()>) {
+        self.lock().stopping = true;
+        self.output.ready.notify_one();
+        if writer.join().is_err() {
+            log::error("The writer thread of a subscriber panicked");
+        }
+    }
+
+    /// Queues output for the client, disconnecting it when that takes it past the limits.
+    /// Returns whether the client was still connected.
+    pub fn push(&self, output: Vec<u8>) -> bool {
+        let mut queue = self.lock();
+        if queue.disconnected {
+            return false;
+        }
+
+        queue.size += output.len();
+        queue.pending.push_back(output);
+        let over_soft_limit = match queue.size > OUTPUT_SOFT_LIMIT {
+            true => {
+                let since = *queue.over_soft_limit_since.get_or_insert_with(Instant::now);
+                since.elapsed() >= OUTPUT_SOFT_LIMIT_TIME
+            }
+            false => {
+                queue.over_soft_limit_since = None;
+                false
+            }
+        };
+
+        if queue.size > OUTPUT_HARD_LIMIT || over_soft_limit {
+            log::error(f!(
+                "Disconnecting subscriber {:?}, over the output buffer limits with {} bytes",
+                self.stream.peer_addr(),
+                queue.size
+            ));
+            self.disconnect(&mut queue);
+        }
+        self.output.ready.notify_one();
+        return true;
+    }
+
+    /// Queues a push message for the client, returning whether it was still connected.
+    fn send(&self, parts: &[&str]) -> bool {
+        let mut output = Vec::new();
+        // NOTE: writing to a Vec cannot fail
+        _ = reply::bulk_string_array(&mut output, parts);
+        return self.push(output);
+    }
+
+    /// Body of the writer thread. A client that went away is only logged, its thread
+    /// unsubscribes it once it notices.
+    fn write_pending(&self) {
+        loop {
+            let pending = {
+                let mut queue = self.lock();
+                while queue.pending.is_empty() && !queue.stopping && !queue.disconnected {
+                    queue = self
+                        .output
+                        .ready
+                        .wait(queue)
+                        .unwrap_or_else(|poisoned| return poisoned.into_inner());
+                }
+                if queue.pending.is_empty() || queue.disconnected {
+                    return;
+                }
+                mem::take(&mut queue.pending)
+            };
+
+            // NOTE: what is being written still counts against the limits until it is out
+            let mut writer = BufWriter::new(&*self.stream);
+            let result = pending
+                .iter()
+                .try_for_each(|output| return writer.write_all(output))
+                .and_then(|_| return writer.flush());
+            if let Err(e) = result {
+                log::error(f!(
+                    "Could not send to subscriber {:?}: {}",
+                    self.stream.peer_addr(),
+                    e
+                ));
+                self.disconnect(&mut self.lock());
+                return;
+            }
+
+            let written: usize = pending.iter().map(Vec::len).sum();
+            let mut queue = self.lock();
+            queue.size = queue.size.saturating_sub(written);
+            if queue.size <= OUTPUT_SOFT_LIMIT {
+                queue.over_soft_limit_since = None;
+            }
+        }
+    }
+
+    /// Drops the connection, which also ends the read of the client thread.
+    fn disconnect(&self, queue: &mut OutputQueue) {
+        queue.disconnected = true;
+        queue.pending.clear();
+        queue.size = 0;
+        _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, OutputQueue> {
+        return self
+            .output
+            .queue
+            .lock()
+            .unwrap_or_else(|poisoned| return poisoned.into_inner());
+    }
+
+    fn is(&self, other: &Subscriber) -> bool {
+        return Arc::ptr_eq(&self.stream, &other.stream);
+    }
+}
+
+fn matching<'a>(names: impl Iterator<Item = &'a String>, pattern: Option<&str>) -> Vec<String> {
+    return names
+        .filter(|name| return pattern.map_or(true, |pattern| return glob::matches(pattern, name)))
+        .cloned()
+        .collect();
+}
+
+fn add(
+    registry: &mut HashMap<String, Vec<Subscriber>>,
+    name: &str,
+    subscriber: &Subscriber,
+) -> bool {
+    let subscribers = registry.entry(name.to_string()).or_default();
+    if subscribers
+        .iter()
+        .any(|current| return current.is(subscriber))
+    {
+        return false;
+    }
+    subscribers.push(subscriber.clone());
+    return true;
+}
+
+fn remove(
+    registry: &mut HashMap<String, Vec<Subscriber>>,
+    name: &str,
+    subscriber: &Subscriber,
+) -> bool {
+    let subscribers = match registry.get_mut(name) {
+        Some(subscribers) => subscribers,
+        None => return false,
+    };
+    let count = subscribers.len();
+    subscribers.retain(|current| return !current.is(subscriber));
+    let removed = subscribers.len() < count;
+    if subscribers.is_empty() {
+        registry.remove(name);
+    }
+    return removed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PubSub, Subscriber, OUTPUT_HARD_LIMIT};
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn disconnects_subscribers_over_the_output_limit() {
+        let (_client, subscriber) = subscriber();
+        let pubsub = PubSub::new();
+        pubsub.lock().subscribe("news", &subscriber);
+
+        assert_disconnected_past_limit(&subscriber, || {
+            return pubsub.lock().publish("news", &"x".repeat(1024 * 1024));
+        });
+        assert_eq!(pubsub.lock().publish("news", "x"), 0);
+    }
+
+    #[test]
+    fn disconnects_shard_subscribers_over_the_output_limit() {
+        let (_client, subscriber) = subscriber();
+        let pubsub = PubSub::new();
+        pubsub.lock().ssubscribe("news", &subscriber);
+
+        assert_disconnected_past_limit(&subscriber, || {
+            return pubsub.lock().spublish("news", &"x".repeat(1024 * 1024));
+        });
+        assert_eq!(pubsub.lock().spublish("news", "x"), 0);
+    }
+
+    /// A subscriber on a connection whose other end is returned, and never read.
+    fn subscriber() -> (TcpStream, Subscriber) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        return (client, Subscriber::new(&stream).unwrap());
+    }
+
+    /// Publishes messages of 1mb until they go past the hard limit.
+    fn assert_disconnected_past_limit(subscriber: &Subscriber, publish: impl Fn() -> usize) {
+        // NOTE: without a writer thread nothing is written out, as with a client that does
+        // not read
+        for _ in 0..OUTPUT_HARD_LIMIT / (1024 * 1024) - 1 {
+            assert_eq!(publish(), 1);
+        }
+        assert!(!subscriber.lock().disconnected);
+
+        publish();
+        let queue = subscriber.lock();
+        assert!(queue.disconnected);
+        assert!(queue.pending.is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(seed());
+}
+
+/// Seeds each thread from the per process random keys std uses for `HashMap`.
+fn seed() -> u64 {
+    let seed = RandomState::new().build_hasher().finish();
+    return if seed == 0 {
+        0x9E37_79B9_7F4A_7C15
+    } else {
+        seed
+    };
+}
+
+/// xorshift64*: fast and good enough for sampling, not for anything security related.
+pub fn next_u64() -> u64 {
+    return STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        return x.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    });
+}
+
+/// Random index in `0..bound`. `bound` must not be zero.
+pub fn below(bound: usize) -> usize {
+    return (next_u64() % bound as u64) as usize;
+}
+
+/// Random float in `0.0..1.0`.
+pub fn unit() -> f64 {
+    return (next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+}
+
+/// Picks up to `count` distinct positions of `0..len`, in random order.
+pub fn distinct_indexes(len: usize, count: usize) -> Vec<usize> {
+    let mut indexes = (0..len).collect::<Vec<usize>>();
+    let count = count.min(len);
+    for i in 0..count {
+        let j = i + below(len - i);
+        indexes.swap(i, j);
+    }
+    indexes.truncate(count);
+    return indexes;
+}
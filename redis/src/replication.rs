@@ -0,0 +1,153 @@
+use std::{
+    cell::{Cell, RefCell},
+    io::Write,
+    net::TcpStream,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+
+use crate::{log, prelude::*, resp_protocol::reply};
+
+/// Connections of the replicas that completed a PSYNC with this node. Every write cmd
+/// executed here is propagated to them so they can apply it to their own store.
+///
+/// Each client propagates through its own clone, which knows the database the client
+/// selected: cmds are preceded by a SELECT whenever they run on another database than the
+/// previous cmd in the stream.
+#[derive(Clone)]
+pub struct Replicas {
+    stream: Arc<Mutex<ReplicationStream>>,
+    db: Cell<usize>,
+    /// Cmds held back while the client of this clone runs a transaction, with the database
+    /// each ran on.
+    transaction: RefCell<Option<Vec<DbCmd>>>,
+    /// Cmds propagated by the cmd running through this clone, held back until `release`.
+    running: RefCell<Option<Vec<DbCmd>>>,
+}
+
+/// A cmd and the database it runs on.
+type DbCmd = (usize, Vec<Vec<u8>>);
+
+struct ReplicationStream {
+    replicas: Vec<TcpStream>,
+    /// Database the replicas have selected, unknown until the first SELECT is sent.
+    db: Option<usize>,
+}
+
+impl Replicas {
+    pub fn new() -> Self {
+        return Replicas {
+            stream: Arc::new(Mutex::new(ReplicationStream {
+                replicas: Vec::new(),
+                db: None,
+            })),
+            db: Cell::new(0),
+            transaction: RefCell::new(None),
+            running: RefCell::new(None),
+        };
+    }
+
+    pub fn register(&self, stream: &TcpStream) -> Result<()> {
+        let stream = stream.try_clone()?;
+        log::info(f!("Registering replica {:?}", stream.peer_addr()));
+        let mut replication = self.stream.lock().unwrap();
+        replication.replicas.push(stream);
+        // NOTE: the new replica has not seen the SELECT sent to the others
+        replication.db = None;
+        return Ok(());
+    }
+
+    /// Database the cmds propagated through this clone run on.
+    pub fn select(&self, db: usize) {
+        self.db.set(db);
+    }
+
+    pub fn propagate<S: AsRef<[u8]>>(&self, cmd: &[S]) {
+        self.propagate_in(self.db.get(), cmd);
+    }
+
+    /// Propagates a cmd on database `db`, whatever the one of this clone.
+    pub fn propagate_in<S: AsRef<[u8]>>(&self, db: usize, cmd: &[S]) {
+        let cmd = cmd
+            .iter()
+            .map(|arg| return arg.as_ref().to_vec())
+            .collect::<Vec<Vec<u8>>>();
+        match self.running.borrow_mut().as_mut() {
+            Some(held) => held.push((db, cmd)),
+            None => self.forward(vec![(db, cmd)]),
+        }
+    }
+
+    /// Holds back the cmds propagated through this clone from now on, until `release`.
+    pub fn hold(&self) {
+        *self.running.borrow_mut() = Some(Vec::new());
+    }
+
+    /// Propagates the cmds held back since `hold`, preceded by DELs of the keys that were
+    /// deleted meanwhile for having expired, which the cmds may rely on.
+    pub fn release(&self, expired: &[(usize, String)]) {
+        let held = self.running.borrow_mut().take().unwrap_or_default();
+        let mut cmds = expired
+            .iter()
+            .map(|(db, key)| return (*db, vec![b"DEL".to_vec(), key.as_bytes().to_vec()]))
+            .collect::<Vec<DbCmd>>();
+        cmds.extend(held);
+        if !cmds.is_empty() {
+            self.forward(cmds);
+        }
+    }
+
+    /// Holds back the cmds propagated through this clone from now on, until `exec`.
+    pub fn multi(&self) {
+        *self.transaction.borrow_mut() = Some(Vec::new());
+    }
+
+    /// Sends the cmds held back since `multi` as a single MULTI/EXEC block, so the replicas
+    /// apply them atomically too. Nothing is sent when none were.
+    pub fn exec(&self) {
+        let held = self.transaction.borrow_mut().take().unwrap_or_default();
+        let (first_db, last_db) = match (held.first(), held.last()) {
+            (Some((first_db, _)), Some((last_db, _))) => (*first_db, *last_db),
+            _ => return,
+        };
+
+        let mut block = vec![(first_db, vec![b"MULTI".to_vec()])];
+        block.extend(held);
+        block.push((last_db, vec![b"EXEC".to_vec()]));
+        self.send(&block);
+    }
+
+    /// Sends the cmds, unless they are held back by a transaction.
+    fn forward(&self, cmds: Vec<DbCmd>) {
+        match self.transaction.borrow_mut().as_mut() {
+            Some(held) => held.extend(cmds),
+            None => self.send(&cmds),
+        }
+    }
+
+    fn send(&self, cmds: &[DbCmd]) {
+        let mut replication = self.stream.lock().unwrap();
+        if replication.replicas.is_empty() {
+            return;
+        }
+
+        let mut encoded = Vec::new();
+        for (db, cmd) in cmds {
+            if replication.db != Some(*db) {
+                encoded.extend(reply::encode_cmd(&["SELECT", &db.to_string()]));
+                replication.db = Some(*db);
+            }
+            encoded.extend(reply::encode_cmd(cmd));
+        }
+        replication.replicas.retain_mut(|stream| {
+            return match stream.write_all(&encoded) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::error(f!("Dropping replica {:?}: {}", stream.peer_addr(), e));
+                    false
+                }
+            };
+        });
+    }
+}
@@ -1,14 +1,30 @@
 use std::{
-    io::{BufReader, BufWriter, Read},
+    io::{BufReader, BufWriter, Read, Write},
     net::TcpStream,
     sync::Arc,
 };
 
 use anyhow::{anyhow, Context, Ok, Result};
 
-use crate::{log, persistence::Store, prelude::*, resp_protocol::util, Config};
+use crate::{
+    errors::RedisError,
+    exec_lock::ExecLock,
+    log,
+    persistence::{ListEnd, ScoreEnd, SetOperation, Store},
+    prelude::*,
+    pubsub::PubSub,
+    replication::Replicas,
+    resp_protocol::util,
+    Config,
+};
 
-use super::{echo, get, info, ping, psync, repl_conf, set};
+use super::{
+    cmds_bitmaps as bitmaps, cmds_databases as databases, cmds_geo as geo, cmds_hashes as hashes,
+    cmds_hyperloglogs as hyperloglogs, cmds_keys as keys, cmds_lists as lists,
+    cmds_objects as objects, cmds_pubsub as pubsub, cmds_sets as sets,
+    cmds_sorted_sets as sorted_sets, cmds_stream_groups as stream_groups, cmds_streams as streams,
+    cmds_strings as strings, echo, get, info, ping, psync, repl_conf, reply, set,
+};
 
 use super::data_types::ArrayStack;
 
@@ -18,9 +34,171 @@ pub enum RESPCmd {
     ECHO,
     SET,
     GET,
+    INCR,
+    DECR,
+    INCRBY,
+    DECRBY,
+    INCRBYFLOAT,
+    APPEND,
+    STRLEN,
+    GETRANGE,
+    SETRANGE,
+    GETDEL,
+    GETEX,
+    SETNX,
+    SETEX,
+    PSETEX,
+    MSET,
+    MGET,
+    MSETNX,
+    LCS,
+    SETBIT,
+    GETBIT,
+    BITCOUNT,
+    BITPOS,
+    BITOP,
+    BITFIELD,
+    #[allow(non_camel_case_types)]
+    BITFIELD_RO,
+    PFADD,
+    PFCOUNT,
+    PFMERGE,
+    GEOADD,
+    GEOPOS,
+    GEODIST,
+    GEOHASH,
+    GEOSEARCH,
+    GEOSEARCHSTORE,
+    DEL,
+    UNLINK,
+    EXISTS,
+    TYPE,
+    RENAME,
+    RENAMENX,
+    COPY,
+    TOUCH,
+    RANDOMKEY,
+    DBSIZE,
+    EXPIRE,
+    PEXPIRE,
+    EXPIREAT,
+    PEXPIREAT,
+    TTL,
+    PTTL,
+    EXPIRETIME,
+    PEXPIRETIME,
+    PERSIST,
+    KEYS,
+    SCAN,
+    SELECT,
+    MOVE,
+    SWAPDB,
+    FLUSHDB,
+    FLUSHALL,
+    OBJECT,
+    MEMORY,
     INFO,
     REPLCONF,
     PSYNC,
+    MULTI,
+    EXEC,
+    DISCARD,
+    WATCH,
+    UNWATCH,
+    SUBSCRIBE,
+    UNSUBSCRIBE,
+    PSUBSCRIBE,
+    PUNSUBSCRIBE,
+    PUBLISH,
+    PUBSUB,
+    SSUBSCRIBE,
+    SUNSUBSCRIBE,
+    SPUBLISH,
+    QUIT,
+    RESET,
+    LPUSH,
+    RPUSH,
+    LPUSHX,
+    RPUSHX,
+    LPOP,
+    RPOP,
+    LLEN,
+    LRANGE,
+    LMOVE,
+    LMPOP,
+    BLPOP,
+    BRPOP,
+    BLMOVE,
+    BLMPOP,
+    HSET,
+    HMSET,
+    HSETNX,
+    HGET,
+    HMGET,
+    HDEL,
+    HGETALL,
+    HKEYS,
+    HVALS,
+    HLEN,
+    HEXISTS,
+    HINCRBY,
+    HINCRBYFLOAT,
+    HRANDFIELD,
+    HSCAN,
+    HEXPIRE,
+    HPEXPIRE,
+    HEXPIREAT,
+    HPEXPIREAT,
+    HTTL,
+    HPERSIST,
+    SADD,
+    SREM,
+    SMEMBERS,
+    SISMEMBER,
+    SMISMEMBER,
+    SCARD,
+    SPOP,
+    SRANDMEMBER,
+    SINTER,
+    SUNION,
+    SDIFF,
+    SINTERSTORE,
+    SUNIONSTORE,
+    SDIFFSTORE,
+    SINTERCARD,
+    SMOVE,
+    SSCAN,
+    ZADD,
+    ZINCRBY,
+    ZREM,
+    ZSCORE,
+    ZCARD,
+    ZRANK,
+    ZREVRANK,
+    ZRANGE,
+    ZCOUNT,
+    ZLEXCOUNT,
+    ZPOPMIN,
+    ZPOPMAX,
+    BZPOPMIN,
+    BZPOPMAX,
+    ZUNIONSTORE,
+    ZINTERSTORE,
+    ZSCAN,
+    XADD,
+    XRANGE,
+    XREVRANGE,
+    XLEN,
+    XTRIM,
+    XDEL,
+    XREAD,
+    XGROUP,
+    XREADGROUP,
+    XACK,
+    XPENDING,
+    XCLAIM,
+    XAUTOCLAIM,
+    XINFO,
 }
 
 pub fn parse(
@@ -48,31 +226,916 @@ pub fn parse(
         "ECHO" => Ok(RESPCmd::ECHO),
         "SET" => Ok(RESPCmd::SET),
         "GET" => Ok(RESPCmd::GET),
+        "INCR" => Ok(RESPCmd::INCR),
+        "DECR" => Ok(RESPCmd::DECR),
+        "INCRBY" => Ok(RESPCmd::INCRBY),
+        "DECRBY" => Ok(RESPCmd::DECRBY),
+        "INCRBYFLOAT" => Ok(RESPCmd::INCRBYFLOAT),
+        "APPEND" => Ok(RESPCmd::APPEND),
+        "STRLEN" => Ok(RESPCmd::STRLEN),
+        "GETRANGE" => Ok(RESPCmd::GETRANGE),
+        "SETRANGE" => Ok(RESPCmd::SETRANGE),
+        "GETDEL" => Ok(RESPCmd::GETDEL),
+        "GETEX" => Ok(RESPCmd::GETEX),
+        "SETNX" => Ok(RESPCmd::SETNX),
+        "SETEX" => Ok(RESPCmd::SETEX),
+        "PSETEX" => Ok(RESPCmd::PSETEX),
+        "MSET" => Ok(RESPCmd::MSET),
+        "MGET" => Ok(RESPCmd::MGET),
+        "MSETNX" => Ok(RESPCmd::MSETNX),
+        "LCS" => Ok(RESPCmd::LCS),
+        "SETBIT" => Ok(RESPCmd::SETBIT),
+        "GETBIT" => Ok(RESPCmd::GETBIT),
+        "BITCOUNT" => Ok(RESPCmd::BITCOUNT),
+        "BITPOS" => Ok(RESPCmd::BITPOS),
+        "BITOP" => Ok(RESPCmd::BITOP),
+        "BITFIELD" => Ok(RESPCmd::BITFIELD),
+        "BITFIELD_RO" => Ok(RESPCmd::BITFIELD_RO),
+        "PFADD" => Ok(RESPCmd::PFADD),
+        "PFCOUNT" => Ok(RESPCmd::PFCOUNT),
+        "PFMERGE" => Ok(RESPCmd::PFMERGE),
+        "GEOADD" => Ok(RESPCmd::GEOADD),
+        "GEOPOS" => Ok(RESPCmd::GEOPOS),
+        "GEODIST" => Ok(RESPCmd::GEODIST),
+        "GEOHASH" => Ok(RESPCmd::GEOHASH),
+        "GEOSEARCH" => Ok(RESPCmd::GEOSEARCH),
+        "GEOSEARCHSTORE" => Ok(RESPCmd::GEOSEARCHSTORE),
+        "DEL" => Ok(RESPCmd::DEL),
+        "UNLINK" => Ok(RESPCmd::UNLINK),
+        "EXISTS" => Ok(RESPCmd::EXISTS),
+        "TYPE" => Ok(RESPCmd::TYPE),
+        "RENAME" => Ok(RESPCmd::RENAME),
+        "RENAMENX" => Ok(RESPCmd::RENAMENX),
+        "COPY" => Ok(RESPCmd::COPY),
+        "TOUCH" => Ok(RESPCmd::TOUCH),
+        "RANDOMKEY" => Ok(RESPCmd::RANDOMKEY),
+        "DBSIZE" => Ok(RESPCmd::DBSIZE),
+        "EXPIRE" => Ok(RESPCmd::EXPIRE),
+        "PEXPIRE" => Ok(RESPCmd::PEXPIRE),
+        "EXPIREAT" => Ok(RESPCmd::EXPIREAT),
+        "PEXPIREAT" => Ok(RESPCmd::PEXPIREAT),
+        "TTL" => Ok(RESPCmd::TTL),
+        "PTTL" => Ok(RESPCmd::PTTL),
+        "EXPIRETIME" => Ok(RESPCmd::EXPIRETIME),
+        "PEXPIRETIME" => Ok(RESPCmd::PEXPIRETIME),
+        "PERSIST" => Ok(RESPCmd::PERSIST),
+        "KEYS" => Ok(RESPCmd::KEYS),
+        "SCAN" => Ok(RESPCmd::SCAN),
+        "SELECT" => Ok(RESPCmd::SELECT),
+        "MOVE" => Ok(RESPCmd::MOVE),
+        "SWAPDB" => Ok(RESPCmd::SWAPDB),
+        "FLUSHDB" => Ok(RESPCmd::FLUSHDB),
+        "FLUSHALL" => Ok(RESPCmd::FLUSHALL),
+        "OBJECT" => Ok(RESPCmd::OBJECT),
+        "MEMORY" => Ok(RESPCmd::MEMORY),
         "INFO" => Ok(RESPCmd::INFO),
         "REPLCONF" => Ok(RESPCmd::REPLCONF),
         "PSYNC" => Ok(RESPCmd::PSYNC),
-        _ => Err(anyhow!("Unsupported cmd {}", cmd_id)),
+        "MULTI" => Ok(RESPCmd::MULTI),
+        "EXEC" => Ok(RESPCmd::EXEC),
+        "DISCARD" => Ok(RESPCmd::DISCARD),
+        "WATCH" => Ok(RESPCmd::WATCH),
+        "UNWATCH" => Ok(RESPCmd::UNWATCH),
+        "SUBSCRIBE" => Ok(RESPCmd::SUBSCRIBE),
+        "UNSUBSCRIBE" => Ok(RESPCmd::UNSUBSCRIBE),
+        "PSUBSCRIBE" => Ok(RESPCmd::PSUBSCRIBE),
+        "PUNSUBSCRIBE" => Ok(RESPCmd::PUNSUBSCRIBE),
+        "PUBLISH" => Ok(RESPCmd::PUBLISH),
+        "PUBSUB" => Ok(RESPCmd::PUBSUB),
+        "SSUBSCRIBE" => Ok(RESPCmd::SSUBSCRIBE),
+        "SUNSUBSCRIBE" => Ok(RESPCmd::SUNSUBSCRIBE),
+        "SPUBLISH" => Ok(RESPCmd::SPUBLISH),
+        "QUIT" => Ok(RESPCmd::QUIT),
+        "RESET" => Ok(RESPCmd::RESET),
+        "LPUSH" => Ok(RESPCmd::LPUSH),
+        "RPUSH" => Ok(RESPCmd::RPUSH),
+        "LPUSHX" => Ok(RESPCmd::LPUSHX),
+        "RPUSHX" => Ok(RESPCmd::RPUSHX),
+        "LPOP" => Ok(RESPCmd::LPOP),
+        "RPOP" => Ok(RESPCmd::RPOP),
+        "LLEN" => Ok(RESPCmd::LLEN),
+        "LRANGE" => Ok(RESPCmd::LRANGE),
+        "LMOVE" => Ok(RESPCmd::LMOVE),
+        "LMPOP" => Ok(RESPCmd::LMPOP),
+        "BLPOP" => Ok(RESPCmd::BLPOP),
+        "BRPOP" => Ok(RESPCmd::BRPOP),
+        "BLMOVE" => Ok(RESPCmd::BLMOVE),
+        "BLMPOP" => Ok(RESPCmd::BLMPOP),
+        "HSET" => Ok(RESPCmd::HSET),
+        "HMSET" => Ok(RESPCmd::HMSET),
+        "HSETNX" => Ok(RESPCmd::HSETNX),
+        "HGET" => Ok(RESPCmd::HGET),
+        "HMGET" => Ok(RESPCmd::HMGET),
+        "HDEL" => Ok(RESPCmd::HDEL),
+        "HGETALL" => Ok(RESPCmd::HGETALL),
+        "HKEYS" => Ok(RESPCmd::HKEYS),
+        "HVALS" => Ok(RESPCmd::HVALS),
+        "HLEN" => Ok(RESPCmd::HLEN),
+        "HEXISTS" => Ok(RESPCmd::HEXISTS),
+        "HINCRBY" => Ok(RESPCmd::HINCRBY),
+        "HINCRBYFLOAT" => Ok(RESPCmd::HINCRBYFLOAT),
+        "HRANDFIELD" => Ok(RESPCmd::HRANDFIELD),
+        "HSCAN" => Ok(RESPCmd::HSCAN),
+        "HEXPIRE" => Ok(RESPCmd::HEXPIRE),
+        "HPEXPIRE" => Ok(RESPCmd::HPEXPIRE),
+        "HEXPIREAT" => Ok(RESPCmd::HEXPIREAT),
+        "HPEXPIREAT" => Ok(RESPCmd::HPEXPIREAT),
+        "HTTL" => Ok(RESPCmd::HTTL),
+        "HPERSIST" => Ok(RESPCmd::HPERSIST),
+        "SADD" => Ok(RESPCmd::SADD),
+        "SREM" => Ok(RESPCmd::SREM),
+        "SMEMBERS" => Ok(RESPCmd::SMEMBERS),
+        "SISMEMBER" => Ok(RESPCmd::SISMEMBER),
+        "SMISMEMBER" => Ok(RESPCmd::SMISMEMBER),
+        "SCARD" => Ok(RESPCmd::SCARD),
+        "SPOP" => Ok(RESPCmd::SPOP),
+        "SRANDMEMBER" => Ok(RESPCmd::SRANDMEMBER),
+        "SINTER" => Ok(RESPCmd::SINTER),
+        "SUNION" => Ok(RESPCmd::SUNION),
+        "SDIFF" => Ok(RESPCmd::SDIFF),
+        "SINTERSTORE" => Ok(RESPCmd::SINTERSTORE),
+        "SUNIONSTORE" => Ok(RESPCmd::SUNIONSTORE),
+        "SDIFFSTORE" => Ok(RESPCmd::SDIFFSTORE),
+        "SINTERCARD" => Ok(RESPCmd::SINTERCARD),
+        "SMOVE" => Ok(RESPCmd::SMOVE),
+        "SSCAN" => Ok(RESPCmd::SSCAN),
+        "ZADD" => Ok(RESPCmd::ZADD),
+        "ZINCRBY" => Ok(RESPCmd::ZINCRBY),
+        "ZREM" => Ok(RESPCmd::ZREM),
+        "ZSCORE" => Ok(RESPCmd::ZSCORE),
+        "ZCARD" => Ok(RESPCmd::ZCARD),
+        "ZRANK" => Ok(RESPCmd::ZRANK),
+        "ZREVRANK" => Ok(RESPCmd::ZREVRANK),
+        "ZRANGE" => Ok(RESPCmd::ZRANGE),
+        "ZCOUNT" => Ok(RESPCmd::ZCOUNT),
+        "ZLEXCOUNT" => Ok(RESPCmd::ZLEXCOUNT),
+        "ZPOPMIN" => Ok(RESPCmd::ZPOPMIN),
+        "ZPOPMAX" => Ok(RESPCmd::ZPOPMAX),
+        "BZPOPMIN" => Ok(RESPCmd::BZPOPMIN),
+        "BZPOPMAX" => Ok(RESPCmd::BZPOPMAX),
+        "ZUNIONSTORE" => Ok(RESPCmd::ZUNIONSTORE),
+        "ZINTERSTORE" => Ok(RESPCmd::ZINTERSTORE),
+        "ZSCAN" => Ok(RESPCmd::ZSCAN),
+        "XADD" => Ok(RESPCmd::XADD),
+        "XRANGE" => Ok(RESPCmd::XRANGE),
+        "XREVRANGE" => Ok(RESPCmd::XREVRANGE),
+        "XLEN" => Ok(RESPCmd::XLEN),
+        "XTRIM" => Ok(RESPCmd::XTRIM),
+        "XDEL" => Ok(RESPCmd::XDEL),
+        "XREAD" => Ok(RESPCmd::XREAD),
+        "XGROUP" => Ok(RESPCmd::XGROUP),
+        "XREADGROUP" => Ok(RESPCmd::XREADGROUP),
+        "XACK" => Ok(RESPCmd::XACK),
+        "XPENDING" => Ok(RESPCmd::XPENDING),
+        "XCLAIM" => Ok(RESPCmd::XCLAIM),
+        "XAUTOCLAIM" => Ok(RESPCmd::XAUTOCLAIM),
+        "XINFO" => Ok(RESPCmd::XINFO),
+        _ => Err(anyhow!(RedisError::UnknownCommand(cmd_id.to_lowercase()))),
     };
 }
 
 impl RESPCmd {
+    #[allow(clippy::too_many_arguments)]
     pub fn execute<T: Store>(
         &self,
-        reader: &mut BufReader<&TcpStream>,
         writer: &mut BufWriter<&TcpStream>,
-        array_stack: &mut ArrayStack,
+        args: &[Vec<u8>],
         store: &mut T,
         config: &Arc<Config>,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+        pubsub: &PubSub,
     ) -> Result<()> {
         log::debug(f!("Running cmd {:?}", &self));
+        let result = if self.is_blocking() {
+            // NOTE: made room for before parking, blocking cmds take the exec lock themselves
+            exec_lock
+                .run(|| return make_room(store, replicas, self.denies_oom()))
+                .and_then(|_| return self.run_blocking(writer, args, store, replicas, exec_lock))
+        } else {
+            exec_lock.run(|| {
+                make_room(store, replicas, self.denies_oom())?;
+                return self
+                    .run_and_propagate(writer, args, store, config, replicas, exec_lock, pubsub);
+            })
+        };
+        return respond(writer, result);
+    }
+
+    /// Runs a cmd queued in a transaction, from EXEC which already holds the exec lock and
+    /// made room for the whole transaction.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn execute_queued<T: Store>(
+        &self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[Vec<u8>],
+        store: &mut T,
+        config: &Arc<Config>,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        log::debug(f!("Running queued cmd {:?}", &self));
+        let result = if self.is_blocking() {
+            self.run_blocking(writer, args, store, replicas, exec_lock)
+        } else {
+            self.run_and_propagate(writer, args, store, config, replicas, exec_lock, pubsub)
+        };
+        return respond(writer, result);
+    }
+
+    /// Blocking cmds take the exec lock themselves, releasing it while parked.
+    fn run_blocking<T: Store>(
+        &self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[Vec<u8>],
+        store: &mut T,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+    ) -> Result<()> {
+        let args = &util::text_args(args)?;
+        return match &self {
+            RESPCmd::BLPOP => {
+                lists::blocking_pop(writer, args, store, replicas, exec_lock, ListEnd::Left)
+            }
+            RESPCmd::BRPOP => {
+                lists::blocking_pop(writer, args, store, replicas, exec_lock, ListEnd::Right)
+            }
+            RESPCmd::BLMOVE => lists::blmove(writer, args, store, replicas, exec_lock),
+            RESPCmd::BLMPOP => lists::blmpop(writer, args, store, replicas, exec_lock),
+            RESPCmd::BZPOPMIN => {
+                sorted_sets::blocking_zpop(writer, args, store, replicas, exec_lock, ScoreEnd::Min)
+            }
+            RESPCmd::BZPOPMAX => {
+                sorted_sets::blocking_zpop(writer, args, store, replicas, exec_lock, ScoreEnd::Max)
+            }
+            RESPCmd::XREAD => streams::xread(writer, args, store, exec_lock),
+            RESPCmd::XREADGROUP => {
+                stream_groups::xreadgroup(writer, args, store, replicas, exec_lock)
+            }
+            _ => unreachable!("Only blocking cmds are run by run_blocking"),
+        };
+    }
+
+    /// Runs the cmd under the exec lock, propagating it to the replicas once applied.
+    #[allow(clippy::too_many_arguments)]
+    fn run_and_propagate<T: Store>(
+        &self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[Vec<u8>],
+        store: &mut T,
+        config: &Arc<Config>,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let result = propagating_expired(store, replicas, |store| {
+            self.run(writer, args, store, config, replicas, exec_lock, pubsub)?;
+            if self.is_write() {
+                let mut cmd = vec![f!("{:?}", self).into_bytes()];
+                cmd.extend_from_slice(args);
+                replicas.propagate(&cmd);
+            }
+            return Ok(());
+        });
+        if result.is_ok() && (self.is_write() || self.propagates_itself()) {
+            exec_lock.signal_keys_ready();
+        }
+        return result;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run<T: Store>(
+        &self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[Vec<u8>],
+        store: &mut T,
+        config: &Arc<Config>,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        if self.is_binary_safe() {
+            return self.run_binary_safe(writer, args, store, replicas);
+        }
+
+        let args = &util::text_args(args)?;
         return match &self {
             RESPCmd::PING => ping(writer),
-            RESPCmd::ECHO => echo(reader, writer, array_stack),
-            RESPCmd::SET => set(reader, writer, array_stack, store),
-            RESPCmd::GET => get(reader, writer, array_stack, store),
-            RESPCmd::INFO => info(reader, writer, array_stack, config),
-            RESPCmd::REPLCONF => repl_conf(reader, writer, array_stack, config),
-            RESPCmd::PSYNC => psync(reader, writer, array_stack, config),
+            RESPCmd::ECHO => echo(writer, args),
+            RESPCmd::GET => get(writer, args, store),
+            RESPCmd::INCR => strings::incr(writer, args, store, false),
+            RESPCmd::DECR => strings::incr(writer, args, store, true),
+            RESPCmd::INCRBY => strings::incrby(writer, args, store, false),
+            RESPCmd::DECRBY => strings::incrby(writer, args, store, true),
+            RESPCmd::INCRBYFLOAT => strings::incrbyfloat(writer, args, store),
+            RESPCmd::STRLEN => strings::strlen(writer, args, store),
+            RESPCmd::GETRANGE => strings::getrange(writer, args, store),
+            RESPCmd::GETDEL => strings::getdel(writer, args, store),
+            RESPCmd::GETEX => strings::getex(writer, args, store, replicas),
+            RESPCmd::MGET => strings::mget(writer, args, store),
+            RESPCmd::LCS => strings::lcs(writer, args, store),
+            RESPCmd::SETBIT => bitmaps::setbit(writer, args, store),
+            RESPCmd::GETBIT => bitmaps::getbit(writer, args, store),
+            RESPCmd::BITCOUNT => bitmaps::bitcount(writer, args, store),
+            RESPCmd::BITPOS => bitmaps::bitpos(writer, args, store),
+            RESPCmd::BITOP => bitmaps::bitop(writer, args, store),
+            RESPCmd::BITFIELD => bitmaps::bitfield(writer, args, store, replicas, false),
+            RESPCmd::BITFIELD_RO => bitmaps::bitfield(writer, args, store, replicas, true),
+            RESPCmd::PFADD => hyperloglogs::pfadd(writer, args, store),
+            RESPCmd::PFCOUNT => hyperloglogs::pfcount(writer, args, store),
+            RESPCmd::PFMERGE => hyperloglogs::pfmerge(writer, args, store),
+            RESPCmd::GEOADD => geo::geoadd(writer, args, store),
+            RESPCmd::GEOPOS => geo::geopos(writer, args, store),
+            RESPCmd::GEODIST => geo::geodist(writer, args, store),
+            RESPCmd::GEOHASH => geo::geohash(writer, args, store),
+            RESPCmd::GEOSEARCH => geo::geosearch(writer, args, store),
+            RESPCmd::GEOSEARCHSTORE => geo::geosearchstore(writer, args, store),
+            RESPCmd::DEL => keys::del(writer, args, store, false),
+            RESPCmd::UNLINK => keys::del(writer, args, store, true),
+            RESPCmd::EXISTS => keys::exists(writer, args, store, "exists"),
+            RESPCmd::TYPE => keys::key_type(writer, args, store),
+            RESPCmd::RENAME => keys::rename(writer, args, store, false),
+            RESPCmd::RENAMENX => keys::rename(writer, args, store, true),
+            RESPCmd::COPY => keys::copy(writer, args, store),
+            RESPCmd::TOUCH => keys::exists(writer, args, store, "touch"),
+            RESPCmd::RANDOMKEY => keys::randomkey(writer, args, store),
+            RESPCmd::DBSIZE => keys::dbsize(writer, args, store),
+            RESPCmd::EXPIRE => keys::expire(writer, args, store, replicas, 1000, true),
+            RESPCmd::PEXPIRE => keys::expire(writer, args, store, replicas, 1, true),
+            RESPCmd::EXPIREAT => keys::expire(writer, args, store, replicas, 1000, false),
+            RESPCmd::PEXPIREAT => keys::expire(writer, args, store, replicas, 1, false),
+            RESPCmd::TTL => keys::ttl(writer, args, store, false),
+            RESPCmd::PTTL => keys::ttl(writer, args, store, true),
+            RESPCmd::EXPIRETIME => keys::expiretime(writer, args, store, false),
+            RESPCmd::PEXPIRETIME => keys::expiretime(writer, args, store, true),
+            RESPCmd::PERSIST => keys::persist(writer, args, store),
+            RESPCmd::KEYS => keys::keys(writer, args, store),
+            RESPCmd::SCAN => keys::scan(writer, args, store),
+            RESPCmd::SELECT => databases::select(writer, args, store, replicas, exec_lock),
+            RESPCmd::MOVE => databases::move_key(writer, args, store),
+            RESPCmd::SWAPDB => databases::swapdb(writer, args, store),
+            RESPCmd::FLUSHDB => databases::flush(writer, args, store, false),
+            RESPCmd::FLUSHALL => databases::flush(writer, args, store, true),
+            RESPCmd::OBJECT => objects::object(writer, args, store, config),
+            RESPCmd::MEMORY => objects::memory(writer, args, store),
+            RESPCmd::INFO => info(writer, args, config),
+            RESPCmd::REPLCONF => repl_conf(writer, args, config),
+            RESPCmd::PSYNC => psync(writer, args, config, replicas),
+            RESPCmd::LPUSH => lists::push(writer, args, store, ListEnd::Left, false),
+            RESPCmd::RPUSH => lists::push(writer, args, store, ListEnd::Right, false),
+            RESPCmd::LPUSHX => lists::push(writer, args, store, ListEnd::Left, true),
+            RESPCmd::RPUSHX => lists::push(writer, args, store, ListEnd::Right, true),
+            RESPCmd::LPOP => lists::pop(writer, args, store, ListEnd::Left),
+            RESPCmd::RPOP => lists::pop(writer, args, store, ListEnd::Right),
+            RESPCmd::LLEN => lists::llen(writer, args, store),
+            RESPCmd::LRANGE => lists::lrange(writer, args, store),
+            RESPCmd::LMOVE => lists::lmove(writer, args, store),
+            RESPCmd::LMPOP => lists::lmpop(writer, args, store),
+            RESPCmd::HSET => hashes::hset(writer, args, store),
+            RESPCmd::HMSET => hashes::hmset(writer, args, store),
+            RESPCmd::HSETNX => hashes::hsetnx(writer, args, store),
+            RESPCmd::HGET => hashes::hget(writer, args, store),
+            RESPCmd::HMGET => hashes::hmget(writer, args, store),
+            RESPCmd::HDEL => hashes::hdel(writer, args, store),
+            RESPCmd::HGETALL => hashes::hgetall(writer, args, store, true, true),
+            RESPCmd::HKEYS => hashes::hgetall(writer, args, store, true, false),
+            RESPCmd::HVALS => hashes::hgetall(writer, args, store, false, true),
+            RESPCmd::HLEN => hashes::hlen(writer, args, store),
+            RESPCmd::HEXISTS => hashes::hexists(writer, args, store),
+            RESPCmd::HINCRBY => hashes::hincrby(writer, args, store),
+            RESPCmd::HINCRBYFLOAT => hashes::hincrbyfloat(writer, args, store),
+            RESPCmd::HRANDFIELD => hashes::hrandfield(writer, args, store),
+            RESPCmd::HSCAN => hashes::hscan(writer, args, store),
+            RESPCmd::HEXPIRE => hashes::hexpire(writer, args, store, replicas, 1000, true),
+            RESPCmd::HPEXPIRE => hashes::hexpire(writer, args, store, replicas, 1, true),
+            RESPCmd::HEXPIREAT => hashes::hexpire(writer, args, store, replicas, 1000, false),
+            RESPCmd::HPEXPIREAT => hashes::hexpire(writer, args, store, replicas, 1, false),
+            RESPCmd::HTTL => hashes::httl(writer, args, store),
+            RESPCmd::HPERSIST => hashes::hpersist(writer, args, store),
+            RESPCmd::SADD => sets::sadd(writer, args, store),
+            RESPCmd::SREM => sets::srem(writer, args, store),
+            RESPCmd::SMEMBERS => sets::smembers(writer, args, store),
+            RESPCmd::SISMEMBER => sets::sismember(writer, args, store),
+            RESPCmd::SMISMEMBER => sets::smismember(writer, args, store),
+            RESPCmd::SCARD => sets::scard(writer, args, store),
+            RESPCmd::SPOP => sets::spop(writer, args, store, replicas),
+            RESPCmd::SRANDMEMBER => sets::srandmember(writer, args, store),
+            RESPCmd::SINTER => sets::combine(writer, args, store, SetOperation::Intersection),
+            RESPCmd::SUNION => sets::combine(writer, args, store, SetOperation::Union),
+            RESPCmd::SDIFF => sets::combine(writer, args, store, SetOperation::Difference),
+            RESPCmd::SINTERSTORE => {
+                sets::combine_store(writer, args, store, SetOperation::Intersection)
+            }
+            RESPCmd::SUNIONSTORE => sets::combine_store(writer, args, store, SetOperation::Union),
+            RESPCmd::SDIFFSTORE => {
+                sets::combine_store(writer, args, store, SetOperation::Difference)
+            }
+            RESPCmd::SINTERCARD => sets::sintercard(writer, args, store),
+            RESPCmd::SMOVE => sets::smove(writer, args, store),
+            RESPCmd::SSCAN => sets::sscan(writer, args, store),
+            RESPCmd::ZADD => sorted_sets::zadd(writer, args, store),
+            RESPCmd::ZINCRBY => sorted_sets::zincrby(writer, args, store),
+            RESPCmd::ZREM => sorted_sets::zrem(writer, args, store),
+            RESPCmd::ZSCORE => sorted_sets::zscore(writer, args, store),
+            RESPCmd::ZCARD => sorted_sets::zcard(writer, args, store),
+            RESPCmd::ZRANK => sorted_sets::zrank(writer, args, store, false),
+            RESPCmd::ZREVRANK => sorted_sets::zrank(writer, args, store, true),
+            RESPCmd::ZRANGE => sorted_sets::zrange(writer, args, store),
+            RESPCmd::ZCOUNT => sorted_sets::zcount(writer, args, store),
+            RESPCmd::ZLEXCOUNT => sorted_sets::zlexcount(writer, args, store),
+            RESPCmd::ZPOPMIN => sorted_sets::zpop(writer, args, store, ScoreEnd::Min),
+            RESPCmd::ZPOPMAX => sorted_sets::zpop(writer, args, store, ScoreEnd::Max),
+            RESPCmd::ZUNIONSTORE => {
+                sorted_sets::combine_store(writer, args, store, SetOperation::Union)
+            }
+            RESPCmd::ZINTERSTORE => {
+                sorted_sets::combine_store(writer, args, store, SetOperation::Intersection)
+            }
+            RESPCmd::ZSCAN => sorted_sets::zscan(writer, args, store),
+            RESPCmd::XADD => streams::xadd(writer, args, store, replicas),
+            RESPCmd::XRANGE => streams::xrange(writer, args, store, false),
+            RESPCmd::XREVRANGE => streams::xrange(writer, args, store, true),
+            RESPCmd::XLEN => streams::xlen(writer, args, store),
+            RESPCmd::XTRIM => streams::xtrim(writer, args, store, replicas),
+            RESPCmd::XDEL => streams::xdel(writer, args, store),
+            RESPCmd::XGROUP => stream_groups::xgroup(writer, args, store),
+            RESPCmd::XACK => stream_groups::xack(writer, args, store),
+            RESPCmd::XPENDING => stream_groups::xpending(writer, args, store),
+            RESPCmd::XCLAIM => stream_groups::xclaim(writer, args, store, replicas),
+            RESPCmd::XAUTOCLAIM => stream_groups::xautoclaim(writer, args, store, replicas),
+            RESPCmd::XINFO => stream_groups::xinfo(writer, args, store),
+            RESPCmd::BLPOP
+            | RESPCmd::BRPOP
+            | RESPCmd::BLMOVE
+            | RESPCmd::BLMPOP
+            | RESPCmd::BZPOPMIN
+            | RESPCmd::BZPOPMAX
+            | RESPCmd::XREAD
+            | RESPCmd::XREADGROUP => {
+                unreachable!("Blocking cmds are executed outside the exec lock")
+            }
+            RESPCmd::SET
+            | RESPCmd::SETNX
+            | RESPCmd::SETEX
+            | RESPCmd::PSETEX
+            | RESPCmd::APPEND
+            | RESPCmd::SETRANGE
+            | RESPCmd::MSET
+            | RESPCmd::MSETNX => {
+                unreachable!("Binary safe cmds are run by run_binary_safe")
+            }
+            // NOTE: only queued in a transaction, whose keys EXEC unwatches anyway
+            RESPCmd::UNWATCH => reply::ok(writer),
+            RESPCmd::MULTI | RESPCmd::EXEC | RESPCmd::DISCARD | RESPCmd::WATCH => {
+                unreachable!("Transactions are handled by the client loop")
+            }
+            RESPCmd::PUBLISH => pubsub::publish(writer, args, pubsub),
+            RESPCmd::PUBSUB => pubsub::pubsub(writer, args, pubsub),
+            RESPCmd::SPUBLISH => pubsub::spublish(writer, args, pubsub),
+            RESPCmd::SUBSCRIBE
+            | RESPCmd::UNSUBSCRIBE
+            | RESPCmd::PSUBSCRIBE
+            | RESPCmd::PUNSUBSCRIBE
+            | RESPCmd::SSUBSCRIBE
+            | RESPCmd::SUNSUBSCRIBE
+            | RESPCmd::QUIT
+            | RESPCmd::RESET => {
+                unreachable!("Connection state cmds are handled by the client loop")
+            }
+        };
+    }
+
+    /// Runs the cmds writing string values, which are kept as the raw bytes sent.
+    fn run_binary_safe<T: Store>(
+        &self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[Vec<u8>],
+        store: &mut T,
+        replicas: &Replicas,
+    ) -> Result<()> {
+        return match &self {
+            RESPCmd::SET => set(writer, args, store, replicas),
+            RESPCmd::SETNX => strings::setnx(writer, args, store),
+            RESPCmd::SETEX => strings::setex(writer, args, store, replicas, "EX"),
+            RESPCmd::PSETEX => strings::setex(writer, args, store, replicas, "PX"),
+            RESPCmd::APPEND => strings::append(writer, args, store),
+            RESPCmd::SETRANGE => strings::setrange(writer, args, store),
+            RESPCmd::MSET => strings::mset(writer, args, store, false),
+            RESPCmd::MSETNX => strings::mset(writer, args, store, true),
+            _ => unreachable!("Only binary safe cmds are run by run_binary_safe"),
+        };
+    }
+
+    /// Args the cmd takes, counting its name like the arity of redis: exactly that many when
+    /// positive, at least minus that many when negative. Checked when cmds are queued in a
+    /// transaction, the cmds themselves check their args when they run.
+    fn arity(&self) -> i64 {
+        return match self {
+            RESPCmd::RANDOMKEY
+            | RESPCmd::DBSIZE
+            | RESPCmd::MULTI
+            | RESPCmd::EXEC
+            | RESPCmd::DISCARD
+            | RESPCmd::UNWATCH
+            | RESPCmd::RESET => 1,
+            RESPCmd::ECHO
+            | RESPCmd::GET
+            | RESPCmd::INCR
+            | RESPCmd::DECR
+            | RESPCmd::STRLEN
+            | RESPCmd::GETDEL
+            | RESPCmd::TYPE
+            | RESPCmd::TTL
+            | RESPCmd::PTTL
+            | RESPCmd::EXPIRETIME
+            | RESPCmd::PEXPIRETIME
+            | RESPCmd::PERSIST
+            | RESPCmd::KEYS
+            | RESPCmd::SELECT
+            | RESPCmd::LLEN
+            | RESPCmd::HGETALL
+            | RESPCmd::HKEYS
+            | RESPCmd::HVALS
+            | RESPCmd::HLEN
+            | RESPCmd::SMEMBERS
+            | RESPCmd::SCARD
+            | RESPCmd::ZCARD
+            | RESPCmd::XLEN => 2,
+            RESPCmd::INCRBY
+            | RESPCmd::DECRBY
+            | RESPCmd::INCRBYFLOAT
+            | RESPCmd::APPEND
+            | RESPCmd::SETNX
+            | RESPCmd::GETBIT
+            | RESPCmd::RENAME
+            | RESPCmd::RENAMENX
+            | RESPCmd::MOVE
+            | RESPCmd::SWAPDB
+            | RESPCmd::HGET
+            | RESPCmd::HEXISTS
+            | RESPCmd::SISMEMBER
+            | RESPCmd::ZSCORE
+            | RESPCmd::PUBLISH
+            | RESPCmd::SPUBLISH => 3,
+            RESPCmd::GETRANGE
+            | RESPCmd::SETRANGE
+            | RESPCmd::SETEX
+            | RESPCmd::PSETEX
+            | RESPCmd::SETBIT
+            | RESPCmd::LRANGE
+            | RESPCmd::HSETNX
+            | RESPCmd::HINCRBY
+            | RESPCmd::HINCRBYFLOAT
+            | RESPCmd::SMOVE
+            | RESPCmd::ZINCRBY
+            | RESPCmd::ZCOUNT
+            | RESPCmd::ZLEXCOUNT => 4,
+            RESPCmd::LMOVE => 5,
+            RESPCmd::BLMOVE => 6,
+            RESPCmd::PING
+            | RESPCmd::FLUSHDB
+            | RESPCmd::FLUSHALL
+            | RESPCmd::INFO
+            | RESPCmd::REPLCONF
+            | RESPCmd::UNSUBSCRIBE
+            | RESPCmd::PUNSUBSCRIBE
+            | RESPCmd::SUNSUBSCRIBE
+            | RESPCmd::QUIT => -1,
+            RESPCmd::GETEX
+            | RESPCmd::MGET
+            | RESPCmd::BITCOUNT
+            | RESPCmd::BITFIELD
+            | RESPCmd::BITFIELD_RO
+            | RESPCmd::PFADD
+            | RESPCmd::PFCOUNT
+            | RESPCmd::PFMERGE
+            | RESPCmd::GEOPOS
+            | RESPCmd::GEOHASH
+            | RESPCmd::DEL
+            | RESPCmd::UNLINK
+            | RESPCmd::EXISTS
+            | RESPCmd::TOUCH
+            | RESPCmd::SCAN
+            | RESPCmd::OBJECT
+            | RESPCmd::MEMORY
+            | RESPCmd::LPOP
+            | RESPCmd::RPOP
+            | RESPCmd::HRANDFIELD
+            | RESPCmd::SPOP
+            | RESPCmd::SRANDMEMBER
+            | RESPCmd::SINTER
+            | RESPCmd::SUNION
+            | RESPCmd::SDIFF
+            | RESPCmd::ZPOPMIN
+            | RESPCmd::ZPOPMAX
+            | RESPCmd::XGROUP
+            | RESPCmd::XINFO
+            | RESPCmd::WATCH
+            | RESPCmd::SUBSCRIBE
+            | RESPCmd::PSUBSCRIBE
+            | RESPCmd::SSUBSCRIBE
+            | RESPCmd::PUBSUB => -2,
+            RESPCmd::SET
+            | RESPCmd::MSET
+            | RESPCmd::MSETNX
+            | RESPCmd::LCS
+            | RESPCmd::BITPOS
+            | RESPCmd::COPY
+            | RESPCmd::EXPIRE
+            | RESPCmd::PEXPIRE
+            | RESPCmd::EXPIREAT
+            | RESPCmd::PEXPIREAT
+            | RESPCmd::PSYNC
+            | RESPCmd::LPUSH
+            | RESPCmd::RPUSH
+            | RESPCmd::LPUSHX
+            | RESPCmd::RPUSHX
+            | RESPCmd::BLPOP
+            | RESPCmd::BRPOP
+            | RESPCmd::HMGET
+            | RESPCmd::HDEL
+            | RESPCmd::HSCAN
+            | RESPCmd::SADD
+            | RESPCmd::SREM
+            | RESPCmd::SMISMEMBER
+            | RESPCmd::SINTERSTORE
+            | RESPCmd::SUNIONSTORE
+            | RESPCmd::SDIFFSTORE
+            | RESPCmd::SINTERCARD
+            | RESPCmd::SSCAN
+            | RESPCmd::ZREM
+            | RESPCmd::ZRANK
+            | RESPCmd::ZREVRANK
+            | RESPCmd::BZPOPMIN
+            | RESPCmd::BZPOPMAX
+            | RESPCmd::ZSCAN
+            | RESPCmd::XDEL
+            | RESPCmd::XPENDING => -3,
+            RESPCmd::BITOP
+            | RESPCmd::GEODIST
+            | RESPCmd::LMPOP
+            | RESPCmd::HSET
+            | RESPCmd::HMSET
+            | RESPCmd::ZADD
+            | RESPCmd::ZRANGE
+            | RESPCmd::ZUNIONSTORE
+            | RESPCmd::ZINTERSTORE
+            | RESPCmd::XRANGE
+            | RESPCmd::XREVRANGE
+            | RESPCmd::XTRIM
+            | RESPCmd::XREAD
+            | RESPCmd::XACK => -4,
+            RESPCmd::GEOADD
+            | RESPCmd::BLMPOP
+            | RESPCmd::HTTL
+            | RESPCmd::HPERSIST
+            | RESPCmd::XADD => -5,
+            RESPCmd::HEXPIRE
+            | RESPCmd::HPEXPIRE
+            | RESPCmd::HEXPIREAT
+            | RESPCmd::HPEXPIREAT
+            | RESPCmd::XCLAIM
+            | RESPCmd::XAUTOCLAIM => -6,
+            RESPCmd::GEOSEARCH | RESPCmd::XREADGROUP => -7,
+            RESPCmd::GEOSEARCHSTORE => -8,
         };
     }
+
+    /// Whether `count` args (the name aside) fit the arity of the cmd.
+    pub(super) fn accepts_args(&self, count: usize) -> bool {
+        let arity = self.arity();
+        return if arity >= 0 {
+            count as i64 + 1 == arity
+        } else {
+            count as i64 + 1 >= -arity
+        };
+    }
+
+    /// Name of the cmd, as used in error messages.
+    pub(super) fn name(&self) -> String {
+        return f!("{:?}", self).to_lowercase();
+    }
+
+    fn is_blocking(&self) -> bool {
+        return matches!(
+            self,
+            RESPCmd::BLPOP
+                | RESPCmd::BRPOP
+                | RESPCmd::BLMOVE
+                | RESPCmd::BLMPOP
+                | RESPCmd::BZPOPMIN
+                | RESPCmd::BZPOPMAX
+                | RESPCmd::XREAD
+                | RESPCmd::XREADGROUP
+        );
+    }
+
+    /// Cmds whose string values may hold any bytes. Every other arg, of every cmd, has to be
+    /// valid UTF-8.
+    pub fn is_binary_safe(&self) -> bool {
+        return matches!(
+            self,
+            RESPCmd::SET
+                | RESPCmd::SETNX
+                | RESPCmd::SETEX
+                | RESPCmd::PSETEX
+                | RESPCmd::APPEND
+                | RESPCmd::SETRANGE
+                | RESPCmd::MSET
+                | RESPCmd::MSETNX
+        );
+    }
+
+    /// Cmds forwarded verbatim to the replicas, (S)PUBLISH included so the subscribers of
+    /// the replicas get the messages too. Blocking cmds are not listed here since they propagate
+    /// the non blocking equivalent of what they did once served.
+    fn is_write(&self) -> bool {
+        return matches!(
+            self,
+            RESPCmd::INCR
+                | RESPCmd::DECR
+                | RESPCmd::INCRBY
+                | RESPCmd::DECRBY
+                | RESPCmd::INCRBYFLOAT
+                | RESPCmd::APPEND
+                | RESPCmd::SETRANGE
+                | RESPCmd::GETDEL
+                | RESPCmd::SETNX
+                | RESPCmd::MSET
+                | RESPCmd::MSETNX
+                | RESPCmd::SETBIT
+                | RESPCmd::BITOP
+                | RESPCmd::PFADD
+                | RESPCmd::PFMERGE
+                | RESPCmd::GEOADD
+                | RESPCmd::GEOSEARCHSTORE
+                | RESPCmd::DEL
+                | RESPCmd::UNLINK
+                | RESPCmd::RENAME
+                | RESPCmd::RENAMENX
+                | RESPCmd::COPY
+                | RESPCmd::PERSIST
+                | RESPCmd::MOVE
+                | RESPCmd::SWAPDB
+                | RESPCmd::FLUSHDB
+                | RESPCmd::FLUSHALL
+                | RESPCmd::LPUSH
+                | RESPCmd::RPUSH
+                | RESPCmd::LPUSHX
+                | RESPCmd::RPUSHX
+                | RESPCmd::LPOP
+                | RESPCmd::RPOP
+                | RESPCmd::LMOVE
+                | RESPCmd::LMPOP
+                | RESPCmd::HSET
+                | RESPCmd::HMSET
+                | RESPCmd::HSETNX
+                | RESPCmd::HDEL
+                | RESPCmd::HINCRBY
+                | RESPCmd::HINCRBYFLOAT
+                | RESPCmd::HPERSIST
+                | RESPCmd::SADD
+                | RESPCmd::SREM
+                | RESPCmd::SINTERSTORE
+                | RESPCmd::SUNIONSTORE
+                | RESPCmd::SDIFFSTORE
+                | RESPCmd::SMOVE
+                | RESPCmd::ZADD
+                | RESPCmd::ZINCRBY
+                | RESPCmd::ZREM
+                | RESPCmd::ZPOPMIN
+                | RESPCmd::ZPOPMAX
+                | RESPCmd::ZUNIONSTORE
+                | RESPCmd::ZINTERSTORE
+                | RESPCmd::XDEL
+                | RESPCmd::XGROUP
+                | RESPCmd::XACK
+                | RESPCmd::PUBLISH
+                | RESPCmd::SPUBLISH
+        );
+    }
+
+    /// Cmds that may grow the dataset, refused when over maxmemory (`denyoom` in redis).
+    pub(super) fn denies_oom(&self) -> bool {
+        return matches!(
+            self,
+            RESPCmd::SET
+                | RESPCmd::SETNX
+                | RESPCmd::SETEX
+                | RESPCmd::PSETEX
+                | RESPCmd::MSET
+                | RESPCmd::MSETNX
+                | RESPCmd::APPEND
+                | RESPCmd::SETRANGE
+                | RESPCmd::INCR
+                | RESPCmd::DECR
+                | RESPCmd::INCRBY
+                | RESPCmd::DECRBY
+                | RESPCmd::INCRBYFLOAT
+                | RESPCmd::SETBIT
+                | RESPCmd::BITOP
+                | RESPCmd::BITFIELD
+                | RESPCmd::PFADD
+                | RESPCmd::PFMERGE
+                | RESPCmd::GEOADD
+                | RESPCmd::GEOSEARCHSTORE
+                | RESPCmd::COPY
+                | RESPCmd::LPUSH
+                | RESPCmd::RPUSH
+                | RESPCmd::LPUSHX
+                | RESPCmd::RPUSHX
+                | RESPCmd::LMOVE
+                | RESPCmd::BLMOVE
+                | RESPCmd::HSET
+                | RESPCmd::HMSET
+                | RESPCmd::HSETNX
+                | RESPCmd::HINCRBY
+                | RESPCmd::HINCRBYFLOAT
+                | RESPCmd::SADD
+                | RESPCmd::SINTERSTORE
+                | RESPCmd::SUNIONSTORE
+                | RESPCmd::SDIFFSTORE
+                | RESPCmd::ZADD
+                | RESPCmd::ZINCRBY
+                | RESPCmd::ZUNIONSTORE
+                | RESPCmd::ZINTERSTORE
+                | RESPCmd::XADD
+                | RESPCmd::XGROUP
+        );
+    }
+
+    /// Writes whose effect does not follow from their args alone (random picks, generated
+    /// IDs, relative expiries), so they propagate a deterministic equivalent themselves, and
+    /// cmds that only write depending on their args (BITFIELD).
+    fn propagates_itself(&self) -> bool {
+        return matches!(
+            self,
+            RESPCmd::SET
+                | RESPCmd::GETEX
+                | RESPCmd::SETEX
+                | RESPCmd::PSETEX
+                | RESPCmd::SPOP
+                | RESPCmd::XADD
+                | RESPCmd::XTRIM
+                | RESPCmd::XCLAIM
+                | RESPCmd::XAUTOCLAIM
+                | RESPCmd::BITFIELD
+                | RESPCmd::EXPIRE
+                | RESPCmd::PEXPIRE
+                | RESPCmd::EXPIREAT
+                | RESPCmd::PEXPIREAT
+                | RESPCmd::HEXPIRE
+                | RESPCmd::HPEXPIRE
+                | RESPCmd::HEXPIREAT
+                | RESPCmd::HPEXPIREAT
+        );
+    }
+}
+
+/// Evicts keys if the store is over maxmemory, before any cmd runs like redis does. If that
+/// was not enough, cmds that may grow the dataset are refused.
+pub(super) fn make_room<T: Store>(
+    store: &mut T,
+    replicas: &Replicas,
+    denies_oom: bool,
+) -> Result<()> {
+    let eviction = store.evict()?;
+    for (db, key) in &eviction.evicted {
+        replicas.propagate_in(*db, &["DEL", key]);
+    }
+    if !eviction.fits && denies_oom {
+        return Err(anyhow!(RedisError::OutOfMemory));
+    }
+    return Ok(());
+}
+
+/// Runs a cmd holding back what it propagates, so that the keys it found expired and deleted
+/// are propagated as DEL first: the replicas do not expire keys on their own clocks.
+pub(super) fn propagating_expired<T: Store, R>(
+    store: &mut T,
+    replicas: &Replicas,
+    cmd: impl FnOnce(&mut T) -> Result<R>,
+) -> Result<R> {
+    replicas.hold();
+    let result = cmd(store);
+    // NOTE: released even when the cmd failed, what it deleted is gone all the same
+    let expired = store.take_expired();
+    replicas.release(expired.as_deref().unwrap_or_default());
+    expired?;
+    return result;
+}
+
+/// Replies with the error a cmd failed with when it is meant for the client, then flushes.
+/// Any other error is a server side failure, left to the caller.
+pub(super) fn respond(writer: &mut impl Write, result: Result<()>) -> Result<()> {
+    if let Err(e) = result {
+        match e.downcast_ref::<RedisError>() {
+            Some(redis_error) => reply::error(writer, redis_error)?,
+            None => return Err(e),
+        }
+    }
+
+    writer.flush()?;
+    return Ok(());
 }
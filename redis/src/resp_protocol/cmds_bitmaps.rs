@@ -0,0 +1,274 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    persistence::{
+        BitOperation, BitRange, BitUnit, BitfieldOp, BitfieldOverflow, BitfieldType, Store,
+    },
+    replication::Replicas,
+};
+
+use super::{cmds_strings::MAX_BULK_LEN, reply, util};
+
+/// SETBIT key offset value
+pub fn setbit<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("setbit".into())));
+    }
+
+    let offset = parse_offset(&args[1], 1, false)?;
+    let value = match args[2].as_str() {
+        "0" => false,
+        "1" => true,
+        _ => {
+            return Err(anyhow!(RedisError::Generic(
+                "bit is not an integer or out of range".into()
+            )))
+        }
+    };
+    reply::integer(writer, store.bit_set(&args[0], offset, value)? as i64)?;
+    return Ok(());
+}
+
+/// GETBIT key offset
+pub fn getbit<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("getbit".into())));
+    }
+
+    let offset = parse_offset(&args[1], 1, false)?;
+    reply::integer(writer, store.bit_get(&args[0], offset)? as i64)?;
+    return Ok(());
+}
+
+/// BITCOUNT key [start end [BYTE | BIT]]
+pub fn bitcount<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("bitcount".into())));
+    }
+
+    let range = match args.len() {
+        1 => None,
+        3 | 4 => Some(BitRange {
+            start: util::parse_int(&args[1])?,
+            end: Some(util::parse_int(&args[2])?),
+            unit: parse_unit(args.get(3))?,
+        }),
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+    reply::integer(writer, store.bit_count(&args[0], range)? as i64)?;
+    return Ok(());
+}
+
+/// BITPOS key bit [start [end [BYTE | BIT]]]
+pub fn bitpos<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("bitpos".into())));
+    }
+
+    let value = match util::parse_int(&args[1])? {
+        0 => false,
+        1 => true,
+        _ => {
+            return Err(anyhow!(RedisError::Generic(
+                "The bit argument must be 1 or 0.".into()
+            )))
+        }
+    };
+    let range = match args.len() {
+        2 => None,
+        3..=5 => Some(BitRange {
+            start: util::parse_int(&args[2])?,
+            end: args
+                .get(3)
+                .map(|end| return util::parse_int(end))
+                .transpose()?,
+            unit: parse_unit(args.get(4))?,
+        }),
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+    reply::integer(writer, store.bit_position(&args[0], value, range)?)?;
+    return Ok(());
+}
+
+/// BITOP AND | OR | XOR | NOT destkey key [key ...]
+pub fn bitop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity("bitop".into())));
+    }
+
+    let operation = match args[0].to_uppercase().as_str() {
+        "AND" => BitOperation::And,
+        "OR" => BitOperation::Or,
+        "XOR" => BitOperation::Xor,
+        "NOT" => BitOperation::Not,
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+    let keys = &args[2..];
+    if operation == BitOperation::Not && keys.len() != 1 {
+        return Err(anyhow!(RedisError::Generic(
+            "BITOP NOT must be called with a single source key.".into()
+        )));
+    }
+
+    reply::integer(writer, store.bit_op(operation, &args[1], keys)? as i64)?;
+    return Ok(());
+}
+
+/// BITFIELD key [GET encoding offset | [OVERFLOW WRAP | SAT | FAIL]
+/// SET encoding offset value | INCRBY encoding offset increment ...]
+///
+/// BITFIELD_RO key [GET encoding offset ...] when `read_only` is set.
+///
+/// Only a BITFIELD that writes something is sent to the replicas.
+pub fn bitfield<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    read_only: bool,
+) -> Result<()> {
+    if args.is_empty() {
+        let cmd = if read_only { "bitfield_ro" } else { "bitfield" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let ops = read_bitfield_ops(&args[1..], read_only)?;
+    let replies = store.bitfield(&args[0], &ops)?;
+
+    if ops
+        .iter()
+        .any(|op| return !matches!(op, BitfieldOp::Get { .. }))
+    {
+        let mut cmd = vec!["BITFIELD"];
+        cmd.extend(args.iter().map(|arg| return arg.as_str()));
+        replicas.propagate(&cmd);
+    }
+
+    reply::array_header(writer, replies.len())?;
+    for value in replies {
+        match value {
+            Some(value) => reply::integer(writer, value)?,
+            None => reply::null_bulk_string(writer)?,
+        }
+    }
+    return Ok(());
+}
+
+fn read_bitfield_ops(args: &[String], read_only: bool) -> Result<Vec<BitfieldOp>> {
+    let mut ops = Vec::new();
+    let mut overflow = BitfieldOverflow::Wrap;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let subcommand = arg.to_uppercase();
+        if read_only && subcommand != "GET" {
+            return Err(anyhow!(RedisError::Generic(
+                "BITFIELD_RO only supports the GET subcommand".into()
+            )));
+        }
+        if subcommand == "OVERFLOW" {
+            let mode = args.next().ok_or(RedisError::Syntax)?;
+            overflow = match mode.to_uppercase().as_str() {
+                "WRAP" => BitfieldOverflow::Wrap,
+                "SAT" => BitfieldOverflow::Sat,
+                "FAIL" => BitfieldOverflow::Fail,
+                _ => {
+                    return Err(anyhow!(RedisError::Generic(
+                        "Invalid OVERFLOW type specified".into()
+                    )))
+                }
+            };
+            continue;
+        }
+        if !["GET", "SET", "INCRBY"].contains(&subcommand.as_str()) {
+            return Err(anyhow!(RedisError::Syntax));
+        }
+
+        let field = parse_field_type(args.next().ok_or(RedisError::Syntax)?)?;
+        let offset = parse_offset(args.next().ok_or(RedisError::Syntax)?, field.bits, true)?;
+        let op = match subcommand.as_str() {
+            "GET" => BitfieldOp::Get { field, offset },
+            "SET" => BitfieldOp::Set {
+                field,
+                offset,
+                value: util::parse_int(args.next().ok_or(RedisError::Syntax)?)?,
+                overflow,
+            },
+            _ => BitfieldOp::IncrBy {
+                field,
+                offset,
+                increment: util::parse_int(args.next().ok_or(RedisError::Syntax)?)?,
+                overflow,
+            },
+        };
+        ops.push(op);
+    }
+    return Ok(ops);
+}
+
+/// Parses an i1 .. i64 or u1 .. u63 field type.
+fn parse_field_type(arg: &str) -> Result<BitfieldType> {
+    let signed = arg.starts_with(['i', 'I']);
+    let bits = arg
+        .get(1..)
+        .filter(|_| return signed || arg.starts_with(['u', 'U']))
+        .and_then(|bits| return bits.parse::<u32>().ok());
+    let max_bits = if signed { 64 } else { 63 };
+
+    return match bits {
+        Some(bits) if (1..=max_bits).contains(&bits) => Ok(BitfieldType { signed, bits }),
+        _ => Err(anyhow!(RedisError::Generic(
+            "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported \
+             but i64 is."
+                .into()
+        ))),
+    };
+}
+
+/// Parses a bit offset, which has to fall within the largest string allowed. BITFIELD also
+/// takes `#N` to address the Nth field of `bits` bits.
+fn parse_offset(arg: &str, bits: u32, allow_multiplier: bool) -> Result<usize> {
+    let (multiplier, offset) = match arg.strip_prefix('#') {
+        Some(offset) if allow_multiplier => (bits as i64, offset),
+        _ => (1, arg),
+    };
+    return util::parse_int(offset)
+        .ok()
+        .and_then(|offset| return offset.checked_mul(multiplier))
+        .filter(|offset| return *offset >= 0 && ((*offset >> 3) as usize) < MAX_BULK_LEN)
+        .map(|offset| return offset as usize)
+        .ok_or(anyhow!(RedisError::Generic(
+            "bit offset is not an integer or out of range".into()
+        )));
+}
+
+fn parse_unit(arg: Option<&String>) -> Result<BitUnit> {
+    return match arg.map(|arg| return arg.to_uppercase()).as_deref() {
+        None | Some("BYTE") => Ok(BitUnit::Byte),
+        Some("BIT") => Ok(BitUnit::Bit),
+        Some(_) => Err(anyhow!(RedisError::Syntax)),
+    };
+}
@@ -0,0 +1,99 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError, exec_lock::ExecLock, persistence::Store, prelude::*, replication::Replicas,
+};
+
+use super::{reply, util};
+
+/// SELECT index
+///
+/// Switches the database of the client for every cmd it runs from now on, including what
+/// they propagate and the keys they block on.
+pub fn select<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("select".into())));
+    }
+
+    let db = parse_db(&args[0])?;
+    store.select(db)?;
+    replicas.select(db);
+    exec_lock.select(db);
+    reply::ok(writer)?;
+    return Ok(());
+}
+
+/// MOVE key db
+pub fn move_key<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("move".into())));
+    }
+
+    let db = parse_db(&args[1])?;
+    reply::integer(writer, store.move_key(&args[0], db)? as i64)?;
+    return Ok(());
+}
+
+/// SWAPDB index1 index2
+pub fn swapdb<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("swapdb".into())));
+    }
+
+    let parse = |arg: &str, which: &str| {
+        if util::parse_int(arg).is_err() {
+            return Err(anyhow!(RedisError::Generic(f!(
+                "invalid {} DB index",
+                which
+            ))));
+        }
+        return parse_db(arg);
+    };
+    let first = parse(&args[0], "first")?;
+    let second = parse(&args[1], "second")?;
+
+    store.swap_dbs(first, second)?;
+    reply::ok(writer)?;
+    return Ok(());
+}
+
+/// FLUSHDB [ASYNC | SYNC], FLUSHALL [ASYNC | SYNC] when `all` is set.
+pub fn flush<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    all: bool,
+) -> Result<()> {
+    let lazy = match args {
+        [] => false,
+        [mode] if mode.eq_ignore_ascii_case("ASYNC") => true,
+        [mode] if mode.eq_ignore_ascii_case("SYNC") => false,
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+
+    store.flush(all, lazy)?;
+    reply::ok(writer)?;
+    return Ok(());
+}
+
+/// Parses a database index, negative ones being out of range.
+pub fn parse_db(arg: &str) -> Result<usize> {
+    let db = util::parse_int(arg)?;
+    return usize::try_from(db).map_err(|_| return anyhow!(RedisError::DbIndexOutOfRange));
+}
@@ -0,0 +1,449 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    geohash::{self, Shape},
+    persistence::{ScoreBound, Store, ZAddFlags, ZRange},
+    prelude::*,
+};
+
+use super::{reply, util};
+
+/// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+pub fn geoadd<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 4 {
+        return Err(anyhow!(RedisError::WrongArity("geoadd".into())));
+    }
+
+    let mut flags = ZAddFlags::default();
+    let mut changed = false;
+    let mut rest = &args[1..];
+    while let Some((option, others)) = rest.split_first() {
+        match option.to_uppercase().as_str() {
+            "NX" => flags.only_missing = true,
+            "XX" => flags.only_existing = true,
+            "CH" => changed = true,
+            _ => break,
+        }
+        rest = others;
+    }
+    if rest.is_empty() || rest.len() % 3 != 0 || (flags.only_missing && flags.only_existing) {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+
+    let pairs = rest
+        .chunks(3)
+        .map(|triple| {
+            let (longitude, latitude) = parse_coordinates(&triple[0], &triple[1])?;
+            let score = geohash::encode(longitude, latitude) as f64;
+            return Ok((score, triple[2].clone()));
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let outcome = store.zset_add(&args[0], &pairs, flags)?;
+    let count = if changed {
+        outcome.added + outcome.updated
+    } else {
+        outcome.added
+    };
+    reply::integer(writer, count as i64)?;
+    return Ok(());
+}
+
+/// GEOPOS key [member [member ...]]
+pub fn geopos<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("geopos".into())));
+    }
+
+    let scores = args[1..]
+        .iter()
+        .map(|member| return store.zset_score(&args[0], member))
+        .collect::<Result<Vec<_>>>()?;
+    reply::array_header(writer, scores.len())?;
+    for score in scores {
+        match score {
+            Some(score) => write_coordinates(writer, geohash::decode(score as u64))?,
+            None => reply::null_array(writer)?,
+        }
+    }
+    return Ok(());
+}
+
+/// GEODIST key member1 member2 [M | KM | FT | MI]
+pub fn geodist<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity("geodist".into())));
+    }
+    if args.len() > 4 {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+
+    let unit = args
+        .get(3)
+        .map_or(Ok(1.0), |unit| return parse_unit(unit))?;
+    let from = store.zset_score(&args[0], &args[1])?;
+    let to = store.zset_score(&args[0], &args[2])?;
+    match (from, to) {
+        (Some(from), Some(to)) => {
+            let meters =
+                geohash::distance(geohash::decode(from as u64), geohash::decode(to as u64));
+            reply::bulk_string(writer, &format_distance(meters / unit))?;
+        }
+        _ => reply::null_bulk_string(writer)?,
+    }
+    return Ok(());
+}
+
+/// GEOHASH key [member [member ...]]
+pub fn geohash<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("geohash".into())));
+    }
+
+    let scores = args[1..]
+        .iter()
+        .map(|member| return store.zset_score(&args[0], member))
+        .collect::<Result<Vec<_>>>()?;
+    reply::array_header(writer, scores.len())?;
+    for score in scores {
+        let hash = score.map(|score| return geohash::to_base32(score as u64));
+        reply::optional_bulk_string(writer, hash.as_deref())?;
+    }
+    return Ok(());
+}
+
+/// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+/// BYRADIUS radius M | KM | FT | MI | BYBOX width height M | KM | FT | MI
+/// [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+pub fn geosearch<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 6 {
+        return Err(anyhow!(RedisError::WrongArity("geosearch".into())));
+    }
+
+    let options = read_search_options(&args[1..], false)?;
+    let found = search(store, &args[0], &options)?;
+
+    let extras =
+        options.with_dist as usize + options.with_hash as usize + options.with_coord as usize;
+    reply::array_header(writer, found.len())?;
+    for point in found {
+        if extras == 0 {
+            reply::bulk_string(writer, &point.member)?;
+            continue;
+        }
+
+        reply::array_header(writer, 1 + extras)?;
+        reply::bulk_string(writer, &point.member)?;
+        if options.with_dist {
+            reply::bulk_string(writer, &format_distance(point.distance / options.unit))?;
+        }
+        if options.with_hash {
+            reply::integer(writer, point.score as i64)?;
+        }
+        if options.with_coord {
+            write_coordinates(writer, geohash::decode(point.score))?;
+        }
+    }
+    return Ok(());
+}
+
+/// GEOSEARCHSTORE destination source <GEOSEARCH options> [STOREDIST]
+///
+/// The members found keep their geohash scores, or get their distance with STOREDIST.
+pub fn geosearchstore<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 7 {
+        return Err(anyhow!(RedisError::WrongArity("geosearchstore".into())));
+    }
+
+    let options = read_search_options(&args[2..], true)?;
+    let found = search(store, &args[1], &options)?;
+
+    let pairs = found
+        .into_iter()
+        .map(|point| {
+            let score = if options.store_dist {
+                point.distance / options.unit
+            } else {
+                point.score as f64
+            };
+            return (point.member, score);
+        })
+        .collect();
+    reply::integer(writer, store.zset_store(&args[0], pairs)? as i64)?;
+    return Ok(());
+}
+
+enum Center {
+    Member(String),
+    Coordinates(f64, f64),
+}
+
+struct SearchOptions {
+    center: Center,
+    /// In meters.
+    shape: Shape,
+    /// Meters per unit of the shape, which distances are replied in.
+    unit: f64,
+    descending: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+    store_dist: bool,
+}
+
+struct Found {
+    member: String,
+    score: u64,
+    /// In meters.
+    distance: f64,
+}
+
+fn read_search_options(args: &[String], stores: bool) -> Result<SearchOptions> {
+    let mut center = None;
+    let mut shape = None;
+    let mut descending = None;
+    let mut count = None;
+    let mut any = false;
+    let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+        (false, false, false, false);
+
+    let mut args = args.iter().peekable();
+    while let Some(arg) = args.next() {
+        let mut next = || return args.next().ok_or(RedisError::Syntax);
+        match arg.to_uppercase().as_str() {
+            "FROMMEMBER" if center.is_none() => center = Some(Center::Member(next()?.clone())),
+            "FROMLONLAT" if center.is_none() => {
+                let (longitude, latitude) = parse_coordinates(next()?, next()?)?;
+                center = Some(Center::Coordinates(longitude, latitude));
+            }
+            "BYRADIUS" if shape.is_none() => {
+                let radius = parse_length(next()?, "radius")?;
+                let unit = parse_unit(next()?)?;
+                shape = Some((Shape::Radius(radius * unit), unit));
+            }
+            "BYBOX" if shape.is_none() => {
+                let width = parse_length(next()?, "width")?;
+                let height = parse_length(next()?, "height")?;
+                let unit = parse_unit(next()?)?;
+                let shape_meters = Shape::Box {
+                    width: width * unit,
+                    height: height * unit,
+                };
+                shape = Some((shape_meters, unit));
+            }
+            "ASC" => descending = Some(false),
+            "DESC" => descending = Some(true),
+            "COUNT" => {
+                let value = util::parse_int(next()?)?;
+                if value <= 0 {
+                    return Err(anyhow!(RedisError::Generic("COUNT must be > 0".into())));
+                }
+                count = Some(value as usize);
+                if args
+                    .next_if(|arg| return arg.eq_ignore_ascii_case("ANY"))
+                    .is_some()
+                {
+                    any = true;
+                }
+            }
+            "WITHCOORD" => with_coord = true,
+            "WITHDIST" => with_dist = true,
+            "WITHHASH" => with_hash = true,
+            "STOREDIST" if stores => store_dist = true,
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+
+    let cmd = if stores {
+        "GEOSEARCHSTORE"
+    } else {
+        "GEOSEARCH"
+    };
+    if stores && (with_coord || with_dist || with_hash) {
+        return Err(anyhow!(RedisError::Generic(f!(
+            "{} is not compatible with WITHDIST, WITHHASH and WITHCOORD options",
+            cmd
+        ))));
+    }
+    let center = center.ok_or(RedisError::Generic(f!(
+        "exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+        cmd.to_lowercase()
+    )))?;
+    let (shape, unit) = shape.ok_or(RedisError::Generic(f!(
+        "exactly one of BYRADIUS and BYBOX can be specified for {}",
+        cmd.to_lowercase()
+    )))?;
+
+    return Ok(SearchOptions {
+        center,
+        shape,
+        unit,
+        descending,
+        count,
+        any,
+        with_coord,
+        with_dist,
+        with_hash,
+        store_dist,
+    });
+}
+
+/// Scans the sorted set score ranges of the geohash cells covering the shape, keeping the
+/// members that actually fall within it. With ANY the scan stops as soon as enough members
+/// are found, otherwise COUNT keeps the closest ones.
+fn search<T: Store>(store: &mut T, key: &str, options: &SearchOptions) -> Result<Vec<Found>> {
+    if store.zset_card(key)? == 0 {
+        return Ok(Vec::new());
+    }
+    let center = match &options.center {
+        Center::Member(member) => {
+            let score = store.zset_score(key, member)?.ok_or(RedisError::Generic(
+                "could not decode requested zset member".into(),
+            ))?;
+            geohash::decode(score as u64)
+        }
+        Center::Coordinates(longitude, latitude) => (*longitude, *latitude),
+    };
+
+    let enough = |found: &Vec<Found>| {
+        return options.any
+            && options
+                .count
+                .is_some_and(|count| return found.len() >= count);
+    };
+    let mut found = Vec::new();
+    for (min, max) in geohash::search_ranges(center, options.shape) {
+        if enough(&found) {
+            break;
+        }
+        let range = ZRange::Score {
+            min: ScoreBound {
+                value: min as f64,
+                exclusive: false,
+            },
+            max: ScoreBound {
+                value: max as f64,
+                exclusive: true,
+            },
+        };
+        for (member, score) in store.zset_range(key, &range, false, None)? {
+            if enough(&found) {
+                break;
+            }
+            let point = geohash::decode(score as u64);
+            if let Some(distance) = geohash::distance_if_within(center, options.shape, point) {
+                found.push(Found {
+                    member,
+                    score: score as u64,
+                    distance,
+                });
+            }
+        }
+    }
+
+    // NOTE: COUNT without ANY implies sorting, to keep the closest members
+    let descending = match options.descending {
+        None if options.count.is_some() && !options.any => Some(false),
+        descending => descending,
+    };
+    if let Some(descending) = descending {
+        found.sort_by(|a, b| {
+            let order = a.distance.total_cmp(&b.distance);
+            return if descending { order.reverse() } else { order };
+        });
+    }
+    if let Some(count) = options.count {
+        found.truncate(count);
+    }
+    return Ok(found);
+}
+
+fn parse_coordinates(longitude: &str, latitude: &str) -> Result<(f64, f64)> {
+    let longitude = util::parse_float(longitude)?;
+    let latitude = util::parse_float(latitude)?;
+    if !geohash::is_valid(longitude, latitude) {
+        return Err(anyhow!(RedisError::Generic(f!(
+            "invalid longitude,latitude pair {:.6},{:.6}",
+            longitude,
+            latitude
+        ))));
+    }
+    return Ok((longitude, latitude));
+}
+
+/// Parses a radius, width or height.
+fn parse_length(arg: &str, name: &str) -> Result<f64> {
+    let length = util::parse_float(arg)
+        .map_err(|_| return anyhow!(RedisError::Generic(f!("need numeric {}", name))))?;
+    if length < 0.0 {
+        let message = if name == "radius" {
+            "radius cannot be negative"
+        } else {
+            "height or width cannot be negative"
+        };
+        return Err(anyhow!(RedisError::Generic(message.into())));
+    }
+    return Ok(length);
+}
+
+/// Meters per unit.
+fn parse_unit(arg: &str) -> Result<f64> {
+    return match arg.to_lowercase().as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err(anyhow!(RedisError::Generic(
+            "unsupported unit provided. please use M, KM, FT, MI".into()
+        ))),
+    };
+}
+
+fn format_distance(distance: f64) -> String {
+    return f!("{:.4}", distance);
+}
+
+/// Coordinates are replied with 17 decimals, less any trailing zeros.
+fn write_coordinates(
+    writer: &mut BufWriter<&TcpStream>,
+    (longitude, latitude): (f64, f64),
+) -> Result<()> {
+    let format = |value: f64| {
+        let formatted = f!("{:.17}", value);
+        return formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string();
+    };
+    reply::bulk_string_array(writer, &[format(longitude), format(latitude)])?;
+    return Ok(());
+}
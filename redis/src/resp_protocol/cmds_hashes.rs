@@ -0,0 +1,395 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    glob, log,
+    persistence::{current_timestamp, ExpireCondition, Store},
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{reply, util};
+
+/// HSET key field value [field value ...]
+pub fn hset<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    let pairs = parse_field_value_pairs("hset", args)?;
+    reply::integer(writer, store.hash_set(&args[0], &pairs)? as i64)?;
+    return Ok(());
+}
+
+/// HMSET key field value [field value ...], the deprecated variant of HSET replying OK.
+pub fn hmset<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    let pairs = parse_field_value_pairs("hmset", args)?;
+    store.hash_set(&args[0], &pairs)?;
+    reply::ok(writer)?;
+    return Ok(());
+}
+
+pub fn hsetnx<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("hsetnx".into())));
+    }
+
+    let created = store.hash_set_if_missing(&args[0], &args[1], &args[2])?;
+    reply::integer(writer, created as i64)?;
+    return Ok(());
+}
+
+pub fn hget<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("hget".into())));
+    }
+
+    let value = store.hash_get(&args[0], &args[1..])?.pop().flatten();
+    reply::optional_bulk_string(writer, value.as_deref())?;
+    return Ok(());
+}
+
+pub fn hmget<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("hmget".into())));
+    }
+
+    let values = store.hash_get(&args[0], &args[1..])?;
+    reply::array_header(writer, values.len())?;
+    for value in values {
+        reply::optional_bulk_string(writer, value.as_deref())?;
+    }
+    return Ok(());
+}
+
+pub fn hdel<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("hdel".into())));
+    }
+
+    reply::integer(writer, store.hash_delete(&args[0], &args[1..])? as i64)?;
+    return Ok(());
+}
+
+/// HGETALL, HKEYS and HVALS, which only differ on which half of the entries they reply.
+pub fn hgetall<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    with_fields: bool,
+    with_values: bool,
+) -> Result<()> {
+    if args.len() != 1 {
+        let cmd = match (with_fields, with_values) {
+            (true, false) => "hkeys",
+            (false, true) => "hvals",
+            _ => "hgetall",
+        };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let entries = store.hash_get_all(&args[0])?;
+    write_entries(writer, &entries, with_fields, with_values)?;
+    return Ok(());
+}
+
+pub fn hlen<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("hlen".into())));
+    }
+
+    reply::integer(writer, store.hash_len(&args[0])? as i64)?;
+    return Ok(());
+}
+
+pub fn hexists<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("hexists".into())));
+    }
+
+    let exists = store.hash_get(&args[0], &args[1..])?[0].is_some();
+    reply::integer(writer, exists as i64)?;
+    return Ok(());
+}
+
+pub fn hincrby<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("hincrby".into())));
+    }
+
+    let increment = util::parse_int(&args[2])?;
+    reply::integer(writer, store.hash_incr_by(&args[0], &args[1], increment)?)?;
+    return Ok(());
+}
+
+pub fn hincrbyfloat<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("hincrbyfloat".into())));
+    }
+
+    let increment = util::parse_float(&args[2])?;
+    let updated = store.hash_incr_by_float(&args[0], &args[1], increment)?;
+    reply::bulk_string(writer, &updated)?;
+    return Ok(());
+}
+
+/// HRANDFIELD key [count [WITHVALUES]]
+pub fn hrandfield<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() || args.len() > 3 {
+        return Err(anyhow!(RedisError::WrongArity("hrandfield".into())));
+    }
+
+    if args.len() == 1 {
+        let field = store.hash_random_fields(&args[0], 1)?.pop();
+        reply::optional_bulk_string(writer, field.map(|(field, _)| return field).as_deref())?;
+        return Ok(());
+    }
+
+    let count = util::parse_int(&args[1])?;
+    let with_values = match args.get(2) {
+        Some(option) if option.eq_ignore_ascii_case("WITHVALUES") => true,
+        Some(_) => return Err(anyhow!(RedisError::Syntax)),
+        None => false,
+    };
+    // NOTE: like redis, so that the length of the reply stays representable
+    if count == i64::MIN || (with_values && count < -i64::MAX / 2) {
+        return Err(anyhow!(RedisError::OutOfRange));
+    }
+
+    let entries = store.hash_random_fields(&args[0], count)?;
+    write_entries(writer, &entries, true, with_values)?;
+    return Ok(());
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+///
+/// The whole hash is replied at once with a 0 cursor, which is a valid (if not incremental)
+/// way of scanning it.
+pub fn hscan<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("hscan".into())));
+    }
+
+    let scan_args = util::parse_scan_args(&args[1..], true, false)?;
+
+    let mut entries = store.hash_get_all(&args[0])?;
+    if let Some(pattern) = scan_args.pattern {
+        entries.retain(|(field, _)| return glob::matches(pattern, field));
+    }
+
+    reply::array_header(writer, 2)?;
+    reply::bulk_string(writer, "0")?;
+    write_entries(writer, &entries, true, scan_args.with_values)?;
+    return Ok(());
+}
+
+/// HEXPIRE / HPEXPIRE key time [NX | XX | GT | LT] FIELDS numfields field [field ...], or
+/// HEXPIREAT / HPEXPIREAT with a unix time when `relative` is unset.
+///
+/// Propagated as a HPEXPIREAT of the fields it changed, replicas must not expire them later
+/// than the main does.
+pub fn hexpire<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    unit_in_millis: u128,
+    relative: bool,
+) -> Result<()> {
+    let cmd = match (unit_in_millis, relative) {
+        (1, true) => "hpexpire",
+        (1, false) => "hpexpireat",
+        (_, true) => "hexpire",
+        (_, false) => "hexpireat",
+    };
+    if args.len() < 5 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let time = util::parse_int(&args[1])?;
+    if time < 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "invalid expire time, must be >= 0".into()
+        )));
+    }
+
+    let (condition, fields_at) = match args[2].to_uppercase().as_str() {
+        "NX" => (ExpireCondition::IfNone, 3),
+        "XX" => (ExpireCondition::IfSome, 3),
+        "GT" => (ExpireCondition::IfGreater, 3),
+        "LT" => (ExpireCondition::IfLess, 3),
+        _ => (ExpireCondition::Always, 2),
+    };
+    let fields = parse_fields(&args[fields_at..])?;
+
+    let base = if relative { current_timestamp() } else { 0 };
+    let expires_at = (time as u128)
+        .checked_mul(unit_in_millis)
+        .and_then(|time| return time.checked_add(base))
+        .filter(|expires_at| return *expires_at <= i64::MAX as u128)
+        .ok_or(RedisError::Generic(f!(
+            "invalid expire time in '{}' command",
+            cmd
+        )))?;
+
+    log::debug(f!(
+        "Expiring fields {:?} of {} at {}",
+        fields,
+        &args[0],
+        expires_at
+    ));
+    let codes = store.hash_expire(&args[0], fields, expires_at, condition)?;
+
+    // NOTE: fields whose time has passed were deleted, the same HPEXPIREAT deletes them on
+    // the replicas
+    let changed = fields
+        .iter()
+        .zip(&codes)
+        .filter(|(_, code)| return **code == 1 || **code == 2)
+        .map(|(field, _)| return field.as_str())
+        .collect::<Vec<&str>>();
+    if !changed.is_empty() {
+        let expires_at = expires_at.to_string();
+        let numfields = changed.len().to_string();
+        let mut cmd = vec!["HPEXPIREAT", &args[0], &expires_at, "FIELDS", &numfields];
+        cmd.extend(changed);
+        replicas.propagate(&cmd);
+    }
+    reply::integer_array(writer, &codes)?;
+    return Ok(());
+}
+
+/// HTTL key FIELDS numfields field [field ...]
+pub fn httl<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 4 {
+        return Err(anyhow!(RedisError::WrongArity("httl".into())));
+    }
+
+    let fields = parse_fields(&args[1..])?;
+    let ttls = store
+        .hash_ttl(&args[0], fields)?
+        .into_iter()
+        .map(|ttl| return if ttl < 0 { ttl } else { (ttl + 500) / 1000 })
+        .collect::<Vec<i64>>();
+    reply::integer_array(writer, &ttls)?;
+    return Ok(());
+}
+
+/// HPERSIST key FIELDS numfields field [field ...]
+pub fn hpersist<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 4 {
+        return Err(anyhow!(RedisError::WrongArity("hpersist".into())));
+    }
+
+    let fields = parse_fields(&args[1..])?;
+    reply::integer_array(writer, &store.hash_persist(&args[0], fields)?)?;
+    return Ok(());
+}
+
+fn parse_field_value_pairs(cmd: &str, args: &[String]) -> Result<Vec<(String, String)>> {
+    if args.len() < 3 || args.len() % 2 == 0 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    return Ok(args[1..]
+        .chunks(2)
+        .map(|pair| return (pair[0].clone(), pair[1].clone()))
+        .collect());
+}
+
+/// Parses the `FIELDS numfields field [field ...]` tail of the field TTL cmds.
+fn parse_fields(args: &[String]) -> Result<&[String]> {
+    if args.len() < 2 || !args[0].eq_ignore_ascii_case("FIELDS") {
+        return Err(anyhow!(RedisError::Generic(
+            "Mandatory argument FIELDS is missing or not at the right position".into()
+        )));
+    }
+
+    let num_fields = util::parse_int(&args[1])?;
+    if num_fields <= 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "Parameter `numFields` should be greater than 0".into()
+        )));
+    }
+
+    let fields = &args[2..];
+    if fields.len() as i64 != num_fields {
+        return Err(anyhow!(RedisError::Generic(
+            "The `numfields` parameter must match the number of arguments".into()
+        )));
+    }
+    return Ok(fields);
+}
+
+fn write_entries(
+    writer: &mut BufWriter<&TcpStream>,
+    entries: &[(String, String)],
+    with_fields: bool,
+    with_values: bool,
+) -> Result<()> {
+    let per_entry = with_fields as usize + with_values as usize;
+    reply::array_header(writer, entries.len() * per_entry)?;
+    for (field, value) in entries {
+        if with_fields {
+            reply::bulk_string(writer, field)?;
+        }
+        if with_values {
+            reply::bulk_string(writer, value)?;
+        }
+    }
+    return Ok(());
+}
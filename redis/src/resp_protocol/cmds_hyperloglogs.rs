@@ -0,0 +1,50 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{errors::RedisError, persistence::Store};
+
+use super::reply;
+
+/// PFADD key [element [element ...]]
+pub fn pfadd<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("pfadd".into())));
+    }
+
+    reply::integer(writer, store.hll_add(&args[0], &args[1..])? as i64)?;
+    return Ok(());
+}
+
+/// PFCOUNT key [key ...]
+pub fn pfcount<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("pfcount".into())));
+    }
+
+    reply::integer(writer, store.hll_count(args)? as i64)?;
+    return Ok(());
+}
+
+/// PFMERGE destkey [sourcekey [sourcekey ...]]
+pub fn pfmerge<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("pfmerge".into())));
+    }
+
+    store.hll_merge(&args[0], &args[1..])?;
+    reply::ok(writer)?;
+    return Ok(());
+}
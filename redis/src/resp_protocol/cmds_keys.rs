@@ -0,0 +1,330 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    persistence::{current_timestamp, ExpireCondition, ScanFilter, Store},
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{cmds_databases::parse_db, reply, util};
+
+/// TTL replies for keys that do not exist / have no expiry.
+const NO_SUCH_KEY: i64 = -2;
+const NO_KEY_TTL: i64 = -1;
+
+/// DEL key [key ...]
+///
+/// UNLINK key [key ...] when `lazy` is set, freeing large values in the background.
+pub fn del<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    lazy: bool,
+) -> Result<()> {
+    if args.is_empty() {
+        let cmd = if lazy { "unlink" } else { "del" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    reply::integer(writer, store.delete(args, lazy)? as i64)?;
+    return Ok(());
+}
+
+/// EXISTS key [key ...]
+///
+/// TOUCH key [key ...] shares it, the store keeps no access times to update.
+pub fn exists<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    cmd: &str,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    reply::integer(writer, store.exists(args)? as i64)?;
+    return Ok(());
+}
+
+/// TYPE key
+pub fn key_type<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("type".into())));
+    }
+
+    let name = store.key_type(&args[0])?.unwrap_or("none");
+    reply::simple_string(writer, name)?;
+    return Ok(());
+}
+
+/// RENAME key newkey
+///
+/// RENAMENX key newkey when `only_missing` is set.
+pub fn rename<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    only_missing: bool,
+) -> Result<()> {
+    if args.len() != 2 {
+        let cmd = if only_missing { "renamenx" } else { "rename" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let renamed = store.rename(&args[0], &args[1], only_missing)?;
+    if only_missing {
+        reply::integer(writer, renamed as i64)?;
+    } else {
+        reply::ok(writer)?;
+    }
+    return Ok(());
+}
+
+/// COPY source destination [DB destination-db] [REPLACE]
+pub fn copy<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("copy".into())));
+    }
+
+    let mut db = None;
+    let mut replace = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "DB" => db = Some(parse_db(options.next().ok_or(RedisError::Syntax)?)?),
+            "REPLACE" => replace = true,
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+
+    reply::integer(writer, store.copy(&args[0], &args[1], db, replace)? as i64)?;
+    return Ok(());
+}
+
+/// EXPIRE / PEXPIRE key time [NX | XX | GT | LT], or EXPIREAT / PEXPIREAT with a unix time
+/// when `relative` is unset.
+///
+/// Propagated as a PEXPIREAT, replicas must not expire the key later than the main does.
+pub fn expire<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    unit_in_millis: i64,
+    relative: bool,
+) -> Result<()> {
+    let cmd = match (unit_in_millis, relative) {
+        (1, true) => "pexpire",
+        (1, false) => "pexpireat",
+        (_, true) => "expire",
+        (_, false) => "expireat",
+    };
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let conditions = parse_expire_conditions(&args[2..])?;
+    let base = if relative {
+        current_timestamp() as i64
+    } else {
+        0
+    };
+    // NOTE: times in the past are allowed, they delete the key
+    let expires_at = util::parse_int(&args[1])?
+        .checked_mul(unit_in_millis)
+        .and_then(|time| return time.checked_add(base))
+        .ok_or(RedisError::Generic(f!(
+            "invalid expire time in '{}' command",
+            cmd
+        )))?
+        .max(0) as u128;
+
+    let changed = store.expire(&args[0], expires_at, &conditions)?;
+    if changed {
+        replicas.propagate(&["PEXPIREAT", &args[0], &expires_at.to_string()]);
+    }
+    reply::integer(writer, changed as i64)?;
+    return Ok(());
+}
+
+/// NX, XX, GT and LT can be combined as long as they do not contradict each other: only XX
+/// goes with GT or LT.
+fn parse_expire_conditions(args: &[String]) -> Result<Vec<ExpireCondition>> {
+    let mut conditions = Vec::new();
+    for arg in args {
+        let condition = match arg.to_uppercase().as_str() {
+            "NX" => ExpireCondition::IfNone,
+            "XX" => ExpireCondition::IfSome,
+            "GT" => ExpireCondition::IfGreater,
+            "LT" => ExpireCondition::IfLess,
+            _ => {
+                return Err(anyhow!(RedisError::Generic(f!(
+                    "Unsupported option {}",
+                    arg
+                ))))
+            }
+        };
+        if !conditions.contains(&condition) {
+            conditions.push(condition);
+        }
+    }
+
+    if conditions.contains(&ExpireCondition::IfNone) && conditions.len() > 1 {
+        return Err(anyhow!(RedisError::Generic(
+            "NX and XX, GT or LT options at the same time are not compatible".into()
+        )));
+    }
+    if conditions.contains(&ExpireCondition::IfGreater)
+        && conditions.contains(&ExpireCondition::IfLess)
+    {
+        return Err(anyhow!(RedisError::Generic(
+            "GT and LT options at the same time are not compatible".into()
+        )));
+    }
+    return Ok(conditions);
+}
+
+/// TTL key, PTTL key when `in_millis` is set.
+pub fn ttl<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    in_millis: bool,
+) -> Result<()> {
+    if args.len() != 1 {
+        let cmd = if in_millis { "pttl" } else { "ttl" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let ttl = match store.expires_at(&args[0])? {
+        None => NO_SUCH_KEY,
+        Some(None) => NO_KEY_TTL,
+        Some(Some(expires_at)) => {
+            let ttl = expires_at.saturating_sub(current_timestamp()) as i64;
+            if in_millis {
+                ttl
+            } else {
+                (ttl + 500) / 1000
+            }
+        }
+    };
+    reply::integer(writer, ttl)?;
+    return Ok(());
+}
+
+/// EXPIRETIME key, PEXPIRETIME key when `in_millis` is set.
+pub fn expiretime<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    in_millis: bool,
+) -> Result<()> {
+    if args.len() != 1 {
+        let cmd = if in_millis {
+            "pexpiretime"
+        } else {
+            "expiretime"
+        };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let time = match store.expires_at(&args[0])? {
+        None => NO_SUCH_KEY,
+        Some(None) => NO_KEY_TTL,
+        Some(Some(expires_at)) if in_millis => expires_at as i64,
+        Some(Some(expires_at)) => (expires_at / 1000) as i64,
+    };
+    reply::integer(writer, time)?;
+    return Ok(());
+}
+
+/// PERSIST key
+pub fn persist<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("persist".into())));
+    }
+
+    reply::integer(writer, store.persist(&args[0])? as i64)?;
+    return Ok(());
+}
+
+/// KEYS pattern
+pub fn keys<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("keys".into())));
+    }
+
+    reply::bulk_string_array(writer, &store.keys(&args[0])?)?;
+    return Ok(());
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+pub fn scan<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("scan".into())));
+    }
+
+    let scan_args = util::parse_scan_args(args, false, true)?;
+    let filter = ScanFilter {
+        pattern: scan_args.pattern,
+        key_type: scan_args.key_type,
+    };
+    let (cursor, keys) = store.scan(scan_args.cursor, scan_args.count, filter)?;
+
+    reply::array_header(writer, 2)?;
+    reply::bulk_string(writer, &cursor.to_string())?;
+    reply::bulk_string_array(writer, &keys)?;
+    return Ok(());
+}
+
+/// RANDOMKEY
+pub fn randomkey<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if !args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("randomkey".into())));
+    }
+
+    reply::optional_bulk_string(writer, store.random_key()?.as_deref())?;
+    return Ok(());
+}
+
+/// DBSIZE
+pub fn dbsize<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if !args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("dbsize".into())));
+    }
+
+    reply::integer(writer, store.key_count()? as i64)?;
+    return Ok(());
+}
@@ -0,0 +1,304 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    exec_lock::ExecLock,
+    log,
+    persistence::{ListEnd, Store},
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{cmds, reply, util};
+
+pub fn push<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    end: ListEnd,
+    only_existing: bool,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity(push_cmd_name(
+            end,
+            only_existing
+        ))));
+    }
+
+    let len = store.push(&args[0], &args[1..], end, only_existing)?;
+    log::debug(f!("List {} has {} elements after push", &args[0], len));
+    reply::integer(writer, len as i64)?;
+    return Ok(());
+}
+
+pub fn pop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    end: ListEnd,
+) -> Result<()> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(anyhow!(RedisError::WrongArity(pop_cmd_name(end))));
+    }
+
+    let key = &args[0];
+    if args.len() == 1 {
+        match store.pop(key, end, 1)?.pop() {
+            Some(value) => reply::bulk_string(writer, &value)?,
+            None => reply::null_bulk_string(writer)?,
+        }
+        return Ok(());
+    }
+
+    let count = util::parse_count(&args[1])?;
+    let popped = store.pop(key, end, count)?;
+    if popped.is_empty() && (count > 0 || store.list_len(key)? == 0) {
+        reply::null_array(writer)?;
+    } else {
+        reply::bulk_string_array(writer, &popped)?;
+    }
+    return Ok(());
+}
+
+pub fn llen<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("llen".into())));
+    }
+
+    reply::integer(writer, store.list_len(&args[0])? as i64)?;
+    return Ok(());
+}
+
+pub fn lrange<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("lrange".into())));
+    }
+
+    let start = util::parse_int(&args[1])?;
+    let stop = util::parse_int(&args[2])?;
+    reply::bulk_string_array(writer, &store.range(&args[0], start, stop)?)?;
+    return Ok(());
+}
+
+/// LMOVE source destination <LEFT | RIGHT> <LEFT | RIGHT>
+pub fn lmove<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 4 {
+        return Err(anyhow!(RedisError::WrongArity("lmove".into())));
+    }
+
+    let from = parse_end(&args[2])?;
+    let to = parse_end(&args[3])?;
+
+    match store.move_element(&args[0], &args[1], from, to)? {
+        Some(value) => reply::bulk_string(writer, &value)?,
+        None => reply::null_bulk_string(writer)?,
+    }
+    return Ok(());
+}
+
+/// LMPOP numkeys key [key ...] <LEFT | RIGHT> [COUNT count]
+pub fn lmpop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    let (keys, end, count) = parse_mpop_args("lmpop", args)?;
+
+    match store.pop_first(keys, end, count)? {
+        Some((key, popped)) => write_key_and_elements(writer, &key, &popped)?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+/// BLPOP / BRPOP key [key ...] timeout
+pub fn blocking_pop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+    end: ListEnd,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity(f!(
+            "b{}",
+            pop_cmd_name(end)
+        ))));
+    }
+
+    let (keys, timeout) = args.split_at(args.len() - 1);
+    let timeout = util::parse_timeout(&timeout[0])?;
+    log::debug(f!("Blocking on {:?} for {:?}", keys, timeout));
+
+    let served = exec_lock.block_on(keys, timeout, |key| {
+        return cmds::propagating_expired(store, replicas, |store| {
+            let value = store.pop(key, end, 1)?.pop();
+            if value.is_some() {
+                replicas.propagate(&[&pop_cmd_name(end).to_uppercase(), key]);
+            }
+            return Ok(value.map(|value| return [key.to_string(), value]));
+        });
+    })?;
+
+    match served {
+        Some(key_and_value) => reply::bulk_string_array(writer, &key_and_value)?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+/// BLMOVE source destination <LEFT | RIGHT> <LEFT | RIGHT> timeout
+pub fn blmove<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+) -> Result<()> {
+    if args.len() != 5 {
+        return Err(anyhow!(RedisError::WrongArity("blmove".into())));
+    }
+
+    let from = parse_end(&args[2])?;
+    let to = parse_end(&args[3])?;
+    let timeout = util::parse_timeout(&args[4])?;
+
+    let served = exec_lock.block_on(&args[..1], timeout, |source| {
+        return cmds::propagating_expired(store, replicas, |store| {
+            let moved = store.move_element(source, &args[1], from, to)?;
+            if moved.is_some() {
+                replicas.propagate(&["LMOVE", source, &args[1], &args[2], &args[3]]);
+                // NOTE: someone may be blocked on the destination
+                exec_lock.signal_keys_ready();
+            }
+            return Ok(moved);
+        });
+    })?;
+
+    match served {
+        Some(value) => reply::bulk_string(writer, &value)?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+/// BLMPOP timeout numkeys key [key ...] <LEFT | RIGHT> [COUNT count]
+pub fn blmpop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("blmpop".into())));
+    }
+
+    let timeout = util::parse_timeout(&args[0])?;
+    let (keys, end, count) = parse_mpop_args("blmpop", &args[1..])?;
+
+    let served = exec_lock.block_on(keys, timeout, |key| {
+        return cmds::propagating_expired(store, replicas, |store| {
+            let popped = store.pop(key, end, count)?;
+            if popped.is_empty() {
+                return Ok(None);
+            }
+
+            let count = popped.len().to_string();
+            replicas.propagate(&[&pop_cmd_name(end).to_uppercase(), key, &count]);
+            return Ok(Some((key.to_string(), popped)));
+        });
+    })?;
+
+    match served {
+        Some((key, popped)) => write_key_and_elements(writer, &key, &popped)?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+fn parse_end(arg: &str) -> Result<ListEnd> {
+    return match arg.to_uppercase().as_str() {
+        "LEFT" => Ok(ListEnd::Left),
+        "RIGHT" => Ok(ListEnd::Right),
+        _ => Err(anyhow!(RedisError::Syntax)),
+    };
+}
+
+/// Parses the `numkeys key [key ...] <LEFT | RIGHT> [COUNT count]` tail shared by the
+/// multi pop cmds.
+fn parse_mpop_args<'a>(cmd: &str, args: &'a [String]) -> Result<(&'a [String], ListEnd, usize)> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let num_keys = util::parse_int(&args[0])?;
+    if num_keys <= 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "numkeys should be greater than 0".into()
+        )));
+    }
+
+    let num_keys = num_keys as usize;
+    if args.len() < num_keys + 2 {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+
+    let keys = &args[1..=num_keys];
+    let end = parse_end(&args[num_keys + 1])?;
+
+    let count = match &args[num_keys + 2..] {
+        [] => 1,
+        [option, count] if option.eq_ignore_ascii_case("COUNT") => {
+            let count = util::parse_int(count)?;
+            if count <= 0 {
+                return Err(anyhow!(RedisError::Generic(
+                    "count should be greater than 0".into()
+                )));
+            }
+            count as usize
+        }
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+
+    return Ok((keys, end, count));
+}
+
+fn write_key_and_elements(
+    writer: &mut BufWriter<&TcpStream>,
+    key: &str,
+    elements: &[String],
+) -> Result<()> {
+    reply::array_header(writer, 2)?;
+    reply::bulk_string(writer, key)?;
+    reply::bulk_string_array(writer, elements)?;
+    return Ok(());
+}
+
+fn push_cmd_name(end: ListEnd, only_existing: bool) -> String {
+    let side = if end == ListEnd::Left { "l" } else { "r" };
+    let suffix = if only_existing { "x" } else { "" };
+    return f!("{}push{}", side, suffix);
+}
+
+fn pop_cmd_name(end: ListEnd) -> String {
+    return match end {
+        ListEnd::Left => "lpop".into(),
+        ListEnd::Right => "rpop".into(),
+    };
+}
@@ -0,0 +1,251 @@
+use std::{io::BufWriter, net::TcpStream, sync::Arc};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    persistence::{format_float, MemoryStats, Store, DEFAULT_MEMORY_SAMPLES},
+    prelude::*,
+    Config,
+};
+
+use super::{reply, util};
+
+const OBJECT_HELP: [&str; 15] = [
+    "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "ENCODING <key>",
+    "    Return the kind of internal representation used in order to store the value",
+    "    associated with a <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of the <key>. The returned integer is",
+    "    proportional to the logarithm of the recent access frequency of the key.",
+    "IDLETIME <key>",
+    "    Return the idle time of the <key>, that is the approximated number of",
+    "    seconds elapsed since the last access to the key.",
+    "REFCOUNT <key>",
+    "    Return the number of references of the value associated with the specified",
+    "    <key>.",
+    "HELP",
+    "    Print this help.",
+];
+
+const MEMORY_HELP: [&str; 10] = [
+    "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "DOCTOR",
+    "    Return memory problems reports.",
+    "STATS",
+    "    Return information about the memory usage of the server.",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 5, 0 means sample all).",
+    "HELP",
+    "    Print this help.",
+];
+
+/// Below this much memory used, MEMORY DOCTOR has nothing meaningful to say.
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
+/// MEMORY DOCTOR reports a peak once it was this many times the memory used now.
+const DOCTOR_PEAK_RATIO: f64 = 1.5;
+
+/// OBJECT ENCODING|REFCOUNT|IDLETIME|FREQ key
+/// OBJECT HELP
+///
+/// IDLETIME and FREQ are only available under an LRU and an LFU maxmemory policy
+/// respectively, like in redis where both share the same bits of the object.
+pub fn object<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    config: &Arc<Config>,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("object".into())));
+    }
+
+    let subcommand = args[0].to_uppercase();
+    match (subcommand.as_str(), &args[1..]) {
+        ("HELP", []) => write_help(writer, &OBJECT_HELP)?,
+        ("ENCODING" | "REFCOUNT" | "IDLETIME" | "FREQ", [key]) => {
+            let info = match store.object(key)? {
+                Some(info) => info,
+                None => return reply::null_bulk_string(writer),
+            };
+            let lfu = config.max_memory.policy.is_lfu();
+            match subcommand.as_str() {
+                "ENCODING" => reply::bulk_string(writer, info.encoding)?,
+                "REFCOUNT" => reply::integer(writer, info.refcount)?,
+                "IDLETIME" if lfu => {
+                    return Err(anyhow!(RedisError::Generic(
+                        "An LRU maxmemory policy is not selected, access time not tracked. \
+                         Please note that when switching between policies at runtime LRU and \
+                         LFU data will take some time to adjust."
+                            .into()
+                    )));
+                }
+                "IDLETIME" => reply::integer(writer, (info.idle_time / 1000) as i64)?,
+                _ if !lfu => {
+                    return Err(anyhow!(RedisError::Generic(
+                        "An LFU maxmemory policy is not selected, access frequency not \
+                         tracked. Please note that when switching between policies at runtime \
+                         LRU and LFU data will take some time to adjust."
+                            .into()
+                    )));
+                }
+                _ => reply::integer(writer, info.frequency as i64)?,
+            }
+        }
+        ("HELP" | "ENCODING" | "REFCOUNT" | "IDLETIME" | "FREQ", _) => {
+            return Err(anyhow!(RedisError::WrongArity(f!(
+                "object|{}",
+                subcommand.to_lowercase()
+            ))));
+        }
+        _ => {
+            return Err(anyhow!(RedisError::Generic(f!(
+                "unknown subcommand '{}'. Try OBJECT HELP.",
+                args[0]
+            ))));
+        }
+    }
+    return Ok(());
+}
+
+/// MEMORY USAGE key [SAMPLES count]
+/// MEMORY STATS | DOCTOR | HELP
+pub fn memory<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("memory".into())));
+    }
+
+    let subcommand = args[0].to_uppercase();
+    match (subcommand.as_str(), &args[1..]) {
+        ("HELP", []) => write_help(writer, &MEMORY_HELP)?,
+        ("USAGE", [key, options @ ..]) => {
+            let samples = parse_samples(options)?;
+            match store.memory_usage(key, samples)? {
+                Some(bytes) => reply::integer(writer, bytes as i64)?,
+                None => reply::null_bulk_string(writer)?,
+            }
+        }
+        ("STATS", []) => write_stats(writer, &store.memory_stats()?)?,
+        ("DOCTOR", []) => reply::bulk_string(writer, &doctor_report(&store.memory_stats()?))?,
+        ("HELP" | "USAGE" | "STATS" | "DOCTOR", _) => {
+            return Err(anyhow!(RedisError::WrongArity(f!(
+                "memory|{}",
+                subcommand.to_lowercase()
+            ))));
+        }
+        _ => {
+            return Err(anyhow!(RedisError::Generic(f!(
+                "unknown subcommand '{}'. Try MEMORY HELP.",
+                args[0]
+            ))));
+        }
+    }
+    return Ok(());
+}
+
+/// SAMPLES options of MEMORY USAGE, the last one winning.
+fn parse_samples(options: &[String]) -> Result<usize> {
+    let mut samples = DEFAULT_MEMORY_SAMPLES;
+    for option in options.chunks(2) {
+        match option {
+            [name, count] if name.eq_ignore_ascii_case("SAMPLES") => {
+                samples = util::parse_int(count)?
+                    .try_into()
+                    .map_err(|_| return anyhow!(RedisError::Syntax))?;
+            }
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+    return Ok(samples);
+}
+
+pub(super) fn write_help(writer: &mut BufWriter<&TcpStream>, lines: &[&str]) -> Result<()> {
+    reply::array_header(writer, lines.len())?;
+    for line in lines {
+        reply::simple_string(writer, line)?;
+    }
+    return Ok(());
+}
+
+/// The fields of redis this server can tell. Only the keys and values are accounted for,
+/// there is no startup allocation, replication backlog or client buffers here.
+fn write_stats(writer: &mut BufWriter<&TcpStream>, stats: &MemoryStats) -> Result<()> {
+    let overhead = stats
+        .overheads
+        .iter()
+        .map(|db| return db.main + db.expires)
+        .sum::<usize>();
+    let dataset = stats.used.saturating_sub(overhead);
+    let percentage = |part: usize, whole: usize| {
+        return if whole == 0 {
+            0.0
+        } else {
+            part as f64 * 100.0 / whole as f64
+        };
+    };
+
+    reply::array_header(writer, 2 * (9 + stats.overheads.len()))?;
+    reply::bulk_string(writer, "peak.allocated")?;
+    reply::integer(writer, stats.peak as i64)?;
+    reply::bulk_string(writer, "total.allocated")?;
+    reply::integer(writer, stats.used as i64)?;
+    reply::bulk_string(writer, "startup.allocated")?;
+    reply::integer(writer, 0)?;
+    for db in &stats.overheads {
+        reply::bulk_string(writer, &f!("db.{}", db.db))?;
+        reply::array_header(writer, 4)?;
+        reply::bulk_string(writer, "overhead.hashtable.main")?;
+        reply::integer(writer, db.main as i64)?;
+        reply::bulk_string(writer, "overhead.hashtable.expires")?;
+        reply::integer(writer, db.expires as i64)?;
+    }
+    reply::bulk_string(writer, "overhead.total")?;
+    reply::integer(writer, overhead as i64)?;
+    reply::bulk_string(writer, "keys.count")?;
+    reply::integer(writer, stats.keys as i64)?;
+    reply::bulk_string(writer, "keys.bytes-per-key")?;
+    reply::integer(
+        writer,
+        stats.used.checked_div(stats.keys).unwrap_or(0) as i64,
+    )?;
+    reply::bulk_string(writer, "dataset.bytes")?;
+    reply::integer(writer, dataset as i64)?;
+    reply::bulk_string(writer, "dataset.percentage")?;
+    reply::bulk_string(writer, &format_float(percentage(dataset, stats.used)))?;
+    reply::bulk_string(writer, "peak.percentage")?;
+    reply::bulk_string(writer, &format_float(percentage(stats.used, stats.peak)))?;
+    return Ok(());
+}
+
+/// The report of redis, for the issues this server can have: only a past memory peak, as
+/// there is no allocator fragmentation or client buffers to look at.
+fn doctor_report(stats: &MemoryStats) -> String {
+    if stats.used < DOCTOR_MIN_MEMORY {
+        return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                detector can't be used in these conditions. Please, leave for your mission on \
+                Earth and fill it with some data. The new Sam and I will be back to our \
+                programming as soon as I finished rebooting."
+            .into();
+    }
+    if (stats.peak as f64) <= stats.used as f64 * DOCTOR_PEAK_RATIO {
+        return "Hi Sam, I can't find any memory issue in your instance. I can only account for \
+                what occurs on this base."
+            .into();
+    }
+    return "Sam, I detected a few issues in this Redis instance memory implants:\n\n \
+            * Peak memory: In the past this instance used more than 150% the memory that is \
+            currently using. The allocator is normally not able to release memory after a \
+            peak, so you can expect to see a big fragmentation ratio, however this is actually \
+            harmless and is only due to the memory peak, and if the Redis instance Resident Set \
+            Size (RSS) is currently bigger than expected, the memory will be used as soon as \
+            you fill the Redis instance with more data. If the memory peak was only occasional \
+            and you want to try to reclaim memory, the only option is to restart the \
+            instance.\n\nI'm here to keep you safe, Sam. I want to help you.\n"
+        .into();
+}
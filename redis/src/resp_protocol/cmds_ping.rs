@@ -1,17 +1,15 @@
-use std::{
-    io::{BufWriter, Write},
-    net::TcpStream,
-};
+use std::{io::BufWriter, net::TcpStream};
 
 use anyhow::Result;
 
 use crate::log;
 
+use super::reply;
+
 pub fn ping(writer: &mut BufWriter<&TcpStream>) -> Result<()> {
     log::debug("got PING wrote PONG in response");
 
-    writer.write(b"+PONG\r\n")?;
-    writer.flush()?;
+    reply::simple_string(writer, "PONG")?;
 
     return Ok(());
 }
@@ -0,0 +1,424 @@
+use std::{
+    io::{BufWriter, Write},
+    net::TcpStream,
+    sync::MutexGuard,
+    thread::JoinHandle,
+};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    prelude::*,
+    pubsub::{PubSub, Registry, Subscriber},
+};
+
+use super::{cmds, cmds_objects::write_help, reply};
+
+const PUBSUB_HELP: [&str; 14] = [
+    "PUBSUB <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "CHANNELS [<pattern>]",
+    "    Return the currently active channels matching a <pattern> (default: '*').",
+    "NUMPAT",
+    "    Return number of subscriptions to patterns.",
+    "NUMSUB [<channel> ...]",
+    "    Return the number of subscribers for the specified channels, excluding",
+    "    pattern subscriptions(default: no channels).",
+    "SHARDCHANNELS [<pattern>]",
+    "    Return the currently active shard level channels matching a <pattern> (default: '*').",
+    "SHARDNUMSUB [<shardchannel> ...]",
+    "    Return the number of subscribers for the specified shard level channel(s)",
+    "HELP",
+    "    Print this help.",
+];
+
+/// Channels, patterns and shard channels a client subscribed to. While it has any, the
+/// client is in subscriber mode: it receives the messages published to them and may only
+/// run the cmds managing its subscriptions, PING, QUIT and RESET.
+///
+/// In subscriber mode the replies are queued behind the messages for the client, with the
+/// registry locked, and written out by the thread of its `Subscriber`. Out of it they are
+/// written to the connection as usual.
+pub struct Subscriptions {
+    subscriber: Subscriber,
+    /// Thread writing out the output queued for the client, while in subscriber mode.
+    writer: Option<JoinHandle<()>>,
+    channels: Vec<String>,
+    patterns: Vec<String>,
+    shard_channels: Vec<String>,
+}
+
+impl Subscriptions {
+    pub fn new(stream: &TcpStream) -> Result<Self> {
+        return Ok(Subscriptions {
+            subscriber: Subscriber::new(stream)?,
+            writer: None,
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            shard_channels: Vec::new(),
+        });
+    }
+
+    /// Whether the client is in subscriber mode.
+    pub fn is_active(&self) -> bool {
+        return self.count() > 0 || !self.shard_channels.is_empty();
+    }
+
+    /// SUBSCRIBE channel [channel ...]
+    pub fn subscribe(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let mut registry = pubsub.lock();
+        let mut output = Vec::new();
+        let result = if args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("subscribe".into())))
+        } else {
+            args.iter().try_for_each(|channel| {
+                if registry.subscribe(channel, &self.subscriber) {
+                    self.channels.push(channel.clone());
+                }
+                return self.confirm(&mut output, "subscribe", Some(channel));
+            })
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// UNSUBSCRIBE [channel [channel ...]]
+    ///
+    /// Unsubscribes from every channel when none is given.
+    pub fn unsubscribe(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let mut registry = pubsub.lock();
+        let mut output = Vec::new();
+        let channels = if args.is_empty() {
+            self.channels.clone()
+        } else {
+            args.to_vec()
+        };
+        let result = if channels.is_empty() {
+            self.confirm(&mut output, "unsubscribe", None)
+        } else {
+            channels.iter().try_for_each(|channel| {
+                if registry.unsubscribe(channel, &self.subscriber) {
+                    self.channels.retain(|current| return current != channel);
+                }
+                return self.confirm(&mut output, "unsubscribe", Some(channel));
+            })
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// PSUBSCRIBE pattern [pattern ...]
+    pub fn psubscribe(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let mut registry = pubsub.lock();
+        let mut output = Vec::new();
+        let result = if args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("psubscribe".into())))
+        } else {
+            args.iter().try_for_each(|pattern| {
+                if registry.psubscribe(pattern, &self.subscriber) {
+                    self.patterns.push(pattern.clone());
+                }
+                return self.confirm(&mut output, "psubscribe", Some(pattern));
+            })
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// PUNSUBSCRIBE [pattern [pattern ...]]
+    ///
+    /// Unsubscribes from every pattern when none is given.
+    pub fn punsubscribe(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let mut registry = pubsub.lock();
+        let mut output = Vec::new();
+        let patterns = if args.is_empty() {
+            self.patterns.clone()
+        } else {
+            args.to_vec()
+        };
+        let result = if patterns.is_empty() {
+            self.confirm(&mut output, "punsubscribe", None)
+        } else {
+            patterns.iter().try_for_each(|pattern| {
+                if registry.punsubscribe(pattern, &self.subscriber) {
+                    self.patterns.retain(|current| return current != pattern);
+                }
+                return self.confirm(&mut output, "punsubscribe", Some(pattern));
+            })
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// SSUBSCRIBE shardchannel [shardchannel ...]
+    pub fn ssubscribe(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let mut registry = pubsub.lock();
+        let mut output = Vec::new();
+        let result = if args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("ssubscribe".into())))
+        } else {
+            args.iter().try_for_each(|channel| {
+                if registry.ssubscribe(channel, &self.subscriber) {
+                    self.shard_channels.push(channel.clone());
+                }
+                let count = self.shard_channels.len();
+                return confirm(&mut output, "ssubscribe", Some(channel), count);
+            })
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+    ///
+    /// Unsubscribes from every shard channel when none is given.
+    pub fn sunsubscribe(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let mut registry = pubsub.lock();
+        let mut output = Vec::new();
+        let channels = if args.is_empty() {
+            self.shard_channels.clone()
+        } else {
+            args.to_vec()
+        };
+        let result = if channels.is_empty() {
+            confirm(&mut output, "sunsubscribe", None, 0)
+        } else {
+            channels.iter().try_for_each(|channel| {
+                if registry.sunsubscribe(channel, &self.subscriber) {
+                    self.shard_channels
+                        .retain(|current| return current != channel);
+                }
+                let count = self.shard_channels.len();
+                return confirm(&mut output, "sunsubscribe", Some(channel), count);
+            })
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// PING [message], in subscriber mode where the reply is pushed like a message.
+    pub fn ping(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let registry = pubsub.lock();
+        let mut output = Vec::new();
+        let result = match args {
+            [] => reply::bulk_string_array(&mut output, &["pong", ""]),
+            [message] => reply::bulk_string_array(&mut output, &["pong", message]),
+            _ => Err(anyhow!(RedisError::WrongArity("ping".into()))),
+        };
+        return self.respond(writer, registry, output, result);
+    }
+
+    /// Refuses a cmd sent in subscriber mode.
+    pub fn reject(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        name: &str,
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        return self.reply_error(
+            writer,
+            anyhow!(RedisError::Generic(f!(
+                "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET \
+                 are allowed in this context",
+                name
+            ))),
+            pubsub,
+        );
+    }
+
+    /// Replies with an error to a cmd that never ran, queued in subscriber mode like any
+    /// other reply.
+    pub fn reply_error(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        error: anyhow::Error,
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let registry = pubsub.lock();
+        return self.respond(writer, registry, Vec::new(), Err(error));
+    }
+
+    /// Unsubscribes from everything without replying, once the client is gone or RESET.
+    pub fn close(&mut self, pubsub: &PubSub) {
+        let mut registry = pubsub.lock();
+        for channel in self.channels.drain(..) {
+            registry.unsubscribe(&channel, &self.subscriber);
+        }
+        for pattern in self.patterns.drain(..) {
+            registry.punsubscribe(&pattern, &self.subscriber);
+        }
+        for channel in self.shard_channels.drain(..) {
+            registry.sunsubscribe(&channel, &self.subscriber);
+        }
+        drop(registry);
+        self.stop_writer();
+    }
+
+    /// Sends the output of a cmd, with its error if it failed. It is queued while in
+    /// subscriber mode, the writer thread starting with it and draining the queue once
+    /// the client leaves it.
+    fn respond(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        registry: MutexGuard<'_, Registry>,
+        mut output: Vec<u8>,
+        result: Result<()>,
+    ) -> Result<()> {
+        let result = cmds::respond(&mut output, result);
+        if self.is_active() && self.writer.is_none() {
+            self.writer = Some(self.subscriber.start());
+        }
+        match self.writer {
+            Some(_) => {
+                self.subscriber.push(output);
+            }
+            None => {
+                writer.write_all(&output)?;
+                writer.flush()?;
+            }
+        }
+        drop(registry);
+
+        if !self.is_active() {
+            self.stop_writer();
+        }
+        return result;
+    }
+
+    fn stop_writer(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            self.subscriber.stop(writer);
+        }
+    }
+
+    /// Channels and patterns left, which their (un)subscribe confirmations report. Shard
+    /// channels are counted apart, like in redis.
+    fn count(&self) -> usize {
+        return self.channels.len() + self.patterns.len();
+    }
+
+    fn confirm(&self, writer: &mut impl Write, kind: &str, name: Option<&str>) -> Result<()> {
+        return confirm(writer, kind, name, self.count());
+    }
+}
+
+/// PUBLISH channel message
+pub fn publish(writer: &mut BufWriter<&TcpStream>, args: &[String], pubsub: &PubSub) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("publish".into())));
+    }
+
+    let receivers = pubsub.lock().publish(&args[0], &args[1]);
+    reply::integer(writer, receivers as i64)?;
+    return Ok(());
+}
+
+/// SPUBLISH shardchannel message
+pub fn spublish(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    pubsub: &PubSub,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("spublish".into())));
+    }
+
+    let receivers = pubsub.lock().spublish(&args[0], &args[1]);
+    reply::integer(writer, receivers as i64)?;
+    return Ok(());
+}
+
+/// PUBSUB CHANNELS|SHARDCHANNELS [pattern]
+/// PUBSUB NUMSUB|SHARDNUMSUB [channel [channel ...]]
+/// PUBSUB NUMPAT | HELP
+pub fn pubsub(writer: &mut BufWriter<&TcpStream>, args: &[String], pubsub: &PubSub) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("pubsub".into())));
+    }
+
+    let registry = pubsub.lock();
+    let subcommand = args[0].to_uppercase();
+    match (subcommand.as_str(), &args[1..]) {
+        ("HELP", []) => write_help(writer, &PUBSUB_HELP)?,
+        ("CHANNELS", []) => reply::bulk_string_array(writer, &registry.channels(None))?,
+        ("CHANNELS", [pattern]) => {
+            reply::bulk_string_array(writer, &registry.channels(Some(pattern)))?
+        }
+        ("NUMSUB", channels) => write_numsub(writer, channels, |channel| {
+            return registry.subscribers(channel);
+        })?,
+        ("NUMPAT", []) => reply::integer(writer, registry.patterns() as i64)?,
+        ("SHARDCHANNELS", []) => reply::bulk_string_array(writer, &registry.shard_channels(None))?,
+        ("SHARDCHANNELS", [pattern]) => {
+            reply::bulk_string_array(writer, &registry.shard_channels(Some(pattern)))?
+        }
+        ("SHARDNUMSUB", channels) => write_numsub(writer, channels, |channel| {
+            return registry.shard_subscribers(channel);
+        })?,
+        ("HELP" | "CHANNELS" | "NUMPAT" | "SHARDCHANNELS", _) => {
+            return Err(anyhow!(RedisError::WrongArity(f!(
+                "pubsub|{}",
+                subcommand.to_lowercase()
+            ))));
+        }
+        _ => {
+            return Err(anyhow!(RedisError::Generic(f!(
+                "unknown subcommand '{}'. Try PUBSUB HELP.",
+                args[0]
+            ))));
+        }
+    }
+    return Ok(());
+}
+
+/// Reply to an (un)subscription, with the subscriptions of that family left.
+fn confirm(writer: &mut impl Write, kind: &str, name: Option<&str>, count: usize) -> Result<()> {
+    reply::array_header(writer, 3)?;
+    reply::bulk_string(writer, kind)?;
+    reply::optional_bulk_string(writer, name)?;
+    reply::integer(writer, count as i64)?;
+    return Ok(());
+}
+
+/// Channels each followed by their number of subscribers.
+fn write_numsub(
+    writer: &mut BufWriter<&TcpStream>,
+    channels: &[String],
+    subscribers: impl Fn(&str) -> usize,
+) -> Result<()> {
+    reply::array_header(writer, 2 * channels.len())?;
+    for channel in channels {
+        reply::bulk_string(writer, channel)?;
+        reply::integer(writer, subscribers(channel) as i64)?;
+    }
+    return Ok(());
+}
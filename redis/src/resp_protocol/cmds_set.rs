@@ -1,134 +1,128 @@
-use std::{
-    io::{BufReader, BufWriter, Read, Write},
-    net::TcpStream,
-};
+use std::{io::BufWriter, net::TcpStream};
 
-use anyhow::{anyhow, Context, Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 
-use crate::{log, persistence::Store, prelude::*};
+use crate::{
+    errors::RedisError,
+    log,
+    persistence::{current_timestamp, SetCondition, SetExpiry, SetOptions, Store},
+    prelude::*,
+    replication::Replicas,
+};
 
-use super::{data_types, util};
+use super::{reply, util};
 
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | KEEPTTL]
+///
+/// Relative expiries would be counted from a later point in time on the replicas, so they
+/// receive the write with an absolute PXAT instead.
+///
+/// The value is binary safe.
 pub fn set<T: Store>(
-    reader: &mut BufReader<&TcpStream>,
     writer: &mut BufWriter<&TcpStream>,
-    array_stack: &mut data_types::ArrayStack,
+    args: &[Vec<u8>],
     store: &mut T,
+    replicas: &Replicas,
 ) -> Result<()> {
-    let key = read_key(reader)?;
-    array_stack.decrement()?;
-    let value = read_value(reader)?;
-    array_stack.decrement()?;
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("set".into())));
+    }
+
+    let key = util::text(&args[0])?;
+    let value = args[1].clone();
+    log::info(f!("Read key to SET {}", key));
 
-    let expiration = if array_stack.expects_more() { read_expiration_multiplier(reader)? } else { None };
-    let mut expiration_value: Option<u32> = None;
+    let options = read_options(&util::text_args(&args[2..])?)?;
+    log::debug(f!("SET options {:?}", options));
+    let outcome = store.set(key.clone(), value.clone(), options)?;
 
-    if expiration.is_some() {
-        expiration_value =
-            Some(read_expiration_value(reader).context("Must set expiration value!")?);
-        log::info(f!("Entry expires in {:?} * {:?} ms", expiration, expiration_value));
+    if outcome.applied {
+        propagate_set(replicas, key, value, options.expiry);
     }
 
-    if expiration.is_some() && expiration_value.is_some() {
-        store.set_expiring(key, value, expiration_value.unwrap() * expiration.unwrap());
+    if options.get {
+        reply::optional_bulk_bytes(writer, outcome.previous.as_deref())?;
+    } else if outcome.applied {
+        reply::ok(writer)?;
     } else {
-        store.set(key, value);
+        reply::null_bulk_string(writer)?;
     }
-
-    writer.write(b"+OK\r\n")?;
-    writer.flush()?;
-
     return Ok(());
 }
 
-fn read_key(reader: &mut BufReader<&TcpStream>) -> Result<String> {
-    let next_data = data_types::read_next_data_mandatory(reader);
-
-    if next_data.is_none() {
-        return Err(anyhow!("Expected bulk string as SET key, got nothing."));
-    }
-
-    match next_data.unwrap() {
-        data_types::RESPType::BulkString { size } => {
-            log::debug(f!("Reading key to SET, of size {}", size));
-
-            let mut key_bytes = vec![0; size];
-            reader.read_exact(&mut key_bytes)?;
-            let key = String::from_utf8(key_bytes)?;
-            log::info(f!("Read key to SET {}", key));
-            util::consume_line_break(reader)?;
-            return Ok(key);
+fn read_options(args: &[String]) -> Result<SetOptions> {
+    let mut options = SetOptions {
+        condition: SetCondition::Always,
+        expiry: SetExpiry::Clear,
+        get: false,
+    };
+    let mut has_expiry = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_uppercase().as_str() {
+            "NX" | "XX" if options.condition != SetCondition::Always => {
+                return Err(anyhow!(RedisError::Syntax));
+            }
+            "NX" => options.condition = SetCondition::IfMissing,
+            "XX" => options.condition = SetCondition::IfExists,
+            "GET" => options.get = true,
+            "KEEPTTL" | "EX" | "PX" | "EXAT" | "PXAT" if has_expiry => {
+                return Err(anyhow!(RedisError::Syntax));
+            }
+            "KEEPTTL" => {
+                options.expiry = SetExpiry::Keep;
+                has_expiry = true;
+            }
+            unit @ ("EX" | "PX" | "EXAT" | "PXAT") => {
+                let value = args.next().ok_or(RedisError::Syntax)?;
+                options.expiry = SetExpiry::At(read_expires_at(unit, value, "set")?);
+                has_expiry = true;
+            }
+            _ => {
+                log::error(f!("Unsupported SET argument {}!", arg));
+                return Err(anyhow!(RedisError::Syntax));
+            }
         }
-        _ => return Err(anyhow!("[ERR] The key to be SET must be bulk string!")),
     }
+    return Ok(options);
 }
 
-fn read_value(reader: &mut BufReader<&TcpStream>) -> Result<String> {
-    let next_data = data_types::read_next_data_mandatory(reader);
-
-    if next_data.is_none() {
-        return Err(anyhow!("Expected bulk string to echo, got nothing."));
-    }
-
-    match next_data.unwrap() {
-        data_types::RESPType::BulkString { size } => {
-            log::debug(f!("Reading data to SET, of size {}", size));
-
-            let mut value_bytes = vec![0; size];
-            reader.read_exact(&mut value_bytes)?;
-            let value = String::from_utf8(value_bytes)?;
-            log::info(f!("Read value to SET {}", value));
-            util::consume_line_break(reader)?;
-            return Ok(value);
+/// Propagates a SET that was applied, with its expiry as an absolute PXAT.
+pub fn propagate_set(replicas: &Replicas, key: String, value: Vec<u8>, expiry: SetExpiry) {
+    let mut cmd = vec![b"SET".to_vec(), key.into_bytes(), value];
+    match expiry {
+        SetExpiry::Clear => {}
+        SetExpiry::Keep => cmd.push(b"KEEPTTL".to_vec()),
+        SetExpiry::At(expires_at) => {
+            cmd.extend([b"PXAT".to_vec(), expires_at.to_string().into_bytes()])
         }
-        _ => return Err(anyhow!("[ERR] The value to be SET must be bulk string!")),
     }
+    replicas.propagate(&cmd);
 }
 
-fn read_expiration_multiplier(reader: &mut BufReader<&TcpStream>) -> Result<Option<u32>> {
-    let maybe_px = data_types::read_next_data_optional(reader);
-
-    if maybe_px.is_none() {
-        log::debug("No expiration in SET!");
-        return Ok(None);
+/// Turns the value of EX / PX / EXAT / PXAT into a unix time in ms.
+pub fn read_expires_at(unit: &str, arg: &str, cmd: &str) -> Result<u128> {
+    let value = util::parse_int(arg)?;
+    if value <= 0 {
+        return Err(anyhow!(invalid_expire_time(cmd)));
     }
 
-    match maybe_px.unwrap() {
-        data_types::RESPType::BulkString { size } => {
-            log::debug(f!("Reading expiration type arg of size {}", size));
-
-            let mut px_arg_bytes = vec![0; size];
-            reader.read_exact(&mut px_arg_bytes)?;
-            util::consume_line_break(reader)?;
-
-            let px_arg = String::from_utf8(px_arg_bytes)?;
-            let lowercase_px_arg = px_arg.to_lowercase();
-
-            match lowercase_px_arg.as_str() {
-                "px" => Ok(Some(1)),
-                "ex" => Ok(Some(1000)),
-                _ => return Err(anyhow!("[ERR] Unsupported SET argument {}!", px_arg)),
-            }
-        }
-        _ => return Err(anyhow!("[ERR] The SET arg must be a bulk string!")),
+    let value = value as u128;
+    let expires_at = match unit {
+        "EX" => current_timestamp() + value * 1000,
+        "PX" => current_timestamp() + value,
+        "EXAT" => value * 1000,
+        _ => value,
+    };
+    // NOTE: redis keeps unix times in ms as signed 64 bit integers
+    if expires_at > i64::MAX as u128 {
+        return Err(anyhow!(invalid_expire_time(cmd)));
     }
+    return Ok(expires_at);
 }
 
-fn read_expiration_value(reader: &mut BufReader<&TcpStream>) -> Result<u32> {
-    let expiration = data_types::read_next_data_mandatory(reader);
-
-    match expiration.unwrap() {
-        data_types::RESPType::BulkString { size } => {
-            log::debug(f!("Reading expiration arg of size {}", size));
-
-            let mut expiration_bytes = vec![0; size];
-            reader.read_exact(&mut expiration_bytes)?;
-            util::consume_line_break(reader)?;
-
-            let value_text = String::from_utf8(expiration_bytes)?;
-            let parsed_as_int = value_text.parse::<u32>()?;
-            return Ok(parsed_as_int);
-        }
-        _ => return Err(anyhow!("[ERR] The SET arg must be a bulk string!")),
-    }
+fn invalid_expire_time(cmd: &str) -> RedisError {
+    return RedisError::Generic(f!("invalid expire time in '{}' command", cmd));
 }
@@ -0,0 +1,291 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    glob, log,
+    persistence::{SetOperation, Store},
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{reply, util};
+
+/// SADD key member [member ...]
+pub fn sadd<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("sadd".into())));
+    }
+
+    let added = store.set_add(&args[0], &args[1..])?;
+    log::debug(f!("Added {} members to set {}", added, &args[0]));
+    reply::integer(writer, added as i64)?;
+    return Ok(());
+}
+
+/// SREM key member [member ...]
+pub fn srem<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("srem".into())));
+    }
+
+    reply::integer(writer, store.set_remove(&args[0], &args[1..])? as i64)?;
+    return Ok(());
+}
+
+pub fn smembers<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("smembers".into())));
+    }
+
+    reply::bulk_string_array(writer, &store.set_members(&args[0])?)?;
+    return Ok(());
+}
+
+pub fn sismember<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("sismember".into())));
+    }
+
+    let contained = store.set_contains(&args[0], &args[1..])?;
+    reply::integer(writer, contained[0] as i64)?;
+    return Ok(());
+}
+
+/// SMISMEMBER key member [member ...]
+pub fn smismember<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("smismember".into())));
+    }
+
+    let contained = store
+        .set_contains(&args[0], &args[1..])?
+        .into_iter()
+        .map(|contained| return contained as i64)
+        .collect::<Vec<i64>>();
+    reply::integer_array(writer, &contained)?;
+    return Ok(());
+}
+
+pub fn scard<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("scard".into())));
+    }
+
+    reply::integer(writer, store.set_card(&args[0])? as i64)?;
+    return Ok(());
+}
+
+/// SPOP key [count]
+///
+/// Which members get popped is random, so instead of the cmd itself the replicas receive
+/// an SREM of the members that were actually removed.
+pub fn spop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+) -> Result<()> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(anyhow!(RedisError::WrongArity("spop".into())));
+    }
+
+    let key = &args[0];
+    let count = match args.get(1) {
+        Some(count) => Some(util::parse_count(count)?),
+        None => None,
+    };
+
+    let popped = store.set_pop(key, count.unwrap_or(1))?;
+    if !popped.is_empty() {
+        let mut cmd = vec!["SREM".to_string(), key.clone()];
+        cmd.extend_from_slice(&popped);
+        replicas.propagate(&cmd);
+    }
+
+    match count {
+        Some(_) => reply::bulk_string_array(writer, &popped)?,
+        None => reply::optional_bulk_string(writer, popped.first().map(|m| return m.as_str()))?,
+    }
+    return Ok(());
+}
+
+/// SRANDMEMBER key [count]
+///
+/// A positive count replies with distinct members, a negative one allows the same member to
+/// be picked more than once and always replies with exactly |count| members.
+pub fn srandmember<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(anyhow!(RedisError::WrongArity("srandmember".into())));
+    }
+
+    match args.get(1) {
+        Some(count) => {
+            let count = util::parse_int(count)?;
+            // NOTE: like redis, so that the length of the reply stays representable
+            if count == i64::MIN {
+                return Err(anyhow!(RedisError::OutOfRange));
+            }
+            reply::bulk_string_array(writer, &store.set_random_members(&args[0], count)?)?;
+        }
+        None => {
+            let member = store.set_random_members(&args[0], 1)?.pop();
+            reply::optional_bulk_string(writer, member.as_deref())?;
+        }
+    }
+    return Ok(());
+}
+
+/// SINTER / SUNION / SDIFF key [key ...]
+pub fn combine<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    operation: SetOperation,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity(combine_cmd_name(operation))));
+    }
+
+    reply::bulk_string_array(writer, &store.set_combine(args, operation)?)?;
+    return Ok(());
+}
+
+/// SINTERSTORE / SUNIONSTORE / SDIFFSTORE destination key [key ...]
+pub fn combine_store<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    operation: SetOperation,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity(f!(
+            "{}store",
+            combine_cmd_name(operation)
+        ))));
+    }
+
+    let len = store.set_combine_store(&args[0], &args[1..], operation)?;
+    reply::integer(writer, len as i64)?;
+    return Ok(());
+}
+
+/// SINTERCARD numkeys key [key ...] [LIMIT limit]
+pub fn sintercard<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("sintercard".into())));
+    }
+
+    let num_keys = util::parse_int(&args[0])?;
+    if num_keys <= 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "numkeys should be greater than 0".into()
+        )));
+    }
+
+    let num_keys = num_keys as usize;
+    if args.len() < num_keys + 1 {
+        return Err(anyhow!(RedisError::Generic(
+            "Number of keys can't be greater than number of args".into()
+        )));
+    }
+
+    let limit = match &args[num_keys + 1..] {
+        [] => 0,
+        [option, limit] if option.eq_ignore_ascii_case("LIMIT") => {
+            let limit = util::parse_int(limit)?;
+            if limit < 0 {
+                return Err(anyhow!(RedisError::Generic(
+                    "LIMIT can't be negative".into()
+                )));
+            }
+            limit as usize
+        }
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+
+    let card = store.set_intersection_card(&args[1..=num_keys], limit)?;
+    reply::integer(writer, card as i64)?;
+    return Ok(());
+}
+
+/// SMOVE source destination member
+pub fn smove<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("smove".into())));
+    }
+
+    let moved = store.set_move(&args[0], &args[1], &args[2])?;
+    reply::integer(writer, moved as i64)?;
+    return Ok(());
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+///
+/// Like HSCAN, the whole set is replied at once with a 0 cursor.
+pub fn sscan<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("sscan".into())));
+    }
+
+    let scan_args = util::parse_scan_args(&args[1..], false, false)?;
+
+    let mut members = store.set_members(&args[0])?;
+    if let Some(pattern) = scan_args.pattern {
+        members.retain(|member| return glob::matches(pattern, member));
+    }
+
+    reply::array_header(writer, 2)?;
+    reply::bulk_string(writer, "0")?;
+    reply::bulk_string_array(writer, &members)?;
+    return Ok(());
+}
+
+fn combine_cmd_name(operation: SetOperation) -> String {
+    return match operation {
+        SetOperation::Intersection => "sinter".into(),
+        SetOperation::Union => "sunion".into(),
+        SetOperation::Difference => "sdiff".into(),
+    };
+}
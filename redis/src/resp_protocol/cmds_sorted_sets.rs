@@ -0,0 +1,495 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    exec_lock::ExecLock,
+    glob, log,
+    persistence::{
+        format_float, Aggregate, LexBound, ScoreBound, ScoreEnd, SetOperation, Store, ZAddFlags,
+        ZRange,
+    },
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{cmds, reply, util};
+
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+pub fn zadd<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity("zadd".into())));
+    }
+
+    let mut flags = ZAddFlags::default();
+    let mut count_changed = false;
+    let mut first_pair = 1;
+    while let Some(option) = args.get(first_pair) {
+        match option.to_uppercase().as_str() {
+            "NX" => flags.only_missing = true,
+            "XX" => flags.only_existing = true,
+            "GT" => flags.only_greater = true,
+            "LT" => flags.only_less = true,
+            "CH" => count_changed = true,
+            "INCR" => flags.increment = true,
+            _ => break,
+        }
+        first_pair += 1;
+    }
+
+    let pairs = &args[first_pair..];
+    if pairs.is_empty() || pairs.len() % 2 != 0 {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+    if flags.only_missing && flags.only_existing {
+        return Err(anyhow!(RedisError::Generic(
+            "XX and NX options at the same time are not compatible".into()
+        )));
+    }
+    if (flags.only_greater && flags.only_less)
+        || (flags.only_missing && (flags.only_greater || flags.only_less))
+    {
+        return Err(anyhow!(RedisError::Generic(
+            "GT, LT, and/or NX options at the same time are not compatible".into()
+        )));
+    }
+    if flags.increment && pairs.len() > 2 {
+        return Err(anyhow!(RedisError::Generic(
+            "INCR option supports a single increment-element pair".into()
+        )));
+    }
+
+    let pairs = pairs
+        .chunks(2)
+        .map(|pair| return Ok((util::parse_float(&pair[0])?, pair[1].clone())))
+        .collect::<Result<Vec<(f64, String)>>>()?;
+
+    let outcome = store.zset_add(&args[0], &pairs, flags)?;
+    log::debug(f!("ZADD on {} resulted in {:?}", &args[0], outcome));
+
+    if flags.increment {
+        reply::optional_bulk_string(writer, outcome.score.map(format_float).as_deref())?;
+    } else if count_changed {
+        reply::integer(writer, (outcome.added + outcome.updated) as i64)?;
+    } else {
+        reply::integer(writer, outcome.added as i64)?;
+    }
+    return Ok(());
+}
+
+/// ZINCRBY key increment member
+pub fn zincrby<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("zincrby".into())));
+    }
+
+    let increment = util::parse_float(&args[1])?;
+    let flags = ZAddFlags {
+        increment: true,
+        ..ZAddFlags::default()
+    };
+    let outcome = store.zset_add(&args[0], &[(increment, args[2].clone())], flags)?;
+    reply::optional_bulk_string(writer, outcome.score.map(format_float).as_deref())?;
+    return Ok(());
+}
+
+/// ZREM key member [member ...]
+pub fn zrem<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("zrem".into())));
+    }
+
+    reply::integer(writer, store.zset_remove(&args[0], &args[1..])? as i64)?;
+    return Ok(());
+}
+
+pub fn zscore<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("zscore".into())));
+    }
+
+    let score = store.zset_score(&args[0], &args[1])?;
+    reply::optional_bulk_string(writer, score.map(format_float).as_deref())?;
+    return Ok(());
+}
+
+pub fn zcard<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("zcard".into())));
+    }
+
+    reply::integer(writer, store.zset_card(&args[0])? as i64)?;
+    return Ok(());
+}
+
+/// ZRANK / ZREVRANK key member [WITHSCORE]
+pub fn zrank<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    reverse: bool,
+) -> Result<()> {
+    let cmd = if reverse { "zrevrank" } else { "zrank" };
+    if args.len() < 2 || args.len() > 3 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let with_score = match args.get(2) {
+        Some(option) if option.eq_ignore_ascii_case("WITHSCORE") => true,
+        Some(_) => return Err(anyhow!(RedisError::Syntax)),
+        None => false,
+    };
+
+    match (store.zset_rank(&args[0], &args[1], reverse)?, with_score) {
+        (Some((rank, score)), true) => {
+            reply::array_header(writer, 2)?;
+            reply::integer(writer, rank as i64)?;
+            reply::bulk_string(writer, &format_float(score))?;
+        }
+        (Some((rank, _)), false) => reply::integer(writer, rank as i64)?,
+        (None, true) => reply::null_array(writer)?,
+        (None, false) => reply::null_bulk_string(writer)?,
+    }
+    return Ok(());
+}
+
+/// ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]
+pub fn zrange<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity("zrange".into())));
+    }
+
+    let mut by_score = false;
+    let mut by_lex = false;
+    let mut reverse = false;
+    let mut limit = None;
+    let mut with_scores = false;
+
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "BYSCORE" => by_score = true,
+            "BYLEX" => by_lex = true,
+            "REV" => reverse = true,
+            "WITHSCORES" => with_scores = true,
+            "LIMIT" => {
+                let offset = util::parse_int(options.next().ok_or(RedisError::Syntax)?)?;
+                let count = util::parse_int(options.next().ok_or(RedisError::Syntax)?)?;
+                limit = Some((offset, count));
+            }
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+
+    if by_score && by_lex {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+    if limit.is_some() && !by_score && !by_lex {
+        return Err(anyhow!(RedisError::Generic(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .into()
+        )));
+    }
+    if with_scores && by_lex {
+        return Err(anyhow!(RedisError::Generic(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".into()
+        )));
+    }
+
+    // NOTE: reversed score and lex ranges are given from max to min
+    let (start, stop) = if reverse && (by_score || by_lex) {
+        (&args[2], &args[1])
+    } else {
+        (&args[1], &args[2])
+    };
+    let range = if by_score {
+        ZRange::Score {
+            min: parse_score_bound(start)?,
+            max: parse_score_bound(stop)?,
+        }
+    } else if by_lex {
+        ZRange::Lex {
+            min: parse_lex_bound(start)?,
+            max: parse_lex_bound(stop)?,
+        }
+    } else {
+        ZRange::Rank {
+            start: util::parse_int(start)?,
+            stop: util::parse_int(stop)?,
+        }
+    };
+
+    let entries = store.zset_range(&args[0], &range, reverse, limit)?;
+    write_entries(writer, &entries, with_scores)?;
+    return Ok(());
+}
+
+/// ZCOUNT key min max
+pub fn zcount<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("zcount".into())));
+    }
+
+    let range = ZRange::Score {
+        min: parse_score_bound(&args[1])?,
+        max: parse_score_bound(&args[2])?,
+    };
+    reply::integer(writer, store.zset_count(&args[0], &range)? as i64)?;
+    return Ok(());
+}
+
+/// ZLEXCOUNT key min max
+pub fn zlexcount<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("zlexcount".into())));
+    }
+
+    let range = ZRange::Lex {
+        min: parse_lex_bound(&args[1])?,
+        max: parse_lex_bound(&args[2])?,
+    };
+    reply::integer(writer, store.zset_count(&args[0], &range)? as i64)?;
+    return Ok(());
+}
+
+/// ZPOPMIN / ZPOPMAX key [count]
+pub fn zpop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    end: ScoreEnd,
+) -> Result<()> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(anyhow!(RedisError::WrongArity(pop_cmd_name(end))));
+    }
+
+    let count = match args.get(1) {
+        Some(count) => util::parse_count(count)?,
+        None => 1,
+    };
+
+    let popped = store.zset_pop(&args[0], end, count)?;
+    write_entries(writer, &popped, true)?;
+    return Ok(());
+}
+
+/// BZPOPMIN / BZPOPMAX key [key ...] timeout
+pub fn blocking_zpop<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+    end: ScoreEnd,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity(f!(
+            "b{}",
+            pop_cmd_name(end)
+        ))));
+    }
+
+    let (keys, timeout) = args.split_at(args.len() - 1);
+    let timeout = util::parse_timeout(&timeout[0])?;
+
+    let served = exec_lock.block_on(keys, timeout, |key| {
+        return cmds::propagating_expired(store, replicas, |store| {
+            let popped = store.zset_pop(key, end, 1)?.pop();
+            if popped.is_some() {
+                replicas.propagate(&[&pop_cmd_name(end).to_uppercase(), key]);
+            }
+            return Ok(popped.map(|(member, score)| {
+                return [key.to_string(), member, format_float(score)];
+            }));
+        });
+    })?;
+
+    match served {
+        Some(key_member_and_score) => reply::bulk_string_array(writer, &key_member_and_score)?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+/// ZUNIONSTORE / ZINTERSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]]
+/// [AGGREGATE <SUM | MIN | MAX>]
+pub fn combine_store<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    operation: SetOperation,
+) -> Result<()> {
+    let cmd = match operation {
+        SetOperation::Union => "zunionstore",
+        _ => "zinterstore",
+    };
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let num_keys = util::parse_int(&args[1])?;
+    if num_keys < 1 {
+        return Err(anyhow!(RedisError::Generic(f!(
+            "at least 1 input key is needed for '{}' command",
+            cmd
+        ))));
+    }
+
+    let num_keys = num_keys as usize;
+    if args.len() < num_keys + 2 {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+    let keys = &args[2..num_keys + 2];
+
+    let mut weights = Vec::new();
+    let mut aggregate = Aggregate::Sum;
+    let mut options = args[num_keys + 2..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "WEIGHTS" => {
+                weights = (0..num_keys)
+                    .map(|_| {
+                        let weight = options.next().ok_or(RedisError::Syntax)?;
+                        return weight
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|w| return !w.is_nan())
+                            .ok_or(anyhow!(RedisError::Generic(
+                                "weight value is not a float".into()
+                            )));
+                    })
+                    .collect::<Result<Vec<f64>>>()?;
+            }
+            "AGGREGATE" => {
+                let function = options.next().ok_or(RedisError::Syntax)?;
+                aggregate = match function.to_uppercase().as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err(anyhow!(RedisError::Syntax)),
+                };
+            }
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+
+    let len = store.zset_combine_store(&args[0], keys, &weights, aggregate, operation)?;
+    reply::integer(writer, len as i64)?;
+    return Ok(());
+}
+
+/// Parses a score range boundary: a float (or -inf / +inf), exclusive when prefixed by `(`.
+fn parse_score_bound(arg: &str) -> Result<ScoreBound> {
+    let (value, exclusive) = match arg.strip_prefix('(') {
+        Some(value) => (value, true),
+        None => (arg, false),
+    };
+
+    let value = value
+        .parse::<f64>()
+        .ok()
+        .filter(|value| return !value.is_nan())
+        .ok_or(RedisError::Generic("min or max is not a float".into()))?;
+    return Ok(ScoreBound { value, exclusive });
+}
+
+/// Parses a lex range boundary: `-`, `+`, `[member` (inclusive) or `(member` (exclusive).
+fn parse_lex_bound(arg: &str) -> Result<LexBound> {
+    if arg == "-" {
+        return Ok(LexBound::NegativeInfinity);
+    }
+    if arg == "+" {
+        return Ok(LexBound::PositiveInfinity);
+    }
+    if let Some(member) = arg.strip_prefix('[') {
+        return Ok(LexBound::Inclusive(member.to_string()));
+    }
+    if let Some(member) = arg.strip_prefix('(') {
+        return Ok(LexBound::Exclusive(member.to_string()));
+    }
+    return Err(anyhow!(RedisError::Generic(
+        "min or max not valid string range item".into()
+    )));
+}
+
+/// ZSCAN key cursor [MATCH pattern] [COUNT count]
+///
+/// Replies with the whole sorted set at once, like redis does for small ones.
+pub fn zscan<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("zscan".into())));
+    }
+
+    let scan_args = util::parse_scan_args(&args[1..], false, false)?;
+
+    let range = ZRange::Rank { start: 0, stop: -1 };
+    let mut entries = store.zset_range(&args[0], &range, false, None)?;
+    if let Some(pattern) = scan_args.pattern {
+        entries.retain(|(member, _)| return glob::matches(pattern, member));
+    }
+
+    reply::array_header(writer, 2)?;
+    reply::bulk_string(writer, "0")?;
+    write_entries(writer, &entries, true)?;
+    return Ok(());
+}
+
+fn write_entries(
+    writer: &mut BufWriter<&TcpStream>,
+    entries: &[(String, f64)],
+    with_scores: bool,
+) -> Result<()> {
+    let per_entry = if with_scores { 2 } else { 1 };
+    reply::array_header(writer, entries.len() * per_entry)?;
+    for (member, score) in entries {
+        reply::bulk_string(writer, member)?;
+        if with_scores {
+            reply::bulk_string(writer, &format_float(*score))?;
+        }
+    }
+    return Ok(());
+}
+
+fn pop_cmd_name(end: ScoreEnd) -> String {
+    return match end {
+        ScoreEnd::Min => "zpopmin".into(),
+        ScoreEnd::Max => "zpopmax".into(),
+    };
+}
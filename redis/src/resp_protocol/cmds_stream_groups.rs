@@ -0,0 +1,717 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    exec_lock::ExecLock,
+    persistence::{Claim, ClaimOptions, GroupEntry, GroupStartId, Store, StreamId},
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{
+    cmds,
+    cmds_streams::{
+        parse_block_timeout, parse_id, parse_range_end, parse_range_start, write_entries,
+    },
+    reply, util,
+};
+
+/// XAUTOCLAIM claims up to 100 entries unless told otherwise.
+const AUTOCLAIM_DEFAULT_COUNT: usize = 100;
+
+/// XGROUP CREATE key group <id | $> [MKSTREAM] [ENTRIESREAD entries-read]
+/// XGROUP SETID key group <id | $> [ENTRIESREAD entries-read]
+/// XGROUP DESTROY key group
+/// XGROUP <CREATECONSUMER | DELCONSUMER> key group consumer
+pub fn xgroup<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("xgroup".into())));
+    }
+
+    let subcommand = args[0].to_uppercase();
+    match (subcommand.as_str(), &args[1..]) {
+        ("CREATE", [key, group, id, options @ ..]) => {
+            let mut make_stream = false;
+            let mut entries_read = None;
+            let mut options = options.iter();
+            while let Some(option) = options.next() {
+                match option.to_uppercase().as_str() {
+                    "MKSTREAM" => make_stream = true,
+                    "ENTRIESREAD" => {
+                        entries_read =
+                            parse_entries_read(options.next().ok_or(RedisError::Syntax)?)?
+                    }
+                    _ => return Err(anyhow!(RedisError::Syntax)),
+                }
+            }
+
+            store.stream_group_create(key, group, parse_start(id)?, make_stream, entries_read)?;
+            reply::ok(writer)?;
+        }
+        ("SETID", [key, group, id, options @ ..]) => {
+            let entries_read = match options {
+                [] => None,
+                [option, value] if option.eq_ignore_ascii_case("ENTRIESREAD") => {
+                    parse_entries_read(value)?
+                }
+                _ => return Err(anyhow!(RedisError::Syntax)),
+            };
+
+            store.stream_group_set_id(key, group, parse_start(id)?, entries_read)?;
+            reply::ok(writer)?;
+        }
+        ("DESTROY", [key, group]) => {
+            let destroyed = store.stream_group_destroy(key, group)?;
+            reply::integer(writer, destroyed as i64)?;
+        }
+        ("CREATECONSUMER", [key, group, consumer]) => {
+            let created = store.stream_consumer_create(key, group, consumer)?;
+            reply::integer(writer, created as i64)?;
+        }
+        ("DELCONSUMER", [key, group, consumer]) => {
+            let pending = store.stream_consumer_delete(key, group, consumer)?;
+            reply::integer(writer, pending as i64)?;
+        }
+        ("CREATE" | "SETID" | "DESTROY" | "CREATECONSUMER" | "DELCONSUMER", _) => {
+            return Err(anyhow!(RedisError::WrongArity(f!(
+                "xgroup|{}",
+                subcommand.to_lowercase()
+            ))));
+        }
+        _ => {
+            return Err(anyhow!(RedisError::Generic(f!(
+                "unknown subcommand '{}'. Try XGROUP HELP.",
+                args[0]
+            ))));
+        }
+    }
+    return Ok(());
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key
+/// [key ...] id [id ...]
+///
+/// `>` delivers entries never delivered to the group, any other ID replays the history of
+/// the consumer's pending entries after it. Only reads of new entries block.
+///
+/// Replicas receive XCLAIMs recording the deliveries, followed by an XGROUP SETID moving the
+/// group's last delivered ID, so delivery times and counts match.
+pub fn xreadgroup<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+) -> Result<()> {
+    let mut group = None;
+    let mut count = None;
+    let mut block = None;
+    let mut no_ack = false;
+    let mut next = 0;
+    loop {
+        let option = args.get(next).ok_or(RedisError::Syntax)?;
+        let value = args.get(next + 1).ok_or(RedisError::Syntax);
+        match option.to_uppercase().as_str() {
+            "GROUP" => {
+                let consumer = args.get(next + 2).ok_or(RedisError::Syntax)?;
+                group = Some((value?, consumer));
+                next += 3;
+            }
+            "COUNT" => {
+                let value = util::parse_int(value?)?;
+                count = (value > 0).then_some(value as usize);
+                next += 2;
+            }
+            "BLOCK" => {
+                block = Some(parse_block_timeout(value?)?);
+                next += 2;
+            }
+            "NOACK" => {
+                no_ack = true;
+                next += 1;
+            }
+            "STREAMS" => break,
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+
+    let (group, consumer) = group.ok_or(RedisError::Generic(
+        "Missing GROUP option for XREADGROUP".into(),
+    ))?;
+    let streams = &args[next + 1..];
+    if streams.is_empty() || streams.len() % 2 != 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be \
+             specified."
+                .into()
+        )));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    let after = ids
+        .iter()
+        .map(|id| return parse_group_read_id(id))
+        .collect::<Result<Vec<Option<StreamId>>>>()?;
+
+    let read_key = |store: &mut T, key: &str, after: Option<StreamId>| {
+        return read_group(store, replicas, key, group, consumer, after, count, no_ack);
+    };
+
+    let read = exec_lock.run(|| {
+        return cmds::propagating_expired(store, replicas, |store| {
+            for key in keys {
+                if !store.stream_group_exists(key, group)? {
+                    return Err(anyhow!(RedisError::NoGroup(f!(
+                        "No such key '{key}' or consumer group '{group}' in XREADGROUP with GROUP \
+                         option"
+                    ))));
+                }
+            }
+
+            let mut read_streams = Vec::new();
+            for (key, after) in keys.iter().zip(&after) {
+                let entries = read_key(store, key, *after)?;
+                // NOTE: history reads always reply, even with no entries
+                if after.is_some() || !entries.is_empty() {
+                    read_streams.push((key.clone(), entries));
+                }
+            }
+            return Ok(read_streams);
+        });
+    })?;
+
+    let timeout = match block {
+        Some(timeout) if read.is_empty() => timeout,
+        _ => {
+            write_group_streams(writer, &read)?;
+            return Ok(());
+        }
+    };
+
+    let served = exec_lock.block_on(keys, timeout, |key| {
+        return cmds::propagating_expired(store, replicas, |store| {
+            let entries = read_key(store, key, None)?;
+            return Ok((!entries.is_empty()).then(|| return (key.to_string(), entries)));
+        });
+    })?;
+
+    match served {
+        Some(served) => write_group_streams(writer, &[served])?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+/// XACK key group id [id ...]
+pub fn xack<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity("xack".into())));
+    }
+
+    let ids = args[2..]
+        .iter()
+        .map(|id| return parse_id(id, 0))
+        .collect::<Result<Vec<StreamId>>>()?;
+    reply::integer(writer, store.stream_ack(&args[0], &args[1], &ids)? as i64)?;
+    return Ok(());
+}
+
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+pub fn xpending<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("xpending".into())));
+    }
+
+    let (key, group) = (&args[0], &args[1]);
+    let mut extended = &args[2..];
+    if extended.is_empty() {
+        let summary = store.stream_pending_summary(key, group)?;
+        reply::array_header(writer, 4)?;
+        reply::integer(writer, summary.count as i64)?;
+        let (min, max) = match summary.bounds {
+            Some((min, max)) => (Some(min.to_string()), Some(max.to_string())),
+            None => (None, None),
+        };
+        reply::optional_bulk_string(writer, min.as_deref())?;
+        reply::optional_bulk_string(writer, max.as_deref())?;
+        if summary.consumers.is_empty() {
+            reply::null_array(writer)?;
+            return Ok(());
+        }
+        reply::array_header(writer, summary.consumers.len())?;
+        for (consumer, pending) in &summary.consumers {
+            reply::bulk_string_array(writer, &[consumer.clone(), pending.to_string()])?;
+        }
+        return Ok(());
+    }
+
+    let mut min_idle = 0;
+    if extended[0].eq_ignore_ascii_case("IDLE") {
+        let value = extended.get(1).ok_or(RedisError::Syntax)?;
+        min_idle = util::parse_int(value)?.max(0) as u128;
+        extended = &extended[2..];
+    }
+    let (start, end, count, consumer) = match extended {
+        [start, end, count] => (start, end, count, None),
+        [start, end, count, consumer] => (start, end, count, Some(consumer.as_str())),
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+    let start = parse_range_start(start)?;
+    let end = parse_range_end(end)?;
+    let count = util::parse_int(count)?.max(0) as usize;
+
+    let pending = store.stream_pending(key, group, min_idle, start..=end, count, consumer)?;
+    reply::array_header(writer, pending.len())?;
+    for info in pending {
+        reply::array_header(writer, 4)?;
+        reply::bulk_string(writer, &info.id.to_string())?;
+        reply::bulk_string(writer, &info.consumer)?;
+        reply::integer(writer, info.idle as i64)?;
+        reply::integer(writer, info.deliveries as i64)?;
+    }
+    return Ok(());
+}
+
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-ms]
+/// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+///
+/// Idle times depend on when the cmd runs, so each claim is propagated with its resulting
+/// delivery time and count instead.
+pub fn xclaim<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+) -> Result<()> {
+    if args.len() < 5 {
+        return Err(anyhow!(RedisError::WrongArity("xclaim".into())));
+    }
+
+    let (key, group, consumer) = (&args[0], &args[1], &args[2]);
+    let min_idle = parse_min_idle(&args[3], "XCLAIM")?;
+
+    let mut ids = Vec::new();
+    let mut next = 4;
+    while let Some(id) = args.get(next).and_then(|id| return parse_id(id, 0).ok()) {
+        ids.push(id);
+        next += 1;
+    }
+
+    let mut options = ClaimOptions::default();
+    let mut args = args[next..].iter();
+    while let Some(option) = args.next() {
+        let name = option.to_uppercase();
+        let mut value = || {
+            let value = args.next().ok_or(RedisError::Syntax)?;
+            return util::parse_int(value)
+                .map(|value| return value.max(0) as u128)
+                .map_err(|_| {
+                    return anyhow!(RedisError::Generic(f!(
+                        "Invalid {name} option argument for XCLAIM"
+                    )));
+                });
+        };
+        match name.as_str() {
+            "IDLE" => options.idle = Some(value()?),
+            "TIME" => options.time = Some(value()?),
+            "RETRYCOUNT" => options.retry_count = Some(value()? as u64),
+            "FORCE" => options.force = true,
+            "JUSTID" => options.just_id = true,
+            "LASTID" => {
+                options.last_id = Some(parse_id(args.next().ok_or(RedisError::Syntax)?, 0)?)
+            }
+            _ => {
+                return Err(anyhow!(RedisError::Generic(f!(
+                    "Unrecognized XCLAIM option '{option}'"
+                ))));
+            }
+        }
+    }
+
+    let outcome = store.stream_claim(key, group, consumer, min_idle, &ids, options)?;
+    propagate_claims(replicas, key, group, consumer, &outcome.claimed);
+    propagate_acks(replicas, key, group, &outcome.deleted);
+    propagate_last_delivered(replicas, key, group, outcome.last_delivered);
+
+    write_claimed(writer, &outcome.claimed, options.just_id)?;
+    return Ok(());
+}
+
+/// XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+///
+/// Propagated like XCLAIM.
+pub fn xautoclaim<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+) -> Result<()> {
+    if args.len() < 5 {
+        return Err(anyhow!(RedisError::WrongArity("xautoclaim".into())));
+    }
+
+    let (key, group, consumer) = (&args[0], &args[1], &args[2]);
+    let min_idle = parse_min_idle(&args[3], "XAUTOCLAIM")?;
+    let start = parse_range_start(&args[4])?;
+
+    let mut count = AUTOCLAIM_DEFAULT_COUNT;
+    let mut just_id = false;
+    let mut options = args[5..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "COUNT" => {
+                let value = util::parse_int(options.next().ok_or(RedisError::Syntax)?)?;
+                if value < 1 {
+                    return Err(anyhow!(RedisError::Generic("COUNT must be > 0".into())));
+                }
+                count = value as usize;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+
+    let (outcome, next) =
+        store.stream_auto_claim(key, group, consumer, min_idle, start, count, just_id)?;
+    propagate_claims(replicas, key, group, consumer, &outcome.claimed);
+    propagate_acks(replicas, key, group, &outcome.deleted);
+
+    reply::array_header(writer, 3)?;
+    reply::bulk_string(writer, &next.to_string())?;
+    write_claimed(writer, &outcome.claimed, just_id)?;
+    let deleted: Vec<String> = outcome
+        .deleted
+        .iter()
+        .map(|id| return id.to_string())
+        .collect();
+    reply::bulk_string_array(writer, &deleted)?;
+    return Ok(());
+}
+
+/// XINFO STREAM key / XINFO GROUPS key / XINFO CONSUMERS key group
+pub fn xinfo<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("xinfo".into())));
+    }
+
+    let subcommand = args[0].to_uppercase();
+    match (subcommand.as_str(), &args[1..]) {
+        ("STREAM", [key]) => {
+            let info = store.stream_info(key)?;
+            reply::array_header(writer, 20)?;
+            reply::bulk_string(writer, "length")?;
+            reply::integer(writer, info.length as i64)?;
+            reply::bulk_string(writer, "radix-tree-keys")?;
+            reply::integer(writer, info.radix_tree_keys as i64)?;
+            reply::bulk_string(writer, "radix-tree-nodes")?;
+            reply::integer(writer, info.radix_tree_nodes as i64)?;
+            reply::bulk_string(writer, "last-generated-id")?;
+            reply::bulk_string(writer, &info.last_generated_id.to_string())?;
+            reply::bulk_string(writer, "max-deleted-entry-id")?;
+            reply::bulk_string(writer, &info.max_deleted_id.to_string())?;
+            reply::bulk_string(writer, "entries-added")?;
+            reply::integer(writer, info.entries_added as i64)?;
+            reply::bulk_string(writer, "recorded-first-entry-id")?;
+            reply::bulk_string(writer, &info.recorded_first_id.to_string())?;
+            reply::bulk_string(writer, "groups")?;
+            reply::integer(writer, info.groups as i64)?;
+            for (name, entry) in [
+                ("first-entry", info.first_entry),
+                ("last-entry", info.last_entry),
+            ] {
+                reply::bulk_string(writer, name)?;
+                match entry {
+                    Some((id, fields)) => write_entry(writer, id, Some(&fields))?,
+                    None => reply::null_bulk_string(writer)?,
+                }
+            }
+        }
+        ("GROUPS", [key]) => {
+            let groups = store.stream_groups_info(key)?;
+            reply::array_header(writer, groups.len())?;
+            for group in groups {
+                reply::array_header(writer, 12)?;
+                reply::bulk_string(writer, "name")?;
+                reply::bulk_string(writer, &group.name)?;
+                reply::bulk_string(writer, "consumers")?;
+                reply::integer(writer, group.consumers as i64)?;
+                reply::bulk_string(writer, "pending")?;
+                reply::integer(writer, group.pending as i64)?;
+                reply::bulk_string(writer, "last-delivered-id")?;
+                reply::bulk_string(writer, &group.last_delivered_id.to_string())?;
+                reply::bulk_string(writer, "entries-read")?;
+                write_optional_integer(writer, group.entries_read)?;
+                reply::bulk_string(writer, "lag")?;
+                write_optional_integer(writer, group.lag)?;
+            }
+        }
+        ("CONSUMERS", [key, group]) => {
+            let consumers = store.stream_consumers_info(key, group)?;
+            reply::array_header(writer, consumers.len())?;
+            for consumer in consumers {
+                reply::array_header(writer, 8)?;
+                reply::bulk_string(writer, "name")?;
+                reply::bulk_string(writer, &consumer.name)?;
+                reply::bulk_string(writer, "pending")?;
+                reply::integer(writer, consumer.pending as i64)?;
+                reply::bulk_string(writer, "idle")?;
+                reply::integer(writer, consumer.idle as i64)?;
+                reply::bulk_string(writer, "inactive")?;
+                reply::integer(writer, consumer.inactive.map_or(-1, |ms| return ms as i64))?;
+            }
+        }
+        ("STREAM" | "GROUPS" | "CONSUMERS", _) => {
+            return Err(anyhow!(RedisError::WrongArity(f!(
+                "xinfo|{}",
+                subcommand.to_lowercase()
+            ))));
+        }
+        _ => {
+            return Err(anyhow!(RedisError::Generic(f!(
+                "unknown subcommand '{}'. Try XINFO HELP.",
+                args[0]
+            ))));
+        }
+    }
+    return Ok(());
+}
+
+/// Reads for a consumer and propagates what the read changed in the group.
+#[allow(clippy::too_many_arguments)]
+fn read_group<T: Store>(
+    store: &mut T,
+    replicas: &Replicas,
+    key: &str,
+    group: &str,
+    consumer: &str,
+    after: Option<StreamId>,
+    count: Option<usize>,
+    no_ack: bool,
+) -> Result<Vec<GroupEntry>> {
+    let read = store.stream_group_read(key, group, consumer, after, count, no_ack)?;
+
+    if read.created_consumer {
+        replicas.propagate(&["XGROUP", "CREATECONSUMER", key, group, consumer]);
+    }
+    if read.last_delivered.is_some() && !no_ack {
+        let time = read.delivered_at.to_string();
+        for (id, _) in &read.entries {
+            replicas.propagate(&xclaim_equivalent(key, group, consumer, *id, &time, 1));
+        }
+    }
+    propagate_last_delivered(replicas, key, group, read.last_delivered);
+    return Ok(read.entries);
+}
+
+fn propagate_claims(
+    replicas: &Replicas,
+    key: &str,
+    group: &str,
+    consumer: &str,
+    claimed: &[Claim],
+) {
+    for claim in claimed {
+        let time = claim.delivery_time.to_string();
+        let (id, _) = claim.entry;
+        replicas.propagate(&xclaim_equivalent(
+            key,
+            group,
+            consumer,
+            id,
+            &time,
+            claim.delivery_count,
+        ));
+    }
+}
+
+/// Pending entries whose stream entries were deleted are dropped, which acking replays.
+fn propagate_acks(replicas: &Replicas, key: &str, group: &str, ids: &[StreamId]) {
+    if ids.is_empty() {
+        return;
+    }
+    let mut cmd = vec!["XACK".to_string(), key.to_string(), group.to_string()];
+    cmd.extend(ids.iter().map(|id| return id.to_string()));
+    replicas.propagate(&cmd);
+}
+
+fn propagate_last_delivered(
+    replicas: &Replicas,
+    key: &str,
+    group: &str,
+    last_delivered: Option<(StreamId, Option<u64>)>,
+) {
+    let (id, entries_read) = match last_delivered {
+        Some(last_delivered) => last_delivered,
+        None => return,
+    };
+    let mut cmd = vec![
+        "XGROUP".to_string(),
+        "SETID".into(),
+        key.into(),
+        group.into(),
+        id.to_string(),
+    ];
+    if let Some(entries_read) = entries_read {
+        cmd.extend(["ENTRIESREAD".into(), entries_read.to_string()]);
+    }
+    replicas.propagate(&cmd);
+}
+
+/// XCLAIM recreating a pending entry exactly as it is on the master.
+fn xclaim_equivalent(
+    key: &str,
+    group: &str,
+    consumer: &str,
+    id: StreamId,
+    time: &str,
+    delivery_count: u64,
+) -> Vec<String> {
+    return [
+        "XCLAIM",
+        key,
+        group,
+        consumer,
+        "0",
+        &id.to_string(),
+        "TIME",
+        time,
+        "RETRYCOUNT",
+        &delivery_count.to_string(),
+        "FORCE",
+        "JUSTID",
+    ]
+    .iter()
+    .map(|part| return part.to_string())
+    .collect();
+}
+
+/// `>` reads new entries (`None`), anything else is the ID the history read starts after.
+fn parse_group_read_id(arg: &str) -> Result<Option<StreamId>> {
+    return match arg {
+        ">" => Ok(None),
+        "$" => Err(anyhow!(RedisError::Generic(
+            "The $ ID is meaningless in the context of XREADGROUP: you want to read the history \
+             of this consumer by specifying a proper ID, or use the > ID to get new messages. \
+             The $ ID would just return an empty result set."
+                .into()
+        ))),
+        _ => Ok(Some(parse_id(arg, 0)?)),
+    };
+}
+
+fn parse_start(arg: &str) -> Result<GroupStartId> {
+    if arg == "$" {
+        return Ok(GroupStartId::LastEntry);
+    }
+    return Ok(GroupStartId::Id(parse_id(arg, 0)?));
+}
+
+/// ENTRIESREAD takes a count of entries, or -1 when unknown.
+fn parse_entries_read(arg: &str) -> Result<Option<u64>> {
+    return match util::parse_int(arg)? {
+        -1 => Ok(None),
+        read if read >= 0 => Ok(Some(read as u64)),
+        _ => Err(anyhow!(RedisError::Generic(
+            "value for ENTRIESREAD must be positive or -1".into()
+        ))),
+    };
+}
+
+fn parse_min_idle(arg: &str, cmd: &str) -> Result<u128> {
+    let min_idle = util::parse_int(arg).map_err(|_| {
+        return anyhow!(RedisError::Generic(f!(
+            "Invalid min-idle-time argument for {cmd}"
+        )));
+    })?;
+    return Ok(min_idle.max(0) as u128);
+}
+
+fn write_claimed(
+    writer: &mut BufWriter<&TcpStream>,
+    claimed: &[Claim],
+    just_id: bool,
+) -> Result<()> {
+    if just_id {
+        let ids: Vec<String> = claimed
+            .iter()
+            .map(|claim| return claim.entry.0.to_string())
+            .collect();
+        reply::bulk_string_array(writer, &ids)?;
+        return Ok(());
+    }
+
+    let entries: Vec<_> = claimed
+        .iter()
+        .map(|claim| return claim.entry.clone())
+        .collect();
+    write_entries(writer, &entries)?;
+    return Ok(());
+}
+
+/// Writes `[id, [field, value, ...]]`, with a null array in place of deleted entries.
+fn write_entry(
+    writer: &mut BufWriter<&TcpStream>,
+    id: StreamId,
+    fields: Option<&Vec<(String, String)>>,
+) -> Result<()> {
+    reply::array_header(writer, 2)?;
+    reply::bulk_string(writer, &id.to_string())?;
+    let fields = match fields {
+        Some(fields) => fields,
+        None => {
+            reply::null_array(writer)?;
+            return Ok(());
+        }
+    };
+    reply::array_header(writer, fields.len() * 2)?;
+    for (field, value) in fields {
+        reply::bulk_string(writer, field)?;
+        reply::bulk_string(writer, value)?;
+    }
+    return Ok(());
+}
+
+fn write_group_streams(
+    writer: &mut BufWriter<&TcpStream>,
+    streams: &[(String, Vec<GroupEntry>)],
+) -> Result<()> {
+    if streams.is_empty() {
+        reply::null_array(writer)?;
+        return Ok(());
+    }
+
+    reply::array_header(writer, streams.len())?;
+    for (key, entries) in streams {
+        reply::array_header(writer, 2)?;
+        reply::bulk_string(writer, key)?;
+        reply::array_header(writer, entries.len())?;
+        for (id, fields) in entries {
+            write_entry(writer, *id, fields.as_ref())?;
+        }
+    }
+    return Ok(());
+}
+
+fn write_optional_integer(writer: &mut BufWriter<&TcpStream>, value: Option<u64>) -> Result<()> {
+    return match value {
+        Some(value) => reply::integer(writer, value as i64),
+        None => reply::null_bulk_string(writer),
+    };
+}
@@ -0,0 +1,433 @@
+use std::{io::BufWriter, net::TcpStream, time::Duration};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    exec_lock::ExecLock,
+    log,
+    persistence::{Store, StreamEntry, StreamId, StreamTrim, TrimStrategy, XAddId},
+    prelude::*,
+    replication::Replicas,
+};
+
+use super::{reply, util};
+
+/// XADD key [NOMKSTREAM] [<MAXLEN | MINID> [= | ~] threshold [LIMIT count]] <* | id>
+/// field value [field value ...]
+///
+/// Generated IDs and approximate trims would not play out the same on the replicas, so
+/// they receive the XADD with the ID that was picked and an exact MAXLEN of the resulting
+/// length instead.
+pub fn xadd<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+) -> Result<()> {
+    if args.len() < 4 {
+        return Err(anyhow!(RedisError::WrongArity("xadd".into())));
+    }
+
+    let key = &args[0];
+    let mut make_stream = true;
+    let mut trim = None;
+    let mut next = 1;
+    while let Some(option) = args.get(next) {
+        match option.to_uppercase().as_str() {
+            "NOMKSTREAM" => {
+                make_stream = false;
+                next += 1;
+            }
+            "MAXLEN" | "MINID" => {
+                let (parsed, consumed) = parse_trim(&args[next..])?;
+                trim = Some(parsed);
+                next += consumed;
+            }
+            _ => break,
+        }
+    }
+
+    let fields = match args.get(next + 1..) {
+        Some(fields) if !fields.is_empty() && fields.len() % 2 == 0 => fields,
+        _ => return Err(anyhow!(RedisError::WrongArity("xadd".into()))),
+    };
+    let id = parse_xadd_id(&args[next])?;
+    let fields = fields
+        .chunks(2)
+        .map(|pair| return (pair[0].clone(), pair[1].clone()))
+        .collect();
+
+    let id = match store.stream_add(key, id, fields, make_stream, trim)? {
+        Some(id) => id,
+        None => {
+            reply::null_bulk_string(writer)?;
+            return Ok(());
+        }
+    };
+    log::debug(f!("Added entry {} to stream {}", id, key));
+
+    let mut cmd = vec!["XADD".to_string(), key.clone()];
+    if trim.is_some() {
+        let len = store.stream_len(key)?;
+        cmd.extend(["MAXLEN".into(), "=".into(), len.to_string()]);
+    }
+    cmd.push(id.to_string());
+    cmd.extend_from_slice(&args[next + 1..]);
+    replicas.propagate(&cmd);
+
+    reply::bulk_string(writer, &id.to_string())?;
+    return Ok(());
+}
+
+/// XTRIM key <MAXLEN | MINID> [= | ~] threshold [LIMIT count]
+///
+/// Propagated as an exact MAXLEN of the resulting length, like XADD.
+pub fn xtrim<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+) -> Result<()> {
+    if args.len() < 3 {
+        return Err(anyhow!(RedisError::WrongArity("xtrim".into())));
+    }
+
+    let key = &args[0];
+    let (trim, consumed) = parse_trim(&args[1..])?;
+    if consumed != args.len() - 1 {
+        return Err(anyhow!(RedisError::Syntax));
+    }
+
+    let trimmed = store.stream_trim(key, trim)?;
+    if trimmed > 0 {
+        let len = store.stream_len(key)?.to_string();
+        replicas.propagate(&["XTRIM", key, "MAXLEN", "=", &len]);
+    }
+
+    reply::integer(writer, trimmed as i64)?;
+    return Ok(());
+}
+
+/// XDEL key id [id ...]
+pub fn xdel<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("xdel".into())));
+    }
+
+    let ids = args[1..]
+        .iter()
+        .map(|id| return parse_id(id, 0))
+        .collect::<Result<Vec<StreamId>>>()?;
+    reply::integer(writer, store.stream_delete(&args[0], &ids)? as i64)?;
+    return Ok(());
+}
+
+pub fn xlen<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("xlen".into())));
+    }
+
+    reply::integer(writer, store.stream_len(&args[0])? as i64)?;
+    return Ok(());
+}
+
+/// XRANGE key start end [COUNT count] / XREVRANGE key end start [COUNT count]
+pub fn xrange<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    reverse: bool,
+) -> Result<()> {
+    let cmd = if reverse { "xrevrange" } else { "xrange" };
+    if args.len() != 3 && args.len() != 5 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let (start, end) = if reverse {
+        (&args[2], &args[1])
+    } else {
+        (&args[1], &args[2])
+    };
+    let start = parse_range_start(start)?;
+    let end = parse_range_end(end)?;
+
+    let count = match &args[3..] {
+        [] => None,
+        [option, count] if option.eq_ignore_ascii_case("COUNT") => {
+            Some(util::parse_int(count)?.max(0) as usize)
+        }
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+
+    let entries = store.stream_range(&args[0], start, end, count, reverse)?;
+    write_entries(writer, &entries)?;
+    return Ok(());
+}
+
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+///
+/// `$` reads only entries added after the call and `+` starts from the last entry. When
+/// blocked, the reply only holds the stream that got new entries first.
+pub fn xread<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    exec_lock: &ExecLock,
+) -> Result<()> {
+    let mut count = None;
+    let mut block = None;
+    let mut next = 0;
+    loop {
+        let option = args.get(next).ok_or(RedisError::Syntax)?;
+        let value = args.get(next + 1);
+        match option.to_uppercase().as_str() {
+            "COUNT" => {
+                let value = util::parse_int(value.ok_or(RedisError::Syntax)?)?;
+                count = (value > 0).then_some(value as usize);
+            }
+            "BLOCK" => block = Some(parse_block_timeout(value.ok_or(RedisError::Syntax)?)?),
+            "STREAMS" => break,
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+        next += 2;
+    }
+
+    let streams = &args[next + 1..];
+    if streams.is_empty() || streams.len() % 2 != 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be \
+             specified."
+                .into()
+        )));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+
+    let (after, read) = exec_lock.run(|| {
+        let after = keys
+            .iter()
+            .zip(ids)
+            .map(|(key, id)| return resolve_read_id(store, key, id))
+            .collect::<Result<Vec<StreamId>>>()?;
+
+        let mut read = Vec::new();
+        for (key, after) in keys.iter().zip(&after) {
+            let entries = read_after(store, key, *after, count)?;
+            if !entries.is_empty() {
+                read.push((key.clone(), entries));
+            }
+        }
+        return Ok((after, read));
+    })?;
+
+    let timeout = match block {
+        Some(timeout) if read.is_empty() => timeout,
+        _ => {
+            write_streams(writer, &read)?;
+            return Ok(());
+        }
+    };
+
+    let served = exec_lock.block_on_shared(keys, timeout, |key| {
+        let index = keys.iter().position(|k| return k == key).unwrap();
+        let entries = read_after(store, key, after[index], count)?;
+        return Ok((!entries.is_empty()).then(|| return (key.to_string(), entries)));
+    })?;
+
+    match served {
+        Some(served) => write_streams(writer, &[served])?,
+        None => reply::null_array(writer)?,
+    }
+    return Ok(());
+}
+
+/// Parses `<MAXLEN | MINID> [= | ~] threshold [LIMIT count]`, returning how many args it
+/// took.
+fn parse_trim(args: &[String]) -> Result<(StreamTrim, usize)> {
+    let (approximate, mut next) = match args.get(1).map(|arg| return arg.as_str()) {
+        Some("~") => (true, 2),
+        Some("=") => (false, 2),
+        _ => (false, 1),
+    };
+
+    let threshold = args.get(next).ok_or(RedisError::Syntax)?;
+    next += 1;
+    let strategy = if args[0].eq_ignore_ascii_case("MAXLEN") {
+        let max_len = util::parse_int(threshold)?;
+        if max_len < 0 {
+            return Err(anyhow!(RedisError::Generic(
+                "The MAXLEN argument must be >= 0.".into()
+            )));
+        }
+        TrimStrategy::MaxLen(max_len as usize)
+    } else {
+        TrimStrategy::MinId(parse_id(threshold, 0)?)
+    };
+
+    let mut limit = None;
+    if args
+        .get(next)
+        .is_some_and(|arg| return arg.eq_ignore_ascii_case("LIMIT"))
+    {
+        let count = util::parse_int(args.get(next + 1).ok_or(RedisError::Syntax)?)?;
+        if count < 0 {
+            return Err(anyhow!(RedisError::Generic(
+                "The LIMIT argument must be >= 0.".into()
+            )));
+        }
+        if !approximate {
+            return Err(anyhow!(RedisError::Generic(
+                "syntax error, LIMIT cannot be used without the special ~ option".into()
+            )));
+        }
+        limit = Some(count as usize);
+        next += 2;
+    }
+
+    let trim = StreamTrim {
+        strategy,
+        approximate,
+        limit,
+    };
+    return Ok((trim, next));
+}
+
+/// Turns the ID given to XREAD into the ID entries must come after.
+fn resolve_read_id<T: Store>(store: &T, key: &str, id: &str) -> Result<StreamId> {
+    return match id {
+        "$" => Ok(store.stream_last_id(key)?.unwrap_or(StreamId::MIN)),
+        "+" => {
+            let last_entry =
+                store.stream_range(key, StreamId::MIN, StreamId::MAX, Some(1), true)?;
+            Ok(last_entry
+                .first()
+                .and_then(|(id, _)| return id.previous())
+                .unwrap_or(StreamId::MIN))
+        }
+        _ => parse_id(id, 0),
+    };
+}
+
+fn read_after<T: Store>(
+    store: &T,
+    key: &str,
+    after: StreamId,
+    count: Option<usize>,
+) -> Result<Vec<StreamEntry>> {
+    return match after.next() {
+        Some(start) => store.stream_range(key, start, StreamId::MAX, count, false),
+        None => Ok(Vec::new()),
+    };
+}
+
+/// BLOCK takes milliseconds, 0 meaning forever.
+pub fn parse_block_timeout(arg: &str) -> Result<Option<Duration>> {
+    let millis = util::parse_int(arg).map_err(|_| {
+        return anyhow!(RedisError::Generic(
+            "timeout is not an integer or out of range".into()
+        ));
+    })?;
+    if millis < 0 {
+        return Err(anyhow!(RedisError::NegativeTimeout));
+    }
+    return Ok((millis > 0).then(|| return Duration::from_millis(millis as u64)));
+}
+
+fn parse_xadd_id(arg: &str) -> Result<XAddId> {
+    if arg == "*" {
+        return Ok(XAddId::Auto);
+    }
+    if let Some(ms) = arg.strip_suffix("-*") {
+        let ms = ms.parse::<u64>().map_err(|_| return invalid_id())?;
+        return Ok(XAddId::AutoSequence(ms));
+    }
+    return Ok(XAddId::Explicit(parse_id(arg, 0)?));
+}
+
+/// Parses `<ms>-<seq>`, or a bare `<ms>` taking `missing_seq` as its sequence number.
+pub fn parse_id(arg: &str, missing_seq: u64) -> Result<StreamId> {
+    let (ms, seq) = match arg.split_once('-') {
+        Some((ms, seq)) => (ms, seq.parse::<u64>().map_err(|_| return invalid_id())?),
+        None => (arg, missing_seq),
+    };
+    let ms = ms.parse::<u64>().map_err(|_| return invalid_id())?;
+    return Ok(StreamId { ms, seq });
+}
+
+/// Parses the start of a range: `-`, an ID (`<ms>` meaning `<ms>-0`) or an ID prefixed by
+/// `(` to exclude it.
+pub fn parse_range_start(arg: &str) -> Result<StreamId> {
+    return match arg {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => match arg.strip_prefix('(') {
+            Some(id) => parse_id(id, 0)?.next().ok_or(anyhow!(RedisError::Generic(
+                "invalid start ID for the interval".into()
+            ))),
+            None => parse_id(arg, 0),
+        },
+    };
+}
+
+/// Parses the end of a range: `+`, an ID (`<ms>` meaning its last sequence number) or an
+/// ID prefixed by `(` to exclude it.
+pub fn parse_range_end(arg: &str) -> Result<StreamId> {
+    return match arg {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ => match arg.strip_prefix('(') {
+            Some(id) => parse_id(id, u64::MAX)?
+                .previous()
+                .ok_or(anyhow!(RedisError::Generic(
+                    "invalid end ID for the interval".into()
+                ))),
+            None => parse_id(arg, u64::MAX),
+        },
+    };
+}
+
+fn invalid_id() -> anyhow::Error {
+    return anyhow!(RedisError::Generic(
+        "Invalid stream ID specified as stream command argument".into()
+    ));
+}
+
+pub fn write_entries(writer: &mut BufWriter<&TcpStream>, entries: &[StreamEntry]) -> Result<()> {
+    reply::array_header(writer, entries.len())?;
+    for (id, fields) in entries {
+        reply::array_header(writer, 2)?;
+        reply::bulk_string(writer, &id.to_string())?;
+        reply::array_header(writer, fields.len() * 2)?;
+        for (field, value) in fields {
+            reply::bulk_string(writer, field)?;
+            reply::bulk_string(writer, value)?;
+        }
+    }
+    return Ok(());
+}
+
+fn write_streams(
+    writer: &mut BufWriter<&TcpStream>,
+    streams: &[(String, Vec<StreamEntry>)],
+) -> Result<()> {
+    if streams.is_empty() {
+        reply::null_array(writer)?;
+        return Ok(());
+    }
+
+    reply::array_header(writer, streams.len())?;
+    for (key, entries) in streams {
+        reply::array_header(writer, 2)?;
+        reply::bulk_string(writer, key)?;
+        write_entries(writer, entries)?;
+    }
+    return Ok(());
+}
@@ -0,0 +1,433 @@
+use std::{io::BufWriter, net::TcpStream};
+
+use anyhow::{anyhow, Ok, Result};
+
+use crate::{
+    errors::RedisError,
+    persistence::{SetCondition, SetExpiry, SetOptions, Store},
+    replication::Replicas,
+};
+
+use super::{
+    cmds_set::{propagate_set, read_expires_at},
+    reply, util,
+};
+
+/// Largest string redis replies with (`proto-max-bulk-len`).
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// INCR key / DECR key
+pub fn incr<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    decrement: bool,
+) -> Result<()> {
+    if args.len() != 1 {
+        let cmd = if decrement { "decr" } else { "incr" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let increment = if decrement { -1 } else { 1 };
+    reply::integer(writer, store.string_incr_by(&args[0], increment)?)?;
+    return Ok(());
+}
+
+/// INCRBY key increment / DECRBY key decrement
+pub fn incrby<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    decrement: bool,
+) -> Result<()> {
+    if args.len() != 2 {
+        let cmd = if decrement { "decrby" } else { "incrby" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let mut increment = util::parse_int(&args[1])?;
+    if decrement {
+        increment = increment
+            .checked_neg()
+            .ok_or(RedisError::Generic("decrement would overflow".into()))?;
+    }
+    reply::integer(writer, store.string_incr_by(&args[0], increment)?)?;
+    return Ok(());
+}
+
+pub fn incrbyfloat<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("incrbyfloat".into())));
+    }
+
+    let increment = util::parse_float(&args[1])?;
+    let updated = store.string_incr_by_float(&args[0], increment)?;
+    reply::bulk_string(writer, &updated)?;
+    return Ok(());
+}
+
+/// APPEND key value, with a binary safe value
+pub fn append<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[Vec<u8>],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("append".into())));
+    }
+
+    let len = store.string_append(&util::text(&args[0])?, &args[1])?;
+    reply::integer(writer, len as i64)?;
+    return Ok(());
+}
+
+pub fn strlen<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("strlen".into())));
+    }
+
+    let len = store.get(&args[0])?.map_or(0, |value| return value.len());
+    reply::integer(writer, len as i64)?;
+    return Ok(());
+}
+
+/// GETRANGE key start end, with inclusive byte offsets that can be negative to count from
+/// the end.
+pub fn getrange<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("getrange".into())));
+    }
+
+    let start = util::parse_int(&args[1])?;
+    let end = util::parse_int(&args[2])?;
+    let bytes = store.get(&args[0])?.unwrap_or_default();
+
+    let len = bytes.len() as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+    if start > end || len == 0 {
+        reply::bulk_string(writer, "")?;
+        return Ok(());
+    }
+
+    let range = &bytes[start as usize..=end as usize];
+    reply::bulk_bytes(writer, range)?;
+    return Ok(());
+}
+
+/// SETRANGE key offset value, with a binary safe value
+pub fn setrange<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[Vec<u8>],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity("setrange".into())));
+    }
+
+    let offset = util::parse_int(&util::text(&args[1])?)?;
+    if offset < 0 {
+        return Err(anyhow!(RedisError::Generic(
+            "offset is out of range".into()
+        )));
+    }
+
+    let len = store.string_set_range(&util::text(&args[0])?, offset as usize, &args[2])?;
+    reply::integer(writer, len as i64)?;
+    return Ok(());
+}
+
+pub fn getdel<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 1 {
+        return Err(anyhow!(RedisError::WrongArity("getdel".into())));
+    }
+
+    reply::optional_bulk_bytes(writer, store.string_get_del(&args[0])?.as_deref())?;
+    return Ok(());
+}
+
+/// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]
+///
+/// Propagated with an absolute PXAT, like SET.
+pub fn getex<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+    replicas: &Replicas,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("getex".into())));
+    }
+
+    let key = &args[0];
+    let expiry = match &args[1..] {
+        [] => SetExpiry::Keep,
+        [option] if option.eq_ignore_ascii_case("PERSIST") => SetExpiry::Clear,
+        [unit, value] => match unit.to_uppercase().as_str() {
+            unit @ ("EX" | "PX" | "EXAT" | "PXAT") => {
+                SetExpiry::At(read_expires_at(unit, value, "getex")?)
+            }
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        },
+        _ => return Err(anyhow!(RedisError::Syntax)),
+    };
+
+    let value = store.string_get_ex(key, expiry)?;
+    if value.is_some() {
+        match expiry {
+            SetExpiry::Keep => {}
+            SetExpiry::Clear => replicas.propagate(&["GETEX", key, "PERSIST"]),
+            SetExpiry::At(expires_at) => {
+                replicas.propagate(&["GETEX", key, "PXAT", &expires_at.to_string()])
+            }
+        }
+    }
+
+    reply::optional_bulk_bytes(writer, value.as_deref())?;
+    return Ok(());
+}
+
+/// SETNX key value, with a binary safe value
+pub fn setnx<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[Vec<u8>],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() != 2 {
+        return Err(anyhow!(RedisError::WrongArity("setnx".into())));
+    }
+
+    let options = SetOptions {
+        condition: SetCondition::IfMissing,
+        expiry: SetExpiry::Clear,
+        get: false,
+    };
+    let outcome = store.set(util::text(&args[0])?, args[1].clone(), options)?;
+    reply::integer(writer, outcome.applied as i64)?;
+    return Ok(());
+}
+
+/// SETEX key seconds value / PSETEX key milliseconds value
+///
+/// Propagated as a SET with an absolute PXAT. The value is binary safe.
+pub fn setex<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[Vec<u8>],
+    store: &mut T,
+    replicas: &Replicas,
+    unit: &str,
+) -> Result<()> {
+    let cmd = if unit == "PX" { "psetex" } else { "setex" };
+    if args.len() != 3 {
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let options = SetOptions {
+        condition: SetCondition::Always,
+        expiry: SetExpiry::At(read_expires_at(unit, &util::text(&args[1])?, cmd)?),
+        get: false,
+    };
+    let key = util::text(&args[0])?;
+    store.set(key.clone(), args[2].clone(), options)?;
+    propagate_set(replicas, key, args[2].clone(), options.expiry);
+
+    reply::ok(writer)?;
+    return Ok(());
+}
+
+/// MSET key value [key value ...] / MSETNX key value [key value ...], with binary safe
+/// values
+pub fn mset<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[Vec<u8>],
+    store: &mut T,
+    only_missing: bool,
+) -> Result<()> {
+    if args.is_empty() || args.len() % 2 != 0 {
+        let cmd = if only_missing { "msetnx" } else { "mset" };
+        return Err(anyhow!(RedisError::WrongArity(cmd.into())));
+    }
+
+    let pairs = args
+        .chunks(2)
+        .map(|pair| return Ok((util::text(&pair[0])?, pair[1].clone())))
+        .collect::<Result<Vec<(String, Vec<u8>)>>>()?;
+    let set = store.string_set_many(&pairs, only_missing)?;
+
+    if only_missing {
+        reply::integer(writer, set as i64)?;
+    } else {
+        reply::ok(writer)?;
+    }
+    return Ok(());
+}
+
+pub fn mget<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.is_empty() {
+        return Err(anyhow!(RedisError::WrongArity("mget".into())));
+    }
+
+    let values = store.string_get_many(args)?;
+    reply::array_header(writer, values.len())?;
+    for value in values {
+        reply::optional_bulk_bytes(writer, value.as_deref())?;
+    }
+    return Ok(());
+}
+
+/// LCS key1 key2 [LEN] [IDX] [MINMATCHLEN min-match-len] [WITHMATCHLEN]
+pub fn lcs<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    args: &[String],
+    store: &mut T,
+) -> Result<()> {
+    if args.len() < 2 {
+        return Err(anyhow!(RedisError::WrongArity("lcs".into())));
+    }
+
+    let mut len_only = false;
+    let mut with_indexes = false;
+    let mut min_match_len = 0;
+    let mut with_match_len = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "LEN" => len_only = true,
+            "IDX" => with_indexes = true,
+            "MINMATCHLEN" => {
+                let value = options.next().ok_or(RedisError::Syntax)?;
+                min_match_len = util::parse_int(value)?.max(0) as usize;
+            }
+            "WITHMATCHLEN" => with_match_len = true,
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+    if len_only && with_indexes {
+        return Err(anyhow!(RedisError::Generic(
+            "If you want both the length and indexes, please just use IDX.".into()
+        )));
+    }
+
+    let a = store.get(&args[0])?.unwrap_or_default();
+    let b = store.get(&args[1])?.unwrap_or_default();
+    let (common, matches) = longest_common_subsequence(&a, &b, min_match_len)?;
+
+    if len_only {
+        reply::integer(writer, common.len() as i64)?;
+        return Ok(());
+    }
+    if !with_indexes {
+        reply::bulk_bytes(writer, &common)?;
+        return Ok(());
+    }
+
+    reply::array_header(writer, 4)?;
+    reply::bulk_string(writer, "matches")?;
+    reply::array_header(writer, matches.len())?;
+    for (a_range, b_range, len) in matches {
+        reply::array_header(writer, if with_match_len { 3 } else { 2 })?;
+        reply::integer_array(writer, &[a_range.0 as i64, a_range.1 as i64])?;
+        reply::integer_array(writer, &[b_range.0 as i64, b_range.1 as i64])?;
+        if with_match_len {
+            reply::integer(writer, len as i64)?;
+        }
+    }
+    reply::bulk_string(writer, "len")?;
+    reply::integer(writer, common.len() as i64)?;
+    return Ok(());
+}
+
+/// Inclusive ranges matched in each string, and their length.
+type LcsMatch = ((usize, usize), (usize, usize), usize);
+
+/// Classic dynamic programming LCS, walked back from the end like redis does: matching
+/// ranges come out last to first, and only those at least `min_match_len` long are kept.
+fn longest_common_subsequence(
+    a: &[u8],
+    b: &[u8],
+    min_match_len: usize,
+) -> Result<(Vec<u8>, Vec<LcsMatch>)> {
+    let cells = (a.len() + 1).saturating_mul(b.len() + 1);
+    if cells.saturating_mul(std::mem::size_of::<u32>()) > MAX_BULK_LEN {
+        return Err(anyhow!(RedisError::Generic(
+            "Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len".into()
+        )));
+    }
+
+    let width = b.len() + 1;
+    let mut table = vec![0u32; cells];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    let mut common = Vec::with_capacity(table[a.len() * width + b.len()] as usize);
+    let mut matches = Vec::new();
+    let mut current: Option<((usize, usize), (usize, usize))> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        let mut emit = false;
+        if a[i - 1] == b[j - 1] {
+            common.push(a[i - 1]);
+            match &mut current {
+                None => current = Some(((i - 1, i - 1), (j - 1, j - 1))),
+                Some((a_range, b_range)) if a_range.0 == i && b_range.0 == j => {
+                    a_range.0 -= 1;
+                    b_range.0 -= 1;
+                }
+                Some(_) => emit = true,
+            }
+            // NOTE: the range cannot grow past the start of either string
+            if current.is_some_and(|(a_range, b_range)| return a_range.0 == 0 || b_range.0 == 0) {
+                emit = true;
+            }
+            i -= 1;
+            j -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            emit = current.is_some();
+        }
+
+        if emit {
+            if let Some((a_range, b_range)) = current.take() {
+                let len = a_range.1 - a_range.0 + 1;
+                if len >= min_match_len {
+                    matches.push((a_range, b_range, len));
+                }
+            }
+        }
+    }
+
+    common.reverse();
+    return Ok((common, matches));
+}
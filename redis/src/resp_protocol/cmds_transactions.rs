@@ -0,0 +1,257 @@
+use std::{io::BufWriter, net::TcpStream, sync::Arc};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    errors::RedisError,
+    exec_lock::ExecLock,
+    persistence::{Store, Watcher},
+    pubsub::PubSub,
+    replication::Replicas,
+    Config,
+};
+
+use super::{
+    cmds::{self, RESPCmd},
+    reply,
+};
+
+/// Transaction state of a client: the cmds queued since MULTI, run together by EXEC.
+///
+/// Cmds are only checked for their arity when queued, like redis does. Any that fails it,
+/// or is not a known cmd, makes EXEC discard the whole transaction. Errors raised while the
+/// cmds run are replied in place of their result, the others still run.
+///
+/// The keys watched before MULTI make EXEC skip the cmds, replying a null array, when any
+/// of them was modified in between. They stay watched until EXEC, DISCARD or UNWATCH.
+pub struct Transaction {
+    queued: Option<Vec<(RESPCmd, Vec<Vec<u8>>)>>,
+    aborted: bool,
+    /// Watched keys along with their database.
+    watched: Vec<(usize, String)>,
+    watcher: Watcher,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        return Transaction {
+            queued: None,
+            aborted: false,
+            watched: Vec::new(),
+            watcher: Watcher::new(),
+        };
+    }
+
+    /// Whether the client is inside MULTI, cmds getting queued instead of run.
+    pub fn is_active(&self) -> bool {
+        return self.queued.is_some();
+    }
+
+    /// MULTI
+    pub fn multi(&mut self, writer: &mut BufWriter<&TcpStream>, args: &[String]) -> Result<()> {
+        let result = if !args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("multi".into())))
+        } else if self.is_active() {
+            Err(anyhow!(RedisError::Generic(
+                "MULTI calls can not be nested".into()
+            )))
+        } else {
+            self.queued = Some(Vec::new());
+            reply::ok(writer)
+        };
+        return cmds::respond(writer, result);
+    }
+
+    /// DISCARD
+    pub fn discard<T: Store>(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        store: &mut T,
+    ) -> Result<()> {
+        let result = if !args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("discard".into())))
+        } else if !self.is_active() {
+            Err(anyhow!(RedisError::Generic("DISCARD without MULTI".into())))
+        } else {
+            self.reset();
+            self.unwatch_all(store)
+                .and_then(|_| return reply::ok(writer))
+        };
+        return cmds::respond(writer, result);
+    }
+
+    /// WATCH key [key ...]
+    pub fn watch<T: Store>(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        store: &mut T,
+    ) -> Result<()> {
+        let result = if args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("watch".into())))
+        } else if self.is_active() {
+            Err(anyhow!(RedisError::Generic(
+                "WATCH inside MULTI is not allowed".into()
+            )))
+        } else {
+            self.watch_keys(args, store)
+                .and_then(|_| return reply::ok(writer))
+        };
+        return cmds::respond(writer, result);
+    }
+
+    /// UNWATCH
+    pub fn unwatch<T: Store>(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        store: &mut T,
+    ) -> Result<()> {
+        let result = if !args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("unwatch".into())))
+        } else {
+            self.unwatch_all(store)
+                .and_then(|_| return reply::ok(writer))
+        };
+        return cmds::respond(writer, result);
+    }
+
+    /// Leaves the transaction and stops watching every key, once the client is gone or
+    /// RESET.
+    pub fn close<T: Store>(&mut self, store: &mut T) -> Result<()> {
+        self.reset();
+        return self.unwatch_all(store);
+    }
+
+    /// Queues a cmd sent inside MULTI, to run on EXEC.
+    pub fn queue(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        cmd: RESPCmd,
+        args: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let result = if !cmd.accepts_args(args.len()) {
+            self.aborted = true;
+            Err(anyhow!(RedisError::WrongArity(cmd.name())))
+        } else {
+            self.queued
+                .as_mut()
+                .expect("Cmds are only queued inside MULTI")
+                .push((cmd, args));
+            reply::simple_string(writer, "QUEUED")
+        };
+        return cmds::respond(writer, result);
+    }
+
+    /// Refuses a cmd changing the state of the connection, which cannot run from EXEC. EXEC
+    /// will discard the transaction.
+    pub fn refuse(&mut self, writer: &mut BufWriter<&TcpStream>) -> Result<()> {
+        self.aborted = true;
+        let result = Err(anyhow!(RedisError::Generic(
+            "Command not allowed inside a transaction".into()
+        )));
+        return cmds::respond(writer, result);
+    }
+
+    /// Flags the transaction after a cmd that could not be queued, EXEC will discard it.
+    pub fn abort(&mut self) {
+        if self.is_active() {
+            self.aborted = true;
+        }
+    }
+
+    /// EXEC
+    ///
+    /// Runs the queued cmds under a single hold of the exec lock, so no other client sees
+    /// the store halfway through them. Their writes reach the replicas as one MULTI/EXEC
+    /// block.
+    #[allow(clippy::too_many_arguments)]
+    pub fn exec<T: Store>(
+        &mut self,
+        writer: &mut BufWriter<&TcpStream>,
+        args: &[String],
+        store: &mut T,
+        config: &Arc<Config>,
+        replicas: &Replicas,
+        exec_lock: &ExecLock,
+        pubsub: &PubSub,
+    ) -> Result<()> {
+        let result = if !args.is_empty() {
+            Err(anyhow!(RedisError::WrongArity("exec".into())))
+        } else if !self.is_active() {
+            Err(anyhow!(RedisError::Generic("EXEC without MULTI".into())))
+        } else {
+            let aborted = self.aborted;
+            let queued = self.queued.take().unwrap_or_default();
+            self.reset();
+            let result = if aborted {
+                Err(anyhow!(RedisError::ExecAbort))
+            } else {
+                exec_lock.run(|| {
+                    // NOTE: checked under the exec lock, no cmd can touch the keys in between
+                    if self.watcher.is_dirty()
+                        || store.watched_keys_expired(&self.watched, &self.watcher)?
+                    {
+                        return reply::null_array(writer);
+                    }
+                    return run_queued(writer, &queued, store, config, replicas, exec_lock, pubsub);
+                })
+            };
+            result.and(self.unwatch_all(store))
+        };
+        return cmds::respond(writer, result);
+    }
+
+    fn reset(&mut self) {
+        self.queued = None;
+        self.aborted = false;
+    }
+
+    fn watch_keys<T: Store>(&mut self, keys: &[String], store: &mut T) -> Result<()> {
+        for key in keys {
+            let db = store.watch(key, &self.watcher)?;
+            let watched = (db, key.clone());
+            if !self.watched.contains(&watched) {
+                self.watched.push(watched);
+            }
+        }
+        return Ok(());
+    }
+
+    fn unwatch_all<T: Store>(&mut self, store: &mut T) -> Result<()> {
+        store.unwatch(&self.watched, &self.watcher)?;
+        self.watched.clear();
+        self.watcher.reset();
+        return Ok(());
+    }
+}
+
+fn run_queued<T: Store>(
+    writer: &mut BufWriter<&TcpStream>,
+    queued: &[(RESPCmd, Vec<Vec<u8>>)],
+    store: &mut T,
+    config: &Arc<Config>,
+    replicas: &Replicas,
+    exec_lock: &ExecLock,
+    pubsub: &PubSub,
+) -> Result<()> {
+    // NOTE: like redis, maxmemory is enforced once for the whole transaction
+    let denies_oom = queued.iter().any(|(cmd, _)| return cmd.denies_oom());
+    if let Err(e) = cmds::make_room(store, replicas, denies_oom) {
+        return match e.downcast_ref::<RedisError>() {
+            Some(RedisError::OutOfMemory) => Err(anyhow!(RedisError::ExecAbortBecause(
+                RedisError::OutOfMemory.to_string()
+            ))),
+            _ => Err(e),
+        };
+    }
+
+    reply::array_header(writer, queued.len())?;
+    replicas.multi();
+    let result = queued.iter().try_for_each(|(cmd, args)| {
+        return cmd.execute_queued(writer, args, store, config, replicas, exec_lock, pubsub);
+    });
+    replicas.exec();
+    return result;
+}
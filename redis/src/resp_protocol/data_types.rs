@@ -63,25 +63,24 @@ impl ArrayStack {
 }
 
 fn read_next_data(reader: &mut BufReader<&TcpStream>, optional: bool) -> Option<RESPType> {
-    for byte in reader.bytes() {
-        if let Err(error) = byte {
-            return if optional {
-                None
-            } else {
-                Some(RESPType::Error {
-                    _msg: f!("Could not read next data type symbol: {}", error),
-                })
-            };
-        }
-
-        return match parse_data(byte.unwrap(), reader) {
-            Ok(parsed) => Some(parsed),
-            Err(e) => Some(RESPType::Error {
-                _msg: e.to_string(),
-            }),
+    let byte = reader.bytes().next()?;
+
+    if let Err(error) = byte {
+        return if optional {
+            None
+        } else {
+            Some(RESPType::Error {
+                _msg: f!("Could not read next data type symbol: {}", error),
+            })
         };
     }
-    return None;
+
+    return match parse_data(byte.unwrap(), reader) {
+        Ok(parsed) => Some(parsed),
+        Err(e) => Some(RESPType::Error {
+            _msg: e.to_string(),
+        }),
+    };
 }
 
 fn parse_data(data_type_char: u8, reader: &mut BufReader<&TcpStream>) -> Result<RESPType> {
@@ -109,7 +108,7 @@ fn parse_simple_string(
     return Ok(RESPType::SimpleString { value: String::from_utf8(value)? });
 }
 
-fn parse_bulk_string<'a>(reader: &mut BufReader<&TcpStream>) -> Result<RESPType> {
+fn parse_bulk_string(reader: &mut BufReader<&TcpStream>) -> Result<RESPType> {
     log::debug("Parsing RESP BulkString!");
     let bulk_string_size = util::read_size(reader)?;
     log::debug(f!("Parsed a RESP BulkString of size {}", bulk_string_size));
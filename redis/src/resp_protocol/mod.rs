@@ -1,19 +1,37 @@
 pub mod cmds;
 pub mod data_types;
+pub mod reply;
 pub mod util;
 
+mod cmds_bitmaps;
+mod cmds_databases;
 mod cmds_echo;
+mod cmds_geo;
 mod cmds_get;
+mod cmds_hashes;
+mod cmds_hyperloglogs;
 mod cmds_info;
+mod cmds_keys;
+mod cmds_lists;
+mod cmds_objects;
 mod cmds_ping;
+mod cmds_psync;
+mod cmds_pubsub;
 mod cmds_repl_conf;
 mod cmds_set;
-mod cmds_psync;
+mod cmds_sets;
+mod cmds_sorted_sets;
+mod cmds_stream_groups;
+mod cmds_streams;
+mod cmds_strings;
+mod cmds_transactions;
 
 pub use cmds_echo::echo;
 pub use cmds_get::get;
 pub use cmds_info::info;
 pub use cmds_ping::ping;
+pub use cmds_psync::psync;
+pub use cmds_pubsub::Subscriptions;
 pub use cmds_repl_conf::repl_conf;
 pub use cmds_set::set;
-pub use cmds_psync::psync;
+pub use cmds_transactions::Transaction;
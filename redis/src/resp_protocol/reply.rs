@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::{errors::RedisError, prelude::*};
+
+pub fn ok(writer: &mut impl Write) -> Result<()> {
+    writer.write_all(b"+OK\r\n")?;
+    return Ok(());
+}
+
+pub fn simple_string(writer: &mut impl Write, value: &str) -> Result<()> {
+    writer.write_all(f!("+{}\r\n", value).as_bytes())?;
+    return Ok(());
+}
+
+pub fn error(writer: &mut impl Write, error: &RedisError) -> Result<()> {
+    writer.write_all(f!("-{}\r\n", error).as_bytes())?;
+    return Ok(());
+}
+
+pub fn integer(writer: &mut impl Write, value: i64) -> Result<()> {
+    writer.write_all(f!(":{}\r\n", value).as_bytes())?;
+    return Ok(());
+}
+
+pub fn bulk_string(writer: &mut impl Write, value: &str) -> Result<()> {
+    return bulk_bytes(writer, value.as_bytes());
+}
+
+/// Bulk string with a binary-safe payload, for string values.
+pub fn bulk_bytes(writer: &mut impl Write, value: &[u8]) -> Result<()> {
+    writer.write_all(f!("${}\r\n", value.len()).as_bytes())?;
+    writer.write_all(value)?;
+    writer.write_all(b"\r\n")?;
+    return Ok(());
+}
+
+pub fn null_bulk_string(writer: &mut impl Write) -> Result<()> {
+    writer.write_all(b"$-1\r\n")?;
+    return Ok(());
+}
+
+pub fn optional_bulk_string(writer: &mut impl Write, value: Option<&str>) -> Result<()> {
+    return match value {
+        Some(value) => bulk_string(writer, value),
+        None => null_bulk_string(writer),
+    };
+}
+
+pub fn optional_bulk_bytes(writer: &mut impl Write, value: Option<&[u8]>) -> Result<()> {
+    return match value {
+        Some(value) => bulk_bytes(writer, value),
+        None => null_bulk_string(writer),
+    };
+}
+
+pub fn array_header(writer: &mut impl Write, size: usize) -> Result<()> {
+    writer.write_all(f!("*{}\r\n", size).as_bytes())?;
+    return Ok(());
+}
+
+pub fn null_array(writer: &mut impl Write) -> Result<()> {
+    writer.write_all(b"*-1\r\n")?;
+    return Ok(());
+}
+
+pub fn bulk_string_array<S: AsRef<str>>(writer: &mut impl Write, values: &[S]) -> Result<()> {
+    array_header(writer, values.len())?;
+    for value in values {
+        bulk_string(writer, value.as_ref())?;
+    }
+    return Ok(());
+}
+
+pub fn integer_array(writer: &mut impl Write, values: &[i64]) -> Result<()> {
+    array_header(writer, values.len())?;
+    for value in values {
+        integer(writer, *value)?;
+    }
+    return Ok(());
+}
+
+/// Encodes a cmd as a RESP array of bulk strings, the way clients send them.
+pub fn encode_cmd<S: AsRef<[u8]>>(parts: &[S]) -> Vec<u8> {
+    let mut encoded = f!("*{}\r\n", parts.len()).into_bytes();
+    for part in parts {
+        let part = part.as_ref();
+        encoded.extend_from_slice(f!("${}\r\n", part.len()).as_bytes());
+        encoded.extend_from_slice(part);
+        encoded.extend_from_slice(b"\r\n");
+    }
+    return encoded;
+}
@@ -1,13 +1,14 @@
 use std::{
     io::{BufReader, Read},
     net::TcpStream,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context, Result};
 
-use crate::{log, prelude::*};
+use crate::{errors::RedisError, log, persistence::parse_integer, prelude::*};
 
-use super::data_types::{self, RESPType};
+use super::data_types::{self, ArrayStack, RESPType};
 
 pub fn read_size(reader: &mut BufReader<&TcpStream>) -> Result<usize> {
     let expected_size = read_until_line_break(reader, 10)?;
@@ -19,10 +20,146 @@ pub fn read_size(reader: &mut BufReader<&TcpStream>) -> Result<usize> {
     return Ok(size);
 }
 
+/// Reads the remaining bulk strings of the array currently being parsed (the cmd arguments),
+/// as raw bytes.
+pub fn read_args(
+    reader: &mut BufReader<&TcpStream>,
+    array_stack: &mut ArrayStack,
+) -> Result<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+
+    while array_stack.expects_more() {
+        let next_data = data_types::read_next_data_mandatory(reader);
+
+        if next_data.is_none() {
+            return Err(anyhow!(
+                "Expected bulk string as cmd argument, got nothing."
+            ));
+        }
+
+        match next_data.unwrap() {
+            RESPType::BulkString { size } => {
+                log::debug(f!("Reading cmd argument of size {}", size));
+
+                let mut arg_bytes = vec![0; size];
+                reader.read_exact(&mut arg_bytes)?;
+                consume_line_break(reader)?;
+                args.push(arg_bytes);
+            }
+            data => {
+                return Err(anyhow!(
+                    "[ERR] Expected bulk string as cmd argument, got {:?}",
+                    data
+                ))
+            }
+        }
+        array_stack.decrement()?;
+    }
+
+    return Ok(args);
+}
+
+/// Reads args as text. Only the values of string cmds are binary safe, every other arg
+/// has to be valid UTF-8.
+pub fn text_args(args: &[Vec<u8>]) -> Result<Vec<String>> {
+    return args.iter().map(|arg| return text(arg)).collect();
+}
+
+pub fn text(arg: &[u8]) -> Result<String> {
+    return String::from_utf8(arg.to_vec()).map_err(|_| return anyhow!(RedisError::NotUtf8));
+}
+
+/// Parses an integer as strictly as redis does, see `persistence::parse_integer`.
+pub fn parse_int(arg: &str) -> Result<i64> {
+    return parse_integer(arg).ok_or(anyhow!(RedisError::NotInteger));
+}
+
+pub fn parse_float(arg: &str) -> Result<f64> {
+    return arg
+        .parse::<f64>()
+        .ok()
+        .filter(|value| return !value.is_nan())
+        .ok_or(anyhow!(RedisError::NotFloat));
+}
+
+pub fn parse_count(arg: &str) -> Result<usize> {
+    let count = parse_int(arg)?;
+    if count < 0 {
+        return Err(anyhow!(RedisError::NotPositive));
+    }
+    return Ok(count as usize);
+}
+
+/// Parses a blocking cmd timeout given in (possibly fractional) seconds. Zero blocks forever.
+pub fn parse_timeout(arg: &str) -> Result<Option<Duration>> {
+    let seconds = arg
+        .parse::<f64>()
+        .map_err(|_| anyhow!(RedisError::InvalidTimeout))?;
+
+    if seconds < 0.0 {
+        return Err(anyhow!(RedisError::NegativeTimeout));
+    }
+
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+
+    let timeout =
+        Duration::try_from_secs_f64(seconds).map_err(|_| anyhow!(RedisError::InvalidTimeout))?;
+    return Ok(Some(timeout));
+}
+
+/// Arguments shared by the SCAN family of cmds: `cursor [MATCH pattern] [COUNT count]`, plus
+/// NOVALUES (HSCAN) and TYPE (SCAN) for the cmds that accept them.
+pub struct ScanArgs<'a> {
+    pub cursor: u64,
+    pub pattern: Option<&'a str>,
+    pub count: usize,
+    pub with_values: bool,
+    pub key_type: Option<&'a str>,
+}
+
+pub fn parse_scan_args(
+    args: &[String],
+    accepts_no_values: bool,
+    accepts_type: bool,
+) -> Result<ScanArgs<'_>> {
+    let cursor = args[0]
+        .parse::<u64>()
+        .map_err(|_| return anyhow!(RedisError::Generic("invalid cursor".into())))?;
+
+    let mut scan_args = ScanArgs {
+        cursor,
+        pattern: None,
+        count: 10,
+        with_values: true,
+        key_type: None,
+    };
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "MATCH" => scan_args.pattern = Some(options.next().ok_or(RedisError::Syntax)?),
+            "COUNT" => {
+                let count = parse_int(options.next().ok_or(RedisError::Syntax)?)?;
+                if count < 1 {
+                    return Err(anyhow!(RedisError::Syntax));
+                }
+                scan_args.count = count as usize;
+            }
+            "NOVALUES" if accepts_no_values => scan_args.with_values = false,
+            "TYPE" if accepts_type => {
+                scan_args.key_type = Some(options.next().ok_or(RedisError::Syntax)?)
+            }
+            _ => return Err(anyhow!(RedisError::Syntax)),
+        }
+    }
+    return Ok(scan_args);
+}
+
 pub fn consume_line_break(reader: &mut BufReader<&TcpStream>) -> Result<()> {
     let mut line_break = [0; 2];
     reader.read_exact(&mut line_break)?;
-    if !(&line_break == b"\r\n") {
+    if &line_break != b"\r\n" {
         return match std::str::from_utf8(&line_break) {
             Ok(content) => Err(anyhow!("Expected line break after a cmd! Got {}", content)),
             Err(_) => Err(anyhow!("Expected line break after a cmd!")),
@@ -92,3 +229,43 @@ pub fn assert_response(reader: &mut BufReader<&TcpStream>, expected: &str) -> Re
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_int, parse_timeout, text_args};
+    use crate::errors::RedisError;
+    use std::time::Duration;
+
+    #[test]
+    fn reads_text_args_only_when_valid_utf8() {
+        let args = vec![b"key".to_vec(), "\u{e9}".as_bytes().to_vec()];
+        assert_eq!(text_args(&args).unwrap(), ["key", "\u{e9}"]);
+        assert!(text_args(&[b"key".to_vec(), vec![0xff, 0x00]]).is_err());
+    }
+
+    #[test]
+    fn rejects_integers_redis_would_not_write() {
+        assert_eq!(parse_int("-12").unwrap(), -12);
+        let error = parse_int("01").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RedisError>(),
+            Some(RedisError::NotInteger)
+        ));
+    }
+
+    #[test]
+    fn parses_timeouts() {
+        assert_eq!(parse_timeout("0").unwrap(), None);
+        assert_eq!(
+            parse_timeout("0.5").unwrap(),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_timeout("9223372036854775807").unwrap(),
+            Some(Duration::from_secs(i64::MAX as u64 + 1))
+        );
+        assert!(parse_timeout("-1").is_err());
+        assert!(parse_timeout("1e400").is_err());
+        assert!(parse_timeout("abc").is_err());
+    }
+}